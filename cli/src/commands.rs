@@ -0,0 +1,386 @@
+//! Runs the commands of the command line client against the REST API
+
+use crate::output::{render, Message};
+use crate::{Cli, CliError, Command, ConfirmArgs, VertiportsCommand};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use std::io::Read;
+use std::path::Path;
+use svc_cargo_client_rest::types::*;
+use svc_cargo_client_rest::{CargoRestClient, ClientConfig};
+
+/// Reads a JSON request body from a file, or from standard input for `-`
+pub fn read_body<T: DeserializeOwned>(path: &Path) -> Result<T, CliError> {
+    let contents = if path == Path::new("-") {
+        let mut contents = String::new();
+        std::io::stdin()
+            .read_to_string(&mut contents)
+            .map_err(|e| CliError::Input(format!("could not read standard input: {e}")))?;
+        contents
+    } else {
+        std::fs::read_to_string(path)
+            .map_err(|e| CliError::Input(format!("could not read {}: {e}", path.display())))?
+    };
+
+    serde_json::from_str(&contents)
+        .map_err(|e| CliError::Input(format!("invalid request body {}: {e}", path.display())))
+}
+
+/// Reads an image file, base64 encoded for the REST API
+fn read_image(path: &Path) -> Result<String, CliError> {
+    let contents = std::fs::read(path)
+        .map_err(|e| CliError::Input(format!("could not read {}: {e}", path.display())))?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(contents))
+}
+
+/// Time window of two optional bounds, clap makes sure both or neither are given
+fn window(min: Option<DateTime<Utc>>, max: Option<DateTime<Utc>>) -> Option<TimeWindow> {
+    Some(TimeWindow {
+        timestamp_min: min?,
+        timestamp_max: max?,
+    })
+}
+
+/// Recipient of a confirmed parcel, if any of their contact details are given
+fn recipient(args: &ConfirmArgs) -> Option<RecipientContact> {
+    let recipient = RecipientContact {
+        name: args.recipient_name.clone(),
+        email: args.recipient_email.clone(),
+        phone: args.recipient_phone.clone(),
+    };
+    (recipient != RecipientContact::default()).then_some(recipient)
+}
+
+/// Takes an argument clap requires when no body file is given
+fn required<T>(value: Option<T>) -> T {
+    value.expect("required argument checked by clap")
+}
+
+/// Runs the command, returns the formatted result
+pub async fn run(cli: &Cli) -> Result<String, CliError> {
+    let profile = cli.resolve_profile()?;
+    let client = CargoRestClient::new(ClientConfig {
+        base_url: profile.base_url(),
+        api_key: profile.api_key,
+        ..Default::default()
+    })?;
+    let format = cli.output;
+
+    match &cli.command {
+        Command::Vertiports {
+            command:
+                VertiportsCommand::Near {
+                    latitude,
+                    longitude,
+                },
+        } => {
+            let query = VertiportsQuery {
+                latitude: *latitude,
+                longitude: *longitude,
+            };
+            Ok(render(&client.vertiports(&query).await?, format))
+        }
+        Command::Request(args) => {
+            let request = match &args.file {
+                Some(path) => read_body(path)?,
+                None => FlightRequest {
+                    vertiport_depart_id: required(args.from.clone()),
+                    vertiport_arrive_id: required(args.to.clone()),
+                    time_depart_window: window(args.depart_after, args.depart_before),
+                    time_arrive_window: window(args.arrive_after, args.arrive_before),
+                    cargo_weight_kg: required(args.weight_kg),
+                    sort_by: args.sort_by,
+                    filter: (args.max_legs.is_some() || args.max_price.is_some()).then(|| {
+                        ItineraryFilter {
+                            max_legs: args.max_legs,
+                            max_price: args.max_price,
+                            ..Default::default()
+                        }
+                    }),
+                    limit: args.limit,
+                },
+            };
+            Ok(render(&client.request_flight(&request).await?, format))
+        }
+        Command::Confirm(args) => {
+            let confirm = match &args.file {
+                Some(path) => read_body(path)?,
+                None => ItineraryConfirm {
+                    id: required(args.itinerary_id.clone()),
+                    user_id: required(args.user_id.clone()),
+                    weight_grams: required(args.weight_grams),
+                    recipient: recipient(args),
+                },
+            };
+            Ok(render(&client.confirm(&confirm).await?, format))
+        }
+        Command::Cancel(args) => {
+            let cancel = match &args.file {
+                Some(path) => read_body(path)?,
+                None => ItineraryCancel {
+                    id: required(args.itinerary_id.clone()),
+                },
+            };
+            client.cancel(&cancel).await?;
+            let message = format!("Itinerary {} cancelled.", cancel.id);
+            Ok(render(&Message { message }, format))
+        }
+        Command::Scan(args) => {
+            let scan = match &args.file {
+                Some(path) => read_body(path)?,
+                None => ParcelScan {
+                    scanner_id: required(args.scanner_id.clone()),
+                    parcel_id: required(args.parcel_id.clone()),
+                    latitude: required(args.latitude),
+                    longitude: required(args.longitude),
+                },
+            };
+            client.scan(&scan).await?;
+            let message = format!("Scan of parcel {} recorded.", scan.parcel_id);
+            Ok(render(&Message { message }, format))
+        }
+        Command::Track(args) => {
+            let query = TrackingQuery {
+                parcel_id: args.parcel_id.clone(),
+            };
+            Ok(render(&client.track(&query).await?, format))
+        }
+        Command::PublicTrack(args) => {
+            Ok(render(&client.public_tracking(&args.token).await?, format))
+        }
+        Command::Label(args) => {
+            let params = LabelParams {
+                format: args.format,
+            };
+            let label = client.parcel_label(&args.parcel_id, &params).await?;
+            let path = match &args.output_file {
+                Some(path) => path.clone(),
+                None => {
+                    let extension = match args.format.unwrap_or_default() {
+                        LabelFormat::Png => "png",
+                        LabelFormat::Svg => "svg",
+                        LabelFormat::Pdf => "pdf",
+                        LabelFormat::Zpl => "zpl",
+                    };
+                    format!("parcel-{}.{extension}", args.parcel_id).into()
+                }
+            };
+            std::fs::write(&path, label)
+                .map_err(|e| CliError::Input(format!("could not write {}: {e}", path.display())))?;
+            let message = format!(
+                "Label of parcel {} saved to {}.",
+                args.parcel_id,
+                path.display()
+            );
+            Ok(render(&Message { message }, format))
+        }
+        Command::Deliver(args) => {
+            let proof = DeliveryProof {
+                scanner_id: args.scanner_id.clone(),
+                recipient_name: args.recipient_name.clone(),
+                signature: args.signature_file.as_deref().map(read_image).transpose()?,
+                pin: args.pin.clone(),
+                photo: args.photo_file.as_deref().map(read_image).transpose()?,
+                latitude: args.latitude,
+                longitude: args.longitude,
+            };
+            let delivery = client.deliver_parcel(&args.parcel_id, &proof).await?;
+            Ok(render(&delivery, format))
+        }
+        Command::Pickup(args) => {
+            let verification = PickupVerification {
+                scanner_id: args.scanner_id.clone(),
+                code: args.code.clone(),
+                latitude: args.latitude,
+                longitude: args.longitude,
+            };
+            let pickup = client.verify_pickup(&args.parcel_id, &verification).await?;
+            Ok(render(&pickup, format))
+        }
+        Command::Landings(args) => {
+            let params = LandingsParams {
+                vertiport_id: args.vertiport_id.clone(),
+                arrival_min: args.arrive_after,
+                arrival_max: args.arrive_before,
+                departure_min: args.depart_after,
+                departure_max: args.depart_before,
+                limit: args.limit,
+                cursor: args.cursor.clone(),
+            };
+            Ok(render(&client.landings(&params).await?, format))
+        }
+        Command::Health => {
+            let health = client.health().await?;
+            let report = render(&health, format);
+            if health.ready {
+                Ok(report)
+            } else {
+                Err(CliError::NotReady(report))
+            }
+        }
+    }
+}
+
+/// Runs the command and prints its result, returns the exit code
+pub async fn run_and_print(cli: &Cli) -> i32 {
+    match run(cli).await {
+        Ok(output) => {
+            print!("{output}");
+            0
+        }
+        Err(CliError::NotReady(report)) => {
+            print!("{report}");
+            3
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            e.exit_code()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::net::SocketAddr;
+    use svc_cargo::rest::server::build_app;
+    use svc_cargo::Config;
+    use tokio::sync::watch;
+
+    /// Serves the REST API in process, returns its port
+    ///
+    /// Scanners and shipments are kept in memory.
+    async fn serve() -> u16 {
+        let config = Config {
+            rest_scanner_registry_file: String::new(),
+            rest_shipment_file: String::new(),
+            rest_delivery_dir: String::new(),
+            ..Config::default()
+        };
+        let (_, live_config) = watch::channel(config.clone());
+        let app = build_app(&config, live_config, false).await.unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>()),
+        );
+
+        port
+    }
+
+    fn cli(port: u16, args: &[&str]) -> Cli {
+        let port = port.to_string();
+        let mut arguments = vec!["cargo-cli", "--host", "127.0.0.1", "--port", &port];
+        arguments.extend(args);
+        Cli::try_parse_from(arguments).unwrap()
+    }
+
+    #[test]
+    fn ut_read_body() {
+        let path = std::env::temp_dir().join(format!("cargo-cli-body-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"scanner_id": "s", "parcel_id": "p", "latitude": 52.3, "longitude": -4.9}"#,
+        )
+        .unwrap();
+        let scan: ParcelScan = read_body(&path).unwrap();
+        assert_eq!(scan.parcel_id, "p");
+        assert_eq!(scan.longitude, -4.9);
+
+        let error = read_body::<ItineraryCancel>(&path).unwrap_err();
+        assert!(matches!(error, CliError::Input(_)), "{error}");
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read_body::<ParcelScan>(&path).unwrap_err().exit_code(), 2);
+    }
+
+    #[tokio::test]
+    async fn ut_run_commands() {
+        let port = serve().await;
+
+        // Health is reported with the table or JSON
+        let health = cli(port, &["--output", "json", "health"]);
+        let report = match run(&health).await {
+            Ok(report) | Err(CliError::NotReady(report)) => report,
+            Err(e) => panic!("health failed: {e}"),
+        };
+        // The dependencies may not be probed yet
+        assert!(serde_json::from_str::<HealthResponse>(&report).is_ok());
+
+        // Errors of the server are returned with their message
+        let track = cli(port, &["track", "not-a-uuid"]);
+        let error = run(&track).await.unwrap_err();
+        assert_eq!(error.exit_code(), 1);
+        let CliError::Request(error) = error else {
+            panic!("unexpected error {error}");
+        };
+        assert_eq!(error.status().map(|s| s.as_u16()), Some(400));
+
+        // Nothing is saved when the label can't be made
+        let label = cli(port, &["label", "not-a-uuid", "--format", "zpl"]);
+        let CliError::Request(error) = run(&label).await.unwrap_err() else {
+            panic!("label of an invalid parcel saved");
+        };
+        assert_eq!(error.status().map(|s| s.as_u16()), Some(400));
+        assert!(!Path::new("parcel-not-a-uuid.zpl").exists());
+
+        // Pickup codes are checked before the parcel is looked up
+        let pickup = cli(
+            port,
+            &[
+                "pickup",
+                "cabcdd14-03ab-4ac0-b58c-dd4175bc587e",
+                "--scanner-id",
+                "8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1",
+                "--code",
+                "12",
+                "--latitude",
+                "52.37",
+                "--longitude",
+                "4.90",
+            ],
+        );
+        let CliError::Request(error) = run(&pickup).await.unwrap_err() else {
+            panic!("short pickup code accepted");
+        };
+        assert_eq!(error.status().map(|s| s.as_u16()), Some(400));
+
+        // Tracking links of unknown parcels are not found
+        let public_track = cli(port, &["public-track", "not-a-token"]);
+        let CliError::Request(error) = run(&public_track).await.unwrap_err() else {
+            panic!("unknown tracking link found");
+        };
+        assert_eq!(error.status().map(|s| s.as_u16()), Some(404));
+
+        // Images are read before the delivery is sent
+        let deliver = cli(
+            port,
+            &[
+                "deliver",
+                "cabcdd14-03ab-4ac0-b58c-dd4175bc587e",
+                "--scanner-id",
+                "8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1",
+                "--recipient-name",
+                "Ada Lovelace",
+                "--signature-file",
+                "/nonexistent/signature.png",
+                "--latitude",
+                "52.37",
+                "--longitude",
+                "4.90",
+            ],
+        );
+        assert_eq!(run(&deliver).await.unwrap_err().exit_code(), 2);
+
+        // Unreachable servers fail the request
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed_port = closed.local_addr().unwrap().port();
+        drop(closed);
+        let error = run(&cli(closed_port, &["health"])).await.unwrap_err();
+        assert!(matches!(error, CliError::Request(_)), "{error}");
+    }
+}
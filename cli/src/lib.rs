@@ -0,0 +1,596 @@
+//! Command line client of the svc-cargo REST API
+//!
+//! Calls the REST API with [`svc_cargo_client_rest`] for smoke tests and
+//!  operations. Results are printed as tables or as JSON.
+//!
+//! Exit codes:
+//! - `0`: the command succeeded
+//! - `1`: the request failed or the server answered with an error
+//! - `2`: invalid arguments, profiles or request body files
+//! - `3`: `health` reached a service that isn't ready
+
+pub mod commands;
+pub mod output;
+pub mod profile;
+
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand};
+use output::Format;
+use profile::{Profile, DEFAULT_PROFILE};
+use std::fmt;
+use std::path::PathBuf;
+use svc_cargo_client_rest::types::{ItinerarySortBy, LabelFormat};
+
+/// Command line arguments
+#[derive(Debug, Parser)]
+#[command(name = "cargo-cli", version, about = "Calls the svc-cargo REST API")]
+pub struct Cli {
+    /// Profile of the profiles file to connect with
+    #[arg(long, global = true, env = "CARGO_CLI_PROFILE", default_value = DEFAULT_PROFILE)]
+    pub profile: String,
+
+    /// Profiles file, `~/.config/svc-cargo/cli.toml` by default
+    #[arg(long, global = true, env = "CARGO_CLI_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Host of the REST server, instead of the host of the profile
+    #[arg(long, global = true)]
+    pub host: Option<String>,
+
+    /// Port of the REST server, instead of the port of the profile
+    #[arg(long, global = true)]
+    pub port: Option<u16>,
+
+    /// API key, instead of the API key of the profile
+    #[arg(long, global = true, env = "CARGO_CLI_API_KEY", hide_env_values = true)]
+    pub api_key: Option<String>,
+
+    /// Output format
+    #[arg(long, short, global = true, value_enum, default_value_t = Format::Table)]
+    pub output: Format,
+
+    /// Command to run
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+impl Cli {
+    /// Loads the selected profile with the overrides of the command line
+    pub fn resolve_profile(&self) -> Result<Profile, CliError> {
+        let mut profile = profile::load_profile(self.config.as_deref(), &self.profile)
+            .map_err(CliError::Input)?;
+        if let Some(host) = &self.host {
+            profile.host = host.clone();
+        }
+        if let Some(port) = self.port {
+            profile.port = port;
+        }
+        if let Some(api_key) = &self.api_key {
+            profile.api_key = Some(api_key.clone());
+        }
+
+        Ok(profile)
+    }
+}
+
+/// Commands of the command line client
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Vertiports
+    Vertiports {
+        /// Vertiports command to run
+        #[command(subcommand)]
+        command: VertiportsCommand,
+    },
+
+    /// Searches flights for a parcel
+    Request(RequestArgs),
+
+    /// Confirms an itinerary
+    Confirm(ConfirmArgs),
+
+    /// Cancels an itinerary
+    Cancel(CancelArgs),
+
+    /// Records a parcel scan
+    Scan(ScanArgs),
+
+    /// Shows the scans of a parcel
+    Track(TrackArgs),
+
+    /// Shows the progress of a parcel from the token of its tracking link
+    PublicTrack(PublicTrackArgs),
+
+    /// Saves the printable label of a parcel
+    Label(LabelArgs),
+
+    /// Records the delivery of a parcel to its recipient
+    Deliver(DeliverArgs),
+
+    /// Checks the pickup code given by the recipient of a parcel
+    Pickup(PickupArgs),
+
+    /// Shows arrivals and departures at a vertiport
+    Landings(LandingsArgs),
+
+    /// Shows the readiness of the service and its dependencies
+    Health,
+}
+
+/// Vertiports commands
+#[derive(Debug, Copy, Clone, Subcommand)]
+pub enum VertiportsCommand {
+    /// Lists the vertiports near a location
+    Near {
+        /// Latitude of the location
+        #[arg(long, allow_negative_numbers = true)]
+        latitude: f32,
+
+        /// Longitude of the location
+        #[arg(long, allow_negative_numbers = true)]
+        longitude: f32,
+    },
+}
+
+/// Arguments of `request`
+#[derive(Debug, Args)]
+pub struct RequestArgs {
+    /// JSON flight request body, `-` for standard input
+    #[arg(long, conflicts_with_all = ["from", "to", "weight_kg"])]
+    pub file: Option<PathBuf>,
+
+    /// ID of the departure vertiport
+    #[arg(long, required_unless_present = "file")]
+    pub from: Option<String>,
+
+    /// ID of the arrival vertiport
+    #[arg(long, required_unless_present = "file")]
+    pub to: Option<String>,
+
+    /// Weight of the parcel in kilograms
+    #[arg(long, required_unless_present = "file")]
+    pub weight_kg: Option<f32>,
+
+    /// Earliest departure, e.g. 2024-05-01T08:00:00Z
+    #[arg(long, requires = "depart_before")]
+    pub depart_after: Option<DateTime<Utc>>,
+
+    /// Latest departure
+    #[arg(long, requires = "depart_after")]
+    pub depart_before: Option<DateTime<Utc>>,
+
+    /// Earliest arrival
+    #[arg(long, requires = "arrive_before")]
+    pub arrive_after: Option<DateTime<Utc>>,
+
+    /// Latest arrival
+    #[arg(long, requires = "arrive_after")]
+    pub arrive_before: Option<DateTime<Utc>>,
+
+    /// Order of the itineraries, e.g. price or fewest_legs
+    #[arg(long, value_parser = parse_sort_by)]
+    pub sort_by: Option<ItinerarySortBy>,
+
+    /// Maximum number of legs per itinerary
+    #[arg(long)]
+    pub max_legs: Option<u32>,
+
+    /// Maximum price per itinerary
+    #[arg(long)]
+    pub max_price: Option<f32>,
+
+    /// Maximum number of itineraries
+    #[arg(long)]
+    pub limit: Option<u32>,
+}
+
+/// Arguments of `confirm`
+#[derive(Debug, Args)]
+pub struct ConfirmArgs {
+    /// JSON confirmation body, `-` for standard input
+    #[arg(long, conflicts_with_all = ["itinerary_id", "user_id", "weight_grams", "recipient_name", "recipient_email", "recipient_phone"])]
+    pub file: Option<PathBuf>,
+
+    /// ID of the itinerary to confirm
+    #[arg(required_unless_present = "file")]
+    pub itinerary_id: Option<String>,
+
+    /// ID of the user confirming the itinerary
+    #[arg(long, required_unless_present = "file")]
+    pub user_id: Option<String>,
+
+    /// Weight of the parcel in grams
+    #[arg(long, required_unless_present = "file")]
+    pub weight_grams: Option<u32>,
+
+    /// Name of the recipient to notify
+    #[arg(long)]
+    pub recipient_name: Option<String>,
+
+    /// Email address of the recipient to notify
+    #[arg(long)]
+    pub recipient_email: Option<String>,
+
+    /// Phone number of the recipient to notify, in international format
+    #[arg(long)]
+    pub recipient_phone: Option<String>,
+}
+
+/// Arguments of `cancel`
+#[derive(Debug, Args)]
+pub struct CancelArgs {
+    /// JSON cancellation body, `-` for standard input
+    #[arg(long, conflicts_with = "itinerary_id")]
+    pub file: Option<PathBuf>,
+
+    /// ID of the itinerary to cancel
+    #[arg(required_unless_present = "file")]
+    pub itinerary_id: Option<String>,
+}
+
+/// Arguments of `scan`
+#[derive(Debug, Args)]
+pub struct ScanArgs {
+    /// JSON scan body, `-` for standard input
+    #[arg(long, conflicts_with_all = ["parcel_id", "scanner_id", "latitude", "longitude"])]
+    pub file: Option<PathBuf>,
+
+    /// ID of the scanned parcel
+    #[arg(required_unless_present = "file")]
+    pub parcel_id: Option<String>,
+
+    /// ID of the scanner
+    #[arg(long, required_unless_present = "file")]
+    pub scanner_id: Option<String>,
+
+    /// Latitude of the scan
+    #[arg(long, allow_negative_numbers = true, required_unless_present = "file")]
+    pub latitude: Option<f64>,
+
+    /// Longitude of the scan
+    #[arg(long, allow_negative_numbers = true, required_unless_present = "file")]
+    pub longitude: Option<f64>,
+}
+
+/// Arguments of `track`
+#[derive(Debug, Args)]
+pub struct TrackArgs {
+    /// ID of the parcel
+    pub parcel_id: String,
+}
+
+/// Arguments of `public-track`
+#[derive(Debug, Args)]
+pub struct PublicTrackArgs {
+    /// Token of the tracking link, returned when the itinerary is confirmed
+    pub token: String,
+}
+
+/// Arguments of `label`
+#[derive(Debug, Args)]
+pub struct LabelArgs {
+    /// ID of the parcel
+    pub parcel_id: String,
+
+    /// File format of the label: pdf, png, svg or zpl
+    #[arg(long, value_parser = parse_label_format)]
+    pub format: Option<LabelFormat>,
+
+    /// File the label is saved to, `parcel-<ID>.<format>` if not given
+    #[arg(long)]
+    pub output_file: Option<PathBuf>,
+}
+
+/// Arguments of `deliver`
+#[derive(Debug, Args)]
+pub struct DeliverArgs {
+    /// ID of the parcel
+    pub parcel_id: String,
+
+    /// ID of the scanner of the courier, its API key is the profile API key
+    #[arg(long)]
+    pub scanner_id: String,
+
+    /// Name of the person the parcel is handed to
+    #[arg(long)]
+    pub recipient_name: String,
+
+    /// PNG or JPEG image of the signature of the recipient
+    #[arg(long, required_unless_present = "pin", conflicts_with = "pin")]
+    pub signature_file: Option<PathBuf>,
+
+    /// Pickup code given by the recipient instead of a signature
+    #[arg(long)]
+    pub pin: Option<String>,
+
+    /// PNG or JPEG photo of the handover
+    #[arg(long)]
+    pub photo_file: Option<PathBuf>,
+
+    /// Latitude of the handover
+    #[arg(long, allow_negative_numbers = true)]
+    pub latitude: f64,
+
+    /// Longitude of the handover
+    #[arg(long, allow_negative_numbers = true)]
+    pub longitude: f64,
+}
+
+/// Arguments of `pickup`
+#[derive(Debug, Args)]
+pub struct PickupArgs {
+    /// ID of the parcel
+    pub parcel_id: String,
+
+    /// ID of the scanner checking the code, its API key is the profile API key
+    #[arg(long)]
+    pub scanner_id: String,
+
+    /// Pickup code given by the recipient
+    #[arg(long)]
+    pub code: String,
+
+    /// Latitude of the pickup
+    #[arg(long, allow_negative_numbers = true)]
+    pub latitude: f64,
+
+    /// Longitude of the pickup
+    #[arg(long, allow_negative_numbers = true)]
+    pub longitude: f64,
+}
+
+/// Arguments of `landings`
+#[derive(Debug, Args)]
+pub struct LandingsArgs {
+    /// ID of the vertiport
+    pub vertiport_id: String,
+
+    /// Earliest arrival
+    #[arg(long, requires = "arrive_before")]
+    pub arrive_after: Option<DateTime<Utc>>,
+
+    /// Latest arrival
+    #[arg(long, requires = "arrive_after")]
+    pub arrive_before: Option<DateTime<Utc>>,
+
+    /// Earliest departure
+    #[arg(long, requires = "depart_before")]
+    pub depart_after: Option<DateTime<Utc>>,
+
+    /// Latest departure
+    #[arg(long, requires = "depart_after")]
+    pub depart_before: Option<DateTime<Utc>>,
+
+    /// Maximum number of landings
+    #[arg(long)]
+    pub limit: Option<u32>,
+
+    /// Cursor of the next page, from a previous response
+    #[arg(long)]
+    pub cursor: Option<String>,
+}
+
+/// Parses a sort option by its name in the REST API
+fn parse_sort_by(value: &str) -> Result<ItinerarySortBy, String> {
+    serde_json::from_value(serde_json::Value::String(value.to_string()))
+        .map_err(|_| format!("unknown sort option '{value}'"))
+}
+
+/// Parses a label format by its name in the REST API
+fn parse_label_format(value: &str) -> Result<LabelFormat, String> {
+    serde_json::from_value(serde_json::Value::String(value.to_string()))
+        .map_err(|_| format!("unknown label format '{value}'"))
+}
+
+/// Reasons a command fails
+#[derive(Debug)]
+pub enum CliError {
+    /// Invalid profile or request body
+    Input(String),
+
+    /// The request failed
+    Request(svc_cargo_client_rest::Error),
+
+    /// The service isn't ready, with the rendered health report
+    NotReady(String),
+}
+
+impl CliError {
+    /// Exit code of the process
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Request(_) => 1,
+            CliError::Input(_) => 2,
+            CliError::NotReady(_) => 3,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Input(e) => write!(f, "{e}"),
+            CliError::Request(e) => write!(f, "{e}"),
+            CliError::NotReady(_) => write!(f, "service is not ready"),
+        }
+    }
+}
+
+impl std::error::Error for CliError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CliError::Request(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<svc_cargo_client_rest::Error> for CliError {
+    fn from(e: svc_cargo_client_rest::Error) -> Self {
+        CliError::Request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ut_parse_arguments() {
+        let cli = Cli::try_parse_from([
+            "cargo-cli",
+            "--profile",
+            "staging",
+            "-o",
+            "json",
+            "vertiports",
+            "near",
+            "--latitude",
+            "52.37",
+            "--longitude",
+            "-4.91",
+        ])
+        .unwrap();
+        assert_eq!(cli.profile, "staging");
+        assert_eq!(cli.output, Format::Json);
+        let Command::Vertiports {
+            command: VertiportsCommand::Near { longitude, .. },
+        } = cli.command
+        else {
+            panic!("unexpected command {:?}", cli.command);
+        };
+        assert_eq!(longitude, -4.91);
+
+        let cli = Cli::try_parse_from([
+            "cargo-cli",
+            "request",
+            "--from",
+            "a",
+            "--to",
+            "b",
+            "--weight-kg",
+            "1.5",
+            "--sort-by",
+            "fewest_legs",
+        ])
+        .unwrap();
+        let Command::Request(args) = cli.command else {
+            panic!("unexpected command {:?}", cli.command);
+        };
+        assert_eq!(args.sort_by, Some(ItinerarySortBy::FewestLegs));
+
+        // A body file replaces the body arguments
+        assert!(Cli::try_parse_from(["cargo-cli", "request", "--file", "-"]).is_ok());
+        assert!(
+            Cli::try_parse_from(["cargo-cli", "request", "--file", "-", "--from", "a"]).is_err()
+        );
+        assert!(Cli::try_parse_from(["cargo-cli", "request", "--from", "a"]).is_err());
+        assert!(Cli::try_parse_from(["cargo-cli", "cancel"]).is_err());
+
+        // Recipients are given with the arguments or in the body file
+        let cli = Cli::try_parse_from([
+            "cargo-cli",
+            "confirm",
+            "itinerary",
+            "--user-id",
+            "user",
+            "--weight-grams",
+            "1250",
+            "--recipient-email",
+            "ada@example.com",
+        ])
+        .unwrap();
+        let Command::Confirm(args) = cli.command else {
+            panic!("unexpected command {:?}", cli.command);
+        };
+        assert_eq!(args.recipient_email.as_deref(), Some("ada@example.com"));
+        assert!(Cli::try_parse_from([
+            "cargo-cli",
+            "confirm",
+            "--file",
+            "-",
+            "--recipient-phone",
+            "+31612345678",
+        ])
+        .is_err());
+
+        // Deliveries need either a signature or a PIN code
+        let deliver = |extra: &[&str]| {
+            let mut arguments = vec![
+                "cargo-cli",
+                "deliver",
+                "parcel",
+                "--scanner-id",
+                "scanner",
+                "--recipient-name",
+                "Ada",
+                "--latitude",
+                "52.37",
+                "--longitude",
+                "-4.9",
+            ];
+            arguments.extend(extra);
+            Cli::try_parse_from(arguments)
+        };
+        assert!(deliver(&["--pin", "123456"]).is_ok());
+        assert!(deliver(&["--signature-file", "signature.png"]).is_ok());
+        assert!(deliver(&[]).is_err());
+        assert!(deliver(&["--pin", "123456", "--signature-file", "signature.png"]).is_err());
+
+        // Windows need both ends
+        assert!(Cli::try_parse_from([
+            "cargo-cli",
+            "landings",
+            "vertiport",
+            "--arrive-after",
+            "2024-05-01T08:00:00Z",
+        ])
+        .is_err());
+        assert!(Cli::try_parse_from([
+            "cargo-cli",
+            "request",
+            "--from",
+            "a",
+            "--to",
+            "b",
+            "--weight-kg",
+            "1",
+            "--sort-by",
+            "slowest",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn ut_resolve_profile() {
+        let path = std::env::temp_dir().join(format!("cargo-cli-lib-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[ops]\nhost = \"ops.example.com\"\napi_key = \"ops-key\"\n",
+        )
+        .unwrap();
+        let config = path.to_str().unwrap();
+
+        let cli = Cli::try_parse_from([
+            "cargo-cli",
+            "--config",
+            config,
+            "--profile",
+            "ops",
+            "--port",
+            "9000",
+            "health",
+        ])
+        .unwrap();
+        let profile = cli.resolve_profile().unwrap();
+        assert_eq!(profile.base_url(), "http://ops.example.com:9000");
+        assert_eq!(profile.api_key.as_deref(), Some("ops-key"));
+
+        let cli =
+            Cli::try_parse_from(["cargo-cli", "--config", config, "--profile", "x", "health"])
+                .unwrap();
+        assert_eq!(cli.resolve_profile().unwrap_err().exit_code(), 2);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
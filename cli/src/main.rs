@@ -0,0 +1,10 @@
+//! Command line client of the svc-cargo REST API
+
+use clap::Parser;
+use svc_cargo_cli::{commands, Cli};
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    std::process::exit(commands::run_and_print(&cli).await);
+}
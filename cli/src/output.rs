@@ -0,0 +1,350 @@
+//! Table and JSON output of the command line client
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fmt;
+use svc_cargo_client_rest::types::*;
+
+/// Output format of command results
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Aligned columns for people
+    Table,
+
+    /// Pretty printed JSON, the response bodies of the REST API
+    Json,
+}
+
+/// Rows of text in aligned columns
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+    note: Option<String>,
+}
+
+impl Table {
+    /// Creates an empty table with the given column headers
+    pub fn new(headers: &[&'static str]) -> Self {
+        Table {
+            headers: headers.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    /// Adds a row, with a cell for each column
+    pub fn row(&mut self, cells: Vec<String>) {
+        self.rows.push(cells);
+    }
+
+    /// Adds a line below the table
+    pub fn note(&mut self, note: String) {
+        self.note = Some(note);
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.chars().count()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let headers = self.headers.iter().map(|h| h.to_string()).collect();
+        for cells in std::iter::once(&headers).chain(&self.rows) {
+            let line = cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect::<Vec<_>>()
+                .join("  ");
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
+        if let Some(note) = &self.note {
+            writeln!(f, "{note}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Command result that can be shown as a table
+pub trait Render: Serialize {
+    /// Text of the result in the table format
+    fn text(&self) -> String;
+}
+
+/// Formats a command result
+pub fn render<T: Render>(value: &T, format: Format) -> String {
+    match format {
+        Format::Table => value.text(),
+        Format::Json => match serde_json::to_string_pretty(value) {
+            Ok(json) => format!("{json}\n"),
+            Err(e) => format!("{{\"error\": \"{e}\"}}\n"),
+        },
+    }
+}
+
+/// Outcome of a command without a response body
+#[derive(Debug, Clone, Serialize)]
+pub struct Message {
+    /// What was done
+    pub message: String,
+}
+
+impl Render for Message {
+    fn text(&self) -> String {
+        format!("{}\n", self.message)
+    }
+}
+
+fn timestamp(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn optional<T: ToString>(value: &Option<T>) -> String {
+    value
+        .as_ref()
+        .map_or_else(|| "-".to_string(), ToString::to_string)
+}
+
+fn price(price: &Option<f32>, currency: &Option<String>) -> String {
+    match (price, currency) {
+        (Some(price), Some(currency)) => format!("{price:.2} {currency}"),
+        (Some(price), None) => format!("{price:.2}"),
+        _ => "-".to_string(),
+    }
+}
+
+/// Name of a serialized enum value, like in the JSON output
+fn variant<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => "-".to_string(),
+    }
+}
+
+impl Render for Vec<Vertiport> {
+    fn text(&self) -> String {
+        let mut table = Table::new(&["ID", "LABEL", "LATITUDE", "LONGITUDE"]);
+        for vertiport in self {
+            table.row(vec![
+                vertiport.id.clone(),
+                vertiport.label.clone(),
+                vertiport.latitude.to_string(),
+                vertiport.longitude.to_string(),
+            ]);
+        }
+
+        table.to_string()
+    }
+}
+
+impl Render for Vec<Itinerary> {
+    fn text(&self) -> String {
+        let mut table = Table::new(&[
+            "ID", "DEPART", "ARRIVE", "LEGS", "DURATION", "DISTANCE", "PRICE",
+        ]);
+        for itinerary in self {
+            table.row(vec![
+                itinerary.id.clone(),
+                timestamp(&itinerary.timestamp_depart),
+                timestamp(&itinerary.timestamp_arrive),
+                itinerary.num_legs.to_string(),
+                format!("{}m", itinerary.total_duration_seconds / 60),
+                format!("{:.1}km", itinerary.total_distance_meters / 1000.0),
+                price(&itinerary.base_pricing, &itinerary.currency_type),
+            ]);
+        }
+
+        table.to_string()
+    }
+}
+
+impl Render for ItineraryConfirmation {
+    fn text(&self) -> String {
+        let mut table = Table::new(&["ITINERARY", "PARCEL", "TRACKING", "PICKUP CODE"]);
+        table.row(vec![
+            self.itinerary_id.clone(),
+            self.parcel_id.clone(),
+            optional(&self.tracking_token),
+            optional(&self.pickup_code),
+        ]);
+        table.to_string()
+    }
+}
+
+impl Render for TrackingResponse {
+    fn text(&self) -> String {
+        let mut table = Table::new(&["SCANNER", "LATITUDE", "LONGITUDE"]);
+        for scan in &self.scans {
+            table.row(vec![
+                scan.scanner_id.clone(),
+                scan.latitude.to_string(),
+                scan.longitude.to_string(),
+            ]);
+        }
+        if let Some(delivery) = &self.delivery {
+            table.note(format!(
+                "Delivered to {} at {}",
+                delivery.recipient_name,
+                timestamp(&delivery.delivered_at)
+            ));
+        }
+
+        table.to_string()
+    }
+}
+
+impl Render for PublicTracking {
+    fn text(&self) -> String {
+        let mut table = Table::new(&["STATUS", "FROM", "TO", "ARRIVAL", "DELIVERED"]);
+        table.row(vec![
+            variant(&self.status),
+            optional(&self.origin),
+            optional(&self.destination),
+            self.estimated_arrival
+                .as_ref()
+                .map_or_else(|| "-".to_string(), timestamp),
+            self.delivered_at
+                .as_ref()
+                .map_or_else(|| "-".to_string(), timestamp),
+        ]);
+        table.to_string()
+    }
+}
+
+impl Render for Delivery {
+    fn text(&self) -> String {
+        let mut table = Table::new(&["PARCEL", "RECIPIENT", "CONFIRMATION", "PHOTO", "DELIVERED"]);
+        table.row(vec![
+            self.parcel_id.clone(),
+            self.recipient_name.clone(),
+            variant(&self.confirmation),
+            self.has_photo.to_string(),
+            timestamp(&self.delivered_at),
+        ]);
+        table.to_string()
+    }
+}
+
+impl Render for Pickup {
+    fn text(&self) -> String {
+        let mut table = Table::new(&["PARCEL", "SCANNER", "VERTIPORT", "PICKED UP"]);
+        table.row(vec![
+            self.parcel_id.clone(),
+            self.scanner_id.clone(),
+            self.vertiport_id.clone(),
+            timestamp(&self.picked_up_at),
+        ]);
+        table.to_string()
+    }
+}
+
+impl Render for LandingsResponse {
+    fn text(&self) -> String {
+        let mut table = Table::new(&[
+            "TIME",
+            "KIND",
+            "STATUS",
+            "PAD",
+            "AIRCRAFT",
+            "FLIGHT PLAN",
+            "DELIVER",
+            "ACQUIRE",
+        ]);
+        for landing in &self.landings {
+            table.row(vec![
+                timestamp(&landing.timestamp),
+                variant(&landing.kind),
+                variant(&landing.status),
+                landing.vertipad_name.clone(),
+                landing.aircraft_callsign.clone(),
+                landing.flight_plan_id.clone(),
+                landing.parcels_deliver.len().to_string(),
+                landing.parcels_acquire.len().to_string(),
+            ]);
+        }
+        if let Some(cursor) = &self.next_cursor {
+            table.note(format!("More landings with --cursor {cursor}"));
+        }
+
+        table.to_string()
+    }
+}
+
+impl Render for HealthResponse {
+    fn text(&self) -> String {
+        let mut table = Table::new(&["DEPENDENCY", "HEALTHY", "LATENCY", "ERROR"]);
+        for dependency in &self.dependencies {
+            table.row(vec![
+                dependency.name.clone(),
+                dependency.healthy.to_string(),
+                format!("{}ms", dependency.latency_ms),
+                optional(&dependency.error),
+            ]);
+        }
+        let checked_at = self
+            .checked_at
+            .as_ref()
+            .map_or_else(|| "-".to_string(), timestamp);
+        table.note(format!(
+            "{} (checked at {checked_at})",
+            if self.ready { "Ready" } else { "Not ready" }
+        ));
+
+        table.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ut_table() {
+        let mut table = Table::new(&["ID", "LABEL"]);
+        table.row(vec!["1".to_string(), "Mercy Hospital".to_string()]);
+        table.row(vec!["1234".to_string(), "Dock".to_string()]);
+        table.note("2 vertiports".to_string());
+
+        assert_eq!(
+            table.to_string(),
+            "ID    LABEL\n1     Mercy Hospital\n1234  Dock\n2 vertiports\n"
+        );
+        assert_eq!(Table::new(&["ID"]).to_string(), "ID\n");
+    }
+
+    #[test]
+    fn ut_render() {
+        let health = HealthResponse {
+            ready: false,
+            checked_at: None,
+            dependencies: vec![DependencyHealth {
+                name: "storage".to_string(),
+                healthy: false,
+                latency_ms: 12,
+                error: Some("unavailable".to_string()),
+            }],
+        };
+        assert_eq!(
+            render(&health, Format::Table),
+            "DEPENDENCY  HEALTHY  LATENCY  ERROR\n\
+             storage     false    12ms     unavailable\n\
+             Not ready (checked at -)\n"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&render(&health, Format::Json)).unwrap();
+        assert_eq!(json["dependencies"][0]["name"], "storage");
+        assert_eq!(json["ready"], false);
+
+        let message = Message {
+            message: "Scan recorded.".to_string(),
+        };
+        assert_eq!(render(&message, Format::Table), "Scan recorded.\n");
+    }
+}
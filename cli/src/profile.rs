@@ -0,0 +1,164 @@
+//! Connection profiles of the command line client
+//!
+//! Profiles are tables of a TOML file, by default
+//!  `$XDG_CONFIG_HOME/svc-cargo/cli.toml` or `~/.config/svc-cargo/cli.toml`:
+//!
+//! ```toml
+//! [default]
+//! host = "localhost"
+//! port = 8000
+//!
+//! [staging]
+//! scheme = "https"
+//! host = "cargo.staging.example.com"
+//! port = 443
+//! api_key = "staging-key"
+//! ```
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Name of the profile used when none is given
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Server to connect to, and how to authenticate
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// `http` or `https`
+    pub scheme: String,
+
+    /// Host name or address of the REST server
+    pub host: String,
+
+    /// Port of the REST server
+    pub port: u16,
+
+    /// API key sent with every request
+    pub api_key: Option<String>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            scheme: "http".to_string(),
+            host: "localhost".to_string(),
+            port: 8000,
+            api_key: None,
+        }
+    }
+}
+
+impl Profile {
+    /// Base URL of the REST API
+    pub fn base_url(&self) -> String {
+        format!("{}://{}:{}", self.scheme, self.host, self.port)
+    }
+}
+
+/// Default location of the profiles file, if a home directory is known
+pub fn default_profiles_path() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(config_dir.join("svc-cargo").join("cli.toml"))
+}
+
+/// Parses the profiles of a profiles file
+pub fn parse_profiles(contents: &str) -> Result<HashMap<String, Profile>, String> {
+    toml::from_str(contents).map_err(|e| e.to_string())
+}
+
+/// Loads a profile from the profiles file
+///
+/// Without a profiles file at the default location the default profile
+///  connects to a local server. A profiles file given explicitly must exist.
+pub fn load_profile(path: Option<&Path>, name: &str) -> Result<Profile, String> {
+    let (path, explicit) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_profiles_path() {
+            Some(path) => (path, false),
+            None => (PathBuf::new(), false),
+        },
+    };
+
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(_) if !explicit && name == DEFAULT_PROFILE => return Ok(Profile::default()),
+        Err(e) => {
+            return Err(format!(
+                "could not read profiles file {}: {e}",
+                path.display()
+            ))
+        }
+    };
+
+    let mut profiles = parse_profiles(&contents)
+        .map_err(|e| format!("invalid profiles file {}: {e}", path.display()))?;
+    match profiles.remove(name) {
+        Some(profile) => Ok(profile),
+        None if name == DEFAULT_PROFILE => Ok(Profile::default()),
+        None => Err(format!(
+            "profile '{name}' is not in profiles file {}",
+            path.display()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ut_parse_profiles() {
+        let profiles = parse_profiles(
+            r#"
+            [default]
+            port = 8080
+
+            [staging]
+            scheme = "https"
+            host = "cargo.staging.example.com"
+            port = 443
+            api_key = "staging-key"
+            "#,
+        )
+        .unwrap();
+
+        let default = &profiles[DEFAULT_PROFILE];
+        assert_eq!(default.base_url(), "http://localhost:8080");
+        assert_eq!(default.api_key, None);
+
+        let staging = &profiles["staging"];
+        assert_eq!(staging.base_url(), "https://cargo.staging.example.com:443");
+        assert_eq!(staging.api_key.as_deref(), Some("staging-key"));
+
+        assert!(parse_profiles("[default]\nhostname = \"localhost\"").is_err());
+        assert!(parse_profiles("[default]\nport = \"eighty\"").is_err());
+    }
+
+    #[test]
+    fn ut_load_profile() {
+        let path = std::env::temp_dir().join(format!("cargo-cli-{}.toml", std::process::id()));
+        std::fs::write(&path, "[ops]\nhost = \"ops.example.com\"\n").unwrap();
+
+        let ops = load_profile(Some(&path), "ops").unwrap();
+        assert_eq!(ops.host, "ops.example.com");
+        assert_eq!(ops.port, 8000);
+
+        // The default profile doesn't have to be in the file
+        assert_eq!(
+            load_profile(Some(&path), DEFAULT_PROFILE).unwrap(),
+            Profile::default()
+        );
+        assert!(load_profile(Some(&path), "prod")
+            .unwrap_err()
+            .contains("profile 'prod'"));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(load_profile(Some(&path), DEFAULT_PROFILE).is_err());
+    }
+}
@@ -1,283 +1,112 @@
 //! Example communication with this service
 
 use chrono::{Duration, Utc};
-use hyper::{Body, Client, Method, Request, Response};
-use hyper::{Error, StatusCode};
 use lib_common::grpc::get_endpoint_from_env;
 use svc_cargo_client_rest::types::*;
+use svc_cargo_client_rest::{CargoRestClient, ClientConfig, Error};
 use uuid::Uuid;
 
-fn evaluate(resp: Result<Response<Body>, Error>, expected_code: StatusCode) -> (bool, String) {
-    let mut ok = true;
-    let result_str: String = match resp {
-        Ok(r) => {
-            let tmp = r.status() == expected_code;
-            ok &= tmp;
-            println!("{:?}", r.body());
-
-            r.status().to_string()
+/// Prints the outcome of a call, returns if it succeeded
+fn evaluate<T: std::fmt::Debug>(endpoint: &str, result: Result<T, Error>) -> bool {
+    match result {
+        Ok(response) => {
+            println!("{}: {:?}", endpoint, response);
+            true
         }
         Err(e) => {
-            ok = false;
-            e.to_string()
+            println!("{}: {}", endpoint, e);
+            false
         }
-    };
-
-    (ok, result_str)
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("NOTE: Ensure the server is running, or this example will fail.");
 
-    let rate_limit = std::env::var("REQUEST_LIMIT_PER_SECOND")
-        .unwrap_or_else(|_| "2".to_string())
-        .parse::<u64>()
-        .unwrap_or(2);
-
     let (host, port) = get_endpoint_from_env("SERVER_HOSTNAME", "SERVER_PORT_REST");
-    let url = format!("http://{host}:{port}");
+    let client = CargoRestClient::new(ClientConfig {
+        base_url: format!("http://{host}:{port}"),
+        api_key: std::env::var("API_KEY").ok(),
+        ..Default::default()
+    })?;
     let mut ok = true;
-    let client = Client::builder()
-        .pool_idle_timeout(std::time::Duration::from_secs(10))
-        .build_http();
-
-    // Too many requests
-    {
-        let data = VertiportsQuery {
-            latitude: 52.37488619450752,
-            longitude: 4.916048576268328,
-        };
 
-        let Ok(data) = serde_json::to_string(&data) else {
-            panic!("Failed to serialize data");
-        };
-
-        let uri = format!("{}/cargo/vertiports", url);
-
-        for x in 0..=rate_limit {
-            let Ok(request) = Request::builder()
-                .method(Method::POST)
-                .uri(uri.clone())
-                .header("content-type", "application/json")
-                .body(Body::from(data.clone()))
-            else {
-                panic!("Failed to build request");
-            };
-            if x >= rate_limit {
-                let resp: Result<Response<Body>, Error> = client.request(request).await;
-                let (success, result_str) = evaluate(resp, StatusCode::TOO_MANY_REQUESTS);
-                ok &= success;
-                println!("{}: {}", uri, result_str);
-            } else {
-                let _ = client.request(request).await;
-            }
-        }
-    }
-
-    std::thread::sleep(std::time::Duration::from_secs(1));
+    // Rate limited requests are retried by the client
+    let health = client.health().await;
+    ok &= evaluate("GET /health/ready", health);
 
     // POST /cargo/vertiports
-    {
-        let data = VertiportsQuery {
-            latitude: 52.37488619450752,
-            longitude: 4.916048576268328,
-        };
-
-        let Ok(data_str) = serde_json::to_string(&data) else {
-            panic!("Failed to serialize data");
-        };
-
-        let uri = format!("{}/cargo/vertiports", url);
-        let Ok(req) = Request::builder()
-            .method(Method::POST)
-            .uri(uri.clone())
-            .header("content-type", "application/json")
-            .body(Body::from(data_str))
-        else {
-            panic!("Failed to build request");
-        };
-
-        let resp = client.request(req).await;
-        let (success, result_str) = evaluate(resp, StatusCode::OK);
-        ok &= success;
-
-        println!("{}: {}", uri, result_str);
-    }
-
-    // Avoid too many requests
-    std::thread::sleep(std::time::Duration::from_secs(1));
+    let query = VertiportsQuery {
+        latitude: 52.37488619450752,
+        longitude: 4.916048576268328,
+    };
+    ok &= evaluate("POST /cargo/vertiports", client.vertiports(&query).await);
 
     // POST /cargo/request
-    {
-        let depart_timestamp_min = Utc::now() + Duration::seconds(60);
-        let data = FlightRequest {
-            // Arbitrary UUIDs
-            vertiport_depart_id: "cabcdd14-03ab-4ac0-b58c-dd4175bc587e".to_string(),
-            vertiport_arrive_id: "59e51ad1-d57d-4d2c-bc2d-e2387367d17f".to_string(),
-            time_depart_window: Some(TimeWindow {
-                timestamp_min: depart_timestamp_min,
-                timestamp_max: depart_timestamp_min + Duration::seconds(360),
-            }),
-            time_arrive_window: None,
-            cargo_weight_kg: 1.0,
-        };
-
-        let Ok(data_str) = serde_json::to_string(&data) else {
-            panic!("Failed to serialize data");
-        };
-
-        let uri = format!("{}/cargo/request", url);
-        let Ok(req) = Request::builder()
-            .method(Method::POST)
-            .uri(uri.clone())
-            .header("content-type", "application/json")
-            .body(Body::from(data_str))
-        else {
-            panic!("Failed to build request");
-        };
-
-        let resp = client.request(req).await;
-        let (success, result_str) = evaluate(resp, StatusCode::ACCEPTED);
-        ok &= success;
-
-        println!("{}: {}", uri, result_str);
-    }
-
-    // Avoid too many requests
-    std::thread::sleep(std::time::Duration::from_secs(1));
+    let depart_timestamp_min = Utc::now() + Duration::seconds(60);
+    let request = FlightRequest {
+        // Arbitrary UUIDs
+        vertiport_depart_id: "cabcdd14-03ab-4ac0-b58c-dd4175bc587e".to_string(),
+        vertiport_arrive_id: "59e51ad1-d57d-4d2c-bc2d-e2387367d17f".to_string(),
+        time_depart_window: Some(TimeWindow {
+            timestamp_min: depart_timestamp_min,
+            timestamp_max: depart_timestamp_min + Duration::seconds(360),
+        }),
+        time_arrive_window: None,
+        cargo_weight_kg: 1.0,
+        sort_by: Some(ItinerarySortBy::Price),
+        filter: Some(ItineraryFilter {
+            max_legs: Some(2),
+            ..Default::default()
+        }),
+        limit: Some(10),
+    };
+    ok &= evaluate("POST /cargo/request", client.request_flight(&request).await);
 
     // PUT /cargo/confirm
-    {
-        let data = ItineraryConfirm {
-            // Arbitrary UUID
-            id: Uuid::new_v4().to_string(),
-            user_id: Uuid::new_v4().to_string(),
-            weight_grams: 1,
-        };
-
-        let Ok(data_str) = serde_json::to_string(&data) else {
-            panic!("Failed to serialize data");
-        };
-
-        let uri = format!("{}/cargo/confirm", url);
-        let Ok(req) = Request::builder()
-            .method(Method::PUT)
-            .uri(uri.clone())
-            .header("content-type", "application/json")
-            .body(Body::from(data_str))
-        else {
-            panic!("Failed to build request");
-        };
-
-        let resp = client.request(req).await;
-        let (success, result_str) = evaluate(resp, StatusCode::OK);
-        ok &= success;
-
-        println!("{}: {}", uri, result_str);
-    }
-
-    // Avoid too many requests
-    std::thread::sleep(std::time::Duration::from_secs(1));
+    let confirm = ItineraryConfirm {
+        // Arbitrary UUID
+        id: Uuid::new_v4().to_string(),
+        user_id: Uuid::new_v4().to_string(),
+        weight_grams: 1,
+        recipient: None,
+    };
+    ok &= evaluate("PUT /cargo/confirm", client.confirm(&confirm).await);
 
     // DELETE /cargo/cancel
-    {
-        let data = ItineraryCancel {
-            // arbitrary UUID
-            id: "cabcdd14-03ab-4ac0-b58c-dd4175bc587e".to_string(),
-        };
-
-        let Ok(data_str) = serde_json::to_string(&data) else {
-            panic!("Failed to serialize data");
-        };
-
-        let uri = format!("{}/cargo/cancel", url);
-        let Ok(req) = Request::builder()
-            .method(Method::DELETE)
-            .uri(uri.clone())
-            .header("content-type", "application/json")
-            .body(Body::from(data_str))
-        else {
-            panic!("Failed to build request");
-        };
-
-        let resp = client.request(req).await;
-        let (success, result_str) = evaluate(resp, StatusCode::OK);
-        ok &= success;
-
-        println!("{}: {}", uri, result_str);
-    }
-
-    // Avoid too many requests
-    std::thread::sleep(std::time::Duration::from_secs(1));
+    let cancel = ItineraryCancel {
+        // arbitrary UUID
+        id: "cabcdd14-03ab-4ac0-b58c-dd4175bc587e".to_string(),
+    };
+    ok &= evaluate("DELETE /cargo/cancel", client.cancel(&cancel).await);
 
     // PUT /cargo/scan
-    {
-        let data = ParcelScan {
-            scanner_id: Uuid::new_v4().to_string(),
-            parcel_id: Uuid::new_v4().to_string(),
-            latitude: 52.37474373455002,
-            longitude: 4.9167298573581295,
-        };
-
-        let Ok(data) = serde_json::to_string(&data) else {
-            panic!("Failed to serialize data");
-        };
-
-        let uri = format!("{}/cargo/scan", url);
-        let Ok(request) = Request::builder()
-            .method(Method::PUT)
-            .uri(uri.clone())
-            .header("content-type", "application/json")
-            .body(Body::from(data))
-        else {
-            panic!("Failed to build request");
-        };
-
-        let resp = client.request(request).await;
-        let (success, result_str) = evaluate(resp, StatusCode::ACCEPTED);
-        ok &= success;
-
-        println!("{}: {}", uri, result_str);
-    }
-
-    // Avoid too many requests
-    std::thread::sleep(std::time::Duration::from_secs(1));
-
-    // GET /cargo/flights
-    {
-        let data = LandingsQuery {
-            vertiport_id: Uuid::new_v4().to_string(),
-            arrival_window: Some(TimeWindow {
-                timestamp_min: Utc::now(),
-                timestamp_max: Utc::now() + Duration::seconds(3600),
-            }),
-            limit: 20,
-        };
-
-        let Ok(data) = serde_json::to_string(&data) else {
-            panic!("Failed to serialize data");
-        };
-
-        let uri = format!("{}/cargo/landings", url);
-        let Ok(request) = Request::builder()
-            .method(Method::GET)
-            .uri(uri.clone())
-            .header("content-type", "application/json")
-            .body(Body::from(data))
-        else {
-            panic!("Failed to build request");
-        };
-
-        let resp = client.request(request).await;
-        let (success, result_str) = evaluate(resp, StatusCode::ACCEPTED);
-        ok &= success;
-
-        println!("{}: {}", uri, result_str);
-    }
-
-    // Avoid too many requests
-    std::thread::sleep(std::time::Duration::from_secs(1));
+    let parcel_id = Uuid::new_v4().to_string();
+    let scan = ParcelScan {
+        scanner_id: Uuid::new_v4().to_string(),
+        parcel_id: parcel_id.clone(),
+        latitude: 52.37474373455002,
+        longitude: 4.9167298573581295,
+    };
+    ok &= evaluate("PUT /cargo/scan", client.scan(&scan).await);
+
+    // GET /cargo/track
+    let query = TrackingQuery { parcel_id };
+    ok &= evaluate("GET /cargo/track", client.track(&query).await);
+
+    // GET /cargo/landings
+    let now = Utc::now();
+    let params = LandingsParams {
+        vertiport_id: Uuid::new_v4().to_string(),
+        arrival_min: Some(now),
+        arrival_max: Some(now + Duration::seconds(3600)),
+        departure_min: None,
+        departure_max: None,
+        limit: Some(20),
+        cursor: None,
+    };
+    ok &= evaluate("GET /cargo/landings", client.landings(&params).await);
 
     if ok {
         println!("\u{1F9c1} All endpoints responded!");
@@ -0,0 +1,384 @@
+//! Blocking client of the svc-cargo REST API, for programs without an async runtime
+//!
+//! Takes the same settings and returns the same errors as the async
+//!  [`crate::client::CargoRestClient`].
+
+use crate::client::{
+    api_error, base_url, decode_body, default_headers, endpoint, health_report, retry_delay,
+    signed_body, ClientConfig, Error, SCANNER_SIGNATURE_HEADER,
+};
+use crate::types::*;
+use reqwest::blocking::{Request, RequestBuilder, Response};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Method, Url};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Blocking client of the svc-cargo REST API
+///
+/// Waits for the delay given by the server before sending a rate limited request again.
+///
+/// # Example:
+/// ```no_run
+/// use svc_cargo_client_rest::blocking::CargoRestClient;
+/// use svc_cargo_client_rest::client::ClientConfig;
+/// use svc_cargo_client_rest::types::ParcelScan;
+/// fn example() -> Result<(), svc_cargo_client_rest::client::Error> {
+///     let client = CargoRestClient::new(ClientConfig {
+///         base_url: "https://cargo.example.com".to_string(),
+///         api_key: Some("scanner-key".to_string()),
+///         ..Default::default()
+///     })?;
+///     client.scan(&ParcelScan {
+///         scanner_id: "8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1".to_string(),
+///         parcel_id: "cabcdd14-03ab-4ac0-b58c-dd4175bc587e".to_string(),
+///         latitude: 52.3747,
+///         longitude: 4.9167,
+///     })
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CargoRestClient {
+    http: reqwest::blocking::Client,
+    base_url: Url,
+    config: ClientConfig,
+}
+
+impl CargoRestClient {
+    /// Creates a client with the given settings
+    ///
+    /// Must not be called from an async runtime, like the blocking client of reqwest.
+    pub fn new(config: ClientConfig) -> Result<Self, Error> {
+        let http = reqwest::blocking::Client::builder()
+            .default_headers(default_headers(&config)?)
+            .timeout(config.timeout)
+            .connect_timeout(config.connect_timeout)
+            .build()?;
+
+        Ok(CargoRestClient {
+            http,
+            base_url: base_url(&config)?,
+            config,
+        })
+    }
+
+    /// Settings of this client
+    pub fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+
+    /// Searches flights for a parcel, see `POST /cargo/request`
+    pub fn request_flight(&self, request: &FlightRequest) -> Result<Vec<Itinerary>, Error> {
+        let request = self
+            .request(Method::POST, "/cargo/request")
+            .json(request)
+            .build()?;
+        decode(self.execute(request)?)
+    }
+
+    /// Confirms an itinerary, see `PUT /cargo/confirm`
+    pub fn confirm(&self, confirm: &ItineraryConfirm) -> Result<ItineraryConfirmation, Error> {
+        let request = self
+            .request(Method::PUT, "/cargo/confirm")
+            .json(confirm)
+            .build()?;
+        decode(self.execute(request)?)
+    }
+
+    /// Cancels an itinerary, see `DELETE /cargo/cancel`
+    pub fn cancel(&self, cancel: &ItineraryCancel) -> Result<(), Error> {
+        let request = self
+            .request(Method::DELETE, "/cargo/cancel")
+            .json(cancel)
+            .build()?;
+        self.execute(request).map(|_| ())
+    }
+
+    /// Records a parcel scan, see `PUT /cargo/scan`
+    pub fn scan(&self, scan: &ParcelScan) -> Result<(), Error> {
+        let request = self
+            .request(Method::PUT, "/cargo/scan")
+            .json(scan)
+            .build()?;
+        self.execute(request).map(|_| ())
+    }
+
+    /// Records scans captured by a scanner device, see `PUT /cargo/scan/batch`
+    ///
+    /// Batches are signed with [`ClientConfig::scanner_signing_secret`] if it is set.
+    pub fn scan_batch(&self, batch: &ParcelScanBatch) -> Result<ParcelScanBatchResponse, Error> {
+        let (body, signature) = signed_body(batch, &self.config)?;
+        let mut request = self
+            .request(Method::PUT, "/cargo/scan/batch")
+            .header(CONTENT_TYPE, "application/json")
+            .body(body);
+        if let Some(signature) = signature {
+            request = request.header(SCANNER_SIGNATURE_HEADER, signature);
+        }
+        let request = request.build()?;
+        decode(self.execute(request)?)
+    }
+
+    /// Scans recorded away from where their parcel was expected, see `GET /cargo/scan/anomalies`
+    pub fn scan_anomalies(
+        &self,
+        params: &ScanAnomaliesParams,
+    ) -> Result<ScanAnomaliesResponse, Error> {
+        self.get("/cargo/scan/anomalies", params)
+    }
+
+    /// Registers a scanner device and issues its API key, see `POST /cargo/scanners`
+    pub fn register_scanner(
+        &self,
+        registration: &ScannerRegistration,
+    ) -> Result<ScannerCredentials, Error> {
+        let request = self
+            .request(Method::POST, "/cargo/scanners")
+            .json(registration)
+            .build()?;
+        decode(self.execute(request)?)
+    }
+
+    /// Registered scanner devices, see `GET /cargo/scanners`
+    pub fn scanners(&self, params: &ScannersParams) -> Result<ScannersResponse, Error> {
+        self.get("/cargo/scanners", params)
+    }
+
+    /// A registered scanner device, see `GET /cargo/scanners/{id}`
+    pub fn scanner(&self, scanner_id: &str) -> Result<Scanner, Error> {
+        let request = self
+            .request(Method::GET, &format!("/cargo/scanners/{scanner_id}"))
+            .build()?;
+        decode(self.execute(request)?)
+    }
+
+    /// Issues a new API key for a scanner device, see `POST /cargo/scanners/{id}/key`
+    pub fn rotate_scanner_key(&self, scanner_id: &str) -> Result<ScannerCredentials, Error> {
+        let request = self
+            .request(Method::POST, &format!("/cargo/scanners/{scanner_id}/key"))
+            .build()?;
+        decode(self.execute(request)?)
+    }
+
+    /// Assigns a scanner device to a vertiport, see `PUT /cargo/scanners/{id}/vertiport`
+    pub fn assign_scanner(
+        &self,
+        scanner_id: &str,
+        assignment: &ScannerAssignment,
+    ) -> Result<Scanner, Error> {
+        let request = self
+            .request(
+                Method::PUT,
+                &format!("/cargo/scanners/{scanner_id}/vertiport"),
+            )
+            .json(assignment)
+            .build()?;
+        decode(self.execute(request)?)
+    }
+
+    /// Deactivates a scanner device, see `DELETE /cargo/scanners/{id}`
+    pub fn deactivate_scanner(&self, scanner_id: &str) -> Result<Scanner, Error> {
+        let request = self
+            .request(Method::DELETE, &format!("/cargo/scanners/{scanner_id}"))
+            .build()?;
+        decode(self.execute(request)?)
+    }
+
+    /// Printable label of a parcel, see `GET /cargo/parcels/{id}/label`
+    pub fn parcel_label(&self, parcel_id: &str, params: &LabelParams) -> Result<Vec<u8>, Error> {
+        let request = self
+            .request(Method::GET, &format!("/cargo/parcels/{parcel_id}/label"))
+            .query(params)
+            .build()?;
+        Ok(self.execute(request)?.bytes()?.to_vec())
+    }
+
+    /// Records the delivery of a parcel to its recipient, see `POST /cargo/parcels/{id}/delivery`
+    pub fn deliver_parcel(
+        &self,
+        parcel_id: &str,
+        proof: &DeliveryProof,
+    ) -> Result<Delivery, Error> {
+        let request = self
+            .request(
+                Method::POST,
+                &format!("/cargo/parcels/{parcel_id}/delivery"),
+            )
+            .json(proof)
+            .build()?;
+        decode(self.execute(request)?)
+    }
+
+    /// Picks up a parcel with the code given by its recipient, see `POST /cargo/parcels/{id}/pickup`
+    ///
+    /// Sent by vertiport staff, with the API key of their scanner.
+    pub fn verify_pickup(
+        &self,
+        parcel_id: &str,
+        verification: &PickupVerification,
+    ) -> Result<Pickup, Error> {
+        let request = self
+            .request(Method::POST, &format!("/cargo/parcels/{parcel_id}/pickup"))
+            .json(verification)
+            .build()?;
+        decode(self.execute(request)?)
+    }
+
+    /// Progress of a parcel from its tracking link, see `GET /cargo/public/track/{token}`
+    pub fn public_tracking(&self, token: &str) -> Result<PublicTracking, Error> {
+        let request = self
+            .request(Method::GET, &format!("/cargo/public/track/{token}"))
+            .build()?;
+        decode(self.execute(request)?)
+    }
+
+    /// Scans of a parcel, see `GET /cargo/track`
+    pub fn track(&self, query: &TrackingQuery) -> Result<TrackingResponse, Error> {
+        self.get("/cargo/track", query)
+    }
+
+    /// Arrivals and departures at a vertiport, see `GET /cargo/landings`
+    pub fn landings(&self, params: &LandingsParams) -> Result<LandingsResponse, Error> {
+        self.get("/cargo/landings", params)
+    }
+
+    /// Vertiports near a location, see `POST /cargo/vertiports`
+    pub fn vertiports(&self, query: &VertiportsQuery) -> Result<Vec<Vertiport>, Error> {
+        let request = self
+            .request(Method::POST, "/cargo/vertiports")
+            .json(query)
+            .build()?;
+        decode(self.execute(request)?)
+    }
+
+    /// Availability calendar between two vertiports, see `GET /cargo/availability`
+    pub fn availability(&self, query: &AvailabilityQuery) -> Result<AvailabilityResponse, Error> {
+        self.get("/cargo/availability", query)
+    }
+
+    /// Readiness of the service and its dependencies, see `GET /health/ready`
+    ///
+    /// A service that isn't ready is reported in the response rather than as an error.
+    pub fn health(&self) -> Result<HealthResponse, Error> {
+        let request = self.request(Method::GET, "/health/ready").build()?;
+        match self.execute(request) {
+            Ok(response) => decode(response),
+            Err(Error::Api(e)) => health_report(e),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.http.request(method, endpoint(&self.base_url, path))
+    }
+
+    fn get<Q: Serialize, T: DeserializeOwned>(&self, path: &str, query: &Q) -> Result<T, Error> {
+        let request = self.request(Method::GET, path).query(query).build()?;
+        decode(self.execute(request)?)
+    }
+
+    /// Sends the request, again while it is rate limited, and turns error
+    ///  responses into [`Error::Api`]
+    fn execute(&self, request: Request) -> Result<Response, Error> {
+        let mut retries = 0;
+        loop {
+            // Bodies are serialized in memory, so requests can always be copied
+            let attempt = request
+                .try_clone()
+                .ok_or_else(|| Error::InvalidConfig("request body can't be sent again.".into()))?;
+            let response = self.http.execute(attempt)?;
+            let status = response.status();
+            if status.is_success() {
+                return Ok(response);
+            }
+
+            let headers = response.headers().clone();
+            let error = api_error(status, &headers, response.text().unwrap_or_default());
+            let Some(delay) = retry_delay(&error, retries, &self.config) else {
+                return Err(Error::Api(error));
+            };
+            retries += 1;
+            std::thread::sleep(delay);
+        }
+    }
+}
+
+fn decode<T: DeserializeOwned>(response: Response) -> Result<T, Error> {
+    decode_body(&response.bytes()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use svc_cargo::rest::server::build_app;
+    use svc_cargo::Config;
+    use tokio::sync::watch;
+
+    /// Serves the REST API on its own runtime, the blocking client can't run on one
+    ///
+    /// Scanners and shipments are kept in memory.
+    fn serve(config: Config) -> String {
+        let config = Config {
+            rest_scanner_registry_file: String::new(),
+            rest_shipment_file: String::new(),
+            rest_delivery_dir: String::new(),
+            ..config
+        };
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(async move {
+                let (_, live_config) = watch::channel(config.clone());
+                let app = build_app(&config, live_config, false).await.unwrap();
+                axum::Server::from_tcp(listener)
+                    .unwrap()
+                    .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                    .await
+                    .unwrap();
+            });
+        });
+
+        format!("http://{address}")
+    }
+
+    #[test]
+    fn ut_blocking_client() {
+        let config = Config {
+            rest_request_limit_per_second: 1,
+            rest_route_limits_per_second: String::new(),
+            ..Config::default()
+        };
+        let client = CargoRestClient::new(ClientConfig {
+            base_url: serve(config),
+            api_key: Some("blocking".to_string()),
+            max_retries: 1,
+            ..Default::default()
+        })
+        .unwrap();
+
+        // Error bodies are decoded like with the async client
+        let query = TrackingQuery {
+            parcel_id: "not-a-uuid".to_string(),
+        };
+        let Err(Error::Api(error)) = client.track(&query) else {
+            panic!("invalid parcel id accepted");
+        };
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+        assert!(error.request_id.is_some());
+
+        // The limit of the route was used, the retry waits for the next token
+        let start = std::time::Instant::now();
+        let query = TrackingQuery {
+            parcel_id: "also-not-a-uuid".to_string(),
+        };
+        assert_eq!(
+            client.track(&query).unwrap_err().status(),
+            Some(StatusCode::BAD_REQUEST)
+        );
+        assert!(start.elapsed() >= Duration::from_millis(900));
+    }
+}
@@ -0,0 +1,865 @@
+//! Async client of the svc-cargo REST API, and the settings and errors
+//!  shared with the blocking client
+
+use crate::types::*;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER};
+use reqwest::{Method, Request, Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::time::Duration;
+
+/// Header carrying the API key of the client
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Header carrying the id of a request, also returned in error bodies
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Header carrying the HMAC-SHA256 of a scan batch, in hexadecimal
+pub const SCANNER_SIGNATURE_HEADER: &str = "x-scanner-signature";
+
+/// Settings of a [`CargoRestClient`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientConfig {
+    /// Address of the server, e.g. `http://localhost:8000`
+    pub base_url: String,
+    /// Sent in the `X-Api-Key` header, identifies the client for rate limits and quotas
+    pub api_key: Option<String>,
+    /// Sent as a bearer token in the `Authorization` header
+    pub bearer_token: Option<String>,
+    /// Time after which a request fails, including reading the response,
+    ///  the browser decides in WebAssembly
+    pub timeout: Duration,
+    /// Time after which connecting to the server fails, the browser decides in WebAssembly
+    pub connect_timeout: Duration,
+    /// Number of times a rate limited request is sent again, never in WebAssembly
+    pub max_retries: u32,
+    /// Longest wait before retrying a rate limited request, longer waits fail instead
+    pub max_retry_delay: Duration,
+    /// Secret scan batches are signed with, if the server checks signatures,
+    ///  not supported in WebAssembly
+    pub scanner_signing_secret: Option<String>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            base_url: String::from("http://localhost:8000"),
+            api_key: None,
+            bearer_token: None,
+            timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(5),
+            max_retries: 2,
+            max_retry_delay: Duration::from_secs(10),
+            scanner_signing_secret: None,
+        }
+    }
+}
+
+/// Error response of the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    /// Status code of the response
+    pub status: StatusCode,
+    /// Message of the error body, or the status reason if the body has none
+    pub message: String,
+    /// Id of the failed request, to refer to it in logs
+    pub request_id: Option<String>,
+    /// Time after which the request may succeed, for rate limited or unavailable services
+    pub retry_after: Option<Duration>,
+}
+
+/// Errors of [`CargoRestClient`] calls
+#[derive(Debug)]
+pub enum Error {
+    /// The client settings are invalid
+    InvalidConfig(String),
+    /// The request could not be sent or the response could not be read
+    Transport(reqwest::Error),
+    /// The server answered with an error status
+    Api(ApiError),
+    /// The response body is not what the endpoint returns
+    Decode(String),
+}
+
+impl Error {
+    /// Status code of the error response, if the server answered
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::Api(e) => Some(e.status),
+            Error::Transport(e) => e.status(),
+            _ => None,
+        }
+    }
+
+    /// Whether the request timed out
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Error::Transport(e) if e.is_timeout())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidConfig(e) => write!(f, "invalid client configuration: {e}"),
+            Error::Transport(e) => write!(f, "request failed: {e}"),
+            Error::Api(e) => {
+                write!(f, "server answered {}: {}", e.status, e.message)?;
+                if let Some(request_id) = &e.request_id {
+                    write!(f, " (request id {request_id})")?;
+                }
+
+                Ok(())
+            }
+            Error::Decode(e) => write!(f, "unexpected response body: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Transport(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Transport(e)
+    }
+}
+
+/// Client of the svc-cargo REST API
+///
+/// Rate limited requests are sent again after the delay given by the
+///  server, they were rejected before reaching the handler so this is safe
+///  for all endpoints. WebAssembly has no timer to wait with, there the
+///  [`ApiError`] carries the delay instead.
+///
+/// # Example:
+/// ```no_run
+/// use svc_cargo_client_rest::client::{CargoRestClient, ClientConfig};
+/// use svc_cargo_client_rest::types::TrackingQuery;
+/// async fn example() -> Result<(), svc_cargo_client_rest::client::Error> {
+///     let client = CargoRestClient::new(ClientConfig {
+///         base_url: "http://localhost:8000".to_string(),
+///         api_key: Some("my-key".to_string()),
+///         ..Default::default()
+///     })?;
+///     let query = TrackingQuery {
+///         parcel_id: "cabcdd14-03ab-4ac0-b58c-dd4175bc587e".to_string(),
+///     };
+///     let tracking = client.track(&query).await?;
+///     println!("{} scans", tracking.scans.len());
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CargoRestClient {
+    http: reqwest::Client,
+    base_url: Url,
+    config: ClientConfig,
+}
+
+impl CargoRestClient {
+    /// Creates a client with the given settings
+    pub fn new(config: ClientConfig) -> Result<Self, Error> {
+        let base_url = base_url(&config)?;
+        let builder = reqwest::Client::builder().default_headers(default_headers(&config)?);
+        #[cfg(not(target_arch = "wasm32"))]
+        let builder = builder
+            .timeout(config.timeout)
+            .connect_timeout(config.connect_timeout);
+
+        Ok(CargoRestClient {
+            http: builder.build()?,
+            base_url,
+            config,
+        })
+    }
+
+    /// Settings of this client
+    pub fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+
+    /// Searches flights for a parcel, see `POST /cargo/request`
+    pub async fn request_flight(&self, request: &FlightRequest) -> Result<Vec<Itinerary>, Error> {
+        let request = self
+            .request(Method::POST, "/cargo/request")
+            .json(request)
+            .build()?;
+        decode(self.execute(request).await?).await
+    }
+
+    /// Confirms an itinerary, see `PUT /cargo/confirm`
+    pub async fn confirm(
+        &self,
+        confirm: &ItineraryConfirm,
+    ) -> Result<ItineraryConfirmation, Error> {
+        let request = self
+            .request(Method::PUT, "/cargo/confirm")
+            .json(confirm)
+            .build()?;
+        decode(self.execute(request).await?).await
+    }
+
+    /// Cancels an itinerary, see `DELETE /cargo/cancel`
+    pub async fn cancel(&self, cancel: &ItineraryCancel) -> Result<(), Error> {
+        let request = self
+            .request(Method::DELETE, "/cargo/cancel")
+            .json(cancel)
+            .build()?;
+        self.execute(request).await.map(|_| ())
+    }
+
+    /// Records a parcel scan, see `PUT /cargo/scan`
+    pub async fn scan(&self, scan: &ParcelScan) -> Result<(), Error> {
+        let request = self
+            .request(Method::PUT, "/cargo/scan")
+            .json(scan)
+            .build()?;
+        self.execute(request).await.map(|_| ())
+    }
+
+    /// Records scans captured by a scanner device, see `PUT /cargo/scan/batch`
+    ///
+    /// Batches are signed with [`ClientConfig::scanner_signing_secret`] if it is set.
+    pub async fn scan_batch(
+        &self,
+        batch: &ParcelScanBatch,
+    ) -> Result<ParcelScanBatchResponse, Error> {
+        let (body, signature) = signed_body(batch, &self.config)?;
+        let mut request = self
+            .request(Method::PUT, "/cargo/scan/batch")
+            .header(CONTENT_TYPE, "application/json")
+            .body(body);
+        if let Some(signature) = signature {
+            request = request.header(SCANNER_SIGNATURE_HEADER, signature);
+        }
+        let request = request.build()?;
+        decode(self.execute(request).await?).await
+    }
+
+    /// Scans recorded away from where their parcel was expected, see `GET /cargo/scan/anomalies`
+    pub async fn scan_anomalies(
+        &self,
+        params: &ScanAnomaliesParams,
+    ) -> Result<ScanAnomaliesResponse, Error> {
+        self.get("/cargo/scan/anomalies", params).await
+    }
+
+    /// Registers a scanner device and issues its API key, see `POST /cargo/scanners`
+    pub async fn register_scanner(
+        &self,
+        registration: &ScannerRegistration,
+    ) -> Result<ScannerCredentials, Error> {
+        let request = self
+            .request(Method::POST, "/cargo/scanners")
+            .json(registration)
+            .build()?;
+        decode(self.execute(request).await?).await
+    }
+
+    /// Registered scanner devices, see `GET /cargo/scanners`
+    pub async fn scanners(&self, params: &ScannersParams) -> Result<ScannersResponse, Error> {
+        self.get("/cargo/scanners", params).await
+    }
+
+    /// A registered scanner device, see `GET /cargo/scanners/{id}`
+    pub async fn scanner(&self, scanner_id: &str) -> Result<Scanner, Error> {
+        let request = self
+            .request(Method::GET, &format!("/cargo/scanners/{scanner_id}"))
+            .build()?;
+        decode(self.execute(request).await?).await
+    }
+
+    /// Issues a new API key for a scanner device, see `POST /cargo/scanners/{id}/key`
+    pub async fn rotate_scanner_key(&self, scanner_id: &str) -> Result<ScannerCredentials, Error> {
+        let request = self
+            .request(Method::POST, &format!("/cargo/scanners/{scanner_id}/key"))
+            .build()?;
+        decode(self.execute(request).await?).await
+    }
+
+    /// Assigns a scanner device to a vertiport, see `PUT /cargo/scanners/{id}/vertiport`
+    pub async fn assign_scanner(
+        &self,
+        scanner_id: &str,
+        assignment: &ScannerAssignment,
+    ) -> Result<Scanner, Error> {
+        let request = self
+            .request(
+                Method::PUT,
+                &format!("/cargo/scanners/{scanner_id}/vertiport"),
+            )
+            .json(assignment)
+            .build()?;
+        decode(self.execute(request).await?).await
+    }
+
+    /// Deactivates a scanner device, see `DELETE /cargo/scanners/{id}`
+    pub async fn deactivate_scanner(&self, scanner_id: &str) -> Result<Scanner, Error> {
+        let request = self
+            .request(Method::DELETE, &format!("/cargo/scanners/{scanner_id}"))
+            .build()?;
+        decode(self.execute(request).await?).await
+    }
+
+    /// Printable label of a parcel, see `GET /cargo/parcels/{id}/label`
+    pub async fn parcel_label(
+        &self,
+        parcel_id: &str,
+        params: &LabelParams,
+    ) -> Result<Vec<u8>, Error> {
+        let request = self
+            .request(Method::GET, &format!("/cargo/parcels/{parcel_id}/label"))
+            .query(params)
+            .build()?;
+        Ok(self.execute(request).await?.bytes().await?.to_vec())
+    }
+
+    /// Records the delivery of a parcel to its recipient, see `POST /cargo/parcels/{id}/delivery`
+    pub async fn deliver_parcel(
+        &self,
+        parcel_id: &str,
+        proof: &DeliveryProof,
+    ) -> Result<Delivery, Error> {
+        let request = self
+            .request(
+                Method::POST,
+                &format!("/cargo/parcels/{parcel_id}/delivery"),
+            )
+            .json(proof)
+            .build()?;
+        decode(self.execute(request).await?).await
+    }
+
+    /// Picks up a parcel with the code given by its recipient, see `POST /cargo/parcels/{id}/pickup`
+    ///
+    /// Sent by vertiport staff, with the API key of their scanner.
+    pub async fn verify_pickup(
+        &self,
+        parcel_id: &str,
+        verification: &PickupVerification,
+    ) -> Result<Pickup, Error> {
+        let request = self
+            .request(Method::POST, &format!("/cargo/parcels/{parcel_id}/pickup"))
+            .json(verification)
+            .build()?;
+        decode(self.execute(request).await?).await
+    }
+
+    /// Progress of a parcel from its tracking link, see `GET /cargo/public/track/{token}`
+    pub async fn public_tracking(&self, token: &str) -> Result<PublicTracking, Error> {
+        let request = self
+            .request(Method::GET, &format!("/cargo/public/track/{token}"))
+            .build()?;
+        decode(self.execute(request).await?).await
+    }
+
+    /// Scans of a parcel, see `GET /cargo/track`
+    pub async fn track(&self, query: &TrackingQuery) -> Result<TrackingResponse, Error> {
+        self.get("/cargo/track", query).await
+    }
+
+    /// Arrivals and departures at a vertiport, see `GET /cargo/landings`
+    pub async fn landings(&self, params: &LandingsParams) -> Result<LandingsResponse, Error> {
+        self.get("/cargo/landings", params).await
+    }
+
+    /// Vertiports near a location, see `POST /cargo/vertiports`
+    pub async fn vertiports(&self, query: &VertiportsQuery) -> Result<Vec<Vertiport>, Error> {
+        let request = self
+            .request(Method::POST, "/cargo/vertiports")
+            .json(query)
+            .build()?;
+        decode(self.execute(request).await?).await
+    }
+
+    /// Availability calendar between two vertiports, see `GET /cargo/availability`
+    pub async fn availability(
+        &self,
+        query: &AvailabilityQuery,
+    ) -> Result<AvailabilityResponse, Error> {
+        self.get("/cargo/availability", query).await
+    }
+
+    /// Readiness of the service and its dependencies, see `GET /health/ready`
+    ///
+    /// A service that isn't ready is reported in the response rather than as an error.
+    pub async fn health(&self) -> Result<HealthResponse, Error> {
+        let request = self.request(Method::GET, "/health/ready").build()?;
+        match self.execute(request).await {
+            Ok(response) => decode(response).await,
+            Err(Error::Api(e)) => health_report(e),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> reqwest::RequestBuilder {
+        self.http.request(method, endpoint(&self.base_url, path))
+    }
+
+    async fn get<Q: Serialize, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &Q,
+    ) -> Result<T, Error> {
+        let request = self.request(Method::GET, path).query(query).build()?;
+        decode(self.execute(request).await?).await
+    }
+
+    /// Sends the request, again while it is rate limited, and turns error
+    ///  responses into [`Error::Api`]
+    async fn execute(&self, request: Request) -> Result<Response, Error> {
+        let mut retries = 0;
+        loop {
+            // Bodies are serialized in memory, so requests can always be copied
+            let attempt = request
+                .try_clone()
+                .ok_or_else(|| Error::InvalidConfig("request body can't be sent again.".into()))?;
+            let response = self.http.execute(attempt).await?;
+            let status = response.status();
+            if status.is_success() {
+                return Ok(response);
+            }
+
+            let headers = response.headers().clone();
+            let error = api_error(status, &headers, response.text().await.unwrap_or_default());
+            let Some(delay) = retry_delay(&error, retries, &self.config) else {
+                return Err(Error::Api(error));
+            };
+            retries += 1;
+            sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn sleep(delay: Duration) {
+    tokio::time::sleep(delay).await
+}
+
+/// WebAssembly has no timer to wait with, [`retry_delay`] never retries there
+#[cfg(target_arch = "wasm32")]
+async fn sleep(_delay: Duration) {}
+
+async fn decode<T: DeserializeOwned>(response: Response) -> Result<T, Error> {
+    decode_body(&response.bytes().await?)
+}
+
+/// Parses the base URL of the settings
+pub(crate) fn base_url(config: &ClientConfig) -> Result<Url, Error> {
+    let base_url = Url::parse(&config.base_url)
+        .map_err(|e| Error::InvalidConfig(format!("base_url '{}': {e}", config.base_url)))?;
+    if base_url.cannot_be_a_base() {
+        return Err(Error::InvalidConfig(format!(
+            "base_url '{}' is not an http or https URL.",
+            config.base_url
+        )));
+    }
+
+    Ok(base_url)
+}
+
+/// Authentication headers sent with every request
+pub(crate) fn default_headers(config: &ClientConfig) -> Result<HeaderMap, Error> {
+    let mut headers = HeaderMap::new();
+    if let Some(api_key) = &config.api_key {
+        let value = HeaderValue::from_str(api_key)
+            .map_err(|_| Error::InvalidConfig("api_key is not a valid header value.".into()))?;
+        headers.insert(API_KEY_HEADER, value);
+    }
+    if let Some(token) = &config.bearer_token {
+        let mut value = HeaderValue::from_str(&format!("Bearer {token}")).map_err(|_| {
+            Error::InvalidConfig("bearer_token is not a valid header value.".into())
+        })?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+
+    Ok(headers)
+}
+
+/// URL of an endpoint, below the path of the base URL if it has one
+pub(crate) fn endpoint(base_url: &Url, path: &str) -> Url {
+    let mut url = base_url.clone();
+    let base_path = url.path().trim_end_matches('/').to_string();
+    url.set_path(&format!("{base_path}{path}"));
+    url
+}
+
+/// JSON body of a request, with its signature if the settings have a signing secret
+pub(crate) fn signed_body<T: Serialize>(
+    body: &T,
+    config: &ClientConfig,
+) -> Result<(Vec<u8>, Option<String>), Error> {
+    let body = serde_json::to_vec(body).map_err(|e| Error::Decode(e.to_string()))?;
+    let Some(secret) = &config.scanner_signing_secret else {
+        return Ok((body, None));
+    };
+
+    let signature = sign(secret, &body)?;
+    Ok((body, Some(signature)))
+}
+
+/// Hexadecimal HMAC-SHA256 of a body
+#[cfg(not(target_arch = "wasm32"))]
+fn sign(secret: &str, body: &[u8]) -> Result<String, Error> {
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+    Ok(ring::hmac::sign(&key, body)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn sign(_secret: &str, _body: &[u8]) -> Result<String, Error> {
+    Err(Error::InvalidConfig(
+        "scanner_signing_secret is not supported in WebAssembly.".into(),
+    ))
+}
+
+/// Reads an error response, with the message and request id of its [`ErrorResponse`] body
+pub(crate) fn api_error(status: StatusCode, headers: &HeaderMap, body: String) -> ApiError {
+    let retry_after = headers
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs);
+    let header_request_id = headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let (message, request_id) = match serde_json::from_str::<ErrorResponse>(&body) {
+        Ok(error) => (error.message, error.request_id.or(header_request_id)),
+        Err(_) if body.is_empty() => (
+            status.canonical_reason().unwrap_or("error").to_string(),
+            header_request_id,
+        ),
+        Err(_) => (body, header_request_id),
+    };
+
+    ApiError {
+        status,
+        message,
+        request_id,
+        retry_after,
+    }
+}
+
+/// Delay before sending a rate limited request again, none if it shouldn't be retried
+pub(crate) fn retry_delay(
+    error: &ApiError,
+    retries: u32,
+    config: &ClientConfig,
+) -> Option<Duration> {
+    if cfg!(target_arch = "wasm32")
+        || error.status != StatusCode::TOO_MANY_REQUESTS
+        || retries >= config.max_retries
+    {
+        return None;
+    }
+
+    error
+        .retry_after
+        .filter(|delay| *delay <= config.max_retry_delay)
+}
+
+/// Health report of a service that isn't ready, its body is the report rather than an error body
+pub(crate) fn health_report(error: ApiError) -> Result<HealthResponse, Error> {
+    if error.status != StatusCode::SERVICE_UNAVAILABLE {
+        return Err(Error::Api(error));
+    }
+
+    serde_json::from_str(&error.message).map_err(|_| Error::Api(error))
+}
+
+pub(crate) fn decode_body<T: DeserializeOwned>(body: &[u8]) -> Result<T, Error> {
+    serde_json::from_slice(body).map_err(|e| Error::Decode(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use svc_cargo::rest::server::build_app;
+    use svc_cargo::Config;
+    use tokio::sync::watch;
+
+    /// Serves the REST API of svc-cargo with stubbed backends, returns its address
+    ///
+    /// Scanners and shipments are kept in memory, the admin API key is `admin`.
+    async fn serve(config: Config) -> String {
+        let config = Config {
+            rest_scanner_registry_file: String::new(),
+            rest_shipment_file: String::new(),
+            rest_delivery_dir: String::new(),
+            rest_scanner_admin_key: "admin".to_string(),
+            ..config
+        };
+        let (_, live_config) = watch::channel(config.clone());
+        let app = build_app(&config, live_config, false).await.unwrap();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+        tokio::spawn(server);
+
+        format!("http://{address}")
+    }
+
+    fn client(base_url: &str, api_key: &str, max_retries: u32) -> CargoRestClient {
+        CargoRestClient::new(ClientConfig {
+            base_url: base_url.to_string(),
+            api_key: Some(api_key.to_string()),
+            max_retries,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn ut_client_config() {
+        assert!(CargoRestClient::new(ClientConfig::default()).is_ok());
+
+        for config in [
+            ClientConfig {
+                base_url: "localhost:8000".to_string(),
+                ..Default::default()
+            },
+            ClientConfig {
+                base_url: "not a url".to_string(),
+                ..Default::default()
+            },
+            ClientConfig {
+                api_key: Some("line\nbreak".to_string()),
+                ..Default::default()
+            },
+        ] {
+            let result = CargoRestClient::new(config.clone());
+            assert!(
+                matches!(result, Err(Error::InvalidConfig(_))),
+                "{:?}",
+                config
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn ut_client_error_body() {
+        let base_url = serve(Config::default()).await;
+        let client = client(&base_url, "errors", 0);
+
+        let query = TrackingQuery {
+            parcel_id: "not-a-uuid".to_string(),
+        };
+        let Err(Error::Api(error)) = client.track(&query).await else {
+            panic!("invalid parcel id accepted");
+        };
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+        assert!(!error.message.is_empty());
+        assert!(error.request_id.is_some());
+
+        let cancel = ItineraryCancel {
+            id: "not-a-uuid".to_string(),
+        };
+        let error = client.cancel(&cancel).await.unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::BAD_REQUEST));
+        assert!(error.to_string().contains("request id"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn ut_client_endpoints() {
+        let base_url = serve(Config::default()).await;
+        let admin = client(&base_url, "admin", 0);
+        let client = client(&base_url, "endpoints", 0);
+
+        let health = client.health().await.unwrap();
+        assert_eq!(health.ready, health.dependencies.iter().all(|d| d.healthy));
+
+        // Parameters are sent in the query string, the server requires a time window
+        let params = LandingsParams {
+            vertiport_id: "cabcdd14-03ab-4ac0-b58c-dd4175bc587e".to_string(),
+            arrival_min: None,
+            arrival_max: None,
+            departure_min: None,
+            departure_max: None,
+            limit: Some(10),
+            cursor: None,
+        };
+        let error = client.landings(&params).await.unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::BAD_REQUEST));
+
+        let params = ScanAnomaliesParams {
+            parcel_id: Some("cabcdd14-03ab-4ac0-b58c-dd4175bc587e".to_string()),
+            ..Default::default()
+        };
+        let anomalies = admin.scan_anomalies(&params).await.unwrap();
+        assert!(anomalies.anomalies.is_empty());
+
+        let params = LabelParams {
+            format: Some(LabelFormat::Zpl),
+        };
+        let error = client
+            .parcel_label("not-a-uuid", &params)
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::BAD_REQUEST));
+
+        // Deliveries need a signature or a PIN code
+        let proof = DeliveryProof {
+            recipient_name: "Ada Lovelace".to_string(),
+            latitude: 52.37,
+            longitude: 4.90,
+            ..Default::default()
+        };
+        let error = client
+            .deliver_parcel("cabcdd14-03ab-4ac0-b58c-dd4175bc587e", &proof)
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::BAD_REQUEST));
+
+        // Pickup codes have six digits
+        let verification = PickupVerification {
+            scanner_id: "8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1".to_string(),
+            code: "12ab".to_string(),
+            latitude: 52.37,
+            longitude: 4.90,
+        };
+        let error = client
+            .verify_pickup("cabcdd14-03ab-4ac0-b58c-dd4175bc587e", &verification)
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::BAD_REQUEST));
+
+        // Tracking links of unknown parcels are not found
+        let error = client.public_tracking(&"00".repeat(32)).await.unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::NOT_FOUND));
+
+        // Nothing listens on this address
+        let client = CargoRestClient::new(ClientConfig {
+            base_url: "http://127.0.0.1:9".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(matches!(client.health().await, Err(Error::Transport(_))));
+    }
+
+    #[tokio::test]
+    async fn ut_client_scan_batch() {
+        let base_url = serve(Config {
+            rest_scanner_signing_secret: "scanner-secret".to_string(),
+            ..Config::default()
+        })
+        .await;
+
+        // Scanners are registered first, and send their own API key
+        let admin = client(&base_url, "admin", 0);
+        let registration = ScannerRegistration {
+            label: "Handheld".to_string(),
+            vertiport_id: None,
+        };
+        let credentials = admin.register_scanner(&registration).await.unwrap();
+        let scanner_id = credentials.scanner.id.clone();
+        assert_eq!(
+            admin.scanner(&scanner_id).await.unwrap(),
+            credentials.scanner
+        );
+        let rotated = admin.rotate_scanner_key(&scanner_id).await.unwrap();
+        assert_ne!(rotated.api_key, credentials.api_key);
+        let scanners = admin.scanners(&ScannersParams::default()).await.unwrap();
+        assert_eq!(scanners.scanners, vec![rotated.scanner]);
+
+        let batch = ParcelScanBatch {
+            scanner_id: scanner_id.clone(),
+            scans: vec![BatchScan {
+                parcel_id: None,
+                barcode: Some("not-a-barcode".to_string()),
+                latitude: 52.3747,
+                longitude: 4.9167,
+                timestamp: chrono::Utc::now(),
+            }],
+        };
+
+        // Unsigned batches are refused
+        let client = client(&base_url, "unsigned", 0);
+        let error = client.scan_batch(&batch).await.unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::UNAUTHORIZED));
+
+        let client = CargoRestClient::new(ClientConfig {
+            base_url,
+            api_key: Some(rotated.api_key),
+            scanner_signing_secret: Some("scanner-secret".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        let response = client.scan_batch(&batch).await.unwrap();
+        assert_eq!(response.rejected, 1);
+        assert_eq!(response.results[0].status, BatchScanStatus::Rejected);
+
+        // Deactivated scanners can't record scans
+        let scanner = admin.deactivate_scanner(&scanner_id).await.unwrap();
+        assert!(!scanner.active);
+        let error = client.scan_batch(&batch).await.unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::FORBIDDEN));
+    }
+
+    #[tokio::test]
+    async fn ut_client_rate_limited() {
+        let config = Config {
+            rest_request_limit_per_second: 1,
+            rest_route_limits_per_second: "/cargo/scanners=100".to_string(),
+            ..Config::default()
+        };
+        let base_url = serve(config).await;
+
+        // Limits are kept for each scanner API key
+        let admin = client(&base_url, "admin", 0);
+        let mut api_keys = vec![];
+        for label in ["first", "second", "third"] {
+            let registration = ScannerRegistration {
+                label: label.to_string(),
+                vertiport_id: None,
+            };
+            let credentials = admin.register_scanner(&registration).await.unwrap();
+            api_keys.push(credentials.api_key);
+        }
+
+        // Without retries the rate limit is reported with its delay
+        let first = client(&base_url, &api_keys[0], 0);
+        assert!(first.health().await.is_ok());
+        let Err(Error::Api(error)) = first.health().await else {
+            panic!("rate limit not applied");
+        };
+        assert_eq!(error.status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(error.retry_after, Some(Duration::from_secs(1)));
+
+        // Each API key has its own limits, and retries wait for the given delay
+        let second = client(&base_url, &api_keys[1], 1);
+        assert!(second.health().await.is_ok());
+        let start = std::time::Instant::now();
+        assert!(second.health().await.is_ok());
+        assert!(start.elapsed() >= Duration::from_millis(900));
+
+        // Longer delays than allowed fail rather than wait
+        let third = CargoRestClient::new(ClientConfig {
+            base_url,
+            api_key: Some(api_keys[2].clone()),
+            max_retries: 1,
+            max_retry_delay: Duration::from_millis(100),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(third.health().await.is_ok());
+        let error = third.health().await.unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::TOO_MANY_REQUESTS));
+    }
+}
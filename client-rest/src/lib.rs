@@ -1,6 +1,21 @@
-//! Imports the types used in REST communication with the svc-cargo server
+//! Client of the svc-cargo REST API, and the types used in REST communication
+//!  with the svc-cargo server
+//!
+//! Without default features the types and the async client have no gRPC or
+//!  OpenAPI dependencies and build for `wasm32-unknown-unknown`.
+//!
+//! Features:
+//! - `openapi` (default): OpenAPI schemas of the types
+//! - `grpc_types` (default): conversions to the gRPC types of the backend services
+//! - `blocking`: [`blocking::CargoRestClient`], not available in WebAssembly
+
+#[cfg(all(feature = "blocking", not(target_arch = "wasm32")))]
+pub mod blocking;
+pub mod client;
 
 /// Types for messages to svc-cargo server
 pub mod types {
     include!("../../openapi/types.rs");
 }
+
+pub use client::{ApiError, CargoRestClient, ClientConfig, Error};
@@ -1,14 +1,49 @@
 use chrono::{DateTime, Utc};
 /// Types used for REST communication with the svc-cargo server
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "openapi")]
 use utoipa::{IntoParams, ToSchema};
-use svc_scheduler_client_grpc::prelude::scheduler_storage::GeoPoint;
 
 /// Don't allow overly large numbers of landings to be returned
 pub const MAX_LANDINGS_TO_RETURN: u32 = 50;
 
+/// Don't allow overly large numbers of scan anomalies to be returned
+pub const MAX_SCAN_ANOMALIES_TO_RETURN: u32 = 100;
+
+/// A location in degrees
+#[derive(Debug, Copy, Clone, PartialEq, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct GeoPoint {
+    /// The latitude of the location
+    pub latitude: f64,
+
+    /// The longitude of the location
+    pub longitude: f64,
+}
+
+#[cfg(feature = "grpc_types")]
+impl From<svc_scheduler_client_grpc::prelude::scheduler_storage::GeoPoint> for GeoPoint {
+    fn from(point: svc_scheduler_client_grpc::prelude::scheduler_storage::GeoPoint) -> Self {
+        GeoPoint {
+            latitude: point.latitude,
+            longitude: point.longitude,
+        }
+    }
+}
+
+#[cfg(feature = "grpc_types")]
+impl From<GeoPoint> for svc_scheduler_client_grpc::prelude::scheduler_storage::GeoPoint {
+    fn from(point: GeoPoint) -> Self {
+        svc_scheduler_client_grpc::prelude::scheduler_storage::GeoPoint {
+            latitude: point.latitude,
+            longitude: point.longitude,
+        }
+    }
+}
+
 /// Request Body Information for Flight Query
-#[derive(Debug, Clone, IntoParams, ToSchema, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(IntoParams, ToSchema))]
 pub struct FlightRequest {
     /// The String ID of the vertiport to leave from
     pub vertiport_depart_id: String,
@@ -17,17 +52,72 @@ pub struct FlightRequest {
     pub vertiport_arrive_id: String,
 
     /// The window of departure
+    /// The first leg of each itinerary departs within this window
     pub time_depart_window: Option<TimeWindow>,
 
     /// The window of arrival
+    /// The last leg of each itinerary arrives within this window
+    /// At least one of the departure and arrival windows is required
     pub time_arrive_window: Option<TimeWindow>,
 
     /// The estimated weight of cargo
     pub cargo_weight_kg: f32,
+
+    /// The order in which itineraries are returned
+    /// Defaults to the order provided by svc-scheduler
+    pub sort_by: Option<ItinerarySortBy>,
+
+    /// Itinerary constraints, applied after pricing
+    pub filter: Option<ItineraryFilter>,
+
+    /// The maximum number of itineraries to return
+    pub limit: Option<u32>,
+}
+
+/// Sort options for the itineraries returned by a flight request
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ItinerarySortBy {
+    /// Cheapest itinerary first
+    Price,
+
+    /// Earliest departure first
+    EarliestDeparture,
+
+    /// Earliest arrival first
+    EarliestArrival,
+
+    /// Shortest total duration first
+    Duration,
+
+    /// Shortest total distance first
+    Distance,
+
+    /// Fewest legs (transfers) first
+    FewestLegs,
+}
+
+/// Constraints an itinerary must satisfy to be returned
+#[derive(Debug, Copy, Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct ItineraryFilter {
+    /// The maximum number of legs, e.g. 2 for at most one transfer
+    pub max_legs: Option<u32>,
+
+    /// The maximum total price of the itinerary
+    pub max_price: Option<f32>,
+
+    /// The latest acceptable arrival at the destination
+    pub latest_arrival: Option<DateTime<Utc>>,
+
+    /// The maximum total distance of all legs in meters
+    pub max_distance_meters: Option<f32>,
 }
 
 /// Time window (min and max)
-#[derive(Debug, Copy, Clone, IntoParams, ToSchema, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(IntoParams, ToSchema))]
 pub struct TimeWindow {
     /// The start of the pad window
     pub timestamp_min: DateTime<Utc>,
@@ -36,15 +126,72 @@ pub struct TimeWindow {
     pub timestamp_max: DateTime<Utc>,
 }
 
+/// Query string parameters for the availability calendar
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(IntoParams, ToSchema))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct AvailabilityQuery {
+    /// The String ID of the vertiport to leave from
+    pub depart: String,
+
+    /// The String ID of the destination vertiport
+    pub arrive: String,
+
+    /// The start of the calendar range
+    pub from: DateTime<Utc>,
+
+    /// The end of the calendar range
+    pub to: DateTime<Utc>,
+
+    /// The width of each calendar bucket in minutes (default 60)
+    pub bucket_minutes: Option<u32>,
+
+    /// The estimated weight of cargo used for pricing (default 0)
+    pub cargo_weight_kg: Option<f32>,
+}
+
+/// Availability calendar between two vertiports
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct AvailabilityResponse {
+    /// Whether any itinerary connects the vertiports within the range
+    pub reachable: bool,
+
+    /// The time buckets in chronological order
+    pub buckets: Vec<AvailabilityBucket>,
+}
+
+/// Flight availability within a single time bucket
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct AvailabilityBucket {
+    /// The start of the bucket
+    pub timestamp_start: DateTime<Utc>,
+
+    /// The end of the bucket
+    pub timestamp_end: DateTime<Utc>,
+
+    /// The number of itineraries departing within the bucket
+    pub itineraries: u32,
+
+    /// The lowest price of the itineraries departing within the bucket
+    pub lowest_price: Option<f32>,
+
+    /// The currency type of the lowest price, e.g. USD, EUR
+    pub currency_type: Option<String>,
+}
+
 /// Request body information to cancel an itinerary
-#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
 pub struct ItineraryCancel {
     /// Itinerary UUID to Cancel
     pub id: String,
 }
 
 /// Request Body Information for Region Query
-#[derive(Debug, Copy, Clone, Deserialize, Serialize, ToSchema)]
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
 pub struct VertiportsQuery {
     /// Latitude of Client
     pub latitude: f32,
@@ -54,7 +201,8 @@ pub struct VertiportsQuery {
 }
 
 /// Itinerary
-#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
 pub struct Itinerary {
     /// The UUID of the itinerary
     pub id: String,
@@ -67,10 +215,26 @@ pub struct Itinerary {
 
     /// The cost of the trip for the customer
     pub base_pricing: Option<f32>,
+
+    /// Estimated departure timestamp of the first leg
+    pub timestamp_depart: DateTime<Utc>,
+
+    /// Estimated arrival timestamp of the last leg
+    pub timestamp_arrive: DateTime<Utc>,
+
+    /// Time from first departure to final arrival in seconds
+    pub total_duration_seconds: i64,
+
+    /// The estimated distance of all legs in meters
+    pub total_distance_meters: f32,
+
+    /// The number of legs in the itinerary
+    pub num_legs: u32,
 }
 
 /// Leg of a flight
-#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
 pub struct FlightLeg {
     /// Flight Plan ID
     pub flight_plan_id: String,
@@ -101,7 +265,8 @@ pub struct FlightLeg {
 }
 
 /// Customer Itinerary Confirm Option
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
 pub struct ItineraryConfirm {
     /// Itinerary UUID
     pub id: String,
@@ -112,26 +277,54 @@ pub struct ItineraryConfirm {
     /// Weight of Cargo
     /// TODO(R4): this is a little clunky to re-issue the weight here
     pub weight_grams: u32,
+
+    /// Who the parcel is for, notified as it progresses
+    pub recipient: Option<RecipientContact>,
+}
+
+/// How to reach the recipient of a parcel, with an email address, a phone number or both
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct RecipientContact {
+    /// Name of the recipient, used in greetings
+    pub name: Option<String>,
+
+    /// Email address of the recipient
+    #[cfg_attr(feature = "openapi", schema(example = "ada@example.com"))]
+    pub email: Option<String>,
+
+    /// Phone number of the recipient for text messages, in international format
+    #[cfg_attr(feature = "openapi", schema(example = "+31612345678"))]
+    pub phone: Option<String>,
 }
 
 /// UUIDs of the confirmed flight
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
 pub struct ItineraryConfirmation {
     /// UUID of the itinerary
     pub itinerary_id: String,
 
     /// UUID of the package
     pub parcel_id: String,
+
+    /// Token of the public tracking link of the parcel, None if it couldn't be issued
+    pub tracking_token: Option<String>,
+
+    /// One-time code the recipient gives to pick up the parcel, None if it couldn't be issued
+    /// Only returned here, svc-cargo keeps a digest of it
+    pub pickup_code: Option<String>,
 }
 
 /// Vertiport Information
-#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
 pub struct Vertiport {
     /// The unique ID of the vertiport
     pub id: String,
 
     /// The human-readable label of the vertiport
-    #[schema(example = "Mercy Hospital (Public)")]
+    #[cfg_attr(feature = "openapi", schema(example = "Mercy Hospital (Public)"))]
     pub label: String,
 
     /// The latitude (float value) of the vertiport (centroid)
@@ -151,22 +344,23 @@ pub struct Vertiport {
 // }
 
 /// Confirm itinerary Operation Status
-#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
 pub enum ConfirmStatus {
     /// Successful confirmation of itinerary
-    #[schema(example = "Itinerary successfully confirmed.")]
+    #[cfg_attr(feature = "openapi", schema(example = "Itinerary successfully confirmed."))]
     Success(String),
 
     /// Itinerary already confirmed.
-    #[schema(example = "Could not confirm itinerary.")]
+    #[cfg_attr(feature = "openapi", schema(example = "Could not confirm itinerary."))]
     Conflict(String),
 
     /// Itinerary not found by id.
-    #[schema(example = "Provided itinerary ID doesn't match an existing itinerary.")]
+    #[cfg_attr(feature = "openapi", schema(example = "Provided itinerary ID doesn't match an existing itinerary."))]
     NotFound(String),
 
     /// Unauthorized Attempt to Confirm Itinerary
-    #[schema(example = "Unauthorized confirmation by someone other than the customer.")]
+    #[cfg_attr(feature = "openapi", schema(example = "Unauthorized confirmation by someone other than the customer."))]
     Unauthorized(String),
 
     /// Unavailable Service
@@ -174,7 +368,8 @@ pub enum ConfirmStatus {
 }
 
 /// Vertiport Information
-#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
 pub struct ParcelScan {
     /// The unique ID (UUID) of the scanner device
     pub scanner_id: String,
@@ -189,61 +384,593 @@ pub struct ParcelScan {
     pub longitude: f64,
 }
 
+/// Scans uploaded together by a scanner device, e.g. after being offline
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct ParcelScanBatch {
+    /// The unique ID (UUID) of the scanner device
+    pub scanner_id: String,
+
+    /// The scans, in the order they were captured
+    pub scans: Vec<BatchScan>,
+}
+
+/// A scan captured by a scanner device
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct BatchScan {
+    /// The unique ID (UUID) of the parcel
+    /// Either the parcel ID or the barcode is required
+    pub parcel_id: Option<String>,
+
+    /// The barcode printed on the parcel label
+    pub barcode: Option<String>,
+
+    /// The latitude (float value) of the scan location
+    pub latitude: f64,
+
+    /// The longitude (float value) of the scan location
+    pub longitude: f64,
+
+    /// When the scan was captured, by the clock of the device
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Outcome of a scan of a batch
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum BatchScanStatus {
+    /// The scan was recorded
+    Recorded,
+
+    /// The scan was recorded before, e.g. by an earlier upload of the batch
+    Duplicate,
+
+    /// The scan is invalid and won't be recorded, it must not be sent again
+    Rejected,
+
+    /// The scan could not be recorded now, it may be sent again
+    Failed,
+}
+
+/// Result of a scan of a batch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct BatchScanResult {
+    /// The position of the scan in the batch
+    pub index: u32,
+
+    /// The unique ID (UUID) of the parcel, when it is known
+    pub parcel_id: Option<String>,
+
+    /// The outcome of the scan
+    pub status: BatchScanStatus,
+
+    /// Why the scan was rejected or failed
+    pub message: Option<String>,
+}
+
+/// Results of a batch of scans, one per scan in the order of the batch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct ParcelScanBatchResponse {
+    /// The number of recorded scans
+    pub recorded: u32,
+
+    /// The number of scans recorded before
+    pub duplicates: u32,
+
+    /// The number of invalid scans
+    pub rejected: u32,
+
+    /// The number of scans that may be sent again
+    pub failed: u32,
+
+    /// The result of each scan
+    pub results: Vec<BatchScanResult>,
+}
+
+/// Request body to register a scanner device
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct ScannerRegistration {
+    /// A name for people, e.g. where the device is used
+    pub label: String,
+
+    /// The String ID of the vertiport the scanner is used at
+    /// Scans of assigned scanners must be at their vertiport
+    pub vertiport_id: Option<String>,
+}
+
+/// A registered scanner device
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct Scanner {
+    /// The unique ID (UUID) of the scanner device
+    pub id: String,
+
+    /// A name for people, e.g. where the device is used
+    pub label: String,
+
+    /// The String ID of the vertiport the scanner is assigned to
+    pub vertiport_id: Option<String>,
+
+    /// Deactivated scanners can't record scans
+    pub active: bool,
+
+    /// When the scanner was registered
+    pub registered_at: DateTime<Utc>,
+
+    /// When the API key of the scanner was issued
+    pub key_issued_at: DateTime<Utc>,
+}
+
+/// A scanner with its API key, which is only returned once
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct ScannerCredentials {
+    /// The scanner device
+    pub scanner: Scanner,
+
+    /// The key the scanner sends in the `X-Api-Key` header with its scans
+    pub api_key: String,
+}
+
+/// Request body to assign a scanner to a vertiport
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct ScannerAssignment {
+    /// The String ID of the vertiport, None to unassign the scanner
+    pub vertiport_id: Option<String>,
+}
+
+/// Query string parameters for the list of scanners
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(IntoParams, ToSchema))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct ScannersParams {
+    /// Only scanners assigned to this vertiport
+    pub vertiport_id: Option<String>,
+
+    /// Only active or only deactivated scanners
+    pub active: Option<bool>,
+}
+
+/// Registered scanners
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct ScannersResponse {
+    /// list of scanners, in the order they were registered
+    pub scanners: Vec<Scanner>,
+}
+
+/// How a scan deviates from the itinerary of its parcel
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ScanAnomalyKind {
+    /// Scanned before departure, away from the origin vertiport
+    NotAtOrigin,
+
+    /// Scanned during the flight, away from the flight path
+    OffCorridor,
+
+    /// Scanned after arrival, away from the destination vertiport
+    NotAtDestination,
+}
+
+/// A scan recorded away from where the parcel was expected
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct ScanAnomaly {
+    /// The unique ID (UUID) of the anomaly
+    pub id: String,
+
+    /// The unique ID (UUID) of the parcel
+    pub parcel_id: String,
+
+    /// The unique ID (UUID) of the scanner device
+    pub scanner_id: String,
+
+    /// The latitude of the scan location
+    pub latitude: f64,
+
+    /// The longitude of the scan location
+    pub longitude: f64,
+
+    /// When the scan was captured
+    pub scanned_at: DateTime<Utc>,
+
+    /// When the anomaly was detected
+    pub detected_at: DateTime<Utc>,
+
+    /// How the scan deviates from the itinerary
+    pub kind: ScanAnomalyKind,
+
+    /// The String ID of the vertiport the parcel was expected at, if any
+    pub vertiport_id: Option<String>,
+
+    /// Distance in meters between the scan and where the parcel was expected
+    pub distance_meters: f64,
+}
+
+/// Query string parameters for scan anomalies
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(IntoParams, ToSchema))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct ScanAnomaliesParams {
+    /// Only anomalies of this parcel
+    pub parcel_id: Option<String>,
+
+    /// Only anomalies detected at or after this time
+    pub since: Option<DateTime<Utc>>,
+
+    /// The maximum number of anomalies to return (default and max: [`MAX_SCAN_ANOMALIES_TO_RETURN`])
+    pub limit: Option<u32>,
+}
+
+/// Scan anomalies, the most recently detected first
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct ScanAnomaliesResponse {
+    /// list of anomalies
+    pub anomalies: Vec<ScanAnomaly>,
+}
+
+/// File format of a parcel label
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum LabelFormat {
+    /// PNG image at 203 dpi
+    Png,
+
+    /// SVG image
+    Svg,
+
+    /// PDF document
+    #[default]
+    Pdf,
+
+    /// ZPL for thermal label printers at 203 dpi
+    Zpl,
+}
+
+/// Query string parameters for a parcel label
+#[derive(Debug, Copy, Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(IntoParams, ToSchema))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct LabelParams {
+    /// File format of the label, PDF if not given
+    pub format: Option<LabelFormat>,
+}
+
+/// Proof that a parcel was handed to its recipient
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct DeliveryProof {
+    /// The unique ID (UUID) of the scanner of the courier
+    pub scanner_id: String,
+
+    /// Name of the person the parcel was handed to
+    pub recipient_name: String,
+
+    /// Signature of the recipient, a base64 encoded PNG or JPEG image; only for parcels without a pickup code
+    pub signature: Option<String>,
+
+    /// Pickup code of the parcel given by the recipient, instead of a signature
+    pub pin: Option<String>,
+
+    /// Photo of the handover, a base64 encoded PNG or JPEG image
+    pub photo: Option<String>,
+
+    /// Latitude of the handover
+    pub latitude: f64,
+
+    /// Longitude of the handover
+    pub longitude: f64,
+}
+
+/// How the recipient acknowledged a delivery
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryConfirmation {
+    /// The recipient signed
+    Signature,
+
+    /// The recipient gave a PIN code
+    Pin,
+}
+
+/// A parcel handed to its recipient
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct Delivery {
+    /// The String ID of the parcel
+    pub parcel_id: String,
+
+    /// Name of the person the parcel was handed to
+    pub recipient_name: String,
+
+    /// How the recipient acknowledged the delivery
+    pub confirmation: DeliveryConfirmation,
+
+    /// Whether a photo of the handover was taken
+    pub has_photo: bool,
+
+    /// The String ID of the destination vertiport
+    pub vertiport_id: String,
+
+    /// Latitude of the handover
+    pub latitude: f64,
+
+    /// Longitude of the handover
+    pub longitude: f64,
+
+    /// When the parcel was handed over
+    pub delivered_at: DateTime<Utc>,
+}
+
+/// Pickup code given by the recipient at the destination vertiport
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct PickupVerification {
+    /// The unique ID (UUID) of the scanner of the vertiport staff
+    pub scanner_id: String,
+
+    /// Pickup code issued when the itinerary was confirmed
+    pub code: String,
+
+    /// Latitude of the pickup
+    pub latitude: f64,
+
+    /// Longitude of the pickup
+    pub longitude: f64,
+}
+
+/// A parcel picked up by its recipient with its pickup code, and now delivered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct Pickup {
+    /// The String ID of the parcel
+    pub parcel_id: String,
+
+    /// The String ID of the scanner that checked the code
+    pub scanner_id: String,
+
+    /// The String ID of the destination vertiport
+    pub vertiport_id: String,
+
+    /// When the parcel was picked up
+    pub picked_up_at: DateTime<Utc>,
+}
+
 /// Request Body Information for Landings at a Given Vertiport
-#[derive(Debug, Clone, IntoParams, ToSchema, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(IntoParams, ToSchema))]
 pub struct LandingsQuery {
     /// The String ID of the vertiport
     pub vertiport_id: String,
 
-    /// The window to search for landings
+    /// The window to search for arrivals
     pub arrival_window: Option<TimeWindow>,
 
+    /// The window to search for departures
+    /// At least one of the arrival and departure windows is required
+    pub departure_window: Option<TimeWindow>,
+
     /// The maximum number of landings to return (max: [`MAX_LANDINGS_TO_RETURN`]])
     pub limit: u32,
+
+    /// Continue after the last landing of a previous response
+    pub cursor: Option<String>,
+}
+
+/// Query string parameters for landings at a given vertiport
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(IntoParams, ToSchema))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct LandingsParams {
+    /// The String ID of the vertiport
+    pub vertiport_id: String,
+
+    /// The start of the window to search for arrivals
+    pub arrival_min: Option<DateTime<Utc>>,
+
+    /// The end of the window to search for arrivals
+    pub arrival_max: Option<DateTime<Utc>>,
+
+    /// The start of the window to search for departures
+    pub departure_min: Option<DateTime<Utc>>,
+
+    /// The end of the window to search for departures
+    /// At least one of the arrival and departure windows is required
+    pub departure_max: Option<DateTime<Utc>>,
+
+    /// The maximum number of landings to return (default and max: [`MAX_LANDINGS_TO_RETURN`]])
+    pub limit: Option<u32>,
+
+    /// The `next_cursor` of a previous response, to request the following page
+    pub cursor: Option<String>,
 }
 
 /// Landings Response
-#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
 pub struct LandingsResponse {
     /// list of landing information
     pub landings: Vec<Landing>,
+
+    /// Present if more landings follow; pass as `cursor` to get them
+    pub next_cursor: Option<String>,
+}
+
+/// Whether an aircraft is arriving at or departing from the vertiport
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum LandingKind {
+    /// The aircraft lands at the vertiport
+    Arrival,
+
+    /// The aircraft takes off from the vertiport
+    Departure,
+}
+
+/// Progress of the flight
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum LandingStatus {
+    /// The aircraft has not yet departed
+    Scheduled,
+
+    /// The aircraft has departed and not yet arrived
+    InFlight,
+
+    /// The aircraft has arrived at its destination
+    Landed,
 }
 
 /// Landing
-#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
 pub struct Landing {
     /// The String ID of the flight plan
     pub flight_plan_id: String,
 
+    /// Whether this is an arrival or a departure
+    pub kind: LandingKind,
+
+    /// Progress of the flight
+    pub status: LandingStatus,
+
     /// Vertipad Name
     pub vertipad_name: String,
 
+    /// The String ID of the vertiport the flight departs from
+    pub vertiport_depart_id: Option<String>,
+
+    /// The String ID of the vertiport the flight arrives at
+    pub vertiport_arrive_id: Option<String>,
+
     /// The callsign of the aircraft
     pub aircraft_callsign: String,
 
-    /// The time of arrival
+    /// The nickname of the aircraft
+    pub aircraft_nickname: Option<String>,
+
+    /// The time of arrival or departure
     pub timestamp: DateTime<Utc>,
-    // TODO(R4) Aircraft Nickname
-    // pub aircraft_nickname: String,
 
-    // TODO(R4) Estimated Dwell Time
-    // pub estimated_dwell_seconds: u32,
+    /// Estimated time the aircraft occupies the vertipad
+    pub estimated_dwell_seconds: u32,
 
-    // TODO(R4) Parcels to deliver and acquire
-    // pub parcels_deliver: Vec<String>,
-    // pub parcels_acquire: Vec<String>,
+    /// Parcels to unload at the end of this flight
+    pub parcels_deliver: Vec<String>,
+
+    /// Parcels to load at the start of this flight
+    pub parcels_acquire: Vec<String>,
 }
 
 /// Request Body Information for Tracking a Parcel Query
-#[derive(Debug, Clone, IntoParams, ToSchema, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(IntoParams, ToSchema))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
 pub struct TrackingQuery {
-    /// The String ID of the vertiport
+    /// The String ID of the parcel
     pub parcel_id: String,
 }
 
 /// Tracking Information Response
-#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
 pub struct TrackingResponse {
     /// list of scans
     pub scans: Vec<ParcelScan>,
+
+    /// The delivery of the parcel, once handed to its recipient
+    pub delivery: Option<Delivery>,
+}
+
+/// Progress of a parcel, as shown to its recipient
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum PublicParcelStatus {
+    /// Waiting for its first flight
+    Confirmed,
+
+    /// On its way to the destination vertiport
+    InTransit,
+
+    /// At the destination vertiport, ready for pickup
+    Arrived,
+
+    /// Handed to the recipient
+    Delivered,
+}
+
+/// What anyone with the tracking link of a parcel may see of it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct PublicTracking {
+    /// Progress of the parcel
+    pub status: PublicParcelStatus,
+
+    /// Name of the vertiport the parcel departs from, None until it is on a flight
+    pub origin: Option<String>,
+
+    /// Name of the vertiport the parcel is picked up at, None until it is on a flight
+    pub destination: Option<String>,
+
+    /// When the parcel arrives, or arrived, at the destination vertiport
+    pub estimated_arrival: Option<DateTime<Utc>>,
+
+    /// When the parcel was handed to the recipient
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+/// Body of error responses
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct ErrorResponse {
+    /// Description of the error
+    pub message: String,
+
+    /// Id of the failed request, also returned in the `X-Request-Id` header
+    pub request_id: Option<String>,
+}
+
+/// Health of one dependency of this service
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct DependencyHealth {
+    /// Name of the dependency, e.g. `storage.parcel`
+    pub name: String,
+
+    /// If the dependency answered that it is ready
+    pub healthy: bool,
+
+    /// Time taken by the last probe in milliseconds
+    pub latency_ms: u64,
+
+    /// Why the dependency is unhealthy
+    pub error: Option<String>,
+}
+
+/// Readiness of this service and of its dependencies
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct HealthResponse {
+    /// If all dependencies are healthy
+    pub ready: bool,
+
+    /// When the dependencies were last probed, none before the first probes finish
+    pub checked_at: Option<DateTime<Utc>>,
+
+    /// Health of each dependency
+    pub dependencies: Vec<DependencyHealth>,
 }
@@ -2,13 +2,20 @@
 //!
 //! Define and implement config options for module
 
+use crate::rest::cors::parse_header_names;
+use crate::rest::limit::{parse_route_limits, parse_trusted_proxies};
+use crate::telemetry::TracingExporter;
+use crate::tls::backend_tls;
 use anyhow::Result;
-use config::{ConfigError, Environment};
+use config::{ConfigError, Environment, File};
 use dotenv::dotenv;
 use serde::Deserialize;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::Path;
 
 /// struct holding configuration options
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
 pub struct Config {
     /// port to be used for gRPC server
     pub docker_port_grpc: u16,
@@ -28,13 +35,103 @@ pub struct Config {
     pub scheduler_host_grpc: String,
     /// path to log configuration YAML file
     pub log_config: String,
-    /// Rate limit - requests per second for REST requests
+    /// Rate limit - requests per second for REST requests, per client and route
     pub rest_request_limit_per_second: u8,
+    /// Rate limits of specific routes, e.g. `/cargo/request=2,/cargo/track=20`
+    pub rest_route_limits_per_second: String,
+    /// Requests per day allowed for each scanner API key, unlimited if 0
+    pub rest_daily_quota_per_api_key: u32,
+    /// Comma separated proxy addresses or networks whose `X-Forwarded-For` is trusted
+    pub rest_trusted_proxies: String,
     /// Enforces a limit on the concurrent number of requests the underlying service can handle
     pub rest_concurrency_limit_per_service: u8,
-    /// Full url (including port number) to be allowed as request origin for
-    /// REST requests
+    /// Comma separated origins (including port number) allowed to make REST
+    /// requests, `https://*.example.com` allows any subdomain
     pub rest_cors_allowed_origin: String,
+    /// Request headers browsers may send in cross-origin REST requests, needs a restart
+    pub rest_cors_allowed_headers: String,
+    /// Whether browsers may send cookies and credentials in cross-origin REST requests, needs a restart
+    pub rest_cors_allow_credentials: bool,
+    /// Seconds browsers may cache the result of a CORS preflight request, needs a restart
+    pub rest_cors_max_age_seconds: u32,
+    /// Where to send trace spans: none, otlp, stdout or file
+    pub tracing_exporter: String,
+    /// OTLP collector endpoint used by the otlp tracing exporter
+    pub tracing_otlp_endpoint: String,
+    /// File used by the file tracing exporter
+    pub tracing_file: String,
+    /// Seconds between two rounds of dependency health probes
+    pub health_probe_interval_seconds: u16,
+    /// Time after which a dependency health probe is considered failed
+    pub health_probe_timeout_ms: u16,
+    /// Deadline of calls to svc-storage
+    pub storage_timeout_ms: u32,
+    /// Deadline of calls to svc-scheduler
+    pub scheduler_timeout_ms: u32,
+    /// Deadline of calls to svc-pricing
+    pub pricing_timeout_ms: u32,
+    /// Number of times a failed idempotent backend call is retried
+    pub grpc_max_retries: u8,
+    /// Delay before the first retry, doubled for each further retry
+    pub grpc_retry_base_delay_ms: u16,
+    /// Consecutive failures after which calls to a backend are rejected
+    pub circuit_breaker_failure_threshold: u8,
+    /// Time during which calls to a failing backend are rejected
+    pub circuit_breaker_open_seconds: u16,
+    /// PEM certificate chain of the REST and gRPC servers, TLS is disabled if empty
+    pub tls_cert_file: String,
+    /// PEM private key of the REST and gRPC servers
+    pub tls_key_file: String,
+    /// PEM CA certificates gRPC clients must present a certificate of, if not empty
+    pub grpc_tls_client_ca_file: String,
+    /// PEM CA certificates scanner devices must present a certificate of, if not empty
+    pub rest_tls_client_ca_file: String,
+    /// PEM CA certificates the backend services are verified with, connections to them are plaintext if empty
+    pub backend_tls_ca_file: String,
+    /// PEM certificate chain presented to the backend services, none if empty
+    pub backend_tls_cert_file: String,
+    /// PEM private key of the certificate presented to the backend services
+    pub backend_tls_key_file: String,
+    /// How far device timestamps of uploaded scans may be ahead of the server clock
+    pub rest_scan_max_clock_skew_seconds: u32,
+    /// How old uploaded scans may be, older scans are rejected
+    pub rest_scan_max_age_hours: u32,
+    /// Secret scanner devices sign scan batches with, signatures aren't checked if empty
+    pub rest_scanner_signing_secret: String,
+    /// How far outside a vertiport a scan before departure or after arrival may be
+    pub rest_scan_vertiport_margin_meters: u32,
+    /// How far from the flight path a scan during the flight may be
+    pub rest_scan_corridor_meters: u32,
+    /// File scan anomalies are appended to, one JSON object per line; kept in memory if empty
+    pub rest_scan_anomaly_file: String,
+    /// Days scan anomalies are kept in the anomaly file
+    pub rest_scan_anomaly_retention_days: u32,
+    /// URL scan anomalies are posted to as they are detected, not posted if empty
+    pub rest_scan_anomaly_webhook_url: String,
+    /// File registered scanners are kept in; kept in memory if empty
+    pub rest_scanner_registry_file: String,
+    /// API key required by the scanner management and anomaly endpoints, disabled if empty
+    pub rest_scanner_admin_key: String,
+    /// File confirmed shipments are kept in, for their labels and pickup codes; kept in memory if empty
+    pub rest_shipment_file: String,
+    /// Days shipments are kept after they are confirmed, for their labels, pickup codes and notifications
+    pub rest_shipment_retention_days: u32,
+    /// Directory proofs of delivery are kept in; kept in memory if empty, so lost on restart
+    pub rest_delivery_dir: String,
+    /// Start of the public tracking links sent to recipients, followed by the tracking token
+    pub rest_public_tracking_url: String,
+    /// URL email notifications to recipients are posted to; sent to the notification file if empty
+    pub rest_notification_email_url: String,
+    /// URL SMS notifications to recipients are posted to; sent to the notification file if empty
+    pub rest_notification_sms_url: String,
+    /// File notifications without a provider are appended to, one JSON object per line; logged if empty
+    pub rest_notification_file: String,
+    /// Wrong pickup codes allowed before pickups of the parcel are locked
+    pub rest_pickup_max_attempts: u32,
+    /// How long pickups of a parcel are locked after too many wrong codes
+    pub rest_pickup_lockout_seconds: u32,
+    /// Secret digests of pickup codes are keyed with; random on each start if empty
+    pub rest_pickup_code_secret: String,
 }
 
 impl Default for Config {
@@ -57,19 +154,72 @@ impl Config {
             scheduler_port_grpc: 50051,
             scheduler_host_grpc: String::from("svc-scheduler"),
             log_config: String::from("log4rs.yaml"),
-            rest_request_limit_per_second: 2,
+            rest_request_limit_per_second: 10,
+            rest_route_limits_per_second: String::from("/cargo/request=2,/cargo/track=20"),
+            rest_daily_quota_per_api_key: 0,
+            rest_trusted_proxies: String::from(""),
             rest_concurrency_limit_per_service: 5,
             rest_cors_allowed_origin: String::from("http://localhost:3000"),
+            rest_cors_allowed_headers: String::from(
+                "content-type,authorization,x-api-key,x-request-id",
+            ),
+            rest_cors_allow_credentials: false,
+            rest_cors_max_age_seconds: 600,
+            tracing_exporter: String::from("none"),
+            tracing_otlp_endpoint: String::from("http://localhost:4317"),
+            tracing_file: String::from("traces.jsonl"),
+            health_probe_interval_seconds: 10,
+            health_probe_timeout_ms: 2000,
+            storage_timeout_ms: 5000,
+            scheduler_timeout_ms: 10000,
+            pricing_timeout_ms: 5000,
+            grpc_max_retries: 2,
+            grpc_retry_base_delay_ms: 100,
+            circuit_breaker_failure_threshold: 5,
+            circuit_breaker_open_seconds: 30,
+            tls_cert_file: String::from(""),
+            tls_key_file: String::from(""),
+            grpc_tls_client_ca_file: String::from(""),
+            rest_tls_client_ca_file: String::from(""),
+            backend_tls_ca_file: String::from(""),
+            backend_tls_cert_file: String::from(""),
+            backend_tls_key_file: String::from(""),
+            rest_scan_max_clock_skew_seconds: 300,
+            rest_scan_max_age_hours: 72,
+            rest_scanner_signing_secret: String::from(""),
+            rest_scan_vertiport_margin_meters: 100,
+            rest_scan_corridor_meters: 2000,
+            rest_scan_anomaly_file: String::from(""),
+            rest_scan_anomaly_retention_days: 30,
+            rest_scan_anomaly_webhook_url: String::from(""),
+            rest_scanner_registry_file: String::from("scanners.json"),
+            rest_scanner_admin_key: String::from(""),
+            rest_shipment_file: String::from("shipments.json"),
+            rest_shipment_retention_days: 90,
+            rest_delivery_dir: String::from("deliveries"),
+            rest_public_tracking_url: String::from("/cargo/public/track/"),
+            rest_notification_email_url: String::from(""),
+            rest_notification_sms_url: String::from(""),
+            rest_notification_file: String::from(""),
+            rest_pickup_max_attempts: 5,
+            rest_pickup_lockout_seconds: 900,
+            rest_pickup_code_secret: String::from(""),
         }
     }
 
     /// Create a new `Config` object using environment variables
     pub fn try_from_env() -> Result<Self, ConfigError> {
+        Self::load(None)
+    }
+
+    /// Create a new `Config` object from an optional TOML or YAML file,
+    ///  overridden by environment variables
+    pub fn load(config_file: Option<&str>) -> Result<Self, ConfigError> {
         // read .env file if present
         dotenv().ok();
         let default_config = Config::default();
 
-        config::Config::builder()
+        let mut builder = config::Config::builder()
             .set_default("docker_port_grpc", default_config.docker_port_grpc)?
             .set_default("docker_port_rest", default_config.docker_port_rest)?
             .set_default("storage_port_grpc", default_config.storage_port_grpc)?
@@ -84,22 +234,372 @@ impl Config {
                 default_config.rest_concurrency_limit_per_service,
             )?
             .set_default(
-                "rest_request_limit_per_seconds",
+                "rest_request_limit_per_second",
                 default_config.rest_request_limit_per_second,
             )?
+            .set_default(
+                "rest_route_limits_per_second",
+                default_config.rest_route_limits_per_second,
+            )?
+            .set_default(
+                "rest_daily_quota_per_api_key",
+                default_config.rest_daily_quota_per_api_key,
+            )?
+            .set_default("rest_trusted_proxies", default_config.rest_trusted_proxies)?
             .set_default(
                 "rest_cors_allowed_origin",
                 default_config.rest_cors_allowed_origin,
             )?
+            .set_default(
+                "rest_cors_allowed_headers",
+                default_config.rest_cors_allowed_headers,
+            )?
+            .set_default(
+                "rest_cors_allow_credentials",
+                default_config.rest_cors_allow_credentials,
+            )?
+            .set_default(
+                "rest_cors_max_age_seconds",
+                default_config.rest_cors_max_age_seconds,
+            )?
+            .set_default("tracing_exporter", default_config.tracing_exporter)?
+            .set_default(
+                "tracing_otlp_endpoint",
+                default_config.tracing_otlp_endpoint,
+            )?
+            .set_default("tracing_file", default_config.tracing_file)?
+            .set_default(
+                "health_probe_interval_seconds",
+                default_config.health_probe_interval_seconds,
+            )?
+            .set_default(
+                "health_probe_timeout_ms",
+                default_config.health_probe_timeout_ms,
+            )?
+            .set_default("storage_timeout_ms", default_config.storage_timeout_ms)?
+            .set_default("scheduler_timeout_ms", default_config.scheduler_timeout_ms)?
+            .set_default("pricing_timeout_ms", default_config.pricing_timeout_ms)?
+            .set_default("grpc_max_retries", default_config.grpc_max_retries)?
+            .set_default(
+                "grpc_retry_base_delay_ms",
+                default_config.grpc_retry_base_delay_ms,
+            )?
+            .set_default(
+                "circuit_breaker_failure_threshold",
+                default_config.circuit_breaker_failure_threshold,
+            )?
+            .set_default(
+                "circuit_breaker_open_seconds",
+                default_config.circuit_breaker_open_seconds,
+            )?
+            .set_default("tls_cert_file", default_config.tls_cert_file)?
+            .set_default("tls_key_file", default_config.tls_key_file)?
+            .set_default(
+                "grpc_tls_client_ca_file",
+                default_config.grpc_tls_client_ca_file,
+            )?
+            .set_default(
+                "rest_tls_client_ca_file",
+                default_config.rest_tls_client_ca_file,
+            )?
+            .set_default("backend_tls_ca_file", default_config.backend_tls_ca_file)?
+            .set_default(
+                "backend_tls_cert_file",
+                default_config.backend_tls_cert_file,
+            )?
+            .set_default("backend_tls_key_file", default_config.backend_tls_key_file)?
+            .set_default(
+                "rest_scan_max_clock_skew_seconds",
+                default_config.rest_scan_max_clock_skew_seconds,
+            )?
+            .set_default(
+                "rest_scan_max_age_hours",
+                default_config.rest_scan_max_age_hours,
+            )?
+            .set_default(
+                "rest_scanner_signing_secret",
+                default_config.rest_scanner_signing_secret,
+            )?
+            .set_default(
+                "rest_scan_vertiport_margin_meters",
+                default_config.rest_scan_vertiport_margin_meters,
+            )?
+            .set_default(
+                "rest_scan_corridor_meters",
+                default_config.rest_scan_corridor_meters,
+            )?
+            .set_default(
+                "rest_scan_anomaly_file",
+                default_config.rest_scan_anomaly_file,
+            )?
+            .set_default(
+                "rest_scan_anomaly_retention_days",
+                default_config.rest_scan_anomaly_retention_days,
+            )?
+            .set_default(
+                "rest_scan_anomaly_webhook_url",
+                default_config.rest_scan_anomaly_webhook_url,
+            )?
+            .set_default(
+                "rest_scanner_registry_file",
+                default_config.rest_scanner_registry_file,
+            )?
+            .set_default(
+                "rest_scanner_admin_key",
+                default_config.rest_scanner_admin_key,
+            )?
+            .set_default("rest_shipment_file", default_config.rest_shipment_file)?
+            .set_default(
+                "rest_shipment_retention_days",
+                default_config.rest_shipment_retention_days,
+            )?
+            .set_default("rest_delivery_dir", default_config.rest_delivery_dir)?
+            .set_default(
+                "rest_public_tracking_url",
+                default_config.rest_public_tracking_url,
+            )?
+            .set_default(
+                "rest_notification_email_url",
+                default_config.rest_notification_email_url,
+            )?
+            .set_default(
+                "rest_notification_sms_url",
+                default_config.rest_notification_sms_url,
+            )?
+            .set_default(
+                "rest_notification_file",
+                default_config.rest_notification_file,
+            )?
+            .set_default(
+                "rest_pickup_max_attempts",
+                default_config.rest_pickup_max_attempts,
+            )?
+            .set_default(
+                "rest_pickup_lockout_seconds",
+                default_config.rest_pickup_lockout_seconds,
+            )?
+            .set_default(
+                "rest_pickup_code_secret",
+                default_config.rest_pickup_code_secret,
+            )?;
+
+        if let Some(config_file) = config_file {
+            // The format is taken from the file extension
+            builder = builder.add_source(File::with_name(config_file));
+        }
+
+        builder
             .add_source(Environment::default().separator("__"))
             .build()?
             .try_deserialize()
     }
+
+    /// Checks the configuration, reporting all problems at once
+    pub fn validate(&self) -> Result<(), InvalidConfig> {
+        let mut problems = vec![];
+
+        let ports = [
+            ("docker_port_grpc", self.docker_port_grpc),
+            ("docker_port_rest", self.docker_port_rest),
+            ("storage_port_grpc", self.storage_port_grpc),
+            ("pricing_port_grpc", self.pricing_port_grpc),
+            ("scheduler_port_grpc", self.scheduler_port_grpc),
+        ];
+        for (name, port) in ports {
+            if port == 0 {
+                problems.push(format!("{name} must not be 0."));
+            }
+        }
+
+        let hosts = [
+            ("storage_host_grpc", &self.storage_host_grpc),
+            ("pricing_host_grpc", &self.pricing_host_grpc),
+            ("scheduler_host_grpc", &self.scheduler_host_grpc),
+        ];
+        for (name, host) in hosts {
+            if !is_valid_host(host) {
+                problems.push(format!(
+                    "{name} '{host}' is not a valid host name or IP address."
+                ));
+            }
+        }
+
+        for origin in self.rest_cors_allowed_origin.split(',').map(str::trim) {
+            // A wildcard may only stand for the leading subdomains
+            if !is_valid_origin(&origin.replacen("://*.", "://subdomain.", 1)) {
+                problems.push(format!(
+                    "rest_cors_allowed_origin '{origin}' is not an origin like https://host:port."
+                ));
+            }
+        }
+
+        if let Err(e) = parse_header_names(&self.rest_cors_allowed_headers) {
+            problems.push(format!("rest_cors_allowed_headers: {e}"));
+        }
+
+        if let Err(e) = parse_route_limits(&self.rest_route_limits_per_second) {
+            problems.push(format!("rest_route_limits_per_second: {e}"));
+        }
+
+        if let Err(e) = parse_trusted_proxies(&self.rest_trusted_proxies) {
+            problems.push(format!("rest_trusted_proxies: {e}"));
+        }
+
+        if self.log_config.is_empty() {
+            problems.push("log_config must not be empty.".to_string());
+        }
+
+        if let Err(e) = TracingExporter::from_config(self) {
+            problems.push(format!("tracing_exporter: {e}"));
+        }
+
+        if self.tls_cert_file.is_empty() != self.tls_key_file.is_empty() {
+            problems.push("tls_cert_file and tls_key_file must be set together.".to_string());
+        }
+
+        let tls_files = [
+            ("tls_cert_file", &self.tls_cert_file),
+            ("tls_key_file", &self.tls_key_file),
+            ("grpc_tls_client_ca_file", &self.grpc_tls_client_ca_file),
+            ("rest_tls_client_ca_file", &self.rest_tls_client_ca_file),
+        ];
+        for (name, file) in tls_files {
+            if file.is_empty() {
+                continue;
+            }
+
+            if self.tls_cert_file.is_empty() {
+                problems.push(format!("{name} needs TLS, set tls_cert_file."));
+            } else if !Path::new(file).is_file() {
+                problems.push(format!("{name} '{file}' does not exist."));
+            }
+        }
+
+        if let Err(e) = backend_tls(self) {
+            problems.push(e);
+        }
+
+        let urls = [
+            (
+                "rest_scan_anomaly_webhook_url",
+                &self.rest_scan_anomaly_webhook_url,
+            ),
+            (
+                "rest_notification_email_url",
+                &self.rest_notification_email_url,
+            ),
+            ("rest_notification_sms_url", &self.rest_notification_sms_url),
+        ];
+        for (name, url) in urls {
+            if !url.is_empty() && !url.starts_with("http://") && !url.starts_with("https://") {
+                problems.push(format!("{name} '{url}' is not an http or https URL."));
+            }
+        }
+
+        let non_zero = [
+            (
+                "rest_request_limit_per_second",
+                self.rest_request_limit_per_second.into(),
+            ),
+            (
+                "rest_concurrency_limit_per_service",
+                self.rest_concurrency_limit_per_service.into(),
+            ),
+            (
+                "health_probe_interval_seconds",
+                self.health_probe_interval_seconds.into(),
+            ),
+            (
+                "health_probe_timeout_ms",
+                self.health_probe_timeout_ms.into(),
+            ),
+            ("storage_timeout_ms", self.storage_timeout_ms),
+            ("scheduler_timeout_ms", self.scheduler_timeout_ms),
+            ("pricing_timeout_ms", self.pricing_timeout_ms),
+            (
+                "circuit_breaker_failure_threshold",
+                self.circuit_breaker_failure_threshold.into(),
+            ),
+            ("rest_scan_max_age_hours", self.rest_scan_max_age_hours),
+            ("rest_scan_corridor_meters", self.rest_scan_corridor_meters),
+            (
+                "rest_scan_anomaly_retention_days",
+                self.rest_scan_anomaly_retention_days,
+            ),
+            (
+                "rest_shipment_retention_days",
+                self.rest_shipment_retention_days,
+            ),
+            ("rest_pickup_max_attempts", self.rest_pickup_max_attempts),
+        ];
+        for (name, value) in non_zero {
+            if value == 0 {
+                problems.push(format!("{name} must be greater than 0."));
+            }
+        }
+
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(InvalidConfig { problems }),
+        }
+    }
+}
+
+/// Problems found by [`Config::validate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidConfig {
+    /// One message per problem
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for InvalidConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration:")?;
+        for problem in &self.problems {
+            write!(f, "\n  - {problem}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for InvalidConfig {}
+
+/// Host names are made of dot separated labels of letters, digits, `-` and `_`
+fn is_valid_host(host: &str) -> bool {
+    if host.parse::<IpAddr>().is_ok() {
+        return true;
+    }
+
+    !host.is_empty()
+        && host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+}
+
+/// An origin is a scheme, host and optional port, without a path
+fn is_valid_origin(origin: &str) -> bool {
+    let Ok(uri) = origin.parse::<hyper::Uri>() else {
+        return false;
+    };
+
+    matches!(uri.scheme_str(), Some("http") | Some("https"))
+        && uri.host().is_some_and(is_valid_host)
+        && uri.path() == "/"
+        && !origin.ends_with('/')
+        && uri.query().is_none()
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Config;
+    use super::{Config, InvalidConfig};
 
     #[tokio::test]
     async fn test_config_from_default() {
@@ -118,11 +618,71 @@ mod tests {
         assert_eq!(config.scheduler_host_grpc, String::from("svc-scheduler"));
         assert_eq!(config.log_config, String::from("log4rs.yaml"));
         assert_eq!(config.rest_concurrency_limit_per_service, 5);
-        assert_eq!(config.rest_request_limit_per_second, 2);
+        assert_eq!(config.rest_request_limit_per_second, 10);
+        assert_eq!(
+            config.rest_route_limits_per_second,
+            String::from("/cargo/request=2,/cargo/track=20")
+        );
+        assert_eq!(config.rest_daily_quota_per_api_key, 0);
+        assert_eq!(config.rest_trusted_proxies, String::from(""));
         assert_eq!(
             config.rest_cors_allowed_origin,
             String::from("http://localhost:3000")
         );
+        assert_eq!(
+            config.rest_cors_allowed_headers,
+            String::from("content-type,authorization,x-api-key,x-request-id")
+        );
+        assert!(!config.rest_cors_allow_credentials);
+        assert_eq!(config.rest_cors_max_age_seconds, 600);
+        assert_eq!(config.tracing_exporter, String::from("none"));
+        assert_eq!(
+            config.tracing_otlp_endpoint,
+            String::from("http://localhost:4317")
+        );
+        assert_eq!(config.tracing_file, String::from("traces.jsonl"));
+        assert_eq!(config.health_probe_interval_seconds, 10);
+        assert_eq!(config.health_probe_timeout_ms, 2000);
+        assert_eq!(config.storage_timeout_ms, 5000);
+        assert_eq!(config.scheduler_timeout_ms, 10000);
+        assert_eq!(config.pricing_timeout_ms, 5000);
+        assert_eq!(config.grpc_max_retries, 2);
+        assert_eq!(config.grpc_retry_base_delay_ms, 100);
+        assert_eq!(config.circuit_breaker_failure_threshold, 5);
+        assert_eq!(config.circuit_breaker_open_seconds, 30);
+        assert_eq!(config.tls_cert_file, String::from(""));
+        assert_eq!(config.tls_key_file, String::from(""));
+        assert_eq!(config.grpc_tls_client_ca_file, String::from(""));
+        assert_eq!(config.rest_tls_client_ca_file, String::from(""));
+        assert_eq!(config.backend_tls_ca_file, String::from(""));
+        assert_eq!(config.backend_tls_cert_file, String::from(""));
+        assert_eq!(config.backend_tls_key_file, String::from(""));
+        assert_eq!(config.rest_scan_max_clock_skew_seconds, 300);
+        assert_eq!(config.rest_scan_max_age_hours, 72);
+        assert_eq!(config.rest_scanner_signing_secret, String::from(""));
+        assert_eq!(config.rest_scan_vertiport_margin_meters, 100);
+        assert_eq!(config.rest_scan_corridor_meters, 2000);
+        assert_eq!(config.rest_scan_anomaly_file, String::from(""));
+        assert_eq!(config.rest_scan_anomaly_retention_days, 30);
+        assert_eq!(config.rest_scan_anomaly_webhook_url, String::from(""));
+        assert_eq!(
+            config.rest_scanner_registry_file,
+            String::from("scanners.json")
+        );
+        assert_eq!(config.rest_scanner_admin_key, String::from(""));
+        assert_eq!(config.rest_shipment_file, String::from("shipments.json"));
+        assert_eq!(config.rest_shipment_retention_days, 90);
+        assert_eq!(config.rest_delivery_dir, String::from("deliveries"));
+        assert_eq!(
+            config.rest_public_tracking_url,
+            String::from("/cargo/public/track/")
+        );
+        assert_eq!(config.rest_notification_email_url, String::from(""));
+        assert_eq!(config.rest_notification_sms_url, String::from(""));
+        assert_eq!(config.rest_notification_file, String::from(""));
+        assert_eq!(config.rest_pickup_max_attempts, 5);
+        assert_eq!(config.rest_pickup_lockout_seconds, 900);
+        assert_eq!(config.rest_pickup_code_secret, String::from(""));
 
         ut_info!("(test_config_from_default) Success.");
     }
@@ -143,10 +703,60 @@ mod tests {
         std::env::set_var("LOG_CONFIG", "config_file.yaml");
         std::env::set_var("REST_CONCURRENCY_LIMIT_PER_SERVICE", "255");
         std::env::set_var("REST_REQUEST_LIMIT_PER_SECOND", "255");
+        std::env::set_var("REST_ROUTE_LIMITS_PER_SECOND", "/cargo/scan=50");
+        std::env::set_var("REST_DAILY_QUOTA_PER_API_KEY", "10000");
+        std::env::set_var("REST_TRUSTED_PROXIES", "10.0.0.0/8,::1");
         std::env::set_var(
             "REST_CORS_ALLOWED_ORIGIN",
-            "https://allowed.origin.host:443",
+            "https://allowed.origin.host:443,https://*.example.com",
         );
+        std::env::set_var("REST_CORS_ALLOWED_HEADERS", "content-type,x-scanner-id");
+        std::env::set_var("REST_CORS_ALLOW_CREDENTIALS", "true");
+        std::env::set_var("REST_CORS_MAX_AGE_SECONDS", "3600");
+        std::env::set_var("TRACING_EXPORTER", "otlp");
+        std::env::set_var("TRACING_OTLP_ENDPOINT", "http://collector:4317");
+        std::env::set_var("TRACING_FILE", "spans.jsonl");
+        std::env::set_var("HEALTH_PROBE_INTERVAL_SECONDS", "30");
+        std::env::set_var("HEALTH_PROBE_TIMEOUT_MS", "500");
+        std::env::set_var("SCHEDULER_TIMEOUT_MS", "30000");
+        std::env::set_var("GRPC_MAX_RETRIES", "0");
+        std::env::set_var("CIRCUIT_BREAKER_OPEN_SECONDS", "5");
+        std::env::set_var("GRPC_TLS_CLIENT_CA_FILE", "clients-ca.pem");
+        std::env::set_var("BACKEND_TLS_CA_FILE", "backends-ca.pem");
+        std::env::set_var("BACKEND_TLS_CERT_FILE", "svc-cargo.pem");
+        std::env::set_var("BACKEND_TLS_KEY_FILE", "svc-cargo.key");
+        std::env::set_var("REST_SCAN_MAX_CLOCK_SKEW_SECONDS", "60");
+        std::env::set_var("REST_SCAN_MAX_AGE_HOURS", "168");
+        std::env::set_var("REST_SCANNER_SIGNING_SECRET", "scanner-secret");
+        std::env::set_var("REST_SCAN_VERTIPORT_MARGIN_METERS", "50");
+        std::env::set_var("REST_SCAN_CORRIDOR_METERS", "5000");
+        std::env::set_var("REST_SCAN_ANOMALY_FILE", "anomalies.jsonl");
+        std::env::set_var("REST_SCAN_ANOMALY_RETENTION_DAYS", "90");
+        std::env::set_var(
+            "REST_SCAN_ANOMALY_WEBHOOK_URL",
+            "https://ops.example.com/anomalies",
+        );
+        std::env::set_var(
+            "REST_SCANNER_REGISTRY_FILE",
+            "/var/lib/svc-cargo/scanners.json",
+        );
+        std::env::set_var("REST_SCANNER_ADMIN_KEY", "admin-key");
+        std::env::set_var("REST_SHIPMENT_FILE", "/var/lib/svc-cargo/shipments.json");
+        std::env::set_var("REST_SHIPMENT_RETENTION_DAYS", "365");
+        std::env::set_var("REST_DELIVERY_DIR", "/var/lib/svc-cargo/deliveries");
+        std::env::set_var(
+            "REST_PUBLIC_TRACKING_URL",
+            "https://cargo.example.com/track/",
+        );
+        std::env::set_var(
+            "REST_NOTIFICATION_EMAIL_URL",
+            "https://mail.example.com/send",
+        );
+        std::env::set_var("REST_NOTIFICATION_SMS_URL", "https://sms.example.com/send");
+        std::env::set_var("REST_NOTIFICATION_FILE", "notifications.jsonl");
+        std::env::set_var("REST_PICKUP_MAX_ATTEMPTS", "3");
+        std::env::set_var("REST_PICKUP_LOCKOUT_SECONDS", "3600");
+        std::env::set_var("REST_PICKUP_CODE_SECRET", "pickup-secret");
 
         let config = Config::try_from_env();
         assert!(config.is_ok());
@@ -166,11 +776,221 @@ mod tests {
         assert_eq!(config.log_config, String::from("config_file.yaml"));
         assert_eq!(config.rest_concurrency_limit_per_service, 255);
         assert_eq!(config.rest_request_limit_per_second, 255);
+        assert_eq!(
+            config.rest_route_limits_per_second,
+            String::from("/cargo/scan=50")
+        );
+        assert_eq!(config.rest_daily_quota_per_api_key, 10000);
+        assert_eq!(config.rest_trusted_proxies, String::from("10.0.0.0/8,::1"));
         assert_eq!(
             config.rest_cors_allowed_origin,
-            String::from("https://allowed.origin.host:443")
+            String::from("https://allowed.origin.host:443,https://*.example.com")
+        );
+        assert_eq!(
+            config.rest_cors_allowed_headers,
+            String::from("content-type,x-scanner-id")
+        );
+        assert!(config.rest_cors_allow_credentials);
+        assert_eq!(config.rest_cors_max_age_seconds, 3600);
+        assert_eq!(config.tracing_exporter, String::from("otlp"));
+        assert_eq!(
+            config.tracing_otlp_endpoint,
+            String::from("http://collector:4317")
+        );
+        assert_eq!(config.tracing_file, String::from("spans.jsonl"));
+        assert_eq!(config.health_probe_interval_seconds, 30);
+        assert_eq!(config.health_probe_timeout_ms, 500);
+        assert_eq!(config.scheduler_timeout_ms, 30000);
+        assert_eq!(config.grpc_max_retries, 0);
+        assert_eq!(config.circuit_breaker_open_seconds, 5);
+        assert_eq!(
+            config.grpc_tls_client_ca_file,
+            String::from("clients-ca.pem")
+        );
+        assert_eq!(config.backend_tls_ca_file, String::from("backends-ca.pem"));
+        assert_eq!(config.backend_tls_cert_file, String::from("svc-cargo.pem"));
+        assert_eq!(config.backend_tls_key_file, String::from("svc-cargo.key"));
+        assert_eq!(config.rest_scan_max_clock_skew_seconds, 60);
+        assert_eq!(config.rest_scan_max_age_hours, 168);
+        assert_eq!(
+            config.rest_scanner_signing_secret,
+            String::from("scanner-secret")
+        );
+        assert_eq!(config.rest_scan_vertiport_margin_meters, 50);
+        assert_eq!(config.rest_scan_corridor_meters, 5000);
+        assert_eq!(
+            config.rest_scan_anomaly_file,
+            String::from("anomalies.jsonl")
+        );
+        assert_eq!(config.rest_scan_anomaly_retention_days, 90);
+        assert_eq!(
+            config.rest_scan_anomaly_webhook_url,
+            String::from("https://ops.example.com/anomalies")
+        );
+        assert_eq!(
+            config.rest_scanner_registry_file,
+            String::from("/var/lib/svc-cargo/scanners.json")
+        );
+        assert_eq!(config.rest_scanner_admin_key, String::from("admin-key"));
+        assert_eq!(
+            config.rest_shipment_file,
+            String::from("/var/lib/svc-cargo/shipments.json")
+        );
+        assert_eq!(config.rest_shipment_retention_days, 365);
+        assert_eq!(
+            config.rest_delivery_dir,
+            String::from("/var/lib/svc-cargo/deliveries")
+        );
+        assert_eq!(
+            config.rest_public_tracking_url,
+            String::from("https://cargo.example.com/track/")
+        );
+        assert_eq!(
+            config.rest_notification_email_url,
+            String::from("https://mail.example.com/send")
+        );
+        assert_eq!(
+            config.rest_notification_sms_url,
+            String::from("https://sms.example.com/send")
+        );
+        assert_eq!(
+            config.rest_notification_file,
+            String::from("notifications.jsonl")
+        );
+        assert_eq!(config.rest_pickup_max_attempts, 3);
+        assert_eq!(config.rest_pickup_lockout_seconds, 3600);
+        assert_eq!(
+            config.rest_pickup_code_secret,
+            String::from("pickup-secret")
         );
 
         ut_info!("(test_config_from_env) Success.");
     }
+
+    #[test]
+    fn ut_config_validate() {
+        assert_eq!(Config::default().validate(), Ok(()));
+
+        let config = Config {
+            docker_port_rest: 0,
+            storage_host_grpc: "svc storage".to_string(),
+            rest_cors_allowed_origin: "localhost:3000/app".to_string(),
+            rest_request_limit_per_second: 0,
+            rest_route_limits_per_second: "/cargo/request=fast".to_string(),
+            tracing_exporter: "jaeger".to_string(),
+            pricing_timeout_ms: 0,
+            ..Config::default()
+        };
+
+        // All problems are reported at once
+        let Err(InvalidConfig { problems }) = config.validate() else {
+            panic!("invalid config accepted");
+        };
+        assert_eq!(problems.len(), 7, "{:?}", problems);
+        assert!(problems[0].starts_with("docker_port_rest"));
+        assert!(problems[1].starts_with("storage_host_grpc"));
+        assert!(problems[2].starts_with("rest_cors_allowed_origin"));
+
+        // TLS files must exist, and client certificates need TLS
+        let config = Config {
+            tls_cert_file: "missing-cert.pem".to_string(),
+            rest_tls_client_ca_file: "missing-ca.pem".to_string(),
+            ..Config::default()
+        };
+        let Err(InvalidConfig { problems }) = config.validate() else {
+            panic!("invalid config accepted");
+        };
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+
+        let config = Config {
+            grpc_tls_client_ca_file: "Cargo.toml".to_string(),
+            ..Config::default()
+        };
+        assert!(config.validate().is_err());
+
+        // Backend certificates must be readable, and a client certificate needs a CA
+        for config in [
+            Config {
+                backend_tls_ca_file: "missing-ca.pem".to_string(),
+                ..Config::default()
+            },
+            Config {
+                backend_tls_ca_file: "Cargo.toml".to_string(),
+                ..Config::default()
+            },
+            Config {
+                backend_tls_cert_file: "Cargo.toml".to_string(),
+                backend_tls_key_file: "Cargo.toml".to_string(),
+                ..Config::default()
+            },
+        ] {
+            let Err(InvalidConfig { problems }) = config.validate() else {
+                panic!("invalid config accepted: {:?}", config);
+            };
+            assert_eq!(problems.len(), 1, "{:?}", problems);
+        }
+
+        for origin in [
+            "https://allowed.origin.host:443",
+            "http://10.0.0.1",
+            "https://app.example.com, https://*.example.com",
+            "http://*.localhost:3000",
+        ] {
+            let config = Config {
+                rest_cors_allowed_origin: origin.to_string(),
+                ..Config::default()
+            };
+            assert_eq!(config.validate(), Ok(()), "{}", origin);
+        }
+
+        for origin in [
+            "https://*",
+            "https://app*.example.com",
+            "https://app.example.com,",
+            "https://app.example.com,localhost",
+        ] {
+            let config = Config {
+                rest_cors_allowed_origin: origin.to_string(),
+                ..Config::default()
+            };
+            assert!(config.validate().is_err(), "{}", origin);
+        }
+
+        let config = Config {
+            rest_cors_allowed_headers: "content-type,x scanner".to_string(),
+            ..Config::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = Config {
+            rest_scan_anomaly_webhook_url: "ops.example.com/anomalies".to_string(),
+            ..Config::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = Config {
+            rest_notification_sms_url: "ftp://sms.example.com".to_string(),
+            ..Config::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn ut_config_from_file() {
+        // Only uses fields that no other test sets in the environment
+        let path = std::env::temp_dir().join(format!("svc-cargo-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "storage_timeout_ms = 1234\npricing_timeout_ms = 4321\n",
+        )
+        .unwrap();
+
+        let config = Config::load(path.to_str()).unwrap();
+        assert_eq!(config.storage_timeout_ms, 1234);
+        assert_eq!(config.pricing_timeout_ms, 4321);
+        std::fs::remove_file(&path).unwrap();
+
+        // A missing file is an error rather than silently ignored
+        assert!(Config::load(path.to_str()).is_err());
+    }
 }
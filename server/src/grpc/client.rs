@@ -1,21 +1,32 @@
 //! gRPC client helpers implementation
+use super::resilience::Backends;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::OnceCell;
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+use tonic::Status;
+use tracing::Instrument;
 
+use crate::tls::backend_tls;
 use lib_common::grpc::Client;
 use svc_pricing_client_grpc::prelude::PricingClient;
 use svc_scheduler_client_grpc::prelude::SchedulerClient;
 use svc_storage_client_grpc::prelude::Clients;
+use svc_storage_client_grpc::resources::{
+    flight_plan, flight_plan_parcel, parcel, parcel_scan, vehicle, vertipad, vertiport,
+};
 
 pub(crate) static CLIENTS: OnceCell<GrpcClients> = OnceCell::const_new();
 
 /// Returns CLIENTS, a GrpcClients object with default values.
-/// Uses host and port configurations using a Config object generated from
-/// environment variables.
+/// Uses host and port configurations from the running Config object, see
+/// [`crate::reload::current`].
 /// Initializes CLIENTS if it hasn't been initialized yet.
 pub async fn get_clients() -> &'static GrpcClients {
     CLIENTS
         .get_or_init(|| async move {
-            let config = crate::Config::try_from_env().unwrap_or_default();
+            let config = crate::reload::current();
             GrpcClients::default(config)
         })
         .await
@@ -24,6 +35,9 @@ pub async fn get_clients() -> &'static GrpcClients {
 /// Struct to hold all gRPC client connections
 #[derive(Clone, Debug)]
 pub struct GrpcClients {
+    /// Deadlines, retries and circuit breakers of the backend services,
+    ///  shared by all clones
+    pub backends: Arc<Backends>,
     /// All clients enabled from the svc_storage_grpc_client module
     pub storage: Clients,
     /// A GrpcClient provided by the svc_scheduler_grpc_client module
@@ -34,10 +48,16 @@ pub struct GrpcClients {
 
 impl GrpcClients {
     /// Create new GrpcClients with defaults
+    ///
+    /// Connections use TLS when `backend_tls_ca_file` is set. If the
+    ///  certificates can't be read then, calls fail instead of using plaintext.
     pub fn default(config: crate::Config) -> Self {
-        let storage_clients = Clients::new(config.storage_host_grpc, config.storage_port_grpc);
+        let backends = Arc::new(Backends::new(&config));
+        let storage_clients =
+            Clients::new(config.storage_host_grpc.clone(), config.storage_port_grpc);
 
-        GrpcClients {
+        let clients = GrpcClients {
+            backends,
             storage: storage_clients,
             scheduler: SchedulerClient::new_client(
                 &config.scheduler_host_grpc,
@@ -49,10 +69,168 @@ impl GrpcClients {
                 config.pricing_port_grpc,
                 "pricing",
             ),
+        };
+
+        let tls = match backend_tls(&config) {
+            Ok(None) => return clients,
+            Ok(Some(tls)) => Some(tls),
+            Err(e) => {
+                grpc_error!(
+                    "(GrpcClients::default) backend TLS unavailable, calls to the backends will fail: {}",
+                    e
+                );
+                None
+            }
+        };
+
+        clients.connect_tls(&config, tls);
+        clients
+    }
+
+    /// Connects the clients over TLS, the client libraries only connect over plaintext
+    fn connect_tls(&self, config: &crate::Config, tls: Option<ClientTlsConfig>) {
+        if let Some(channel) = tls_channel(
+            &config.storage_host_grpc,
+            config.storage_port_grpc,
+            tls.clone(),
+        ) {
+            let storage = &self.storage;
+            use_connection(
+                &storage.vertiport,
+                vertiport::rpc_service_client::RpcServiceClient::new(channel.clone()),
+            );
+            use_connection(
+                &storage.vertipad,
+                vertipad::rpc_service_client::RpcServiceClient::new(channel.clone()),
+            );
+            use_connection(
+                &storage.vehicle,
+                vehicle::rpc_service_client::RpcServiceClient::new(channel.clone()),
+            );
+            use_connection(
+                &storage.parcel,
+                parcel::rpc_service_client::RpcServiceClient::new(channel.clone()),
+            );
+            use_connection(
+                &storage.parcel_scan,
+                parcel_scan::rpc_service_client::RpcServiceClient::new(channel.clone()),
+            );
+            use_connection(
+                &storage.flight_plan,
+                flight_plan::rpc_service_client::RpcServiceClient::new(channel.clone()),
+            );
+            use_connection(
+                &storage.flight_plan_parcel,
+                flight_plan_parcel::rpc_service_linked_client::RpcServiceLinkedClient::new(channel),
+            );
         }
+
+        if let Some(channel) = tls_channel(
+            &config.scheduler_host_grpc,
+            config.scheduler_port_grpc,
+            tls.clone(),
+        ) {
+            use_connection(
+                &self.scheduler,
+                svc_scheduler_client_grpc::client::rpc_service_client::RpcServiceClient::new(
+                    channel,
+                ),
+            );
+        }
+
+        if let Some(channel) = tls_channel(&config.pricing_host_grpc, config.pricing_port_grpc, tls)
+        {
+            use_connection(
+                &self.pricing,
+                svc_pricing_client_grpc::client::rpc_service_client::RpcServiceClient::new(channel),
+            );
+        }
+    }
+}
+
+/// Channel to a backend service over TLS, connected when first used
+///
+/// Without a usable TLS configuration the channel refuses to connect, tonic
+///  doesn't use plaintext for `https` addresses.
+fn tls_channel(host: &str, port: u16, tls: Option<ClientTlsConfig>) -> Option<Channel> {
+    let endpoint = match Endpoint::from_shared(format!("https://{host}:{port}")) {
+        Ok(endpoint) => endpoint,
+        Err(e) => {
+            // The plaintext address of the client library is just as invalid
+            grpc_error!(
+                "(tls_channel) invalid backend address {}:{}: {}",
+                host,
+                port,
+                e
+            );
+            return None;
+        }
+    };
+
+    let endpoint = match tls.map(|tls| endpoint.clone().tls_config(tls)) {
+        Some(Ok(endpoint)) => endpoint,
+        Some(Err(e)) => {
+            grpc_error!(
+                "(tls_channel) could not set up TLS to {}:{}: {}",
+                host,
+                port,
+                e
+            );
+            endpoint
+        }
+        None => endpoint,
+    };
+
+    Some(endpoint.connect_lazy())
+}
+
+/// Makes a client use the given connection instead of opening its own
+///
+/// The client libraries only open a connection again after a client is
+///  invalidated, which this service doesn't do.
+fn use_connection<T: Clone + Send>(client: &impl Client<T>, connection: T) {
+    // Just created, so not locked by anything else
+    if let Ok(mut inner) = client.get_inner().try_lock() {
+        *inner = Some(connection);
     }
 }
 
+/// Wraps a message in a request carrying the current trace context
+pub fn traced_request<T>(message: T) -> tonic::Request<T> {
+    let mut request = tonic::Request::new(message);
+    crate::telemetry::inject_trace_context(request.metadata_mut());
+    request
+}
+
+/// Calls a backend service in its own span, recording the outcome and latency of the call
+/// `method` is prefixed with the resource for svc-storage calls, e.g. `parcel.search`
+pub async fn instrumented<T, F, Fut>(
+    service: &'static str,
+    method: &'static str,
+    call: F,
+) -> Result<T, Status>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<T, Status>>,
+{
+    let start = Instant::now();
+    let span = tracing::info_span!("grpc_client", service, method);
+    let result = call().instrument(span).await;
+    let code = match &result {
+        Ok(_) => tonic::Code::Ok,
+        Err(status) => status.code(),
+    };
+
+    crate::metrics::metrics().observe_grpc_client(
+        service,
+        method,
+        &format!("{:?}", code),
+        start.elapsed(),
+    );
+
+    result
+}
+
 #[cfg(test)]
 mod tests {
     use lib_common::grpc::Client as CommonClient;
@@ -102,4 +280,72 @@ mod tests {
 
         ut_info!("(test_grpc_clients_default) Success.");
     }
+
+    #[tokio::test]
+    async fn ut_backend_tls() {
+        use crate::tls::tests::TestCa;
+        use crate::tls::{incoming, ClientAuth, ReloadableAcceptor, TlsServer, TlsSettings};
+        use tonic_health::pb::{health_client::HealthClient, HealthCheckRequest};
+
+        // A backend only accepting clients with a certificate of its CA
+        let ca = TestCa::new();
+        let (cert_file, key_file) = ca.write_cert("backend");
+        let acceptor = ReloadableAcceptor::new(TlsSettings {
+            server: TlsServer::Grpc,
+            cert_file,
+            key_file,
+            client_auth: ClientAuth::Required(ca.write_ca()),
+            alpn_protocols: vec![b"h2".to_vec()],
+        })
+        .unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (_, health_service) = tonic_health::server::health_reporter();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(health_service)
+                .serve_with_incoming(incoming(listener, Arc::new(acceptor))),
+        );
+
+        let (cert_file, key_file) = ca.write_cert("svc-cargo");
+        let config = crate::Config {
+            backend_tls_ca_file: ca.write_ca(),
+            backend_tls_cert_file: cert_file,
+            backend_tls_key_file: key_file,
+            ..crate::Config::default()
+        };
+        let check = |tls: Option<ClientTlsConfig>| async move {
+            let channel = tls_channel("localhost", port, tls).unwrap();
+            HealthClient::new(channel)
+                .check(HealthCheckRequest {
+                    service: String::new(),
+                })
+                .await
+        };
+
+        // Calls need the client certificate
+        assert!(check(backend_tls(&config).unwrap()).await.is_ok());
+        let without_cert = crate::Config {
+            backend_tls_cert_file: String::new(),
+            backend_tls_key_file: String::new(),
+            ..config.clone()
+        };
+        assert!(check(backend_tls(&without_cert).unwrap()).await.is_err());
+
+        // Without a TLS configuration, calls fail rather than use plaintext
+        assert!(check(None).await.is_err());
+
+        // The clients use the TLS connections, or open their own without TLS
+        let clients = GrpcClients::default(config);
+        assert!(clients.scheduler.get_inner().try_lock().unwrap().is_some());
+        assert!(clients
+            .storage
+            .parcel
+            .get_inner()
+            .try_lock()
+            .unwrap()
+            .is_some());
+        let clients = GrpcClients::default(crate::Config::default());
+        assert!(clients.pricing.get_inner().try_lock().unwrap().is_none());
+    }
 }
@@ -4,4 +4,5 @@
 #[macro_use]
 pub mod macros;
 pub mod client;
+pub mod resilience;
 pub mod server;
@@ -0,0 +1,490 @@
+//! Deadlines, retries and circuit breakers for calls to backend services
+
+use super::client::instrumented;
+use crate::Config;
+use rand::Rng;
+use std::cell::Cell;
+#[cfg(test)]
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tonic::{Code, Status};
+
+tokio::task_local! {
+    /// Longest time until a circuit breaker that rejected a call of the
+    ///  current request lets calls through again
+    static RETRY_AFTER: Cell<Option<Duration>>;
+}
+
+/// Runs a future, also returning when calls rejected by a circuit breaker
+///  while it ran may be retried
+pub async fn track_retry_after<F: Future>(future: F) -> (F::Output, Option<Duration>) {
+    RETRY_AFTER
+        .scope(Cell::new(None), async move {
+            let output = future.await;
+            (output, RETRY_AFTER.with(Cell::get))
+        })
+        .await
+}
+
+fn record_retry_after(delay: Duration) {
+    // Outside of track_retry_after there is nobody to tell
+    let _ = RETRY_AFTER.try_with(|retry_after| {
+        retry_after.set(Some(retry_after.get().unwrap_or(delay).max(delay)));
+    });
+}
+
+/// Errors showing that the backend is unhealthy, rather than the request invalid
+fn is_backend_failure(code: Code) -> bool {
+    matches!(
+        code,
+        Code::Unavailable
+            | Code::DeadlineExceeded
+            | Code::ResourceExhausted
+            | Code::Internal
+            | Code::Unknown
+    )
+}
+
+/// Errors that may not happen again when retrying the same request
+fn is_retryable(code: Code) -> bool {
+    matches!(
+        code,
+        Code::Unavailable | Code::DeadlineExceeded | Code::ResourceExhausted
+    )
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    trial_started: Option<Instant>,
+}
+
+/// Fails calls fast while a backend service keeps failing
+///
+/// The breaker opens after `failure_threshold` consecutive failures and
+///  rejects calls for `open_duration`. It then lets a single trial call
+///  through, closing again if the trial succeeds or reopening if it fails.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_duration: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    /// Creates a closed circuit breaker
+    pub fn new(failure_threshold: u32, open_duration: Duration) -> Self {
+        CircuitBreaker {
+            failure_threshold: failure_threshold.max(1),
+            open_duration,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, BreakerState> {
+        // The state stays consistent even if a holder panicked
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns if calls are currently being rejected
+    pub fn is_open(&self, now: Instant) -> bool {
+        self.state()
+            .open_until
+            .is_some_and(|open_until| now < open_until)
+    }
+
+    /// Checks if a call may go through, returns how long to wait otherwise
+    pub fn try_acquire(&self, now: Instant) -> Result<(), Duration> {
+        let mut state = self.state();
+        let Some(open_until) = state.open_until else {
+            return Ok(());
+        };
+
+        if now < open_until {
+            return Err(open_until - now);
+        }
+
+        // Only one trial at a time, unless the previous one got lost
+        if let Some(trial_started) = state.trial_started {
+            if now < trial_started + self.open_duration {
+                return Err(trial_started + self.open_duration - now);
+            }
+        }
+
+        state.trial_started = Some(now);
+        Ok(())
+    }
+
+    /// Records the outcome of a call that was let through
+    pub fn record(&self, success: bool, now: Instant) {
+        let mut state = self.state();
+        if success {
+            *state = BreakerState::default();
+            return;
+        }
+
+        state.consecutive_failures += 1;
+        if state.trial_started.is_some() || state.consecutive_failures >= self.failure_threshold {
+            state.open_until = Some(now + self.open_duration);
+            state.trial_started = None;
+        }
+    }
+}
+
+/// Canned answer of a stubbed backend method
+#[cfg(test)]
+enum Stub {
+    /// A successful response, boxed as `T`
+    Answer(Box<dyn Fn() -> Box<dyn std::any::Any + Send> + Send + Sync>),
+    /// An error status
+    Fail(Status),
+}
+
+/// Canned answers of a backend by method, so handlers can be tested
+///  without the backend services
+#[cfg(test)]
+#[derive(Default)]
+struct Stubs(Mutex<HashMap<&'static str, Stub>>);
+
+#[cfg(test)]
+impl std::fmt::Debug for Stubs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let stubs = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        f.debug_set().entries(stubs.keys()).finish()
+    }
+}
+
+/// Deadline, retries and circuit breaker for calls to one backend service
+#[derive(Debug)]
+pub struct Backend {
+    name: &'static str,
+    timeout: Duration,
+    max_retries: u32,
+    retry_base_delay: Duration,
+    breaker: CircuitBreaker,
+    #[cfg(test)]
+    stubs: Stubs,
+}
+
+impl Backend {
+    /// Creates the call policy of a backend with the given deadline
+    pub fn new(name: &'static str, timeout: Duration, config: &Config) -> Self {
+        Backend {
+            name,
+            timeout,
+            max_retries: config.grpc_max_retries.into(),
+            retry_base_delay: Duration::from_millis(config.grpc_retry_base_delay_ms.into()),
+            breaker: CircuitBreaker::new(
+                config.circuit_breaker_failure_threshold.into(),
+                Duration::from_secs(config.circuit_breaker_open_seconds.into()),
+            ),
+            #[cfg(test)]
+            stubs: Stubs::default(),
+        }
+    }
+
+    /// Answers all later calls of `method` with `answer` instead of calling the backend
+    ///
+    /// Stubbed calls still pass the circuit breaker, and their failures count.
+    #[cfg(test)]
+    pub fn stub<T, F>(&self, method: &'static str, answer: F)
+    where
+        T: Send + 'static,
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        let answer = Stub::Answer(Box::new(move || Box::new(answer())));
+        self.stubs
+            .0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(method, answer);
+    }
+
+    /// Fails all later calls of `method` with `status` instead of calling the backend
+    #[cfg(test)]
+    pub fn stub_failure(&self, method: &'static str, status: Status) {
+        self.stubs
+            .0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(method, Stub::Fail(status));
+    }
+
+    /// The canned answer of a stubbed method
+    #[cfg(test)]
+    fn stubbed<T: 'static>(&self, method: &'static str) -> Option<Result<T, Status>> {
+        let stubs = self.stubs.0.lock().unwrap_or_else(|e| e.into_inner());
+        match stubs.get(method)? {
+            Stub::Answer(answer) => match answer().downcast::<T>() {
+                Ok(answer) => Some(Ok(*answer)),
+                Err(_) => panic!("stub of {} answers another type.", method),
+            },
+            Stub::Fail(status) => Some(Err(status.clone())),
+        }
+    }
+
+    /// Calls the backend once, within its deadline and circuit breaker
+    /// Use this for calls that must not be repeated, such as inserts.
+    pub async fn call<T, F, Fut>(&self, method: &'static str, call: F) -> Result<T, Status>
+    where
+        T: 'static,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, Status>>,
+    {
+        if let Err(retry_after) = self.breaker.try_acquire(Instant::now()) {
+            let error_msg = format!("svc-{} circuit breaker open.", self.name);
+            grpc_warn!("(call) {} rejected {}.", &error_msg, method);
+            record_retry_after(retry_after);
+            return Err(Status::unavailable(error_msg));
+        }
+
+        #[cfg(test)]
+        let stubbed = self.stubbed(method);
+        #[cfg(not(test))]
+        let stubbed = None;
+
+        let timeout = self.timeout;
+        let result = match stubbed {
+            Some(result) => result,
+            None => {
+                instrumented(self.name, method, || async move {
+                    match tokio::time::timeout(timeout, call()).await {
+                        Ok(result) => result,
+                        Err(_) => Err(Status::deadline_exceeded(format!(
+                            "no response within {} ms.",
+                            timeout.as_millis()
+                        ))),
+                    }
+                })
+                .await
+            }
+        };
+
+        let success = match &result {
+            Ok(_) => true,
+            Err(status) => !is_backend_failure(status.code()),
+        };
+        self.breaker.record(success, Instant::now());
+        result
+    }
+
+    /// Calls the backend, retrying failures with a jittered exponential backoff
+    /// Only use this for idempotent calls, such as searches and lookups.
+    pub async fn call_idempotent<T, F, Fut>(
+        &self,
+        method: &'static str,
+        call: F,
+    ) -> Result<T, Status>
+    where
+        T: 'static,
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, Status>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match self.call(method, &call).await {
+                Err(status)
+                    if attempt < self.max_retries
+                        && is_retryable(status.code())
+                        && !self.breaker.is_open(Instant::now()) =>
+                {
+                    let delay = self.backoff(attempt);
+                    grpc_debug!(
+                        "(call_idempotent) retrying svc-{} {} in {} ms: {}",
+                        self.name,
+                        method,
+                        delay.as_millis(),
+                        status.message()
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Delay before the given retry, between half and all of the exponential delay
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .retry_base_delay
+            .saturating_mul(2u32.saturating_pow(attempt));
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+/// Call policies of all backend services
+#[derive(Debug)]
+pub struct Backends {
+    /// svc-storage, shared by all of its resources
+    pub storage: Backend,
+    /// svc-scheduler
+    pub scheduler: Backend,
+    /// svc-pricing
+    pub pricing: Backend,
+}
+
+impl Backends {
+    /// Creates the call policies from the configured deadlines, retries and breakers
+    pub fn new(config: &Config) -> Self {
+        Backends {
+            storage: Backend::new(
+                "storage",
+                Duration::from_millis(config.storage_timeout_ms.into()),
+                config,
+            ),
+            scheduler: Backend::new(
+                "scheduler",
+                Duration::from_millis(config.scheduler_timeout_ms.into()),
+                config,
+            ),
+            pricing: Backend::new(
+                "pricing",
+                Duration::from_millis(config.pricing_timeout_ms.into()),
+                config,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn backend(timeout: Duration) -> Backend {
+        let config = Config {
+            grpc_max_retries: 2,
+            grpc_retry_base_delay_ms: 1,
+            circuit_breaker_failure_threshold: 3,
+            ..Config::default()
+        };
+        Backend::new("test", timeout, &config)
+    }
+
+    #[test]
+    fn ut_circuit_breaker() {
+        let open_duration = Duration::from_secs(30);
+        let breaker = CircuitBreaker::new(2, open_duration);
+        let now = Instant::now();
+
+        breaker.record(false, now);
+        assert!(breaker.try_acquire(now).is_ok());
+        breaker.record(false, now);
+        assert!(breaker.is_open(now));
+        assert_eq!(breaker.try_acquire(now), Err(open_duration));
+
+        // A single trial call once the breaker was open long enough
+        let later = now + open_duration;
+        assert!(breaker.try_acquire(later).is_ok());
+        assert!(breaker.try_acquire(later).is_err());
+
+        // A failed trial reopens the breaker
+        breaker.record(false, later);
+        assert!(breaker.is_open(later));
+
+        // A successful trial closes it
+        let much_later = later + open_duration;
+        assert!(breaker.try_acquire(much_later).is_ok());
+        breaker.record(true, much_later);
+        assert!(!breaker.is_open(much_later));
+        assert!(breaker.try_acquire(much_later).is_ok());
+    }
+
+    #[tokio::test]
+    async fn ut_backend_timeout() {
+        let backend = backend(Duration::from_millis(10));
+        let result: Result<(), Status> = backend
+            .call("slow", || async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok(())
+            })
+            .await;
+
+        assert_eq!(result.unwrap_err().code(), Code::DeadlineExceeded);
+    }
+
+    #[tokio::test]
+    async fn ut_backend_retries() {
+        let backend = backend(Duration::from_secs(1));
+        let calls = AtomicU32::new(0);
+        let result = backend
+            .call_idempotent("flaky", || async {
+                match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(Status::unavailable("down")),
+                    n => Ok(n),
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 1);
+
+        // Invalid requests aren't retried
+        let calls = AtomicU32::new(0);
+        let result: Result<(), Status> = backend
+            .call_idempotent("invalid", || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(Status::invalid_argument("bad filter"))
+            })
+            .await;
+        assert_eq!(result.unwrap_err().code(), Code::InvalidArgument);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn ut_backend_stub() {
+        let backend = backend(Duration::from_secs(1));
+        backend.stub("stubbed", || 7u32);
+        backend.stub_failure("failing", Status::not_found("no parcel"));
+
+        let result: Result<u32, Status> = backend
+            .call("stubbed", || async { Err(Status::unavailable("down")) })
+            .await;
+        assert_eq!(result.unwrap(), 7);
+
+        let result: Result<u32, Status> = backend.call("failing", || async { Ok(7) }).await;
+        assert_eq!(result.unwrap_err().code(), Code::NotFound);
+
+        // Other methods still reach the backend
+        let result: Result<u32, Status> = backend
+            .call("other", || async { Err(Status::unavailable("down")) })
+            .await;
+        assert_eq!(result.unwrap_err().code(), Code::Unavailable);
+
+        // Stubbed failures count towards the circuit breaker
+        backend.stub_failure("failing", Status::unavailable("down"));
+        for _ in 0..2 {
+            let result: Result<u32, Status> = backend.call("failing", || async { Ok(7) }).await;
+            assert_eq!(result.unwrap_err().message(), "down");
+        }
+        let (result, retry_after) =
+            track_retry_after(backend.call::<u32, _, _>("stubbed", || async { Ok(7) })).await;
+        assert_eq!(result.unwrap_err().code(), Code::Unavailable);
+        assert!(retry_after.is_some());
+    }
+
+    #[tokio::test]
+    async fn ut_backend_circuit_breaker() {
+        let backend = backend(Duration::from_secs(1));
+        let calls = AtomicU32::new(0);
+        let failing = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(Status::unavailable("down"))
+        };
+
+        // Retries stop once the breaker opens after three failures
+        let (result, retry_after) =
+            track_retry_after(backend.call_idempotent("down", failing)).await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(retry_after, None);
+
+        // Calls are then rejected without reaching the backend
+        let (result, retry_after) = track_retry_after(backend.call("down", failing)).await;
+        assert_eq!(result.unwrap_err().code(), Code::Unavailable);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert!(retry_after.is_some_and(|delay| delay <= Duration::from_secs(30)));
+    }
+}
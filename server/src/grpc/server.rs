@@ -7,11 +7,17 @@ pub mod grpc_server {
 pub use grpc_server::rpc_service_server::{RpcService, RpcServiceServer};
 pub use grpc_server::{ReadyRequest, ReadyResponse};
 
+use crate::health::get_health;
+use crate::metrics::GrpcMetricsLayer;
+use crate::request_id::RequestIdLayer;
 use crate::shutdown_signal;
+use crate::telemetry::TraceContextLayer;
+use crate::tls::{self, ReloadableAcceptor, TlsSettings};
 use crate::Config;
 
 use std::fmt::Debug;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use tonic::transport::Server;
 use tonic::{Request, Response, Status};
 
@@ -22,14 +28,20 @@ pub struct ServerImpl {}
 #[cfg(not(feature = "stub_server"))]
 #[tonic::async_trait]
 impl RpcService for ServerImpl {
-    /// Returns ready:true when service is available
+    /// Returns ready:true when all dependencies are available
+    ///  according to the latest health probes
     async fn is_ready(
         &self,
         request: Request<ReadyRequest>,
     ) -> Result<Response<ReadyResponse>, Status> {
         grpc_info!("(is_ready) cargo server.");
         grpc_debug!("(is_ready) request: {:?}", request);
-        let response = ReadyResponse { ready: true };
+        let ready = get_health()
+            .await
+            .wait_for_report()
+            .await
+            .is_some_and(|report| report.is_ready());
+        let response = ReadyResponse { ready };
         Ok(Response::new(response))
     }
 }
@@ -59,23 +71,80 @@ pub async fn grpc_server(config: Config, shutdown_rx: Option<tokio::sync::onesho
         }
     };
 
+    // TLS, with certificates reloaded along with the configuration
+    let tls_acceptor = match TlsSettings::grpc(&config).map(ReloadableAcceptor::new) {
+        None => None,
+        Some(Ok(acceptor)) => Some(Arc::new(acceptor)),
+        Some(Err(e)) => {
+            grpc_error!("(grpc_server) could not set up TLS: {}", e);
+            return;
+        }
+    };
+
     let imp = ServerImpl::default();
     let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
-    health_reporter
-        .set_serving::<RpcServiceServer<ServerImpl>>()
-        .await;
+
+    // Report the service as serving only while all dependencies are healthy
+    let mut health = get_health().await.clone();
+    tokio::spawn(async move {
+        loop {
+            match health.latest().is_some_and(|report| report.is_ready()) {
+                true => {
+                    health_reporter
+                        .set_serving::<RpcServiceServer<ServerImpl>>()
+                        .await
+                }
+                false => {
+                    health_reporter
+                        .set_not_serving::<RpcServiceServer<ServerImpl>>()
+                        .await
+                }
+            }
+
+            if !health.changed().await {
+                break;
+            }
+        }
+    });
 
     //start server
     grpc_info!(
         "(grpc_server) Starting gRPC services on: {}.",
         full_grpc_addr
     );
-    match Server::builder()
+    let router = Server::builder()
+        .layer(RequestIdLayer::new("grpc"))
+        .layer(TraceContextLayer)
+        .layer(GrpcMetricsLayer)
         .add_service(health_service)
-        .add_service(RpcServiceServer::new(imp))
-        .serve_with_shutdown(full_grpc_addr, shutdown_signal("grpc", shutdown_rx))
-        .await
-    {
+        .add_service(RpcServiceServer::new(imp));
+
+    let result = match tls_acceptor {
+        Some(acceptor) => {
+            acceptor.reload_on_change(crate::reload::subscribe(&config));
+            let listener = match tokio::net::TcpListener::bind(full_grpc_addr).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    grpc_error!("(grpc_server) could not bind {}: {}", full_grpc_addr, e);
+                    return;
+                }
+            };
+
+            router
+                .serve_with_incoming_shutdown(
+                    tls::incoming(listener, acceptor),
+                    shutdown_signal("grpc", shutdown_rx),
+                )
+                .await
+        }
+        None => {
+            router
+                .serve_with_shutdown(full_grpc_addr, shutdown_signal("grpc", shutdown_rx))
+                .await
+        }
+    };
+
+    match result {
         Ok(_) => grpc_info!("(grpc_server) gRPC server running at: {}.", full_grpc_addr),
         Err(e) => {
             grpc_error!("(grpc_server) could not start gRPC server: {}", e);
@@ -0,0 +1,347 @@
+//! Health of the backend services this service depends on
+//!
+//! A background task probes every dependency in parallel at a fixed interval
+//! and keeps the latest report, so that REST and gRPC health checks don't
+//! each fan out to all backends.
+
+use crate::grpc::client::{get_clients, instrumented, GrpcClients};
+use chrono::{DateTime, Utc};
+use futures::future::{join_all, BoxFuture, FutureExt};
+use std::future::Future;
+use std::time::{Duration, Instant};
+use svc_pricing_client_grpc::prelude::{pricing, PricingServiceClient};
+use svc_scheduler_client_grpc::prelude::{scheduler, SchedulerServiceClient};
+use svc_storage_client_grpc::prelude::{ReadyRequest, SimpleClient};
+use tokio::sync::{watch, OnceCell};
+use tokio::time::MissedTickBehavior;
+use tonic::Status;
+
+/// Outcome of probing one dependency
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeResult {
+    /// Name of the dependency, e.g. `storage.parcel`
+    pub name: &'static str,
+
+    /// If the dependency answered that it is ready
+    pub healthy: bool,
+
+    /// Time taken by the probe
+    pub latency: Duration,
+
+    /// Why the dependency is unhealthy
+    pub error: Option<String>,
+}
+
+/// Outcome of probing all dependencies
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthReport {
+    /// When the probes were started
+    pub checked_at: DateTime<Utc>,
+
+    /// One result per dependency
+    pub dependencies: Vec<ProbeResult>,
+}
+
+impl HealthReport {
+    /// The service is ready when all of its dependencies are
+    pub fn is_ready(&self) -> bool {
+        self.dependencies
+            .iter()
+            .all(|dependency| dependency.healthy)
+    }
+}
+
+/// Latest health report, shared by the REST and gRPC servers
+#[derive(Debug, Clone)]
+pub struct HealthState {
+    receiver: watch::Receiver<Option<HealthReport>>,
+}
+
+impl HealthState {
+    /// Returns the latest report, or `None` until the first probes finish
+    pub fn latest(&self) -> Option<HealthReport> {
+        self.receiver.borrow().clone()
+    }
+
+    /// Returns the latest report, waiting for the first probes if needed
+    pub async fn wait_for_report(&self) -> Option<HealthReport> {
+        let mut receiver = self.receiver.clone();
+        let report = receiver.wait_for(Option::is_some).await.ok()?;
+        report.clone()
+    }
+
+    /// Waits for the next report, returns false if the prober stopped
+    pub async fn changed(&mut self) -> bool {
+        self.receiver.changed().await.is_ok()
+    }
+}
+
+/// Runs a single probe, bounded by `timeout`
+async fn probe<F, Fut>(
+    name: &'static str,
+    service: &'static str,
+    method: &'static str,
+    timeout: Duration,
+    call: F,
+) -> ProbeResult
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<bool, Status>>,
+{
+    let start = Instant::now();
+    let result = tokio::time::timeout(timeout, instrumented(service, method, call)).await;
+    let (healthy, error) = match result {
+        Ok(Ok(true)) => (true, None),
+        Ok(Ok(false)) => (false, Some("not ready".to_string())),
+        Ok(Err(status)) => (false, Some(status.message().to_string())),
+        Err(_) => (
+            false,
+            Some(format!("timed out after {} ms", timeout.as_millis())),
+        ),
+    };
+
+    ProbeResult {
+        name,
+        healthy,
+        latency: start.elapsed(),
+        error,
+    }
+}
+
+/// Probes all dependencies in parallel, each bounded by `timeout`
+pub async fn probe_dependencies(grpc_clients: &GrpcClients, timeout: Duration) -> HealthReport {
+    let checked_at = Utc::now();
+    let storage = &grpc_clients.storage;
+    let probes: Vec<BoxFuture<ProbeResult>> = vec![
+        probe(
+            "storage.vertiport",
+            "storage",
+            "vertiport.is_ready",
+            timeout,
+            || async {
+                Ok(storage
+                    .vertiport
+                    .is_ready(ReadyRequest {})
+                    .await?
+                    .into_inner()
+                    .ready)
+            },
+        )
+        .boxed(),
+        probe(
+            "storage.vertipad",
+            "storage",
+            "vertipad.is_ready",
+            timeout,
+            || async {
+                Ok(storage
+                    .vertipad
+                    .is_ready(ReadyRequest {})
+                    .await?
+                    .into_inner()
+                    .ready)
+            },
+        )
+        .boxed(),
+        probe(
+            "storage.parcel",
+            "storage",
+            "parcel.is_ready",
+            timeout,
+            || async {
+                Ok(storage
+                    .parcel
+                    .is_ready(ReadyRequest {})
+                    .await?
+                    .into_inner()
+                    .ready)
+            },
+        )
+        .boxed(),
+        probe(
+            "storage.parcel_scan",
+            "storage",
+            "parcel_scan.is_ready",
+            timeout,
+            || async {
+                Ok(storage
+                    .parcel_scan
+                    .is_ready(ReadyRequest {})
+                    .await?
+                    .into_inner()
+                    .ready)
+            },
+        )
+        .boxed(),
+        probe(
+            "storage.flight_plan",
+            "storage",
+            "flight_plan.is_ready",
+            timeout,
+            || async {
+                Ok(storage
+                    .flight_plan
+                    .is_ready(ReadyRequest {})
+                    .await?
+                    .into_inner()
+                    .ready)
+            },
+        )
+        .boxed(),
+        probe(
+            "storage.vehicle",
+            "storage",
+            "vehicle.is_ready",
+            timeout,
+            || async {
+                Ok(storage
+                    .vehicle
+                    .is_ready(ReadyRequest {})
+                    .await?
+                    .into_inner()
+                    .ready)
+            },
+        )
+        .boxed(),
+        probe("scheduler", "scheduler", "is_ready", timeout, || async {
+            Ok(grpc_clients
+                .scheduler
+                .is_ready(scheduler::ReadyRequest {})
+                .await?
+                .into_inner()
+                .ready)
+        })
+        .boxed(),
+        probe("pricing", "pricing", "is_ready", timeout, || async {
+            Ok(grpc_clients
+                .pricing
+                .is_ready(pricing::ReadyRequest {})
+                .await?
+                .into_inner()
+                .ready)
+        })
+        .boxed(),
+    ];
+
+    HealthReport {
+        checked_at,
+        dependencies: join_all(probes).await,
+    }
+}
+
+/// Starts probing all dependencies every `interval` in a background task
+pub fn spawn_prober(
+    grpc_clients: GrpcClients,
+    interval: Duration,
+    timeout: Duration,
+) -> HealthState {
+    let (sender, receiver) = watch::channel(None);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            let report = probe_dependencies(&grpc_clients, timeout).await;
+            for dependency in report.dependencies.iter().filter(|d| !d.healthy) {
+                grpc_warn!(
+                    "(spawn_prober) {} unhealthy: {}",
+                    dependency.name,
+                    dependency.error.as_deref().unwrap_or("unknown error")
+                );
+            }
+
+            if sender.send(Some(report)).is_err() {
+                // Nobody is interested in the health anymore
+                break;
+            }
+        }
+    });
+
+    HealthState { receiver }
+}
+
+static HEALTH: OnceCell<HealthState> = OnceCell::const_new();
+
+/// Returns the shared health state, starting the prober on first use
+/// Uses the interval and timeout of the running configuration, and the
+///  clients returned by [`get_clients`].
+pub async fn get_health() -> &'static HealthState {
+    HEALTH
+        .get_or_init(|| async move {
+            let config = crate::reload::current();
+            spawn_prober(
+                get_clients().await.clone(),
+                Duration::from_secs(config.health_probe_interval_seconds.into()),
+                Duration::from_millis(config.health_probe_timeout_ms.into()),
+            )
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(name: &'static str, healthy: bool) -> ProbeResult {
+        ProbeResult {
+            name,
+            healthy,
+            latency: Duration::from_millis(1),
+            error: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn ut_probe() {
+        let timeout = Duration::from_millis(50);
+
+        let result = probe("a", "a", "is_ready", timeout, || async { Ok(true) }).await;
+        assert!(result.healthy);
+        assert_eq!(result.error, None);
+
+        let result = probe("a", "a", "is_ready", timeout, || async { Ok(false) }).await;
+        assert!(!result.healthy);
+
+        let result = probe("a", "a", "is_ready", timeout, || async {
+            Err(Status::unavailable("connection refused"))
+        })
+        .await;
+        assert!(!result.healthy);
+        assert_eq!(result.error.as_deref(), Some("connection refused"));
+
+        let result = probe("a", "a", "is_ready", timeout, || async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(true)
+        })
+        .await;
+        assert!(!result.healthy);
+        assert_eq!(result.error.as_deref(), Some("timed out after 50 ms"));
+        assert!(result.latency < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn ut_health_state() {
+        let (sender, receiver) = watch::channel(None);
+        let mut state = HealthState { receiver };
+        assert_eq!(state.latest(), None);
+
+        let report = HealthReport {
+            checked_at: Utc::now(),
+            dependencies: vec![result("pricing", true), result("scheduler", false)],
+        };
+        assert!(!report.is_ready());
+
+        let waiting = tokio::spawn({
+            let state = state.clone();
+            async move { state.wait_for_report().await }
+        });
+        sender.send(Some(report.clone())).unwrap();
+        assert_eq!(waiting.await.unwrap(), Some(report.clone()));
+        assert!(state.changed().await);
+        assert_eq!(state.latest(), Some(report));
+
+        drop(sender);
+        assert!(!state.changed().await);
+    }
+}
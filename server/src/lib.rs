@@ -7,19 +7,37 @@ use tokio::sync::OnceCell;
 pub mod test_util;
 
 pub mod config;
+#[macro_use]
 pub mod grpc;
+/// rest implementation module
+#[macro_use]
+pub mod rest;
+
+pub mod health;
+pub mod metrics;
+pub mod reload;
+pub mod request_id;
+pub mod telemetry;
+pub mod tls;
 
 pub use crate::config::Config;
 pub use clap::Parser;
-/// rest implementation module
-pub mod rest;
 
 /// struct holding cli configuration options
 #[derive(Parser, Debug, Clone)]
 pub struct Cli {
     /// Target file to write the OpenAPI Spec
+    #[cfg(feature = "openapi")]
     #[arg(long)]
     pub openapi: Option<String>,
+
+    /// TOML or YAML configuration file, overridden by environment variables
+    #[arg(long)]
+    pub config: Option<String>,
+
+    /// Only validate the configuration, then exit
+    #[arg(long)]
+    pub check_config: bool,
 }
 
 /// Initialized log4rs handle
@@ -30,7 +48,7 @@ pub(crate) async fn get_log_handle() -> Option<log4rs::Handle> {
             // Set up basic logger to make sure we can write to stdout
             let stdout = log4rs::append::console::ConsoleAppender::builder()
                 .encoder(Box::new(log4rs::encode::pattern::PatternEncoder::new(
-                    "{d(%Y-%m-%d %H:%M:%S)} | {I} | {h({l}):5.5} | {f}:{L} | {m}{n}",
+                    "{d(%Y-%m-%d %H:%M:%S)} | {I} | {X(request_id)(-)} | {h({l}):5.5} | {f}:{L} | {m}{n}",
                 )))
                 .build();
             match log4rs::config::Config::builder()
@@ -6,8 +6,19 @@ use svc_cargo::*;
 #[tokio::main]
 #[cfg(not(tarpaulin_include))]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Will use default config settings if no environment vars are found.
-    let config = Config::try_from_env().unwrap_or_default();
+    let args = Cli::parse();
+
+    // Settings from the optional config file, overridden by environment vars.
+    // Invalid settings stop the server rather than falling back to defaults.
+    let config = Config::load(args.config.as_deref())?;
+    if let Err(e) = config.validate() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    if args.check_config {
+        println!("Configuration is valid.");
+        return Ok(());
+    }
 
     // Try to load log configuration from the provided log file.
     // Will default to stdout debug logging if the file can not be loaded.
@@ -15,13 +26,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("(main) Server startup.");
 
+    // Spans are only exported if an exporter is configured
+    telemetry::init_tracing(&config)?;
+
     // Allow option to only generate the spec file to a given location
     // use `make rust-openapi` to generate the OpenAPI specification
-    let args = Cli::parse();
+    #[cfg(feature = "openapi")]
     if let Some(target) = args.openapi {
         return rest::generate_openapi_spec(&target);
     }
 
+    // Apply rate limits, CORS origin and log config changes on SIGHUP
+    reload::spawn_reloader(&config, args.config.clone());
+
     // REST Server
     tokio::spawn(rest::server::rest_server(config.clone(), None));
 
@@ -30,6 +47,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("(main) Server shutdown.");
 
+    // Make sure all spans are exported before shutdown
+    telemetry::shutdown_tracing();
+
     // Make sure all log message are written/ displayed before shutdown
     log::logger().flush();
 
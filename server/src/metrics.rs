@@ -0,0 +1,248 @@
+//! Prometheus metrics for the REST server, gRPC server and backend calls
+
+use axum::{extract::MatchedPath, http::Request, middleware::Next, response::Response};
+use futures::future::BoxFuture;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use std::sync::OnceLock;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::{Layer, Service};
+
+/// Prefix of all metric names exported by this service
+const METRICS_NAMESPACE: &str = "svc_cargo";
+
+/// Metrics exported on the `/metrics` endpoint
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Registry,
+
+    /// REST requests by method, route and status code
+    pub rest_requests: IntCounterVec,
+
+    /// REST request latency by method and route
+    pub rest_request_duration: HistogramVec,
+
+    /// REST requests rejected by the rate and concurrency limits
+    pub rest_rate_limited: IntCounter,
+
+    /// gRPC server requests by method
+    pub grpc_server_requests: IntCounterVec,
+
+    /// gRPC server request latency by method
+    pub grpc_server_request_duration: HistogramVec,
+
+    /// Calls to backend services by service, method and status code
+    pub grpc_client_requests: IntCounterVec,
+
+    /// Latency of calls to backend services by service and method
+    pub grpc_client_request_duration: HistogramVec,
+}
+
+impl Metrics {
+    fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new();
+
+        let rest_requests = IntCounterVec::new(
+            Opts::new("rest_requests_total", "REST requests handled.").namespace(METRICS_NAMESPACE),
+            &["method", "route", "status"],
+        )?;
+        let rest_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "rest_request_duration_seconds",
+                "REST request latency in seconds.",
+            )
+            .namespace(METRICS_NAMESPACE),
+            &["method", "route"],
+        )?;
+        let rest_rate_limited = IntCounter::with_opts(
+            Opts::new(
+                "rest_rate_limited_total",
+                "REST requests rejected by the rate or concurrency limit.",
+            )
+            .namespace(METRICS_NAMESPACE),
+        )?;
+        let grpc_server_requests = IntCounterVec::new(
+            Opts::new("grpc_server_requests_total", "gRPC requests handled.")
+                .namespace(METRICS_NAMESPACE),
+            &["method"],
+        )?;
+        let grpc_server_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "grpc_server_request_duration_seconds",
+                "gRPC request latency in seconds.",
+            )
+            .namespace(METRICS_NAMESPACE),
+            &["method"],
+        )?;
+        let grpc_client_requests = IntCounterVec::new(
+            Opts::new(
+                "grpc_client_requests_total",
+                "Calls made to backend services.",
+            )
+            .namespace(METRICS_NAMESPACE),
+            &["service", "method", "code"],
+        )?;
+        let grpc_client_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "grpc_client_request_duration_seconds",
+                "Latency of calls made to backend services in seconds.",
+            )
+            .namespace(METRICS_NAMESPACE),
+            &["service", "method"],
+        )?;
+
+        registry.register(Box::new(rest_requests.clone()))?;
+        registry.register(Box::new(rest_request_duration.clone()))?;
+        registry.register(Box::new(rest_rate_limited.clone()))?;
+        registry.register(Box::new(grpc_server_requests.clone()))?;
+        registry.register(Box::new(grpc_server_request_duration.clone()))?;
+        registry.register(Box::new(grpc_client_requests.clone()))?;
+        registry.register(Box::new(grpc_client_request_duration.clone()))?;
+
+        Ok(Metrics {
+            registry,
+            rest_requests,
+            rest_request_duration,
+            rest_rate_limited,
+            grpc_server_requests,
+            grpc_server_request_duration,
+            grpc_client_requests,
+            grpc_client_request_duration,
+        })
+    }
+
+    /// Records a REST request
+    pub fn observe_rest(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        self.rest_requests
+            .with_label_values(&[method, route, &status.to_string()])
+            .inc();
+        self.rest_request_duration
+            .with_label_values(&[method, route])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Records a gRPC server request
+    pub fn observe_grpc_server(&self, method: &str, elapsed: Duration) {
+        self.grpc_server_requests.with_label_values(&[method]).inc();
+        self.grpc_server_request_duration
+            .with_label_values(&[method])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Records a call to a backend service
+    pub fn observe_grpc_client(&self, service: &str, method: &str, code: &str, elapsed: Duration) {
+        self.grpc_client_requests
+            .with_label_values(&[service, method, code])
+            .inc();
+        self.grpc_client_request_duration
+            .with_label_values(&[service, method])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Renders all metrics in the Prometheus text format
+    pub fn render(&self) -> Result<String, String> {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(|e| e.to_string())?;
+
+        String::from_utf8(buffer).map_err(|e| e.to_string())
+    }
+}
+
+/// Returns the metrics of this service, creating them on first use
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(|| match Metrics::new() {
+        Ok(metrics) => metrics,
+        // Only fails on duplicate or malformed metric definitions above
+        Err(e) => panic!("(metrics) could not register metrics: {}", e),
+    })
+}
+
+/// REST middleware recording the latency and status of each request
+///
+/// Requests that didn't match a route are grouped under one label so
+///  that arbitrary paths can't grow the number of series.
+pub async fn track_rest_metrics<B>(request: Request<B>, next: Next<B>) -> Response {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let response = next.run(request).await;
+    metrics().observe_rest(&method, &route, response.status().as_u16(), start.elapsed());
+    response
+}
+
+/// Tower layer recording the latency of each gRPC server request
+#[derive(Debug, Default, Copy, Clone)]
+pub struct GrpcMetricsLayer;
+
+impl<S> Layer<S> for GrpcMetricsLayer {
+    type Service = GrpcMetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GrpcMetricsService { inner }
+    }
+}
+
+/// Service created by [`GrpcMetricsLayer`]
+#[derive(Debug, Clone)]
+pub struct GrpcMetricsService<S> {
+    inner: S,
+}
+
+impl<S, B> Service<Request<B>> for GrpcMetricsService<S>
+where
+    S: Service<Request<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<S::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let start = Instant::now();
+        let method = request.uri().path().to_string();
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = future.await;
+            metrics().observe_grpc_server(&method, start.elapsed());
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ut_render_metrics() {
+        let metrics = metrics();
+        metrics.observe_rest("GET", "/health", 200, Duration::from_millis(5));
+        metrics.observe_grpc_client("pricing", "get_pricing", "Ok", Duration::from_millis(5));
+        metrics.rest_rate_limited.inc();
+
+        let text = metrics.render().unwrap();
+        assert!(text.contains(
+            r#"svc_cargo_rest_requests_total{method="GET",route="/health",status="200"}"#
+        ));
+        assert!(text.contains(
+            r#"svc_cargo_grpc_client_requests_total{code="Ok",method="get_pricing",service="pricing"}"#
+        ));
+        assert!(text.contains("svc_cargo_rest_rate_limited_total"));
+        assert!(text.contains("svc_cargo_rest_request_duration_seconds_bucket"));
+    }
+}
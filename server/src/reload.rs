@@ -0,0 +1,152 @@
+//! Reloading the configuration while the service runs
+//!
+//! On `SIGHUP` the configuration file and the environment are read and
+//! validated again. Only fields that can change while requests are being
+//! served are applied: the REST rate limits, quotas, trusted proxies and
+//! concurrency limit, the CORS origin and the log configuration. Changes
+//! to other fields need a restart. This includes the other CORS settings:
+//! allowed headers, credentials and max age are part of the CORS layers
+//! built when the server starts.
+//! TLS certificates are read again as well, see [`crate::tls`].
+
+use crate::{load_logger_config_from_file, Config};
+use std::sync::OnceLock;
+use tokio::sync::watch;
+
+static CONFIG: OnceLock<watch::Sender<Config>> = OnceLock::new();
+
+/// Returns a receiver of the current configuration
+///
+/// The first call sets the initial configuration, later calls ignore it.
+pub fn subscribe(initial: &Config) -> watch::Receiver<Config> {
+    CONFIG
+        .get_or_init(|| watch::channel(initial.clone()).0)
+        .subscribe()
+}
+
+/// Returns the running configuration
+///
+/// Falls back to the environment when no configuration was loaded yet, e.g. in tests.
+pub fn current() -> Config {
+    match CONFIG.get() {
+        Some(sender) => sender.borrow().clone(),
+        None => Config::try_from_env().unwrap_or_default(),
+    }
+}
+
+/// Takes the fields that may be reloaded from `reloaded`, the others from `current`
+///
+/// Of the CORS settings only the allowed origins are read for each request,
+///  see [`crate::rest::cors::CorsPolicy`].
+fn merge_safe_fields(current: &Config, reloaded: &Config) -> Config {
+    Config {
+        rest_request_limit_per_second: reloaded.rest_request_limit_per_second,
+        rest_route_limits_per_second: reloaded.rest_route_limits_per_second.clone(),
+        rest_daily_quota_per_api_key: reloaded.rest_daily_quota_per_api_key,
+        rest_trusted_proxies: reloaded.rest_trusted_proxies.clone(),
+        rest_concurrency_limit_per_service: reloaded.rest_concurrency_limit_per_service,
+        rest_cors_allowed_origin: reloaded.rest_cors_allowed_origin.clone(),
+        log_config: reloaded.log_config.clone(),
+        ..current.clone()
+    }
+}
+
+/// Reads and validates the configuration again, then applies the safe fields
+///
+/// The running configuration is kept if the new one is invalid.
+pub async fn reload(config_file: Option<&str>) -> Result<(), String> {
+    let sender = CONFIG.get().ok_or("(reload) no configuration to reload.")?;
+
+    let reloaded = Config::load(config_file)
+        .map_err(|e| format!("(reload) could not load configuration: {}", e))?;
+    reloaded
+        .validate()
+        .map_err(|e| format!("(reload) keeping the running configuration, {}", e))?;
+
+    let current = sender.borrow().clone();
+    let merged = merge_safe_fields(&current, &reloaded);
+    if merged != reloaded {
+        rest_warn!(
+            "(reload) only rate limits, CORS origins and log configuration are reloaded, restart to apply the other changes, including CORS headers, credentials and max age."
+        );
+    }
+
+    // The log configuration file may have changed even if its path didn't
+    load_logger_config_from_file(&merged.log_config).await?;
+
+    sender.send_replace(merged);
+    rest_info!("(reload) configuration reloaded.");
+    Ok(())
+}
+
+/// Sets the running configuration and reloads it in a background task each
+///  time the process receives `SIGHUP`
+#[cfg(not(tarpaulin_include))]
+// no_coverage: Needs signals sent to the process.
+pub fn spawn_reloader(initial: &Config, config_file: Option<String>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    subscribe(initial);
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            rest_warn!("(spawn_reloader) could not listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            rest_info!("(spawn_reloader) SIGHUP received, reloading configuration.");
+            if let Err(e) = reload(config_file.as_deref()).await {
+                rest_error!("{}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ut_merge_safe_fields() {
+        let current = Config::default();
+        let reloaded = Config {
+            rest_request_limit_per_second: 20,
+            rest_concurrency_limit_per_service: 50,
+            rest_daily_quota_per_api_key: 1000,
+            rest_cors_allowed_origin: "https://app.example.com".to_string(),
+            rest_cors_allowed_headers: "authorization".to_string(),
+            rest_cors_allow_credentials: true,
+            rest_cors_max_age_seconds: 60,
+            log_config: "log4rs.debug.yaml".to_string(),
+            docker_port_rest: 8080,
+            storage_timeout_ms: 100,
+            ..Config::default()
+        };
+
+        let merged = merge_safe_fields(&current, &reloaded);
+        assert_eq!(merged.rest_request_limit_per_second, 20);
+        assert_eq!(merged.rest_concurrency_limit_per_service, 50);
+        assert_eq!(merged.rest_daily_quota_per_api_key, 1000);
+        assert_eq!(merged.rest_cors_allowed_origin, "https://app.example.com");
+        assert_eq!(merged.log_config, "log4rs.debug.yaml");
+
+        // Fields needing a restart keep their running value
+        assert_eq!(merged.docker_port_rest, current.docker_port_rest);
+        assert_eq!(merged.storage_timeout_ms, current.storage_timeout_ms);
+        assert_eq!(
+            merged.rest_cors_allowed_headers,
+            current.rest_cors_allowed_headers
+        );
+        assert_eq!(
+            merged.rest_cors_allow_credentials,
+            current.rest_cors_allow_credentials
+        );
+        assert_eq!(
+            merged.rest_cors_max_age_seconds,
+            current.rest_cors_max_age_seconds
+        );
+    }
+}
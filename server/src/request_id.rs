@@ -0,0 +1,284 @@
+//! Request ids and access logs
+//!
+//! Every REST and gRPC request is assigned an id, taken from the caller's
+//! `X-Request-Id` header when valid or generated otherwise. While the request
+//! is handled the id is kept in the log MDC under `request_id`, so that every
+//! log record can include it. The id is echoed in the `X-Request-Id` response
+//! header, and one JSON access log record is written per request to the
+//! `app::access` target.
+
+use axum::extract::MatchedPath;
+use futures::future::BoxFuture;
+use hyper::http::{HeaderMap, HeaderName, HeaderValue, Request, Response};
+use serde::Serialize;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower::{Layer, Service};
+
+/// Header carrying the id of a request
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Header identifying the caller, set by the authenticating gateway
+pub const USER_HEADER: HeaderName = HeaderName::from_static("x-forwarded-user");
+
+/// Header carrying the gRPC status of responses without a body
+const GRPC_STATUS_HEADER: HeaderName = HeaderName::from_static("grpc-status");
+
+/// MDC key holding the id of the request being handled
+pub const REQUEST_ID_MDC_KEY: &str = "request_id";
+
+/// Log target of the access log records
+pub const ACCESS_LOG_TARGET: &str = "app::access";
+
+/// Longest request id accepted from a caller
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Returns the caller's request id if it is valid, or a new one otherwise
+pub fn request_id_from_headers(headers: &HeaderMap) -> String {
+    headers
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LENGTH
+                && id.chars().all(|c| c.is_ascii_graphic())
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Returns the id of the request being handled by the current task
+pub fn current_request_id() -> Option<String> {
+    log_mdc::get(REQUEST_ID_MDC_KEY, |id| id.map(str::to_string))
+}
+
+/// Runs a future with the given request id in the log MDC
+///
+/// Use this for tasks spawned while handling a request so that their log
+///  records carry the request id as well.
+pub fn with_request_id<F: Future>(request_id: String, future: F) -> WithRequestId<F> {
+    WithRequestId {
+        request_id,
+        inner: Box::pin(future),
+    }
+}
+
+/// Future created by [`with_request_id`]
+///
+/// The MDC is thread local and a task may be polled on a different thread
+///  each time, so the request id is only set for the duration of each poll.
+#[derive(Debug)]
+pub struct WithRequestId<F> {
+    request_id: String,
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for WithRequestId<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let _guard = log_mdc::insert_scoped(REQUEST_ID_MDC_KEY, self.request_id.clone());
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+/// One line of the access log
+#[derive(Debug, Serialize)]
+struct AccessLogEntry {
+    protocol: &'static str,
+    method: String,
+    route: String,
+    status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    grpc_status: Option<String>,
+    latency_ms: f64,
+    user: Option<String>,
+    request_id: String,
+}
+
+impl AccessLogEntry {
+    fn write(&self) {
+        match serde_json::to_string(self) {
+            Ok(line) => log::info!(target: ACCESS_LOG_TARGET, "{}", line),
+            Err(e) => log::warn!(
+                target: ACCESS_LOG_TARGET,
+                "(access_log) could not serialize entry: {}",
+                e
+            ),
+        }
+    }
+}
+
+/// Tower layer assigning request ids and writing the access log
+///
+/// Used by both the REST and the gRPC server, `protocol` is recorded in
+///  each access log record.
+#[derive(Debug, Copy, Clone)]
+pub struct RequestIdLayer {
+    protocol: &'static str,
+}
+
+impl RequestIdLayer {
+    /// Creates a layer for the server of the given protocol
+    pub fn new(protocol: &'static str) -> Self {
+        RequestIdLayer { protocol }
+    }
+}
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestIdService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestIdService {
+            inner,
+            protocol: self.protocol,
+        }
+    }
+}
+
+/// Service created by [`RequestIdLayer`]
+#[derive(Debug, Clone)]
+pub struct RequestIdService<S> {
+    inner: S,
+    protocol: &'static str,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RequestIdService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<S::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<ReqBody>) -> Self::Future {
+        let start = Instant::now();
+        let request_id = request_id_from_headers(request.headers());
+        let header_value = HeaderValue::from_str(&request_id).ok();
+        if let Some(value) = &header_value {
+            request
+                .headers_mut()
+                .insert(REQUEST_ID_HEADER, value.clone());
+        }
+
+        let mut entry = AccessLogEntry {
+            protocol: self.protocol,
+            method: request.method().to_string(),
+            route: request
+                .extensions()
+                .get::<MatchedPath>()
+                .map(|path| path.as_str().to_string())
+                .unwrap_or_else(|| request.uri().path().to_string()),
+            status: None,
+            grpc_status: None,
+            latency_ms: 0.0,
+            user: request
+                .headers()
+                .get(&USER_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            request_id: request_id.clone(),
+        };
+
+        let future = {
+            let _guard = log_mdc::insert_scoped(REQUEST_ID_MDC_KEY, request_id.clone());
+            self.inner.call(request)
+        };
+
+        Box::pin(with_request_id(request_id, async move {
+            let result = future.await;
+            entry.latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+            let result = result.map(|mut response| {
+                if let Some(value) = header_value {
+                    response.headers_mut().insert(REQUEST_ID_HEADER, value);
+                }
+
+                entry.status = Some(response.status().as_u16());
+                entry.grpc_status = response
+                    .headers()
+                    .get(&GRPC_STATUS_HEADER)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string);
+                response
+            });
+
+            entry.write();
+            result
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use tower::ServiceExt;
+
+    #[test]
+    fn ut_request_id_from_headers() {
+        let mut headers = HeaderMap::new();
+        let generated = request_id_from_headers(&headers);
+        assert!(uuid::Uuid::parse_str(&generated).is_ok());
+        assert_ne!(generated, request_id_from_headers(&headers));
+
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("abc-123"));
+        assert_eq!(request_id_from_headers(&headers), "abc-123");
+
+        // Ids with whitespace or that are too long are replaced
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("abc 123"));
+        assert_ne!(request_id_from_headers(&headers), "abc 123");
+
+        let long_id = "a".repeat(MAX_REQUEST_ID_LENGTH + 1);
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_str(&long_id).unwrap());
+        assert_ne!(request_id_from_headers(&headers), long_id);
+    }
+
+    #[tokio::test]
+    async fn ut_with_request_id() {
+        let request_id = with_request_id("abc-123".to_string(), async {
+            tokio::task::yield_now().await;
+            current_request_id()
+        })
+        .await;
+
+        assert_eq!(request_id, Some("abc-123".to_string()));
+        assert_eq!(current_request_id(), None);
+    }
+
+    #[tokio::test]
+    async fn ut_request_id_layer() {
+        let service = RequestIdLayer::new("rest").layer(tower::service_fn(
+            |request: Request<()>| async move {
+                // Handlers see the same id in the request and in the MDC
+                let header = request.headers().get(REQUEST_ID_HEADER).cloned();
+                assert_eq!(
+                    header.and_then(|value| value.to_str().ok().map(str::to_string)),
+                    current_request_id()
+                );
+
+                Ok::<_, Infallible>(Response::new(()))
+            },
+        ));
+
+        let request = Request::builder()
+            .header(REQUEST_ID_HEADER, "abc-123")
+            .body(())
+            .unwrap();
+        let response = service.clone().oneshot(request).await.unwrap();
+        assert_eq!(
+            response.headers().get(REQUEST_ID_HEADER),
+            Some(&HeaderValue::from_static("abc-123"))
+        );
+
+        let response = service.oneshot(Request::new(())).await.unwrap();
+        assert!(response.headers().contains_key(REQUEST_ID_HEADER));
+    }
+}
@@ -0,0 +1,726 @@
+//! Scans recorded away from where their parcel was expected
+//!
+//! Scans are recorded first and compared with the route of their parcel
+//!  afterwards, so the check never delays or fails a scan. Anomalies are kept
+//!  in an [`AnomalyStore`] and posted to the configured webhook. Scans at
+//!  the destination after the last flight tell the recipient the parcel
+//!  arrived, see [`ScanMonitor::notify_arrivals`].
+
+use super::geofence::{get_parcel_route, ParcelRoute, Tolerance};
+use super::notification::{Notifier, ParcelEvent};
+use super::rest_types::{
+    ScanAnomaliesParams, ScanAnomaliesResponse, ScanAnomaly, MAX_SCAN_ANOMALIES_TO_RETURN,
+};
+use super::utils::is_uuid;
+use crate::grpc::client::GrpcClients;
+use crate::Config;
+use axum::{
+    extract::{Extension, Query},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Anomalies kept by [`MemoryAnomalyStore`], older ones are dropped
+const MAX_ANOMALIES_IN_MEMORY: usize = 10_000;
+
+/// Anomalies recorded by [`FileAnomalyStore`] between removals of expired ones
+const ANOMALIES_BETWEEN_COMPACTIONS: usize = 1_000;
+
+/// Time after which posting an anomaly to the webhook is given up
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Which anomalies to list
+#[derive(Debug, Clone, Default)]
+pub struct AnomalyFilter {
+    /// Only anomalies of this parcel
+    pub parcel_id: Option<String>,
+
+    /// Only anomalies detected at or after this time
+    pub since: Option<DateTime<Utc>>,
+
+    /// The maximum number of anomalies
+    pub limit: usize,
+}
+
+impl AnomalyFilter {
+    fn matches(&self, anomaly: &ScanAnomaly) -> bool {
+        let parcel_matches = match &self.parcel_id {
+            Some(parcel_id) => *parcel_id == anomaly.parcel_id,
+            None => true,
+        };
+        let since_matches = match self.since {
+            Some(since) => anomaly.detected_at >= since,
+            None => true,
+        };
+
+        parcel_matches && since_matches
+    }
+
+    /// The matching anomalies of those in detection order, the most recent first
+    fn newest<I>(&self, anomalies: I) -> Vec<ScanAnomaly>
+    where
+        I: DoubleEndedIterator<Item = ScanAnomaly>,
+    {
+        anomalies
+            .rev()
+            .filter(|anomaly| self.matches(anomaly))
+            .take(self.limit)
+            .collect()
+    }
+}
+
+/// Keeps scan anomalies
+pub trait AnomalyStore: Send + Sync + fmt::Debug {
+    /// Adds an anomaly
+    fn record(&self, anomaly: &ScanAnomaly) -> io::Result<()>;
+
+    /// Anomalies matching the filter, the most recently detected first
+    fn list(&self, filter: &AnomalyFilter) -> io::Result<Vec<ScanAnomaly>>;
+}
+
+/// Keeps the latest anomalies until the service stops
+#[derive(Debug, Default)]
+pub struct MemoryAnomalyStore {
+    anomalies: Mutex<VecDeque<ScanAnomaly>>,
+}
+
+impl AnomalyStore for MemoryAnomalyStore {
+    fn record(&self, anomaly: &ScanAnomaly) -> io::Result<()> {
+        let mut anomalies = self.anomalies.lock().map_err(|_| poisoned())?;
+        if anomalies.len() == MAX_ANOMALIES_IN_MEMORY {
+            anomalies.pop_front();
+        }
+
+        anomalies.push_back(anomaly.clone());
+        Ok(())
+    }
+
+    fn list(&self, filter: &AnomalyFilter) -> io::Result<Vec<ScanAnomaly>> {
+        let anomalies = self.anomalies.lock().map_err(|_| poisoned())?;
+        Ok(filter.newest(anomalies.iter().cloned()))
+    }
+}
+
+/// Appends anomalies to a file, one JSON object per line
+///
+/// Anomalies are kept for the retention period: expired ones are removed
+///  from the file when the store is first written to and every
+///  [`ANOMALIES_BETWEEN_COMPACTIONS`] anomalies after, so the file holds
+///  about as many anomalies as are detected in that period.
+#[derive(Debug)]
+pub struct FileAnomalyStore {
+    path: PathBuf,
+    retention: chrono::Duration,
+    /// Anomalies recorded until expired ones are removed again
+    lock: Mutex<usize>,
+}
+
+impl FileAnomalyStore {
+    /// Stores anomalies in the given file, created when the first anomaly is recorded
+    pub fn new(path: impl Into<PathBuf>, retention: chrono::Duration) -> Self {
+        FileAnomalyStore {
+            path: path.into(),
+            retention,
+            lock: Mutex::new(0),
+        }
+    }
+
+    /// Anomalies in the file, in detection order
+    fn read(&self) -> io::Result<Vec<ScanAnomaly>> {
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+
+        Ok(contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(anomaly) => Some(anomaly),
+                Err(e) => {
+                    rest_warn!("(FileAnomalyStore::read) skipping invalid line: {}", e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Rewrites the file without the anomalies detected before the retention period
+    fn compact(&self) -> io::Result<()> {
+        let expiry = Utc::now() - self.retention;
+        let anomalies = self.read()?;
+        let kept: Vec<&ScanAnomaly> = anomalies
+            .iter()
+            .filter(|anomaly| anomaly.detected_at >= expiry)
+            .collect();
+        if kept.len() == anomalies.len() {
+            return Ok(());
+        }
+
+        let mut contents = vec![];
+        for anomaly in &kept {
+            serde_json::to_writer(&mut contents, anomaly)?;
+            contents.push(b'\n');
+        }
+
+        // Replaced in one step so a failed write leaves the old file
+        let temporary = self.path.with_extension("tmp");
+        std::fs::write(&temporary, contents)?;
+        std::fs::rename(&temporary, &self.path)?;
+        rest_info!(
+            "(FileAnomalyStore::compact) removed {} expired anomalies.",
+            anomalies.len() - kept.len()
+        );
+        Ok(())
+    }
+}
+
+impl AnomalyStore for FileAnomalyStore {
+    fn record(&self, anomaly: &ScanAnomaly) -> io::Result<()> {
+        let mut line = serde_json::to_vec(anomaly)?;
+        line.push(b'\n');
+
+        let mut until_compaction = self.lock.lock().map_err(|_| poisoned())?;
+        if *until_compaction == 0 {
+            if let Err(e) = self.compact() {
+                rest_warn!(
+                    "(FileAnomalyStore::record) could not remove expired anomalies: {}",
+                    e
+                );
+            }
+            *until_compaction = ANOMALIES_BETWEEN_COMPACTIONS;
+        }
+        *until_compaction -= 1;
+
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&line)
+    }
+
+    fn list(&self, filter: &AnomalyFilter) -> io::Result<Vec<ScanAnomaly>> {
+        let anomalies = {
+            let _lock = self.lock.lock().map_err(|_| poisoned())?;
+            self.read()?
+        };
+
+        // Expired anomalies may remain in the file until the next compaction
+        let expiry = Utc::now() - self.retention;
+        Ok(filter.newest(
+            anomalies
+                .into_iter()
+                .filter(|anomaly| anomaly.detected_at >= expiry),
+        ))
+    }
+}
+
+fn poisoned() -> io::Error {
+    io::Error::other("anomaly store lock poisoned")
+}
+
+/// A recorded scan, to be compared with the route of its parcel
+#[derive(Debug, Clone)]
+pub struct RecordedScan {
+    /// The unique ID (UUID) of the scanner device
+    pub scanner_id: String,
+
+    /// The unique ID (UUID) of the parcel
+    pub parcel_id: String,
+
+    /// The latitude of the scan location
+    pub latitude: f64,
+
+    /// The longitude of the scan location
+    pub longitude: f64,
+
+    /// When the scan was captured
+    pub scanned_at: DateTime<Utc>,
+}
+
+/// Compares recorded scans with the routes of their parcels
+#[derive(Debug, Clone)]
+pub struct ScanMonitor {
+    store: Arc<dyn AnomalyStore>,
+    http: reqwest::Client,
+    notifier: Option<Notifier>,
+}
+
+impl ScanMonitor {
+    /// Keeps anomalies in the configured file, or in memory
+    pub fn new(config: &Config) -> Self {
+        let store: Arc<dyn AnomalyStore> = match config.rest_scan_anomaly_file.as_str() {
+            "" => Arc::new(MemoryAnomalyStore::default()),
+            path => Arc::new(FileAnomalyStore::new(
+                path,
+                chrono::Duration::days(config.rest_scan_anomaly_retention_days.into()),
+            )),
+        };
+
+        Self::with_store(store)
+    }
+
+    /// Keeps anomalies in the given store
+    pub fn with_store(store: Arc<dyn AnomalyStore>) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        ScanMonitor {
+            store,
+            http,
+            notifier: None,
+        }
+    }
+
+    /// Notifies recipients when their parcel is scanned at its destination
+    pub fn notify_arrivals(mut self, notifier: Notifier) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    /// The store anomalies are kept in
+    pub fn store(&self) -> &dyn AnomalyStore {
+        self.store.as_ref()
+    }
+
+    /// Checks recorded scans in the background
+    pub fn inspect(&self, grpc_clients: GrpcClients, config: Config, scans: Vec<RecordedScan>) {
+        if scans.is_empty() {
+            return;
+        }
+
+        let monitor = self.clone();
+        tokio::spawn(async move { monitor.check_scans(&grpc_clients, &config, scans).await });
+    }
+
+    /// Checks scans, requesting the route of each parcel once
+    ///
+    /// Scans of parcels whose route can't be requested aren't checked.
+    async fn check_scans(
+        &self,
+        grpc_clients: &GrpcClients,
+        config: &Config,
+        scans: Vec<RecordedScan>,
+    ) {
+        let tolerance = Tolerance {
+            vertiport_margin_meters: config.rest_scan_vertiport_margin_meters.into(),
+            corridor_meters: config.rest_scan_corridor_meters.into(),
+        };
+
+        let mut routes: HashMap<String, Option<ParcelRoute>> = HashMap::new();
+        for scan in scans {
+            if !routes.contains_key(&scan.parcel_id) {
+                let route = match get_parcel_route(&scan.parcel_id, grpc_clients).await {
+                    Ok(route) => route,
+                    Err(status) => {
+                        rest_warn!(
+                            "(ScanMonitor::check_scans) no route for parcel {} ({}), scans not checked.",
+                            scan.parcel_id,
+                            status
+                        );
+                        None
+                    }
+                };
+                routes.insert(scan.parcel_id.clone(), route);
+            }
+
+            let Some(Some(route)) = routes.get(&scan.parcel_id) else {
+                continue;
+            };
+
+            if let Some(anomaly) = detect(route, &scan, &tolerance, Utc::now()) {
+                self.report(anomaly, &config.rest_scan_anomaly_webhook_url)
+                    .await;
+            }
+
+            if let Some(notifier) = &self.notifier {
+                if route.is_arrival(scan.latitude, scan.longitude, scan.scanned_at, &tolerance) {
+                    notifier
+                        .dispatch(&scan.parcel_id, ParcelEvent::Arrived)
+                        .await;
+                }
+            }
+        }
+    }
+
+    /// Keeps an anomaly and posts it to the webhook, if any
+    async fn report(&self, anomaly: ScanAnomaly, webhook_url: &str) {
+        rest_warn!(
+            "(ScanMonitor::report) parcel {} scanned {:.0}m away from where expected ({:?}).",
+            anomaly.parcel_id,
+            anomaly.distance_meters,
+            anomaly.kind
+        );
+
+        if let Err(e) = self.store.record(&anomaly) {
+            rest_error!("(ScanMonitor::report) could not store anomaly: {}", e);
+        }
+
+        if webhook_url.is_empty() {
+            return;
+        }
+
+        let result = self
+            .http
+            .post(webhook_url)
+            .json(&anomaly)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(_) => rest_info!("(ScanMonitor::report) anomaly {} posted.", anomaly.id),
+            Err(e) => rest_error!(
+                "(ScanMonitor::report) could not post anomaly {}: {}",
+                anomaly.id,
+                e
+            ),
+        }
+    }
+}
+
+/// The anomaly of a scan away from the route of its parcel, if it is
+fn detect(
+    route: &ParcelRoute,
+    scan: &RecordedScan,
+    tolerance: &Tolerance,
+    current_time: DateTime<Utc>,
+) -> Option<ScanAnomaly> {
+    let deviation = route.check(scan.latitude, scan.longitude, scan.scanned_at, tolerance)?;
+
+    Some(ScanAnomaly {
+        id: uuid::Uuid::new_v4().to_string(),
+        parcel_id: scan.parcel_id.clone(),
+        scanner_id: scan.scanner_id.clone(),
+        latitude: scan.latitude,
+        longitude: scan.longitude,
+        scanned_at: scan.scanned_at,
+        detected_at: current_time,
+        kind: deviation.kind,
+        vertiport_id: deviation.vertiport_id,
+        distance_meters: deviation.distance_meters,
+    })
+}
+
+/// Scans recorded away from where their parcel was expected
+///
+/// Scans are compared with the itinerary of their parcel after they are
+///  recorded: before departure they are expected at the origin vertiport,
+///  during the flights along the flight paths, and after arrival at the
+///  destination vertiport. Requires the admin API key.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/cargo/scan/anomalies",
+    tag = "svc-cargo",
+    params(ScanAnomaliesParams),
+    responses(
+        (status = 200, description = "Anomalies retrieved successfully", body = ScanAnomaliesResponse),
+        (status = 400, description = "Request parameters are invalid", body = String),
+        (status = 401, description = "Admin API key missing or invalid", body = String),
+        (status = 403, description = "No admin API key is configured", body = String),
+        (status = 500, description = "Anomalies could not be read", body = String)
+    )
+))]
+pub async fn query_anomalies(
+    Extension(scan_monitor): Extension<ScanMonitor>,
+    Query(params): Query<ScanAnomaliesParams>,
+) -> Result<Json<ScanAnomaliesResponse>, (StatusCode, String)> {
+    rest_debug!("(query_anomalies) entry.");
+
+    if let Some(parcel_id) = &params.parcel_id {
+        if !is_uuid(parcel_id) {
+            let error_msg = "parcel ID not in UUID format.".to_string();
+            rest_error!("(query_anomalies) {}", &error_msg);
+            return Err((StatusCode::BAD_REQUEST, error_msg));
+        }
+    }
+
+    let limit = params.limit.unwrap_or(MAX_SCAN_ANOMALIES_TO_RETURN);
+    if limit == 0 || limit > MAX_SCAN_ANOMALIES_TO_RETURN {
+        let error_msg = format!("limit must be 1 to {MAX_SCAN_ANOMALIES_TO_RETURN}.");
+        rest_error!("(query_anomalies) {}", &error_msg);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
+    }
+
+    let filter = AnomalyFilter {
+        parcel_id: params.parcel_id,
+        since: params.since,
+        limit: limit as usize,
+    };
+    match scan_monitor.store().list(&filter) {
+        Ok(anomalies) => Ok(Json(ScanAnomaliesResponse { anomalies })),
+        Err(e) => {
+            let error_msg = "could not read anomalies.".to_string();
+            rest_error!("(query_anomalies) {} {}", &error_msg, e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, error_msg))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::rest_types::ScanAnomalyKind;
+    use super::*;
+    use chrono::Duration;
+
+    fn mock_anomaly(parcel_id: &str, detected_at: DateTime<Utc>) -> ScanAnomaly {
+        ScanAnomaly {
+            id: uuid::Uuid::new_v4().to_string(),
+            parcel_id: parcel_id.to_string(),
+            scanner_id: "8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1".to_string(),
+            latitude: 51.92,
+            longitude: 4.48,
+            scanned_at: detected_at,
+            detected_at,
+            kind: ScanAnomalyKind::OffCorridor,
+            vertiport_id: None,
+            distance_meters: 35_000.0,
+        }
+    }
+
+    fn check_store(store: &dyn AnomalyStore) {
+        let now = Utc::now();
+        let first = mock_anomaly("parcel-1", now - Duration::hours(2));
+        let second = mock_anomaly("parcel-2", now - Duration::hours(1));
+        let third = mock_anomaly("parcel-1", now);
+        for anomaly in [&first, &second, &third] {
+            store.record(anomaly).unwrap();
+        }
+
+        let filter = |parcel_id: Option<&str>, since, limit| AnomalyFilter {
+            parcel_id: parcel_id.map(str::to_string),
+            since,
+            limit,
+        };
+        assert_eq!(
+            store.list(&filter(None, None, 10)).unwrap(),
+            vec![third.clone(), second.clone(), first.clone()]
+        );
+        assert_eq!(
+            store.list(&filter(Some("parcel-1"), None, 10)).unwrap(),
+            vec![third.clone(), first]
+        );
+        assert_eq!(
+            store
+                .list(&filter(None, Some(now - Duration::hours(1)), 10))
+                .unwrap(),
+            vec![third.clone(), second]
+        );
+        assert_eq!(store.list(&filter(None, None, 1)).unwrap(), vec![third]);
+    }
+
+    #[test]
+    fn ut_memory_anomaly_store() {
+        check_store(&MemoryAnomalyStore::default());
+    }
+
+    #[test]
+    fn ut_file_anomaly_store() {
+        let path = std::env::temp_dir().join(format!(
+            "svc-cargo-anomalies-{}.jsonl",
+            uuid::Uuid::new_v4()
+        ));
+        let store = FileAnomalyStore::new(&path, Duration::days(30));
+        assert!(store.list(&AnomalyFilter::default()).unwrap().is_empty());
+        check_store(&store);
+
+        // Anomalies are read back from the file, skipping invalid lines
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(b"not json\n").unwrap();
+        let filter = AnomalyFilter {
+            limit: 10,
+            ..Default::default()
+        };
+        assert_eq!(
+            FileAnomalyStore::new(&path, Duration::days(30))
+                .list(&filter)
+                .unwrap()
+                .len(),
+            3
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn ut_file_anomaly_store_retention() {
+        let path = std::env::temp_dir().join(format!(
+            "svc-cargo-anomalies-{}.jsonl",
+            uuid::Uuid::new_v4()
+        ));
+        let now = Utc::now();
+        let expired = mock_anomaly("parcel-1", now - Duration::days(31));
+        let kept = mock_anomaly("parcel-2", now - Duration::days(29));
+        let store = FileAnomalyStore::new(&path, Duration::days(60));
+        store.record(&expired).unwrap();
+        store.record(&kept).unwrap();
+
+        // Expired anomalies aren't listed and are removed on the first write
+        let filter = AnomalyFilter {
+            limit: 10,
+            ..Default::default()
+        };
+        let store = FileAnomalyStore::new(&path, Duration::days(30));
+        assert_eq!(store.list(&filter).unwrap(), vec![kept.clone()]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        let latest = mock_anomaly("parcel-1", now);
+        store.record(&latest).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+        assert_eq!(store.list(&filter).unwrap(), vec![latest, kept]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn ut_report_anomaly() {
+        use axum::{routing, Router};
+
+        // Webhook receiving the anomalies
+        let received: Arc<Mutex<Vec<ScanAnomaly>>> = Arc::default();
+        let webhook = Router::new().route(
+            "/anomalies",
+            routing::post({
+                let received = received.clone();
+                move |Json(anomaly): Json<ScanAnomaly>| async move {
+                    received.lock().unwrap().push(anomaly);
+                }
+            }),
+        );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(webhook.into_make_service()),
+        );
+
+        let monitor = ScanMonitor::with_store(Arc::new(MemoryAnomalyStore::default()));
+        let anomaly = mock_anomaly("parcel-1", Utc::now());
+        monitor
+            .report(
+                anomaly.clone(),
+                &format!("http://127.0.0.1:{port}/anomalies"),
+            )
+            .await;
+        assert_eq!(*received.lock().unwrap(), vec![anomaly.clone()]);
+
+        // Anomalies are kept even if the webhook fails
+        monitor
+            .report(anomaly, &format!("http://127.0.0.1:{port}/missing"))
+            .await;
+        let filter = AnomalyFilter {
+            limit: 10,
+            ..Default::default()
+        };
+        assert_eq!(monitor.store().list(&filter).unwrap().len(), 2);
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn ut_query_anomalies() {
+        use axum::{body::Body, http::Request, routing, Router};
+        use tower::ServiceExt;
+
+        let parcel_id = "cabcdd14-03ab-4ac0-b58c-dd4175bc587e";
+        let monitor = ScanMonitor::with_store(Arc::new(MemoryAnomalyStore::default()));
+        let now = Utc::now();
+        monitor
+            .store()
+            .record(&mock_anomaly(parcel_id, now))
+            .unwrap();
+        monitor
+            .store()
+            .record(&mock_anomaly("59e51ad1-d57d-4d2c-bc2d-e2387367d17f", now))
+            .unwrap();
+
+        let app = Router::new()
+            .route("/cargo/scan/anomalies", routing::get(query_anomalies))
+            .layer(Extension(monitor));
+        let get = |uri: String| Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(get(format!("/cargo/scan/anomalies?parcel_id={parcel_id}")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let response: ScanAnomaliesResponse = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(response.anomalies.len(), 1);
+        assert_eq!(response.anomalies[0].parcel_id, parcel_id);
+
+        for uri in [
+            "/cargo/scan/anomalies?parcel_id=parcel-1",
+            "/cargo/scan/anomalies?limit=0",
+            "/cargo/scan/anomalies?limit=101",
+        ] {
+            let response = app.clone().oneshot(get(uri.to_string())).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+        }
+    }
+
+    #[test]
+    fn ut_detect() {
+        use geo::{LineString, Polygon};
+
+        let now = Utc::now();
+        let square = |latitude: f64, longitude: f64| {
+            let d = 0.001;
+            Polygon::new(
+                LineString::from(vec![
+                    (longitude - d, latitude - d),
+                    (longitude + d, latitude - d),
+                    (longitude + d, latitude + d),
+                    (longitude - d, latitude + d),
+                ]),
+                vec![],
+            )
+        };
+        let route = ParcelRoute {
+            origin_vertiport_id: "origin".to_string(),
+            origin: square(52.37, 4.90),
+            destination_vertiport_id: "destination".to_string(),
+            destination: square(52.09, 5.12),
+            departure: now + Duration::hours(1),
+            arrival: now + Duration::hours(2),
+            paths: vec![],
+        };
+        let tolerance = Tolerance {
+            vertiport_margin_meters: 100.0,
+            corridor_meters: 2000.0,
+        };
+        let scan = RecordedScan {
+            scanner_id: "scanner".to_string(),
+            parcel_id: "parcel".to_string(),
+            latitude: 52.37,
+            longitude: 4.90,
+            scanned_at: now,
+        };
+        assert_eq!(detect(&route, &scan, &tolerance, now), None);
+
+        let scan = RecordedScan {
+            latitude: 51.92,
+            longitude: 4.48,
+            ..scan
+        };
+        let anomaly = detect(&route, &scan, &tolerance, now).unwrap();
+        assert_eq!(anomaly.kind, ScanAnomalyKind::NotAtOrigin);
+        assert_eq!(anomaly.parcel_id, "parcel");
+        assert_eq!(anomaly.vertiport_id.as_deref(), Some("origin"));
+        assert_eq!(anomaly.detected_at, now);
+        assert!(is_uuid(&anomaly.id));
+    }
+}
@@ -0,0 +1,420 @@
+use super::error::status_from_grpc;
+use super::request::{
+    price_itinerary, unpack_itineraries, MAX_CARGO_WEIGHT_G, MAX_ITINERARY_DURATION_HOURS,
+};
+use super::rest_types::{AvailabilityBucket, AvailabilityQuery, AvailabilityResponse, Itinerary};
+use super::utils::is_uuid;
+use crate::grpc::client::{traced_request, GrpcClients};
+use axum::{
+    extract::{Extension, Query},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use futures::stream::{self, StreamExt};
+use hyper::StatusCode;
+use lib_common::grpc::Client;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use svc_scheduler_client_grpc::prelude::*;
+
+/// Bucket width used when the client doesn't specify one
+const DEFAULT_BUCKET_MINUTES: u32 = 60;
+
+/// Narrowest allowed bucket
+const MIN_BUCKET_MINUTES: u32 = 15;
+
+/// Widest allowed bucket (one day)
+const MAX_BUCKET_MINUTES: u32 = 1440;
+
+/// Don't allow excessively long calendars, one svc-scheduler query is made per bucket
+const MAX_AVAILABILITY_BUCKETS: i64 = 168;
+
+/// Number of svc-scheduler queries in flight at once for a single calendar
+const MAX_CONCURRENT_SCHEDULER_QUERIES: usize = 4;
+
+/// How long a computed bucket is reused before svc-scheduler is queried again
+const AVAILABILITY_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Identifies a single bucket of a calendar
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct AvailabilityKey {
+    vertiport_depart_id: String,
+    vertiport_arrive_id: String,
+    timestamp_start: DateTime<Utc>,
+    timestamp_end: DateTime<Utc>,
+    weight_g: u32,
+}
+
+/// Short-lived cache of computed availability buckets
+///
+/// Shared between requests so that overlapping calendars and repeated
+///  page loads don't each fan out to svc-scheduler and svc-pricing.
+#[derive(Debug, Clone, Default)]
+pub struct AvailabilityCache {
+    entries: Arc<Mutex<HashMap<AvailabilityKey, (Instant, AvailabilityBucket)>>>,
+}
+
+impl AvailabilityCache {
+    /// Returns the cached bucket if it hasn't expired
+    fn get(&self, key: &AvailabilityKey, now: Instant) -> Option<AvailabilityBucket> {
+        let Ok(entries) = self.entries.lock() else {
+            rest_warn!("(AvailabilityCache::get) cache lock poisoned.");
+            return None;
+        };
+
+        let (inserted, bucket) = entries.get(key)?;
+        if now.duration_since(*inserted) >= AVAILABILITY_CACHE_TTL {
+            return None;
+        }
+
+        Some(bucket.clone())
+    }
+
+    /// Stores a bucket, discarding any expired entries
+    fn insert(&self, key: AvailabilityKey, bucket: AvailabilityBucket, now: Instant) {
+        let Ok(mut entries) = self.entries.lock() else {
+            rest_warn!("(AvailabilityCache::insert) cache lock poisoned.");
+            return;
+        };
+
+        entries.retain(|_, (inserted, _)| now.duration_since(*inserted) < AVAILABILITY_CACHE_TTL);
+        entries.insert(key, (now, bucket));
+    }
+}
+
+/// Splits the range into consecutive buckets of the given width
+/// The last bucket is shortened to end with the range
+fn availability_buckets(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    bucket_width: Duration,
+) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+    let mut buckets = vec![];
+    let mut start = from;
+    while start < to {
+        let end = std::cmp::min(start + bucket_width, to);
+        buckets.push((start, end));
+        start = end;
+    }
+
+    buckets
+}
+
+/// Summarizes the itineraries departing within a bucket
+fn summarize_bucket(
+    timestamp_start: DateTime<Utc>,
+    timestamp_end: DateTime<Utc>,
+    itineraries: &[Itinerary],
+) -> AvailabilityBucket {
+    let departing = itineraries.iter().filter(|itinerary| {
+        itinerary.timestamp_depart >= timestamp_start && itinerary.timestamp_depart < timestamp_end
+    });
+
+    let mut count = 0;
+    let mut lowest: Option<&Itinerary> = None;
+    for itinerary in departing {
+        count += 1;
+
+        let Some(price) = itinerary.base_pricing else {
+            continue;
+        };
+
+        if !lowest
+            .and_then(|lowest| lowest.base_pricing)
+            .is_some_and(|lowest| lowest <= price)
+        {
+            lowest = Some(itinerary);
+        }
+    }
+
+    AvailabilityBucket {
+        timestamp_start,
+        timestamp_end,
+        itineraries: count,
+        lowest_price: lowest.and_then(|itinerary| itinerary.base_pricing),
+        currency_type: lowest.and_then(|itinerary| itinerary.currency_type.clone()),
+    }
+}
+
+/// Queries svc-scheduler and svc-pricing for a single bucket
+async fn query_bucket(
+    mut grpc_clients: GrpcClients,
+    key: AvailabilityKey,
+    cargo_weight_kg: f32,
+    current_time: DateTime<Utc>,
+) -> Result<AvailabilityBucket, (StatusCode, String)> {
+    // Nothing can depart in the past
+    if key.timestamp_end <= current_time {
+        return Ok(summarize_bucket(
+            key.timestamp_start,
+            key.timestamp_end,
+            &[],
+        ));
+    }
+
+    let earliest_departure = std::cmp::max(key.timestamp_start, current_time);
+    let latest_arrival = key.timestamp_end + Duration::hours(MAX_ITINERARY_DURATION_HOURS);
+    let flight_query = scheduler::QueryFlightRequest {
+        is_cargo: true,
+        persons: None,
+        weight_grams: Some(key.weight_g),
+        vertiport_depart_id: key.vertiport_depart_id.clone(),
+        vertiport_arrive_id: key.vertiport_arrive_id.clone(),
+        earliest_departure_time: Some(earliest_departure.into()),
+        latest_arrival_time: Some(latest_arrival.into()),
+    };
+
+    let response = grpc_clients
+        .backends
+        .scheduler
+        .call("query_flight", || async {
+            grpc_clients
+                .scheduler
+                .get_client()
+                .await?
+                .query_flight(traced_request(flight_query))
+                .await
+        })
+        .await;
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            let error_msg = "svc-scheduler error.".to_string();
+            rest_error!("(query_bucket) {} {:?}", &error_msg, e);
+            rest_error!("(query_bucket) invalidating svc-scheduler client.");
+            grpc_clients.scheduler.invalidate().await;
+            return Err((status_from_grpc(&e), error_msg));
+        }
+    };
+
+    let mut offerings = unpack_itineraries(response.into_inner().itineraries);
+    offerings.retain(|itinerary| {
+        itinerary.timestamp_depart >= earliest_departure
+            && itinerary.timestamp_depart < key.timestamp_end
+    });
+
+    for itinerary in &mut offerings {
+        if let Err(e) = price_itinerary(&mut grpc_clients, itinerary, cargo_weight_kg).await {
+            let error_msg = "svc-pricing error.".to_string();
+            rest_error!("(query_bucket) {}", &error_msg);
+            return Err((e, error_msg));
+        }
+    }
+
+    Ok(summarize_bucket(
+        key.timestamp_start,
+        key.timestamp_end,
+        &offerings,
+    ))
+}
+
+/// Get an availability calendar between two vertiports
+///
+/// The range is split into buckets and svc-scheduler is queried for
+///  each bucket, a few at a time. Results are cached briefly.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/cargo/availability",
+    tag = "svc-cargo",
+    params(AvailabilityQuery),
+    responses(
+        (status = 200, description = "Availability calendar", body = AvailabilityResponse),
+        (status = 400, description = "Request parameters are invalid", body = String),
+        (status = 500, description = "svc-scheduler or svc-pricing returned error"),
+        (status = 503, description = "Could not connect to other microservice dependencies"),
+        (status = 504, description = "Microservice dependencies did not respond in time")
+    )
+))]
+pub async fn query_availability(
+    Extension(grpc_clients): Extension<GrpcClients>,
+    Extension(cache): Extension<AvailabilityCache>,
+    Query(query): Query<AvailabilityQuery>,
+) -> Result<Json<AvailabilityResponse>, (StatusCode, String)> {
+    rest_debug!("(query_availability) entry.");
+
+    //
+    // Validate Request
+    //
+    if !is_uuid(&query.depart) {
+        let error_msg = "departure port ID not UUID format.".to_string();
+        rest_error!("(query_availability) {}", &error_msg);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
+    }
+
+    if !is_uuid(&query.arrive) {
+        let error_msg = "arrival port ID not UUID format.".to_string();
+        rest_error!("(query_availability) {}", &error_msg);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
+    }
+
+    let cargo_weight_kg = query.cargo_weight_kg.unwrap_or(0.0);
+    if cargo_weight_kg < 0.0 {
+        let error_msg = "cargo weight is negative.".to_string();
+        rest_error!("(query_availability) {}", &error_msg);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
+    }
+
+    let weight_g: u32 = (cargo_weight_kg * 1000.0) as u32;
+    if weight_g >= MAX_CARGO_WEIGHT_G {
+        let error_msg = format!("request cargo weight exceeds {MAX_CARGO_WEIGHT_G}.");
+        rest_error!("(query_availability) {}", &error_msg);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
+    }
+
+    let bucket_minutes = query.bucket_minutes.unwrap_or(DEFAULT_BUCKET_MINUTES);
+    if !(MIN_BUCKET_MINUTES..=MAX_BUCKET_MINUTES).contains(&bucket_minutes) {
+        let error_msg = format!(
+            "bucket minutes must be between {MIN_BUCKET_MINUTES} and {MAX_BUCKET_MINUTES}."
+        );
+        rest_error!("(query_availability) {}", &error_msg);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
+    }
+
+    if query.from >= query.to {
+        let error_msg = "calendar range does not start before it ends.".to_string();
+        rest_error!("(query_availability) {}", &error_msg);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
+    }
+
+    let current_time = Utc::now();
+    if query.to <= current_time {
+        let error_msg = "calendar range ends in the past.".to_string();
+        rest_error!("(query_availability) {}", &error_msg);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
+    }
+
+    let bucket_width = Duration::minutes(bucket_minutes as i64);
+    if query.to - query.from > bucket_width * MAX_AVAILABILITY_BUCKETS as i32 {
+        let error_msg =
+            format!("calendar range exceeds {MAX_AVAILABILITY_BUCKETS} buckets of {bucket_minutes} minutes.");
+        rest_error!("(query_availability) {}", &error_msg);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
+    }
+
+    //
+    // Query each bucket, reusing recent results
+    //
+    let buckets = availability_buckets(query.from, query.to, bucket_width);
+    let results: Vec<Result<AvailabilityBucket, (StatusCode, String)>> = stream::iter(buckets)
+        .map(|(timestamp_start, timestamp_end)| {
+            let grpc_clients = grpc_clients.clone();
+            let cache = cache.clone();
+            let key = AvailabilityKey {
+                vertiport_depart_id: query.depart.clone(),
+                vertiport_arrive_id: query.arrive.clone(),
+                timestamp_start,
+                timestamp_end,
+                weight_g,
+            };
+
+            async move {
+                if let Some(bucket) = cache.get(&key, Instant::now()) {
+                    return Ok(bucket);
+                }
+
+                let bucket =
+                    query_bucket(grpc_clients, key.clone(), cargo_weight_kg, current_time).await?;
+                cache.insert(key, bucket.clone(), Instant::now());
+                Ok(bucket)
+            }
+        })
+        .buffered(MAX_CONCURRENT_SCHEDULER_QUERIES)
+        .collect()
+        .await;
+
+    let buckets = results.into_iter().collect::<Result<Vec<_>, _>>()?;
+    let reachable = buckets.iter().any(|bucket| bucket.itineraries > 0);
+
+    rest_debug!("(query_availability) exit with {} buckets.", buckets.len());
+    Ok(Json(AvailabilityResponse { reachable, buckets }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock_itinerary(timestamp_depart: DateTime<Utc>, base_pricing: Option<f32>) -> Itinerary {
+        Itinerary {
+            id: uuid::Uuid::new_v4().to_string(),
+            legs: vec![],
+            currency_type: base_pricing.map(|_| "usd".to_string()),
+            base_pricing,
+            timestamp_depart,
+            timestamp_arrive: timestamp_depart + Duration::minutes(30),
+            total_duration_seconds: 1800,
+            total_distance_meters: 5000.0,
+            num_legs: 1,
+        }
+    }
+
+    fn mock_key() -> AvailabilityKey {
+        let now = Utc::now();
+        AvailabilityKey {
+            vertiport_depart_id: uuid::Uuid::new_v4().to_string(),
+            vertiport_arrive_id: uuid::Uuid::new_v4().to_string(),
+            timestamp_start: now,
+            timestamp_end: now + Duration::hours(1),
+            weight_g: 0,
+        }
+    }
+
+    #[test]
+    fn ut_availability_buckets() {
+        let from = Utc::now();
+        let buckets = availability_buckets(from, from + Duration::minutes(150), Duration::hours(1));
+
+        assert_eq!(buckets.len(), 3);
+        assert_eq!(buckets[0], (from, from + Duration::hours(1)));
+        assert_eq!(
+            buckets[2],
+            (from + Duration::hours(2), from + Duration::minutes(150))
+        );
+
+        assert!(availability_buckets(from, from, Duration::hours(1)).is_empty());
+    }
+
+    #[test]
+    fn ut_summarize_bucket() {
+        let start = Utc::now();
+        let end = start + Duration::hours(1);
+        let itineraries = vec![
+            mock_itinerary(start, Some(30.0)),
+            mock_itinerary(start + Duration::minutes(10), None),
+            mock_itinerary(start + Duration::minutes(20), Some(10.0)),
+            // Departs at the end of the bucket, belongs to the next one
+            mock_itinerary(end, Some(1.0)),
+        ];
+
+        let bucket = summarize_bucket(start, end, &itineraries);
+        assert_eq!(bucket.itineraries, 3);
+        assert_eq!(bucket.lowest_price, Some(10.0));
+        assert_eq!(bucket.currency_type, Some("usd".to_string()));
+
+        let bucket = summarize_bucket(start, end, &[]);
+        assert_eq!(bucket.itineraries, 0);
+        assert_eq!(bucket.lowest_price, None);
+        assert_eq!(bucket.currency_type, None);
+    }
+
+    #[test]
+    fn ut_availability_cache_ttl() {
+        let cache = AvailabilityCache::default();
+        let key = mock_key();
+        let bucket = summarize_bucket(key.timestamp_start, key.timestamp_end, &[]);
+
+        let now = Instant::now();
+        assert!(cache.get(&key, now).is_none());
+
+        cache.insert(key.clone(), bucket.clone(), now);
+        assert_eq!(cache.get(&key, now), Some(bucket.clone()));
+        assert!(cache.get(&mock_key(), now).is_none());
+
+        let expired = now + AVAILABILITY_CACHE_TTL;
+        assert!(cache.get(&key, expired).is_none());
+
+        // Expired entries are dropped on the next insert
+        cache.insert(mock_key(), bucket, expired);
+        assert_eq!(cache.entries.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,77 @@
+//! Parcel barcodes
+//!
+//! Labels encode the parcel ID as `ARW` followed by the 32 hexadecimal
+//!  digits of the UUID, which fits Code 128 and QR codes alike.
+
+use uuid::Uuid;
+
+/// Prefix of parcel barcodes
+pub const BARCODE_PREFIX: &str = "ARW";
+
+/// Barcode of a parcel, None if the parcel ID isn't a UUID
+pub fn parcel_barcode(parcel_id: &str) -> Option<String> {
+    let parcel_id = Uuid::parse_str(parcel_id).ok()?;
+    Some(format!(
+        "{}{}",
+        BARCODE_PREFIX,
+        parcel_id.simple().to_string().to_uppercase()
+    ))
+}
+
+/// Parcel ID of a barcode, None if it isn't a parcel barcode
+///
+/// Scanners may report the barcode in lower case.
+pub fn parse_parcel_barcode(barcode: &str) -> Option<String> {
+    let barcode = barcode.trim();
+    let digits = barcode
+        .get(..BARCODE_PREFIX.len())
+        .filter(|prefix| prefix.eq_ignore_ascii_case(BARCODE_PREFIX))
+        .and_then(|_| barcode.get(BARCODE_PREFIX.len()..))?;
+    if digits.len() != 32 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    Uuid::parse_str(digits)
+        .ok()
+        .map(|parcel_id| parcel_id.hyphenated().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ut_parcel_barcode() {
+        let parcel_id = "cabcdd14-03ab-4ac0-b58c-dd4175bc587e";
+        let barcode = parcel_barcode(parcel_id).unwrap();
+        assert_eq!(barcode, "ARWCABCDD1403AB4AC0B58CDD4175BC587E");
+        assert_eq!(parse_parcel_barcode(&barcode).as_deref(), Some(parcel_id));
+        assert_eq!(parcel_barcode("not-a-uuid"), None);
+    }
+
+    #[test]
+    fn ut_parse_parcel_barcode() {
+        let parcel_id = "cabcdd14-03ab-4ac0-b58c-dd4175bc587e";
+        let barcode = "ARWCABCDD1403AB4AC0B58CDD4175BC587E";
+        assert_eq!(parse_parcel_barcode(barcode).as_deref(), Some(parcel_id));
+        assert_eq!(
+            parse_parcel_barcode(&barcode.to_lowercase()).as_deref(),
+            Some(parcel_id)
+        );
+
+        assert_eq!(parse_parcel_barcode(parcel_id), None);
+        assert_eq!(parse_parcel_barcode("ARW"), None);
+        assert_eq!(
+            parse_parcel_barcode("XYZCABCDD1403AB4AC0B58CDD4175BC587E"),
+            None
+        );
+        assert_eq!(
+            parse_parcel_barcode("ARWCABCDD1403AB4AC0B58CDD4175BC587"),
+            None
+        );
+        assert_eq!(
+            parse_parcel_barcode("ARW-ABCDD1403AB4AC0B58CDD4175BC587E"),
+            None
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! Binary objects kept by svc-cargo, such as proofs of delivery
+//!
+//! Objects are addressed by keys of path segments separated by `/`, e.g.
+//!  `<parcel id>/signature.png`. Stores only see keys built by svc-cargo,
+//!  they still reject keys that could escape their directory.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Keeps binary objects
+pub trait BlobStore: Send + Sync + fmt::Debug {
+    /// The object with the given key, if any
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+
+    /// Adds an object, or replaces the object with the same key
+    fn put(&self, key: &str, contents: &[u8]) -> io::Result<()>;
+
+    /// Adds an object unless the key is taken, in one step; false if it was
+    fn put_new(&self, key: &str, contents: &[u8]) -> io::Result<bool>;
+
+    /// Removes the object with the given key, if any
+    fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+/// Returns an error unless the key is made of plain file names
+fn check_key(key: &str) -> io::Result<()> {
+    let valid = !key.is_empty()
+        && key.split('/').all(|segment| {
+            !segment.is_empty()
+                && !segment.starts_with('.')
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        });
+
+    if valid {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid blob key {key:?}"),
+        ))
+    }
+}
+
+fn poisoned() -> io::Error {
+    io::Error::other("blob store lock poisoned")
+}
+
+/// Keeps objects until the service stops
+#[derive(Debug, Default)]
+pub struct MemoryBlobStore {
+    blobs: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl BlobStore for MemoryBlobStore {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        check_key(key)?;
+        let blobs = self.blobs.lock().map_err(|_| poisoned())?;
+        Ok(blobs.get(key).cloned())
+    }
+
+    fn put(&self, key: &str, contents: &[u8]) -> io::Result<()> {
+        check_key(key)?;
+        let mut blobs = self.blobs.lock().map_err(|_| poisoned())?;
+        blobs.insert(key.to_string(), contents.to_vec());
+        Ok(())
+    }
+
+    fn put_new(&self, key: &str, contents: &[u8]) -> io::Result<bool> {
+        check_key(key)?;
+        let mut blobs = self.blobs.lock().map_err(|_| poisoned())?;
+        if blobs.contains_key(key) {
+            return Ok(false);
+        }
+
+        blobs.insert(key.to_string(), contents.to_vec());
+        Ok(true)
+    }
+
+    fn delete(&self, key: &str) -> io::Result<()> {
+        check_key(key)?;
+        let mut blobs = self.blobs.lock().map_err(|_| poisoned())?;
+        blobs.remove(key);
+        Ok(())
+    }
+}
+
+/// Keeps each object in a file under a directory, the key is its path
+#[derive(Debug)]
+pub struct FileBlobStore {
+    directory: PathBuf,
+}
+
+impl FileBlobStore {
+    /// Stores objects under the given directory, created with the first object
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        FileBlobStore {
+            directory: directory.into(),
+        }
+    }
+
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        check_key(key)?;
+        Ok(key
+            .split('/')
+            .fold(self.directory.clone(), |path, segment| path.join(segment)))
+    }
+
+    /// Writes the contents to a new temporary file next to the object
+    fn write_temporary(path: &Path, contents: &[u8]) -> io::Result<PathBuf> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // Named uniquely, concurrent writes of the same object don't share it
+        let mut temporary = path.to_path_buf().into_os_string();
+        temporary.push(format!(".{}.tmp", uuid::Uuid::new_v4()));
+        let temporary = PathBuf::from(temporary);
+        let mut file = std::fs::File::create(&temporary)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        Ok(temporary)
+    }
+}
+
+impl BlobStore for FileBlobStore {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match std::fs::read(self.path(key)?) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes a temporary file first, so objects are never left half written
+    fn put(&self, key: &str, contents: &[u8]) -> io::Result<()> {
+        let path = self.path(key)?;
+        let temporary = Self::write_temporary(&path, contents)?;
+        std::fs::rename(&temporary, &path)
+    }
+
+    /// Links a temporary file to the object, which fails if the object exists
+    fn put_new(&self, key: &str, contents: &[u8]) -> io::Result<bool> {
+        let path = self.path(key)?;
+        let temporary = Self::write_temporary(&path, contents)?;
+        let linked = std::fs::hard_link(&temporary, &path);
+        std::fs::remove_file(&temporary)?;
+        match linked {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn delete(&self, key: &str) -> io::Result<()> {
+        match std::fs::remove_file(self.path(key)?) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_store(store: &dyn BlobStore) {
+        let key = "cabcdd14-03ab-4ac0-b58c-dd4175bc587e/signature.png";
+        assert_eq!(store.get(key).unwrap(), None);
+
+        store.put(key, b"first").unwrap();
+        assert_eq!(store.get(key).unwrap().as_deref(), Some(&b"first"[..]));
+
+        store.put(key, b"second").unwrap();
+        assert_eq!(store.get(key).unwrap().as_deref(), Some(&b"second"[..]));
+
+        // Objects are only added once
+        let record = "cabcdd14-03ab-4ac0-b58c-dd4175bc587e/delivery.json";
+        assert!(store.put_new(record, b"first").unwrap());
+        assert!(!store.put_new(record, b"second").unwrap());
+        assert_eq!(store.get(record).unwrap().as_deref(), Some(&b"first"[..]));
+
+        store.delete(record).unwrap();
+        assert_eq!(store.get(record).unwrap(), None);
+        store.delete(record).unwrap();
+        assert!(store.put_new(record, b"third").unwrap());
+
+        for key in ["", "/photo.jpg", "a//b", "../photo.jpg", "a/.hidden", "a b"] {
+            let error = store.put(key, b"").unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{key}");
+        }
+    }
+
+    #[test]
+    fn ut_memory_blob_store() {
+        check_store(&MemoryBlobStore::default());
+    }
+
+    #[test]
+    fn ut_file_blob_store() {
+        let directory =
+            std::env::temp_dir().join(format!("svc-cargo-blobs-{}", uuid::Uuid::new_v4()));
+        check_store(&FileBlobStore::new(&directory));
+
+        // Objects are kept in files named after their key
+        let path = directory
+            .join("cabcdd14-03ab-4ac0-b58c-dd4175bc587e")
+            .join("signature.png");
+        assert_eq!(std::fs::read(path).unwrap(), b"second");
+
+        // Temporary files are removed
+        let files = std::fs::read_dir(directory.join("cabcdd14-03ab-4ac0-b58c-dd4175bc587e"))
+            .unwrap()
+            .count();
+        assert_eq!(files, 2);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,24 +1,26 @@
+use super::error::status_from_grpc;
 use super::rest_types::ItineraryCancel;
 use super::utils::is_uuid;
-use crate::grpc::client::GrpcClients;
+use crate::grpc::client::{traced_request, GrpcClients};
 use axum::{extract::Extension, Json};
 use hyper::StatusCode;
-use svc_scheduler_client_grpc::prelude::SchedulerServiceClient;
 use svc_storage_client_grpc::prelude::*;
 
 /// Cancel a Flight
-#[utoipa::path(
+#[cfg_attr(feature = "openapi", utoipa::path(
     delete,
     path = "/cargo/cancel",
     tag = "svc-cargo",
     responses(
         (status = 200, description = "Flight cancelled successfully"),
         (status = 400, description = "Request body is invalid format"),
+        (status = 404, description = "Itinerary not found"),
         (status = 500, description = "svc-scheduler returned error"),
-        (status = 503, description = "Could not connect to other microservice dependencies")
+        (status = 503, description = "Could not connect to other microservice dependencies"),
+        (status = 504, description = "Microservice dependencies did not respond in time")
     ),
     request_body = ItineraryCancel
-)]
+))]
 pub async fn cancel_itinerary(
     Extension(grpc_clients): Extension<GrpcClients>,
     Json(payload): Json<ItineraryCancel>,
@@ -33,9 +35,17 @@ pub async fn cancel_itinerary(
 
     // Make request, process response
     let response = match grpc_clients
+        .backends
         .scheduler
-        .cancel_itinerary(svc_scheduler_client_grpc::client::Id {
-            id: itinerary_id.clone(),
+        .call("cancel_itinerary", || async {
+            grpc_clients
+                .scheduler
+                .get_client()
+                .await?
+                .cancel_itinerary(traced_request(svc_scheduler_client_grpc::client::Id {
+                    id: itinerary_id.clone(),
+                }))
+                .await
         })
         .await
     {
@@ -43,7 +53,7 @@ pub async fn cancel_itinerary(
         Err(e) => {
             let error_msg = "svc-scheduler request fail.".to_string();
             rest_error!("(cancel_itinerary) {} {:?}", &error_msg, e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(status_from_grpc(&e));
         }
     };
 
@@ -61,12 +71,25 @@ pub async fn cancel_itinerary(
     let filter =
         AdvancedSearchFilter::search_equals("itinerary_id".to_string(), itinerary_id.clone());
 
-    let list = match grpc_clients.storage.parcel.search(filter).await {
+    let list = match grpc_clients
+        .backends
+        .storage
+        .call("parcel.search", || async {
+            grpc_clients
+                .storage
+                .parcel
+                .get_client()
+                .await?
+                .search(traced_request(filter))
+                .await
+        })
+        .await
+    {
         Ok(response) => response.into_inner().list,
         Err(e) => {
             let error_msg = "svc-parcel-storage error.".to_string();
             rest_error!("(cancel_itinerary) {} {:?}", &error_msg, e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(status_from_grpc(&e));
         }
     };
 
@@ -75,9 +98,17 @@ pub async fn cancel_itinerary(
     let mut ok = true;
     for parcel in list.into_iter() {
         let _ = grpc_clients
+            .backends
             .storage
-            .parcel
-            .delete(Id { id: parcel.id })
+            .call("parcel.delete", || async {
+                grpc_clients
+                    .storage
+                    .parcel
+                    .get_client()
+                    .await?
+                    .delete(traced_request(Id { id: parcel.id }))
+                    .await
+            })
             .await
             .map_err(|e| {
                 let error_msg = "svc-parcel-storage error.".to_string();
@@ -1,30 +1,42 @@
+use super::error::status_from_grpc;
+use super::notification::{check_recipient, Notifier, ParcelEvent};
+use super::pickup::{new_pickup_code, PickupCodes, PickupState};
 use super::rest_types::{ItineraryConfirm, ItineraryConfirmation};
-use super::utils::is_uuid;
-use crate::grpc::client::GrpcClients;
+use super::shipment::{Shipment, Shipments};
+use super::utils::{is_uuid, random_token};
+use crate::grpc::client::{traced_request, GrpcClients};
 use axum::{extract::Extension, Json};
+use chrono::Utc;
 use hyper::StatusCode;
 use svc_scheduler_client_grpc::client::ConfirmItineraryRequest;
-use svc_scheduler_client_grpc::prelude::SchedulerServiceClient;
 use svc_storage_client_grpc::prelude::*;
 use svc_storage_client_grpc::resources::parcel::{Data as ParcelData, ParcelStatus};
 
 /// Confirm an itinerary
 /// This will confirm an itinerary with the scheduler, and will register the parcel with
-///  the storage service.
-#[utoipa::path(
+///  the storage service. A recipient given with the itinerary is notified of the
+///  parcel, and of its arrival and delivery. The pickup code of the parcel is
+///  only returned in the response.
+#[cfg_attr(feature = "openapi", utoipa::path(
     put,
     path = "/cargo/confirm",
     tag = "svc-cargo",
     request_body = ItineraryConfirm,
     responses(
         (status = 200, description = "Itinerary confirmed", body = String),
-        (status = 400, description = "Request body is invalid format"),
+        (status = 400, description = "Request body is invalid format, or the recipient contact is invalid"),
+        (status = 404, description = "Itinerary not found"),
+        (status = 409, description = "Itinerary or parcel already exists"),
         (status = 500, description = "Microservice dependency returned error"),
-        (status = 503, description = "Could not connect to other microservice dependencies")
+        (status = 503, description = "Could not connect to other microservice dependencies, or could not record the shipment"),
+        (status = 504, description = "Microservice dependencies did not respond in time")
     )
-)]
+))]
 pub async fn confirm_itinerary(
     Extension(grpc_clients): Extension<GrpcClients>,
+    Extension(shipments): Extension<Shipments>,
+    Extension(notifier): Extension<Notifier>,
+    Extension(pickup_codes): Extension<PickupCodes>,
     Json(payload): Json<ItineraryConfirm>,
 ) -> Result<Json<ItineraryConfirmation>, StatusCode> {
     rest_debug!("(confirm_itinerary) entry.");
@@ -35,6 +47,15 @@ pub async fn confirm_itinerary(
         return Err(StatusCode::BAD_REQUEST);
     }
 
+    let recipient = match payload.recipient.as_ref().map(check_recipient) {
+        Some(Err(error_msg)) => {
+            rest_error!("(confirm_itinerary) {}", &error_msg);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        Some(Ok(recipient)) => Some(recipient),
+        None => None,
+    };
+
     //
     // Confirm itinerary with scheduler
     //
@@ -44,12 +65,24 @@ pub async fn confirm_itinerary(
         user_id: payload.user_id.clone(),
     };
 
-    let response = match grpc_clients.scheduler.confirm_itinerary(data).await {
+    let response = match grpc_clients
+        .backends
+        .scheduler
+        .call("confirm_itinerary", || async {
+            grpc_clients
+                .scheduler
+                .get_client()
+                .await?
+                .confirm_itinerary(traced_request(data))
+                .await
+        })
+        .await
+    {
         Ok(response) => response.into_inner(),
         Err(e) => {
             let error_msg = "svc-scheduler error.".to_string();
             rest_error!("(confirm_itinerary) {} {:?}", &error_msg, e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(status_from_grpc(&e));
         }
     };
 
@@ -64,19 +97,32 @@ pub async fn confirm_itinerary(
     //
     let itinerary_id = response.id;
     let data = ParcelData {
-        user_id: payload.user_id,
+        user_id: payload.user_id.clone(),
         weight_grams: payload.weight_grams,
         status: ParcelStatus::Notdroppedoff as i32,
     };
 
     // TODO(R4): Push to queue, in case this call fails need a retry mechanism
     // Make request, process response
-    let response = match grpc_clients.storage.parcel.insert(data).await {
+    let response = match grpc_clients
+        .backends
+        .storage
+        .call("parcel.insert", || async {
+            grpc_clients
+                .storage
+                .parcel
+                .get_client()
+                .await?
+                .insert(traced_request(data))
+                .await
+        })
+        .await
+    {
         Ok(response) => response.into_inner(),
         Err(e) => {
             let error_msg = "svc-parcel-storage error.".to_string();
             rest_error!("(confirm_itinerary) {} {:?}", &error_msg, e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(status_from_grpc(&e));
         }
     };
 
@@ -99,8 +145,143 @@ pub async fn confirm_itinerary(
         return Err(StatusCode::INTERNAL_SERVER_ERROR);
     }
 
+    // Without its shipment, a parcel could be delivered without its pickup code
+    let tracking_token = match random_token() {
+        Ok(token) => token,
+        Err(e) => {
+            let error_msg = "could not generate a tracking token.".to_string();
+            rest_error!("(confirm_itinerary) {} {}", &error_msg, e);
+            return Err(StatusCode::SERVICE_UNAVAILABLE);
+        }
+    };
+
+    let pickup_code = match new_pickup_code() {
+        Ok(code) => code,
+        Err(e) => {
+            let error_msg = "could not generate a pickup code.".to_string();
+            rest_error!("(confirm_itinerary) {} {}", &error_msg, e);
+            return Err(StatusCode::SERVICE_UNAVAILABLE);
+        }
+    };
+    let pickup = PickupState::issue(&pickup_codes, &parcel_id, &pickup_code);
+
+    let shipment = Shipment {
+        parcel_id: parcel_id.clone(),
+        itinerary_id: itinerary_id.clone(),
+        user_id: payload.user_id,
+        weight_grams: payload.weight_grams,
+        confirmed_at: Utc::now(),
+        recipient,
+        tracking_token: Some(tracking_token.clone()),
+        notified: vec![],
+        pickup,
+    };
+    if let Err(e) = shipments.record(shipment).await {
+        let error_msg = "could not record the shipment.".to_string();
+        rest_error!("(confirm_itinerary) {} {}", &error_msg, e);
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    notifier.notify(&parcel_id, ParcelEvent::Confirmed);
+
     Ok(Json(ItineraryConfirmation {
         itinerary_id,
         parcel_id,
+        tracking_token: Some(tracking_token),
+        pickup_code: Some(pickup_code),
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::shipment::{MemoryShipmentStore, ShipmentStore};
+    use super::*;
+    use crate::Config;
+    use std::io;
+    use std::sync::Arc;
+    use svc_scheduler_client_grpc::client::ConfirmItineraryResponse;
+    use svc_storage_client_grpc::resources::parcel;
+
+    /// A shipment store that can't keep anything
+    #[derive(Debug)]
+    struct FailingShipmentStore;
+
+    impl ShipmentStore for FailingShipmentStore {
+        fn get(&self, _parcel_id: &str) -> io::Result<Option<Shipment>> {
+            Ok(None)
+        }
+
+        fn find_by_token(&self, _token: &str) -> io::Result<Option<Shipment>> {
+            Ok(None)
+        }
+
+        fn put(&self, _shipment: Shipment) -> io::Result<()> {
+            Err(io::Error::other("disk full"))
+        }
+    }
+
+    #[tokio::test]
+    async fn ut_confirm_itinerary() {
+        let parcel_id = "cabcdd14-03ab-4ac0-b58c-dd4175bc587e";
+        let config = Config::default();
+        let grpc_clients = GrpcClients::default(config.clone());
+        grpc_clients
+            .backends
+            .scheduler
+            .stub("confirm_itinerary", || {
+                tonic::Response::new(ConfirmItineraryResponse {
+                    id: "8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1".to_string(),
+                    confirmed: true,
+                    confirmation_time: None,
+                })
+            });
+        grpc_clients
+            .backends
+            .storage
+            .stub("parcel.insert", move || {
+                tonic::Response::new(parcel::Response {
+                    validation_result: Some(ValidationResult {
+                        success: true,
+                        errors: vec![],
+                    }),
+                    object: Some(parcel::Object {
+                        id: parcel_id.to_string(),
+                        data: Some(parcel::mock::get_data_obj()),
+                    }),
+                })
+            });
+        let pickup_codes = PickupCodes::with_secret(b"pickup-secret");
+        let payload = ItineraryConfirm {
+            id: "8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1".to_string(),
+            user_id: "b3c1d7a6-3b0b-4f7c-8c6c-4e0d0d2b9f4e".to_string(),
+            weight_grams: 1250,
+            recipient: None,
+        };
+        let confirm = |shipments: Shipments| {
+            confirm_itinerary(
+                Extension(grpc_clients.clone()),
+                Extension(shipments.clone()),
+                Extension(Notifier::new(&config, shipments)),
+                Extension(pickup_codes.clone()),
+                Json(payload.clone()),
+            )
+        };
+
+        // The pickup code and tracking token are returned once the shipment is kept
+        let shipments = Shipments::with_store(Arc::new(MemoryShipmentStore::default()));
+        let Json(confirmation) = confirm(shipments.clone()).await.unwrap();
+        assert_eq!(confirmation.parcel_id, parcel_id);
+        let shipment = shipments.get(parcel_id).unwrap().unwrap();
+        assert_eq!(shipment.tracking_token, confirmation.tracking_token);
+        assert!(pickup_codes.verify(
+            parcel_id,
+            &confirmation.pickup_code.unwrap(),
+            &shipment.pickup.code_digest.unwrap()
+        ));
+
+        // Without a shipment deliveries couldn't be checked, so confirming fails
+        let shipments = Shipments::with_store(Arc::new(FailingShipmentStore));
+        let result = confirm(shipments).await;
+        assert_eq!(result.unwrap_err(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
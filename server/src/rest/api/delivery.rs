@@ -0,0 +1,890 @@
+//! Proof of delivery
+//!
+//! A parcel is delivered when it is handed to its recipient at the
+//!  destination vertiport of its last flight. The recipient gives the
+//!  pickup code of the parcel as PIN code, or signs for parcels without one,
+//!  and a photo of the handover may be taken. Images are kept in a blob
+//!  store next to the delivery record.
+
+use super::blob::{BlobStore, FileBlobStore, MemoryBlobStore};
+use super::geofence::{distance_to_polygon, get_vertiport_polygon};
+use super::notification::{Notifier, ParcelEvent};
+use super::pickup::{
+    check_pickup_code, is_pickup_code, release_pickup_code, PickupCodes, PICKUP_CODE_LENGTH,
+};
+use super::rest_types::{Delivery, DeliveryConfirmation, DeliveryProof};
+use super::scan::{authorize_scanner, is_valid_location};
+use super::scanner::ScannerRegistry;
+use super::shipment::Shipments;
+use super::utils::{get_parcel_details, get_parcel_legs, is_uuid, update_parcel_status};
+use crate::grpc::client::GrpcClients;
+use crate::Config;
+use axum::{
+    extract::{Extension, Path},
+    http::HeaderMap,
+    Json,
+};
+use base64::Engine;
+use chrono::Utc;
+use geo::Coord;
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::Arc;
+use svc_storage_client_grpc::resources::parcel::ParcelStatus;
+use tokio::sync::watch;
+
+/// Don't allow overly long recipient names
+pub const MAX_RECIPIENT_NAME_LENGTH: usize = 100;
+
+/// Don't allow overly large signatures and photos, once decoded
+pub const MAX_DELIVERY_IMAGE_BYTES: usize = 512 * 1024;
+
+/// A signature or photo of a delivery
+#[derive(Debug, Clone, PartialEq)]
+struct DeliveryImage {
+    /// File extension of the image format
+    extension: &'static str,
+
+    /// Contents of the image file
+    contents: Vec<u8>,
+}
+
+impl DeliveryImage {
+    /// Decodes a base64 encoded PNG or JPEG image
+    fn decode(name: &str, encoded: &str) -> Result<Self, String> {
+        let contents = base64::engine::general_purpose::STANDARD
+            .decode(encoded.trim())
+            .map_err(|_| format!("{name} is not base64 encoded."))?;
+
+        if contents.len() > MAX_DELIVERY_IMAGE_BYTES {
+            return Err(format!(
+                "{name} is larger than {MAX_DELIVERY_IMAGE_BYTES} bytes."
+            ));
+        }
+
+        let extension = if contents.starts_with(b"\x89PNG\r\n\x1a\n") {
+            "png"
+        } else if contents.starts_with(&[0xff, 0xd8, 0xff]) {
+            "jpg"
+        } else {
+            return Err(format!("{name} is not a PNG or JPEG image."));
+        };
+
+        Ok(DeliveryImage {
+            extension,
+            contents,
+        })
+    }
+}
+
+/// A proof of delivery that passed its checks
+#[derive(Debug, Clone, PartialEq)]
+struct CheckedProof {
+    recipient_name: String,
+    confirmation: DeliveryConfirmation,
+    signature: Option<DeliveryImage>,
+    pin: Option<String>,
+    photo: Option<DeliveryImage>,
+}
+
+/// Checks the contents of a proof of delivery, returns why it's rejected
+fn check_proof(proof: &DeliveryProof) -> Result<CheckedProof, String> {
+    let recipient_name = proof.recipient_name.trim();
+    if recipient_name.is_empty() || recipient_name.chars().count() > MAX_RECIPIENT_NAME_LENGTH {
+        return Err(format!(
+            "recipient name must have 1 to {MAX_RECIPIENT_NAME_LENGTH} characters."
+        ));
+    }
+
+    let (confirmation, signature, pin) = match (&proof.signature, &proof.pin) {
+        (Some(signature), None) => (
+            DeliveryConfirmation::Signature,
+            Some(DeliveryImage::decode("signature", signature)?),
+            None,
+        ),
+        (None, Some(pin)) => {
+            let pin = pin.trim();
+            if !is_pickup_code(pin) {
+                return Err(format!("PIN code must have {PICKUP_CODE_LENGTH} digits."));
+            }
+            (DeliveryConfirmation::Pin, None, Some(pin.to_string()))
+        }
+        _ => return Err("either a signature or a PIN code is required.".to_string()),
+    };
+
+    let photo = proof
+        .photo
+        .as_deref()
+        .map(|photo| DeliveryImage::decode("photo", photo))
+        .transpose()?;
+
+    if !is_valid_location(proof.latitude, proof.longitude) {
+        return Err("coordinates out of range.".to_string());
+    }
+
+    Ok(CheckedProof {
+        recipient_name: recipient_name.to_string(),
+        confirmation,
+        signature,
+        pin,
+        photo,
+    })
+}
+
+/// A delivery as kept in the blob store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DeliveryRecord {
+    #[serde(flatten)]
+    delivery: Delivery,
+
+    /// The String ID of the scanner of the courier
+    #[serde(default)]
+    scanner_id: String,
+
+    /// Keys of the signature and photo in the blob store
+    images: Vec<String>,
+}
+
+/// Proofs of delivery, shared by the handlers
+#[derive(Debug, Clone)]
+pub struct Deliveries {
+    store: Arc<dyn BlobStore>,
+}
+
+impl Deliveries {
+    /// Keeps proofs of delivery in the configured directory, or in memory
+    pub fn new(config: &Config) -> Self {
+        let store: Arc<dyn BlobStore> = match config.rest_delivery_dir.as_str() {
+            "" => {
+                rest_warn!("(Deliveries::new) proofs of delivery are kept in memory, they are lost on restart.");
+                Arc::new(MemoryBlobStore::default())
+            }
+            directory => Arc::new(FileBlobStore::new(directory)),
+        };
+
+        Self::with_store(store)
+    }
+
+    /// Keeps proofs of delivery in the given store
+    pub fn with_store(store: Arc<dyn BlobStore>) -> Self {
+        Deliveries { store }
+    }
+
+    fn record_key(parcel_id: &str) -> String {
+        format!("{parcel_id}/delivery.json")
+    }
+
+    /// The delivery of the given parcel, if delivered
+    pub fn get(&self, parcel_id: &str) -> io::Result<Option<Delivery>> {
+        let Some(contents) = self.store.get(&Self::record_key(parcel_id))? else {
+            return Ok(None);
+        };
+
+        let record: DeliveryRecord = serde_json::from_slice(&contents)?;
+        Ok(Some(record.delivery))
+    }
+
+    /// Keeps the record of a delivery, then its images; false if the parcel was already delivered
+    ///
+    /// The record is added in one step, so of concurrent deliveries of a
+    ///  parcel only one is kept.
+    fn record(
+        &self,
+        delivery: &Delivery,
+        scanner_id: &str,
+        signature: Option<&DeliveryImage>,
+        photo: Option<&DeliveryImage>,
+    ) -> io::Result<bool> {
+        let images: Vec<(String, &DeliveryImage)> = [("signature", signature), ("photo", photo)]
+            .into_iter()
+            .filter_map(|(name, image)| {
+                let image = image?;
+                let key = format!("{}/{name}.{}", delivery.parcel_id, image.extension);
+                Some((key, image))
+            })
+            .collect();
+
+        let record = DeliveryRecord {
+            delivery: delivery.clone(),
+            scanner_id: scanner_id.to_string(),
+            images: images.iter().map(|(key, _)| key.clone()).collect(),
+        };
+        let record_key = Self::record_key(&delivery.parcel_id);
+        if !self
+            .store
+            .put_new(&record_key, &serde_json::to_vec_pretty(&record)?)?
+        {
+            return Ok(false);
+        }
+
+        for (key, image) in &images {
+            if let Err(e) = self.store.put(key, &image.contents) {
+                self.remove(&delivery.parcel_id);
+                return Err(e);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Keeps the record of a parcel handed over with its pickup code; false if the parcel was already delivered
+    pub fn record_handover(&self, delivery: &Delivery, scanner_id: &str) -> io::Result<bool> {
+        self.record(delivery, scanner_id, None, None)
+    }
+
+    /// Removes the delivery of a parcel and its images, when the parcel couldn't be completed
+    pub fn remove(&self, parcel_id: &str) {
+        let record_key = Self::record_key(parcel_id);
+        let removed = self.store.get(&record_key).and_then(|contents| {
+            if let Some(contents) = contents {
+                let record: DeliveryRecord = serde_json::from_slice(&contents)?;
+                for key in record.images {
+                    self.store.delete(&key)?;
+                }
+            }
+
+            self.store.delete(&record_key)
+        });
+        if let Err(e) = removed {
+            rest_error!(
+                "(Deliveries::remove) could not remove the delivery of parcel {}: {}",
+                parcel_id,
+                e
+            );
+        }
+    }
+}
+
+/// Record the delivery of a parcel to its recipient
+///
+/// The courier's scanner must be registered and active, and send its API
+///  key in the `X-Api-Key` header. The recipient gives the pickup code of
+///  the parcel as PIN code, or signs if the parcel has no pickup code; a
+///  photo of the handover is optional.
+///  Images are base64 encoded PNG or JPEG files of at most
+///  [`MAX_DELIVERY_IMAGE_BYTES`] bytes. The handover must be at the
+///  destination vertiport of the last flight of the parcel. The parcel is
+///  then complete, and the delivery is part of its tracking information.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/cargo/parcels/{id}/delivery",
+    tag = "svc-cargo",
+    params(
+        ("id" = String, Path, description = "The unique ID (UUID) of the parcel")
+    ),
+    request_body = DeliveryProof,
+    responses(
+        (status = 201, description = "Delivery recorded", body = Delivery),
+        (status = 400, description = "Proof is invalid, or not at the destination vertiport", body = String),
+        (status = 401, description = "Scanner API key missing or invalid", body = String),
+        (status = 403, description = "Wrong or missing PIN code, or scanner unknown, deactivated or assigned to another vertiport", body = String),
+        (status = 404, description = "Parcel not found", body = String),
+        (status = 409, description = "Parcel already delivered or picked up, has no pickup code for the PIN, or is not on any flight", body = String),
+        (status = 423, description = "Too many wrong PIN or pickup codes, try again later", body = String),
+        (status = 500, description = "svc-storage, the shipment store or the delivery store returned error", body = String),
+        (status = 503, description = "Could not connect to other microservice dependencies"),
+        (status = 504, description = "Microservice dependencies did not respond in time")
+    )
+))]
+#[allow(clippy::too_many_arguments)] // Each argument is an extractor
+pub async fn deliver_parcel(
+    Extension(grpc_clients): Extension<GrpcClients>,
+    Extension(deliveries): Extension<Deliveries>,
+    Extension(shipments): Extension<Shipments>,
+    Extension(pickup_codes): Extension<PickupCodes>,
+    Extension(scanner_registry): Extension<ScannerRegistry>,
+    Extension(notifier): Extension<Notifier>,
+    Extension(live_config): Extension<watch::Receiver<Config>>,
+    headers: HeaderMap,
+    Path(parcel_id): Path<String>,
+    Json(payload): Json<DeliveryProof>,
+) -> Result<(StatusCode, Json<Delivery>), (StatusCode, String)> {
+    rest_debug!("(deliver_parcel) entry.");
+
+    //
+    // Validate Request
+    //
+    if !is_uuid(&parcel_id) {
+        let error_msg = "parcel ID not in UUID format.".to_string();
+        rest_error!("(deliver_parcel) {}", &error_msg);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
+    }
+
+    if !is_uuid(&payload.scanner_id) {
+        let error_msg = "scanner ID not in UUID format.".to_string();
+        rest_error!("(deliver_parcel) {}", &error_msg);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
+    }
+
+    let proof = check_proof(&payload).map_err(|error_msg| {
+        rest_error!("(deliver_parcel) {}", &error_msg);
+        (StatusCode::BAD_REQUEST, error_msg)
+    })?;
+
+    let scanner = authorize_scanner(
+        "deliver_parcel",
+        &scanner_registry,
+        &payload.scanner_id,
+        &headers,
+    )?;
+
+    let store_error = |e: io::Error| {
+        let error_msg = "delivery store unavailable.".to_string();
+        rest_error!("(deliver_parcel) {} {}", &error_msg, e);
+        (StatusCode::INTERNAL_SERVER_ERROR, error_msg)
+    };
+    let already_delivered = || {
+        let error_msg = format!("parcel {parcel_id} already delivered.");
+        rest_error!("(deliver_parcel) {}", &error_msg);
+        (StatusCode::CONFLICT, error_msg)
+    };
+
+    if deliveries.get(&parcel_id).map_err(store_error)?.is_some() {
+        return Err(already_delivered());
+    }
+
+    // A signature doesn't replace the pickup code of the parcel
+    let shipment = shipments.get(&parcel_id).map_err(|e| {
+        let error_msg = "shipment store unavailable.".to_string();
+        rest_error!("(deliver_parcel) {} {}", &error_msg, e);
+        (StatusCode::INTERNAL_SERVER_ERROR, error_msg)
+    })?;
+    let has_pickup_code = shipment.is_some_and(|shipment| shipment.pickup.code_digest.is_some());
+    if has_pickup_code && proof.pin.is_none() {
+        let error_msg =
+            format!("parcel {parcel_id} has a pickup code, it must be given as PIN code.");
+        rest_error!("(deliver_parcel) {}", &error_msg);
+        return Err((StatusCode::FORBIDDEN, error_msg));
+    }
+
+    let storage_error = |status: StatusCode| {
+        let error_msg = match status {
+            StatusCode::NOT_FOUND => format!("parcel {parcel_id} not found."),
+            _ => "could not get the parcel from svc-storage.".to_string(),
+        };
+        rest_error!("(deliver_parcel) {}", &error_msg);
+        (status, error_msg)
+    };
+
+    let parcel = get_parcel_details(&parcel_id, &grpc_clients)
+        .await
+        .map_err(storage_error)?;
+    if parcel.status == ParcelStatus::Complete as i32 {
+        return Err(already_delivered());
+    }
+
+    //
+    // The handover must be at the destination
+    //
+    let legs = get_parcel_legs(&parcel_id, &grpc_clients)
+        .await
+        .map_err(storage_error)?;
+    let Some(last) = legs.last() else {
+        let error_msg = "parcel is not on any flight.".to_string();
+        rest_error!("(deliver_parcel) {} {}", &error_msg, parcel_id);
+        return Err((StatusCode::CONFLICT, error_msg));
+    };
+
+    let Some(vertiport_id) = last.target_vertiport_id.clone() else {
+        let error_msg = "flight plan has no destination vertiport.".to_string();
+        rest_error!("(deliver_parcel) {} parcel {}", &error_msg, parcel_id);
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, error_msg));
+    };
+
+    if scanner
+        .vertiport_id
+        .as_ref()
+        .is_some_and(|assigned| *assigned != vertiport_id)
+    {
+        let error_msg = format!(
+            "scanner {} is not assigned to destination vertiport {vertiport_id}.",
+            scanner.id
+        );
+        rest_error!("(deliver_parcel) {}", &error_msg);
+        return Err((StatusCode::FORBIDDEN, error_msg));
+    }
+
+    let outline = get_vertiport_polygon(&vertiport_id, &grpc_clients)
+        .await
+        .map_err(storage_error)?;
+    let location = Coord {
+        x: payload.longitude,
+        y: payload.latitude,
+    };
+    let distance = distance_to_polygon(location, &outline);
+    let config = live_config.borrow().clone();
+    if distance > config.rest_scan_vertiport_margin_meters.into() {
+        let error_msg =
+            format!("delivery is {distance:.0}m away from destination vertiport {vertiport_id}.");
+        rest_error!("(deliver_parcel) {}", &error_msg);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
+    }
+
+    //
+    // Check the PIN code, keep the proof, then complete the parcel
+    //
+    let delivered_at = Utc::now();
+    if let Some(pin) = &proof.pin {
+        check_pickup_code(
+            "deliver_parcel",
+            &shipments,
+            &pickup_codes,
+            &parcel_id,
+            pin,
+            delivered_at,
+            &config,
+        )
+        .await?;
+    }
+    // The code may be given again if the delivery isn't recorded
+    let release_pin = {
+        let pin_given = proof.pin.is_some();
+        let (shipments, parcel_id) = (&shipments, &parcel_id);
+        move || async move {
+            if pin_given {
+                release_pickup_code("deliver_parcel", shipments, parcel_id).await;
+            }
+        }
+    };
+
+    let delivery = Delivery {
+        parcel_id: parcel_id.clone(),
+        recipient_name: proof.recipient_name.clone(),
+        confirmation: proof.confirmation,
+        has_photo: proof.photo.is_some(),
+        vertiport_id,
+        latitude: payload.latitude,
+        longitude: payload.longitude,
+        delivered_at,
+    };
+
+    match deliveries.record(
+        &delivery,
+        &scanner.id,
+        proof.signature.as_ref(),
+        proof.photo.as_ref(),
+    ) {
+        Ok(true) => (),
+        Ok(false) => {
+            release_pin().await;
+            return Err(already_delivered());
+        }
+        Err(e) => {
+            release_pin().await;
+            return Err(store_error(e));
+        }
+    }
+
+    if let Err(status) =
+        update_parcel_status(&parcel_id, ParcelStatus::Complete, &grpc_clients).await
+    {
+        deliveries.remove(&parcel_id);
+        release_pin().await;
+        let error_msg = "could not complete the parcel in svc-storage.".to_string();
+        rest_error!("(deliver_parcel) {}", &error_msg);
+        return Err((status, error_msg));
+    }
+
+    notifier.notify(&parcel_id, ParcelEvent::Delivered);
+
+    rest_info!("(deliver_parcel) parcel {} delivered.", parcel_id);
+    Ok((StatusCode::CREATED, Json(delivery)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Smallest valid PNG header, the contents aren't decoded
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    fn encode(contents: &[u8]) -> String {
+        base64::engine::general_purpose::STANDARD.encode(contents)
+    }
+
+    fn mock_proof() -> DeliveryProof {
+        DeliveryProof {
+            scanner_id: "59e51ad1-d57d-4d2c-bc2d-e2387367d17f".to_string(),
+            recipient_name: " Ada Lovelace ".to_string(),
+            signature: Some(encode(PNG)),
+            pin: None,
+            photo: Some(encode(&[0xff, 0xd8, 0xff, 0xe0])),
+            latitude: 52.37,
+            longitude: 4.90,
+        }
+    }
+
+    #[test]
+    fn ut_check_proof() {
+        let proof = check_proof(&mock_proof()).unwrap();
+        assert_eq!(proof.recipient_name, "Ada Lovelace");
+        assert_eq!(proof.confirmation, DeliveryConfirmation::Signature);
+        assert_eq!(proof.signature.unwrap().extension, "png");
+        assert_eq!(proof.photo.unwrap().extension, "jpg");
+
+        let proof = check_proof(&DeliveryProof {
+            signature: None,
+            pin: Some("042917".to_string()),
+            photo: None,
+            ..mock_proof()
+        })
+        .unwrap();
+        assert_eq!(proof.confirmation, DeliveryConfirmation::Pin);
+        assert_eq!(proof.pin.as_deref(), Some("042917"));
+
+        let invalid = [
+            DeliveryProof {
+                recipient_name: "  ".to_string(),
+                ..mock_proof()
+            },
+            DeliveryProof {
+                recipient_name: "a".repeat(MAX_RECIPIENT_NAME_LENGTH + 1),
+                ..mock_proof()
+            },
+            // Neither or both of a signature and a PIN code
+            DeliveryProof {
+                signature: None,
+                ..mock_proof()
+            },
+            DeliveryProof {
+                pin: Some("1234".to_string()),
+                ..mock_proof()
+            },
+            DeliveryProof {
+                signature: None,
+                pin: Some("12a4".to_string()),
+                ..mock_proof()
+            },
+            DeliveryProof {
+                signature: None,
+                pin: Some("123".to_string()),
+                ..mock_proof()
+            },
+            DeliveryProof {
+                signature: Some("not base64!".to_string()),
+                ..mock_proof()
+            },
+            DeliveryProof {
+                photo: Some(encode(b"GIF89a")),
+                ..mock_proof()
+            },
+            DeliveryProof {
+                photo: Some(encode(&[0xff; MAX_DELIVERY_IMAGE_BYTES + 1])),
+                ..mock_proof()
+            },
+            DeliveryProof {
+                latitude: 91.0,
+                ..mock_proof()
+            },
+        ];
+        for proof in invalid {
+            assert!(check_proof(&proof).is_err(), "{proof:?}");
+        }
+    }
+
+    #[test]
+    fn ut_deliveries() {
+        let store = Arc::new(MemoryBlobStore::default());
+        let deliveries = Deliveries::with_store(store.clone());
+        let parcel_id = "cabcdd14-03ab-4ac0-b58c-dd4175bc587e";
+        let scanner_id = "59e51ad1-d57d-4d2c-bc2d-e2387367d17f";
+        assert_eq!(deliveries.get(parcel_id).unwrap(), None);
+
+        let proof = check_proof(&mock_proof()).unwrap();
+        let delivery = Delivery {
+            parcel_id: parcel_id.to_string(),
+            recipient_name: proof.recipient_name.clone(),
+            confirmation: proof.confirmation,
+            has_photo: true,
+            vertiport_id: "8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1".to_string(),
+            latitude: 52.37,
+            longitude: 4.90,
+            delivered_at: Utc::now(),
+        };
+        let images = (proof.signature.as_ref(), proof.photo.as_ref());
+        assert!(deliveries
+            .record(&delivery, scanner_id, images.0, images.1)
+            .unwrap());
+        assert_eq!(deliveries.get(parcel_id).unwrap(), Some(delivery.clone()));
+
+        // The images are kept as sent
+        let signature = store.get(&format!("{parcel_id}/signature.png")).unwrap();
+        assert_eq!(signature.as_deref(), Some(PNG));
+        let photo = store.get(&format!("{parcel_id}/photo.jpg")).unwrap();
+        assert_eq!(photo.as_deref(), Some(&[0xff, 0xd8, 0xff, 0xe0][..]));
+
+        // Parcels are delivered once
+        let other = Delivery {
+            recipient_name: "Charles Babbage".to_string(),
+            ..delivery.clone()
+        };
+        assert!(!deliveries
+            .record(&other, scanner_id, images.0, images.1)
+            .unwrap());
+        assert!(!deliveries.record_handover(&other, scanner_id).unwrap());
+        assert_eq!(deliveries.get(parcel_id).unwrap(), Some(delivery));
+
+        // Removed with its images when the parcel can't be completed
+        deliveries.remove(parcel_id);
+        assert_eq!(deliveries.get(parcel_id).unwrap(), None);
+        let photo = store.get(&format!("{parcel_id}/photo.jpg")).unwrap();
+        assert_eq!(photo, None);
+
+        // Handovers with the pickup code have no images
+        assert!(deliveries.record_handover(&other, scanner_id).unwrap());
+        assert_eq!(deliveries.get(parcel_id).unwrap(), Some(other));
+        let signature = store.get(&format!("{parcel_id}/signature.png")).unwrap();
+        assert_eq!(signature, None);
+    }
+
+    #[tokio::test]
+    async fn ut_deliver_parcel() {
+        use super::super::pickup::PickupState;
+        use super::super::scanner::MemoryScannerStore;
+        use super::super::shipment::{MemoryShipmentStore, Shipment};
+        use crate::rest::limit::API_KEY_HEADER;
+        use axum::{body::Body, http::Request, routing, Router};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use svc_storage_client_grpc::prelude::{
+            GeoLineString, GeoPoint, GeoPolygon, ValidationResult,
+        };
+        use svc_storage_client_grpc::resources::{
+            flight_plan, flight_plan_parcel, parcel, vertiport,
+        };
+        use tonic::Status;
+        use tower::ServiceExt;
+
+        let parcel_id = "cabcdd14-03ab-4ac0-b58c-dd4175bc587e";
+        let config = Config::default();
+        let (_, live_config) = watch::channel(config.clone());
+
+        // A parcel on one flight to a vertiport around 52.37, 4.90
+        let grpc_clients = GrpcClients::default(config.clone());
+        let storage = &grpc_clients.backends.storage;
+        storage.stub("parcel.get_by_id", || {
+            tonic::Response::new(parcel::Object {
+                id: "cabcdd14-03ab-4ac0-b58c-dd4175bc587e".to_string(),
+                data: Some(parcel::Data {
+                    status: ParcelStatus::Arrived as i32,
+                    ..parcel::mock::get_data_obj()
+                }),
+            })
+        });
+        storage.stub("flight_plan_parcel.search", || {
+            tonic::Response::new(flight_plan_parcel::RowDataList {
+                list: vec![flight_plan_parcel::RowData {
+                    flight_plan_id: "6fd1e0a2-6a4d-4e0e-9f53-2b0c3f4b1a77".to_string(),
+                    parcel_id: "cabcdd14-03ab-4ac0-b58c-dd4175bc587e".to_string(),
+                    acquire: true,
+                    deliver: true,
+                }],
+            })
+        });
+        storage.stub("flight_plan.get_by_id", || {
+            tonic::Response::new(flight_plan::Object {
+                id: "6fd1e0a2-6a4d-4e0e-9f53-2b0c3f4b1a77".to_string(),
+                data: Some(flight_plan::Data {
+                    target_vertiport_id: Some("8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1".to_string()),
+                    ..flight_plan::mock::get_data_obj()
+                }),
+            })
+        });
+        storage.stub("vertiport.get_by_id", || {
+            let d = 0.001;
+            let points = [(-d, -d), (d, -d), (d, d), (-d, d), (-d, -d)]
+                .into_iter()
+                .map(|(dy, dx)| GeoPoint {
+                    latitude: 52.37 + dy,
+                    longitude: 4.90 + dx,
+                })
+                .collect();
+            tonic::Response::new(vertiport::Object {
+                id: "8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1".to_string(),
+                data: Some(vertiport::Data {
+                    geo_location: Some(GeoPolygon {
+                        exterior: Some(GeoLineString { points }),
+                        interiors: vec![],
+                    }),
+                    ..vertiport::mock::get_data_obj()
+                }),
+            })
+        });
+        let completed = Arc::new(AtomicUsize::new(0));
+        let complete = {
+            let completed = completed.clone();
+            move || {
+                completed.fetch_add(1, Ordering::SeqCst);
+                tonic::Response::new(parcel::Response {
+                    validation_result: Some(ValidationResult {
+                        success: true,
+                        errors: vec![],
+                    }),
+                    object: None,
+                })
+            }
+        };
+
+        let scanner_registry = ScannerRegistry::with_store(Arc::new(MemoryScannerStore::default()));
+        let credentials = scanner_registry
+            .register("Courier".to_string(), None)
+            .await
+            .unwrap();
+        let shipments = Shipments::with_store(Arc::new(MemoryShipmentStore::default()));
+        let pickup_codes = PickupCodes::with_secret(b"pickup-secret");
+        shipments
+            .record(Shipment {
+                parcel_id: parcel_id.to_string(),
+                itinerary_id: "b1f0c2d3-4e5f-4a6b-8c7d-9e0f1a2b3c4d".to_string(),
+                user_id: "b3c1d7a6-3b0b-4f7c-8c6c-4e0d0d2b9f4e".to_string(),
+                weight_grams: 1250,
+                confirmed_at: Utc::now(),
+                recipient: None,
+                tracking_token: None,
+                notified: vec![],
+                pickup: PickupState::issue(&pickup_codes, parcel_id, "123456"),
+            })
+            .await
+            .unwrap();
+        let deliveries = Deliveries::with_store(Arc::new(MemoryBlobStore::default()));
+
+        let app = Router::new()
+            .route("/cargo/parcels/:id/delivery", routing::post(deliver_parcel))
+            .layer(Extension(deliveries.clone()))
+            .layer(Extension(Notifier::new(&config, shipments.clone())))
+            .layer(Extension(shipments.clone()))
+            .layer(Extension(pickup_codes))
+            .layer(Extension(scanner_registry))
+            .layer(Extension(live_config))
+            .layer(Extension(grpc_clients.clone()));
+        let uri = format!("/cargo/parcels/{parcel_id}/delivery");
+        let request = |uri: &str, proof: &DeliveryProof, api_key: &str| {
+            Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header(API_KEY_HEADER, api_key)
+                .body(Body::from(serde_json::to_vec(proof).unwrap()))
+                .unwrap()
+        };
+        let api_key = credentials.api_key.as_str();
+        let with_pin = |pin: &str| DeliveryProof {
+            scanner_id: credentials.scanner.id.clone(),
+            signature: None,
+            pin: Some(pin.to_string()),
+            ..mock_proof()
+        };
+        let picked_up = || {
+            shipments
+                .get(parcel_id)
+                .unwrap()
+                .unwrap()
+                .pickup
+                .picked_up_at
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request(
+                "/cargo/parcels/not-a-uuid/delivery",
+                &with_pin("123456"),
+                api_key,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let unsigned = DeliveryProof {
+            pin: None,
+            ..with_pin("123456")
+        };
+        let response = app
+            .clone()
+            .oneshot(request(&uri, &unsigned, api_key))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // Only registered scanners record deliveries
+        let response = app
+            .clone()
+            .oneshot(request(&uri, &with_pin("123456"), "wrong-key"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // A signature doesn't replace the pickup code
+        let signed = DeliveryProof {
+            scanner_id: credentials.scanner.id.clone(),
+            ..mock_proof()
+        };
+        assert!(signed.signature.is_some() && signed.pin.is_none());
+        let response = app
+            .clone()
+            .oneshot(request(&uri, &signed, api_key))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(deliveries.get(parcel_id).unwrap(), None);
+
+        let away = DeliveryProof {
+            latitude: 52.09,
+            ..with_pin("123456")
+        };
+        let response = app
+            .clone()
+            .oneshot(request(&uri, &away, api_key))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // The PIN code is the pickup code of the parcel
+        let response = app
+            .clone()
+            .oneshot(request(&uri, &with_pin("000000"), api_key))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(deliveries.get(parcel_id).unwrap(), None);
+
+        // Parcels that can't be completed aren't delivered, the code can be given again
+        storage.stub_failure(
+            "parcel.update",
+            Status::failed_precondition("parcel locked"),
+        );
+        let response = app
+            .clone()
+            .oneshot(request(&uri, &with_pin("123456"), api_key))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(deliveries.get(parcel_id).unwrap(), None);
+        assert_eq!(picked_up(), None);
+
+        storage.stub("parcel.update", complete);
+        let response = app
+            .clone()
+            .oneshot(request(&uri, &with_pin("123456"), api_key))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let delivery: Delivery = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(delivery.confirmation, DeliveryConfirmation::Pin);
+        assert_eq!(delivery.recipient_name, "Ada Lovelace");
+        assert_eq!(
+            delivery.vertiport_id,
+            "8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1"
+        );
+        assert_eq!(deliveries.get(parcel_id).unwrap(), Some(delivery.clone()));
+        assert_eq!(picked_up(), Some(delivery.delivered_at));
+        assert_eq!(completed.load(Ordering::SeqCst), 1);
+
+        let response = app
+            .oneshot(request(&uri, &with_pin("123456"), api_key))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(completed.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,439 @@
+//! REST error responses
+
+use super::rest_types::ErrorResponse;
+use crate::grpc::resilience::track_retry_after;
+use crate::request_id::current_request_id;
+use axum::{
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use tonic::{Code, Status};
+
+/// REST status code for an error returned by a backend service
+///
+/// Codes without a closer REST equivalent are reported as an internal error.
+pub fn status_from_grpc(status: &Status) -> StatusCode {
+    match status.code() {
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists => StatusCode::CONFLICT,
+        Code::InvalidArgument => StatusCode::BAD_REQUEST,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Replaces the body of error responses with an [`ErrorResponse`]
+///
+/// Handlers report errors as a status code with an optional plain text
+///  message. The message is kept and the request id is added so that
+///  callers can refer to the failed request.
+pub async fn json_error_body<B>(request: Request<B>, next: Next<B>) -> Response {
+    let request_id = current_request_id();
+    let response = next.run(request).await;
+
+    let status = response.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return response;
+    }
+
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    if is_json {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let message = match hyper::body::to_bytes(body).await {
+        Ok(bytes) if !bytes.is_empty() => String::from_utf8_lossy(&bytes).into_owned(),
+        _ => status.canonical_reason().unwrap_or("error").to_string(),
+    };
+
+    let mut response = Json(ErrorResponse {
+        message,
+        request_id,
+    })
+    .into_response();
+    *response.status_mut() = status;
+    for (name, value) in parts.headers.iter() {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            response.headers_mut().insert(name, value.clone());
+        }
+    }
+
+    response
+}
+
+/// Answers 503 with a `Retry-After` header when a circuit breaker rejected
+///  a backend call needed by the request
+///
+/// Handlers report rejected calls like any other backend error, this tells
+///  callers when the backend may be available again.
+pub async fn circuit_breaker_retry_after<B>(request: Request<B>, next: Next<B>) -> Response {
+    let (mut response, retry_after) = track_retry_after(next.run(request)).await;
+    let Some(retry_after) = retry_after else {
+        return response;
+    };
+
+    if response.status().is_server_error() {
+        *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        response.headers_mut().insert(
+            header::RETRY_AFTER,
+            HeaderValue::from((retry_after.as_secs_f64().ceil() as u64).max(1)),
+        );
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grpc::client::GrpcClients;
+    use crate::grpc::resilience::Backend;
+    use crate::request_id::RequestIdLayer;
+    use crate::Config;
+    use axum::{body::Body, middleware, routing, Router};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    async fn error_body(response: Response) -> ErrorResponse {
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn ut_json_error_body() {
+        let app = Router::new()
+            .route(
+                "/message",
+                routing::get(|| async { (StatusCode::BAD_REQUEST, "Invalid parcel id.") }),
+            )
+            .route(
+                "/status",
+                routing::get(|| async { StatusCode::SERVICE_UNAVAILABLE }),
+            )
+            .route("/ok", routing::get(|| async { "ok" }))
+            .layer(middleware::from_fn(json_error_body))
+            .layer(RequestIdLayer::new("rest"));
+
+        let request = Request::builder()
+            .uri("/message")
+            .header("x-request-id", "abc-123")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            error_body(response).await,
+            ErrorResponse {
+                message: "Invalid parcel id.".to_string(),
+                request_id: Some("abc-123".to_string()),
+            }
+        );
+
+        let request = Request::builder()
+            .uri("/status")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let header = response.headers().get("x-request-id").cloned();
+        let body = error_body(response).await;
+        assert_eq!(body.message, "Service Unavailable");
+        assert_eq!(
+            body.request_id.as_deref(),
+            header.as_ref().and_then(|value| value.to_str().ok())
+        );
+
+        let request = Request::builder().uri("/ok").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn ut_circuit_breaker_retry_after() {
+        let config = Config {
+            grpc_max_retries: 0,
+            circuit_breaker_failure_threshold: 1,
+            ..Config::default()
+        };
+        let backend = Arc::new(Backend::new("test", Duration::from_secs(1), &config));
+
+        let handler = |backend: Arc<Backend>| async move {
+            match backend
+                .call("down", || async {
+                    Err::<(), _>(Status::unavailable("down"))
+                })
+                .await
+            {
+                Ok(_) => StatusCode::OK,
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            }
+        };
+        let app = Router::new()
+            .route(
+                "/down",
+                routing::get({
+                    let backend = backend.clone();
+                    move || handler(backend.clone())
+                }),
+            )
+            .layer(middleware::from_fn(circuit_breaker_retry_after));
+
+        // The first failure opens the breaker
+        let request = Request::builder().uri("/down").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(!response.headers().contains_key(header::RETRY_AFTER));
+
+        // Later calls fail fast
+        let request = Request::builder().uri("/down").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            response.headers().get(header::RETRY_AFTER),
+            Some(&HeaderValue::from(30u64))
+        );
+    }
+
+    #[tokio::test]
+    async fn ut_status_from_grpc() {
+        let config = Config {
+            grpc_max_retries: 0,
+            circuit_breaker_failure_threshold: u8::MAX,
+            ..Config::default()
+        };
+        let backend = Backend::new("stub", Duration::from_secs(1), &config);
+
+        let cases = [
+            (Code::NotFound, StatusCode::NOT_FOUND),
+            (Code::AlreadyExists, StatusCode::CONFLICT),
+            (Code::InvalidArgument, StatusCode::BAD_REQUEST),
+            (Code::Unavailable, StatusCode::SERVICE_UNAVAILABLE),
+            (Code::DeadlineExceeded, StatusCode::GATEWAY_TIMEOUT),
+            (Code::PermissionDenied, StatusCode::FORBIDDEN),
+            (Code::Internal, StatusCode::INTERNAL_SERVER_ERROR),
+            (Code::Unknown, StatusCode::INTERNAL_SERVER_ERROR),
+        ];
+
+        for (code, expected) in cases {
+            // Stub backend answering every call with the given code
+            let result: Result<(), Status> = backend
+                .call(
+                    "stub",
+                    || async move { Err(Status::new(code, "stub error")) },
+                )
+                .await;
+
+            let status = status_from_grpc(&result.unwrap_err());
+            assert_eq!(status, expected, "{:?}", code);
+        }
+    }
+
+    /// Routes of the handlers calling backends, with the state they need
+    ///
+    /// Returns the scanner ID and API key of a registered scanner as well.
+    async fn handlers(config: &Config, grpc_clients: GrpcClients) -> (Router, (String, String)) {
+        use super::super::blob::MemoryBlobStore;
+        use super::super::delivery::Deliveries;
+        use super::super::notification::Notifier;
+        use super::super::pickup::PickupCodes;
+        use super::super::scanner::{MemoryScannerStore, ScannerRegistry};
+        use super::super::shipment::{MemoryShipmentStore, Shipments};
+        use super::super::{anomaly::ScanMonitor, cancel, confirm, query, request, scan};
+        use axum::extract::Extension;
+        use tokio::sync::watch;
+
+        let (_, live_config) = watch::channel(config.clone());
+        let scanner_registry = ScannerRegistry::with_store(Arc::new(MemoryScannerStore::default()));
+        let credentials = scanner_registry
+            .register("Handheld".to_string(), None)
+            .await
+            .unwrap();
+        let shipments = Shipments::with_store(Arc::new(MemoryShipmentStore::default()));
+        let app = Router::new()
+            .route("/cargo/request", routing::post(request::request_flight))
+            .route("/cargo/confirm", routing::put(confirm::confirm_itinerary))
+            .route("/cargo/cancel", routing::delete(cancel::cancel_itinerary))
+            .route("/cargo/scan", routing::put(scan::scan_parcel))
+            .route("/cargo/track", routing::get(query::query_scans))
+            .layer(middleware::from_fn(circuit_breaker_retry_after))
+            .layer(Extension(live_config))
+            .layer(Extension(ScanMonitor::new(config)))
+            .layer(Extension(scanner_registry))
+            .layer(Extension(Notifier::new(config, shipments.clone())))
+            .layer(Extension(shipments))
+            .layer(Extension(Deliveries::with_store(Arc::new(
+                MemoryBlobStore::default(),
+            ))))
+            .layer(Extension(PickupCodes::with_secret(b"pickup-secret")))
+            .layer(Extension(grpc_clients));
+
+        (app, (credentials.scanner.id, credentials.api_key))
+    }
+
+    /// Requests of the handlers, with the backend and method each calls first
+    fn handler_request(
+        index: usize,
+        (scanner_id, api_key): &(String, String),
+    ) -> (&'static str, &'static str, Request<Body>) {
+        use crate::rest::limit::API_KEY_HEADER;
+        use chrono::{Duration, Utc};
+
+        let json = |method: &str, uri: &str, body: String| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .header(API_KEY_HEADER, api_key)
+                .body(Body::from(body))
+                .unwrap()
+        };
+        let now = Utc::now();
+        let flight_request = serde_json::json!({
+            "vertiport_depart_id": "59e51ad1-d57d-4d2c-bc2d-e2387367d17f",
+            "vertiport_arrive_id": "6fd1e0a2-6a4d-4e0e-9f53-2b0c3f4b1a77",
+            "time_depart_window": {
+                "timestamp_min": now + Duration::hours(1),
+                "timestamp_max": now + Duration::hours(3),
+            },
+            "cargo_weight_kg": 1.5,
+        });
+        let itinerary = serde_json::json!({
+            "id": "8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1",
+            "user_id": "b3c1d7a6-3b0b-4f7c-8c6c-4e0d0d2b9f4e",
+            "weight_grams": 1250,
+        });
+        let scan = serde_json::json!({
+            "scanner_id": scanner_id,
+            "parcel_id": "cabcdd14-03ab-4ac0-b58c-dd4175bc587e",
+            "latitude": 52.37,
+            "longitude": 4.90,
+        });
+
+        let mut requests = vec![
+            (
+                "scheduler",
+                "query_flight",
+                json("POST", "/cargo/request", flight_request.to_string()),
+            ),
+            (
+                "scheduler",
+                "confirm_itinerary",
+                json("PUT", "/cargo/confirm", itinerary.to_string()),
+            ),
+            (
+                "scheduler",
+                "cancel_itinerary",
+                json("DELETE", "/cargo/cancel", itinerary.to_string()),
+            ),
+            (
+                "storage",
+                "parcel_scan.insert",
+                json("PUT", "/cargo/scan", scan.to_string()),
+            ),
+            (
+                "storage",
+                "parcel_scan.search",
+                json(
+                    "GET",
+                    "/cargo/track?parcel_id=cabcdd14-03ab-4ac0-b58c-dd4175bc587e",
+                    String::new(),
+                ),
+            ),
+        ];
+        requests.swap_remove(index)
+    }
+
+    const HANDLERS: usize = 5;
+
+    fn backend<'a>(grpc_clients: &'a GrpcClients, name: &str) -> &'a Backend {
+        match name {
+            "scheduler" => &grpc_clients.backends.scheduler,
+            _ => &grpc_clients.backends.storage,
+        }
+    }
+
+    #[tokio::test]
+    async fn ut_handler_status_from_grpc() {
+        let config = Config {
+            grpc_max_retries: 0,
+            circuit_breaker_failure_threshold: u8::MAX,
+            ..Config::default()
+        };
+
+        let cases = [
+            (Code::NotFound, StatusCode::NOT_FOUND),
+            (Code::AlreadyExists, StatusCode::CONFLICT),
+            (Code::InvalidArgument, StatusCode::BAD_REQUEST),
+            (Code::Unavailable, StatusCode::SERVICE_UNAVAILABLE),
+            (Code::DeadlineExceeded, StatusCode::GATEWAY_TIMEOUT),
+            (Code::PermissionDenied, StatusCode::FORBIDDEN),
+            (Code::Internal, StatusCode::INTERNAL_SERVER_ERROR),
+            (Code::Unknown, StatusCode::INTERNAL_SERVER_ERROR),
+        ];
+
+        // Each handler answers the status of the failed backend call
+        for (code, expected) in cases {
+            let grpc_clients = GrpcClients::default(config.clone());
+            let (app, scanner) = handlers(&config, grpc_clients.clone()).await;
+            for index in 0..HANDLERS {
+                let (name, method, request) = handler_request(index, &scanner);
+                let uri = request.uri().clone();
+                backend(&grpc_clients, name).stub_failure(method, Status::new(code, "stub error"));
+                let response = app.clone().oneshot(request).await.unwrap();
+                assert_eq!(response.status(), expected, "{} {:?}", uri, code);
+                assert!(!response.headers().contains_key(header::RETRY_AFTER));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn ut_handler_retry_after() {
+        let config = Config {
+            grpc_max_retries: 0,
+            circuit_breaker_failure_threshold: 1,
+            ..Config::default()
+        };
+
+        // Once a failure opens the breaker, handlers tell callers when to retry
+        for index in 0..HANDLERS {
+            let grpc_clients = GrpcClients::default(config.clone());
+            let (app, scanner) = handlers(&config, grpc_clients.clone()).await;
+            let (name, method, first) = handler_request(index, &scanner);
+            let uri = first.uri().clone();
+            backend(&grpc_clients, name).stub_failure(method, Status::unavailable("down"));
+
+            let response = app.clone().oneshot(first).await.unwrap();
+            assert_eq!(
+                response.status(),
+                StatusCode::SERVICE_UNAVAILABLE,
+                "{}",
+                uri
+            );
+            assert!(!response.headers().contains_key(header::RETRY_AFTER));
+
+            let (_, _, again) = handler_request(index, &scanner);
+            let response = app.oneshot(again).await.unwrap();
+            assert_eq!(
+                response.status(),
+                StatusCode::SERVICE_UNAVAILABLE,
+                "{}",
+                uri
+            );
+            assert_eq!(
+                response.headers().get(header::RETRY_AFTER),
+                Some(&HeaderValue::from(30u64)),
+                "{}",
+                uri
+            );
+        }
+    }
+}
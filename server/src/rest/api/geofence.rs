@@ -0,0 +1,342 @@
+//! Where a parcel is expected to be scanned
+//!
+//! Before its first flight departs a parcel is expected at the origin
+//!  vertiport, after its last flight arrives at the destination vertiport,
+//!  and in between along the flight paths of its flights. Distances are
+//!  measured on a plane tangent to the earth at the scan location, which is
+//!  accurate for the few kilometers around it that matter here.
+
+use super::rest_types::ScanAnomalyKind;
+use super::utils::{get_parcel_legs, get_vertiport_details};
+use crate::grpc::client::GrpcClients;
+use chrono::{DateTime, Utc};
+use geo::{Coord, EuclideanDistance, LineString, MapCoords, Point, Polygon};
+use hyper::StatusCode;
+use svc_storage_client_grpc::prelude::{GeoLineString, GeoPolygon};
+
+/// Mean radius of the earth
+const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
+
+/// How far scans may be from where their parcel is expected
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tolerance {
+    /// Distance outside the vertiport polygons
+    pub vertiport_margin_meters: f64,
+
+    /// Distance from the flight paths
+    pub corridor_meters: f64,
+}
+
+/// How a scan deviates from the route of its parcel
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deviation {
+    /// Where the parcel was expected
+    pub kind: ScanAnomalyKind,
+
+    /// The vertiport the parcel was expected at, if any
+    pub vertiport_id: Option<String>,
+
+    /// Distance between the scan and where the parcel was expected
+    pub distance_meters: f64,
+}
+
+/// The vertiports and flight paths of the flights carrying a parcel
+#[derive(Debug, Clone)]
+pub struct ParcelRoute {
+    /// The vertiport the first flight departs from
+    pub origin_vertiport_id: String,
+
+    /// Outline of the origin vertiport
+    pub origin: Polygon,
+
+    /// The vertiport the last flight arrives at
+    pub destination_vertiport_id: String,
+
+    /// Outline of the destination vertiport
+    pub destination: Polygon,
+
+    /// Actual departure of the first flight, else its scheduled departure
+    pub departure: DateTime<Utc>,
+
+    /// Actual arrival of the last flight, else its scheduled arrival
+    pub arrival: DateTime<Utc>,
+
+    /// Flight paths of all flights, empty if any flight has no path
+    pub paths: Vec<LineString>,
+}
+
+impl ParcelRoute {
+    /// Compares a scan with the route, None if the scan is where expected
+    ///
+    /// During the flights, scans at either vertiport are expected as well.
+    /// Without flight paths, scans during the flights aren't checked.
+    pub fn check(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        scanned_at: DateTime<Utc>,
+        tolerance: &Tolerance,
+    ) -> Option<Deviation> {
+        let location = Coord {
+            x: longitude,
+            y: latitude,
+        };
+        let origin = distance_to_polygon(location, &self.origin);
+        let destination = distance_to_polygon(location, &self.destination);
+
+        if scanned_at < self.departure {
+            return (origin > tolerance.vertiport_margin_meters).then(|| Deviation {
+                kind: ScanAnomalyKind::NotAtOrigin,
+                vertiport_id: Some(self.origin_vertiport_id.clone()),
+                distance_meters: origin,
+            });
+        }
+
+        if scanned_at > self.arrival {
+            return (destination > tolerance.vertiport_margin_meters).then(|| Deviation {
+                kind: ScanAnomalyKind::NotAtDestination,
+                vertiport_id: Some(self.destination_vertiport_id.clone()),
+                distance_meters: destination,
+            });
+        }
+
+        if origin <= tolerance.vertiport_margin_meters
+            || destination <= tolerance.vertiport_margin_meters
+            || self.paths.is_empty()
+        {
+            return None;
+        }
+
+        let corridor = self
+            .paths
+            .iter()
+            .map(|path| distance_to_path(location, path))
+            .fold(f64::INFINITY, f64::min);
+        (corridor > tolerance.corridor_meters).then_some(Deviation {
+            kind: ScanAnomalyKind::OffCorridor,
+            vertiport_id: None,
+            distance_meters: corridor,
+        })
+    }
+
+    /// Returns true if a scan shows the parcel arrived at the destination
+    pub fn is_arrival(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        scanned_at: DateTime<Utc>,
+        tolerance: &Tolerance,
+    ) -> bool {
+        let location = Coord {
+            x: longitude,
+            y: latitude,
+        };
+        scanned_at > self.arrival
+            && distance_to_polygon(location, &self.destination) <= tolerance.vertiport_margin_meters
+    }
+}
+
+/// Meters east (x) and north (y) of `origin`
+fn to_meters(origin: Coord, coord: Coord) -> Coord {
+    Coord {
+        x: (coord.x - origin.x).to_radians() * EARTH_RADIUS_METERS * origin.y.to_radians().cos(),
+        y: (coord.y - origin.y).to_radians() * EARTH_RADIUS_METERS,
+    }
+}
+
+/// Meters between a location and a polygon, 0 inside the polygon
+pub fn distance_to_polygon(location: Coord, polygon: &Polygon) -> f64 {
+    let polygon = polygon.map_coords(|coord| to_meters(location, coord));
+    Point::new(0.0, 0.0).euclidean_distance(&polygon)
+}
+
+/// Meters between a location and a path
+fn distance_to_path(location: Coord, path: &LineString) -> f64 {
+    let path = path.map_coords(|coord| to_meters(location, coord));
+    Point::new(0.0, 0.0).euclidean_distance(&path)
+}
+
+/// Converts a svc-storage line string, x is the longitude
+fn line_string(line: &GeoLineString) -> LineString {
+    line.points
+        .iter()
+        .map(|point| Coord {
+            x: point.longitude,
+            y: point.latitude,
+        })
+        .collect()
+}
+
+/// Converts a svc-storage polygon, None without exterior
+fn polygon(polygon: &GeoPolygon) -> Option<Polygon> {
+    let exterior = line_string(polygon.exterior.as_ref()?);
+    let interiors = polygon.interiors.iter().map(line_string).collect();
+    Some(Polygon::new(exterior, interiors))
+}
+
+/// Request the outline of a vertiport
+pub async fn get_vertiport_polygon(
+    vertiport_id: &str,
+    grpc_clients: &GrpcClients,
+) -> Result<Polygon, StatusCode> {
+    let vertiport = get_vertiport_details(vertiport_id, grpc_clients).await?;
+    let Some(outline) = vertiport.geo_location.as_ref().and_then(polygon) else {
+        let error_msg = "vertiport has no location.".to_string();
+        rest_error!("(get_vertiport_polygon) {} {}", &error_msg, vertiport_id);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+
+    Ok(outline)
+}
+
+/// Request the route of a parcel, None if it isn't on any flight
+pub async fn get_parcel_route(
+    parcel_id: &str,
+    grpc_clients: &GrpcClients,
+) -> Result<Option<ParcelRoute>, StatusCode> {
+    let legs = get_parcel_legs(parcel_id, grpc_clients).await?;
+    if legs.is_empty() {
+        return Ok(None);
+    }
+
+    let (first, last) = (&legs[0], &legs[legs.len() - 1]);
+    let departure = first
+        .actual_departure_time
+        .clone()
+        .or_else(|| first.origin_timeslot_start.clone());
+    let arrival = last
+        .actual_arrival_time
+        .clone()
+        .or_else(|| last.target_timeslot_start.clone());
+    let (Some(departure), Some(arrival)) = (departure, arrival) else {
+        let error_msg = "flight plans have no timeslots.".to_string();
+        rest_error!("(get_parcel_route) {} parcel {}", &error_msg, parcel_id);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+
+    let (Some(origin_vertiport_id), Some(destination_vertiport_id)) = (
+        first.origin_vertiport_id.clone(),
+        last.target_vertiport_id.clone(),
+    ) else {
+        let error_msg = "flight plans have no vertiports.".to_string();
+        rest_error!("(get_parcel_route) {} parcel {}", &error_msg, parcel_id);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+
+    let paths = legs
+        .iter()
+        .map(|leg| leg.path.as_ref().map(line_string))
+        .collect::<Option<Vec<_>>>()
+        .unwrap_or_default();
+
+    Ok(Some(ParcelRoute {
+        origin: get_vertiport_polygon(&origin_vertiport_id, grpc_clients).await?,
+        origin_vertiport_id,
+        destination: get_vertiport_polygon(&destination_vertiport_id, grpc_clients).await?,
+        destination_vertiport_id,
+        departure: departure.into(),
+        arrival: arrival.into(),
+        paths,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use geo::{point, HaversineDistance};
+
+    /// A square of about 220m by 140m around the given location
+    fn square(latitude: f64, longitude: f64) -> Polygon {
+        let d = 0.001;
+        Polygon::new(
+            LineString::from(vec![
+                (longitude - d, latitude - d),
+                (longitude + d, latitude - d),
+                (longitude + d, latitude + d),
+                (longitude - d, latitude + d),
+                (longitude - d, latitude - d),
+            ]),
+            vec![],
+        )
+    }
+
+    fn route(now: DateTime<Utc>) -> ParcelRoute {
+        ParcelRoute {
+            origin_vertiport_id: "origin".to_string(),
+            origin: square(52.37, 4.90),
+            destination_vertiport_id: "destination".to_string(),
+            destination: square(52.09, 5.12),
+            departure: now,
+            arrival: now + Duration::hours(1),
+            paths: vec![LineString::from(vec![(4.90, 52.37), (5.12, 52.09)])],
+        }
+    }
+
+    #[test]
+    fn ut_distance_to_polygon() {
+        let outline = square(52.37, 4.90);
+        let inside = Coord {
+            x: 4.9005,
+            y: 52.37,
+        };
+        assert_eq!(distance_to_polygon(inside, &outline), 0.0);
+
+        // North of the square, compared with the great circle distance
+        let north = Coord { x: 4.90, y: 52.39 };
+        let expected = point!(x: 4.90, y: 52.39).haversine_distance(&point!(x: 4.90, y: 52.371));
+        let distance = distance_to_polygon(north, &outline);
+        assert!((distance - expected).abs() < 1.0, "{distance} {expected}");
+
+        let path = LineString::from(vec![(4.90, 52.37), (5.12, 52.09)]);
+        assert!(distance_to_path(Coord { x: 5.01, y: 52.23 }, &path) < 200.0);
+        assert!(distance_to_path(Coord { x: 4.70, y: 52.23 }, &path) > 10_000.0);
+    }
+
+    #[test]
+    fn ut_check_route() {
+        let now = Utc::now();
+        let route = route(now);
+        let tolerance = Tolerance {
+            vertiport_margin_meters: 100.0,
+            corridor_meters: 2000.0,
+        };
+        let before = now - Duration::minutes(10);
+        let during = now + Duration::minutes(30);
+        let after = now + Duration::hours(2);
+
+        // Before departure at the origin only
+        assert_eq!(route.check(52.37, 4.90, before, &tolerance), None);
+        assert_eq!(route.check(52.3715, 4.90, before, &tolerance), None);
+        let deviation = route.check(52.09, 5.12, before, &tolerance).unwrap();
+        assert_eq!(deviation.kind, ScanAnomalyKind::NotAtOrigin);
+        assert_eq!(deviation.vertiport_id.as_deref(), Some("origin"));
+        assert!(deviation.distance_meters > 30_000.0);
+
+        // During the flight along the path, or at either vertiport
+        assert_eq!(route.check(52.23, 5.01, during, &tolerance), None);
+        assert_eq!(route.check(52.37, 4.90, during, &tolerance), None);
+        assert_eq!(route.check(52.09, 5.12, during, &tolerance), None);
+        let deviation = route.check(51.92, 4.48, during, &tolerance).unwrap();
+        assert_eq!(deviation.kind, ScanAnomalyKind::OffCorridor);
+        assert_eq!(deviation.vertiport_id, None);
+
+        // After arrival at the destination only
+        assert_eq!(route.check(52.09, 5.12, after, &tolerance), None);
+        let deviation = route.check(52.37, 4.90, after, &tolerance).unwrap();
+        assert_eq!(deviation.kind, ScanAnomalyKind::NotAtDestination);
+        assert_eq!(deviation.vertiport_id.as_deref(), Some("destination"));
+
+        // Arrivals are scans at the destination after the last flight
+        assert!(route.is_arrival(52.09, 5.12, after, &tolerance));
+        assert!(!route.is_arrival(52.09, 5.12, during, &tolerance));
+        assert!(!route.is_arrival(52.37, 4.90, after, &tolerance));
+
+        // Without flight paths, scans during the flight aren't checked
+        let route = ParcelRoute {
+            paths: vec![],
+            ..route
+        };
+        assert_eq!(route.check(51.92, 4.48, during, &tolerance), None);
+    }
+}
@@ -1,119 +1,147 @@
-use crate::grpc::client::GrpcClients;
-use axum::extract::Extension;
+use super::rest_types::{DependencyHealth, HealthResponse};
+use crate::health::{HealthReport, HealthState};
+use axum::{extract::Extension, Json};
 use hyper::StatusCode;
 
-use svc_scheduler_client_grpc::prelude::{scheduler, SchedulerServiceClient};
-use svc_storage_client_grpc::prelude::{ReadyRequest, SimpleClient};
+/// Converts the latest health report into a response body
+fn health_response(report: Option<HealthReport>) -> HealthResponse {
+    let Some(report) = report else {
+        return HealthResponse {
+            ready: false,
+            checked_at: None,
+            dependencies: vec![],
+        };
+    };
+
+    HealthResponse {
+        ready: report.is_ready(),
+        checked_at: Some(report.checked_at),
+        dependencies: report
+            .dependencies
+            .into_iter()
+            .map(|dependency| DependencyHealth {
+                name: dependency.name.to_string(),
+                healthy: dependency.healthy,
+                latency_ms: dependency.latency.as_millis() as u64,
+                error: dependency.error,
+            })
+            .collect(),
+    }
+}
 
-#[utoipa::path(
+/// Readiness of the service, from the latest dependency probes
+///
+/// Dependencies are probed in the background, this doesn't call them.
+#[cfg_attr(feature = "openapi", utoipa::path(
     get,
-    path = "/health",
+    path = "/health/ready",
     tag = "svc-cargo",
     responses(
-        (status = 200, description = "Service is healthy, all dependencies running."),
-        (status = 503, description = "Service is unhealthy, one or more dependencies unavailable.")
+        (status = 200, description = "Service is ready, all dependencies running.", body = HealthResponse),
+        (status = 503, description = "Service is not ready, one or more dependencies unavailable.", body = HealthResponse)
     )
-)]
-pub async fn health_check(
-    Extension(grpc_clients): Extension<GrpcClients>,
-) -> Result<(), StatusCode> {
-    rest_debug!("(health_check) entry.");
-
-    let mut ok = true;
+))]
+pub async fn health_ready(
+    Extension(health): Extension<HealthState>,
+) -> (StatusCode, Json<HealthResponse>) {
+    rest_debug!("(health_ready) entry.");
 
-    // This health check is to verify that ALL dependencies of this
-    // microservice are running.
-    if grpc_clients
-        .storage
-        .vertiport
-        .is_ready(ReadyRequest {})
-        .await
-        .is_err()
-    {
-        let error_msg = "svc-storage vertiport unavailable.".to_string();
-        rest_error!("(health_check) {}.", &error_msg);
-        ok = false;
+    let response = health_response(health.latest());
+    if response.ready {
+        rest_debug!("(health_ready) ready, all dependencies running.");
+        return (StatusCode::OK, Json(response));
     }
 
-    if grpc_clients
-        .storage
-        .vertipad
-        .is_ready(ReadyRequest {})
-        .await
-        .is_err()
-    {
-        let error_msg = "svc-storage vertipad unavailable.".to_string();
-        rest_error!("(health_check) {}.", &error_msg);
-        ok = false;
-    };
+    let unhealthy: Vec<&str> = response
+        .dependencies
+        .iter()
+        .filter(|dependency| !dependency.healthy)
+        .map(|dependency| dependency.name.as_str())
+        .collect();
+    rest_error!("(health_ready) not ready, unhealthy: {:?}.", unhealthy);
+    (StatusCode::SERVICE_UNAVAILABLE, Json(response))
+}
 
-    if grpc_clients
-        .storage
-        .parcel
-        .is_ready(ReadyRequest {})
-        .await
-        .is_err()
-    {
-        let error_msg = "svc-storage parcel unavailable.".to_string();
-        rest_error!("(health_check) {}.", &error_msg);
-        ok = false;
-    };
+/// Liveness of the service, doesn't depend on its dependencies
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "svc-cargo",
+    responses(
+        (status = 200, description = "Service is running.")
+    )
+))]
+pub async fn health_live() -> StatusCode {
+    StatusCode::OK
+}
 
-    if grpc_clients
-        .storage
-        .parcel_scan
-        .is_ready(ReadyRequest {})
-        .await
-        .is_err()
-    {
-        let error_msg = "svc-storage parcel_scan unavailable.".to_string();
-        rest_error!("(health_check) {}.", &error_msg);
-        ok = false;
-    };
+/// Same as `/health/ready`, kept for existing clients
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/health",
+    tag = "svc-cargo",
+    responses(
+        (status = 200, description = "Service is healthy, all dependencies running.", body = HealthResponse),
+        (status = 503, description = "Service is unhealthy, one or more dependencies unavailable.", body = HealthResponse)
+    )
+))]
+pub async fn health_check(
+    Extension(health): Extension<HealthState>,
+) -> (StatusCode, Json<HealthResponse>) {
+    health_ready(Extension(health)).await
+}
 
-    if grpc_clients
-        .storage
-        .flight_plan
-        .is_ready(ReadyRequest {})
-        .await
-        .is_err()
-    {
-        let error_msg = "svc-storage flight_plan unavailable.".to_string();
-        rest_error!("(health_check) {}.", &error_msg);
-        ok = false;
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::ProbeResult;
+    use chrono::Utc;
+    use std::time::Duration;
 
-    if grpc_clients
-        .storage
-        .vehicle
-        .is_ready(ReadyRequest {})
-        .await
-        .is_err()
-    {
-        let error_msg = "svc-storage vehicle unavailable.".to_string();
-        rest_error!("(health_check) {}.", &error_msg);
-        ok = false;
-    };
+    #[test]
+    fn ut_health_response() {
+        let response = health_response(None);
+        assert!(!response.ready);
+        assert!(response.dependencies.is_empty());
 
-    if grpc_clients
-        .scheduler
-        .is_ready(scheduler::ReadyRequest {})
-        .await
-        .is_err()
-    {
-        let error_msg = "svc-scheduler client unavailable.".to_string();
-        rest_error!("(health_check) {}", &error_msg);
-        ok = false;
-    };
+        let checked_at = Utc::now();
+        let report = HealthReport {
+            checked_at,
+            dependencies: vec![
+                ProbeResult {
+                    name: "pricing",
+                    healthy: true,
+                    latency: Duration::from_millis(12),
+                    error: None,
+                },
+                ProbeResult {
+                    name: "storage.parcel",
+                    healthy: false,
+                    latency: Duration::from_millis(2000),
+                    error: Some("timed out after 2000 ms".to_string()),
+                },
+            ],
+        };
 
-    match ok {
-        true => {
-            rest_info!("(health_check) healthy, all dependencies running.");
-            Ok(())
-        }
-        false => {
-            rest_error!("(health_check) unhealthy, 1+ dependencies down.");
-            Err(StatusCode::SERVICE_UNAVAILABLE)
-        }
+        let response = health_response(Some(report));
+        assert!(!response.ready);
+        assert_eq!(response.checked_at, Some(checked_at));
+        assert_eq!(
+            response.dependencies,
+            vec![
+                DependencyHealth {
+                    name: "pricing".to_string(),
+                    healthy: true,
+                    latency_ms: 12,
+                    error: None,
+                },
+                DependencyHealth {
+                    name: "storage.parcel".to_string(),
+                    healthy: false,
+                    latency_ms: 2000,
+                    error: Some("timed out after 2000 ms".to_string()),
+                },
+            ]
+        );
     }
 }
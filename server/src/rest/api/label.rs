@@ -0,0 +1,928 @@
+//! Printable parcel labels
+//!
+//! Labels are 4 by 6 inches, laid out in the dots of a 203 dpi thermal
+//!  printer and drawn from the same layout in each format. Text is printed
+//!  in capitals with a fixed width font, so it takes the same room in every
+//!  format. A label only depends on its parcel, so the same parcel always
+//!  gets the same file.
+
+use super::barcode::parcel_barcode;
+use super::rest_types::{LabelFormat, LabelParams};
+use super::shipment::Shipments;
+use super::utils::{get_parcel_details, get_parcel_legs, get_vertiport_details, is_uuid};
+use crate::grpc::client::GrpcClients;
+use axum::{
+    extract::{Extension, Path, Query},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str};
+use qrcode::{Color, EcLevel, QrCode};
+use std::fmt::Write;
+use std::io;
+
+/// Printer resolution the layout is made for, in dots per inch
+const DPI: u32 = 203;
+
+/// Label width in dots, 4 inches
+const WIDTH: u32 = 812;
+
+/// Label height in dots, 6 inches
+const HEIGHT: u32 = 1218;
+
+/// Blank border around the label content
+const MARGIN: u32 = 40;
+
+/// Size of a QR code module in dots, the largest ZPL printers draw
+const QR_MODULE: u32 = 10;
+
+/// Glyphs of the label font, 5 dots wide and 7 high, leftmost dot first
+#[rustfmt::skip]
+const GLYPHS: &[(char, [u8; 7])] = &[
+    (' ', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('B', [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110]),
+    ('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110]),
+    ('D', [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110]),
+    ('E', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+    ('F', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111]),
+    ('H', [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100]),
+    ('K', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+    ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+    ('M', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+    ('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+    ('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101]),
+    ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+    ('S', [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110]),
+    ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100]),
+    ('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010]),
+    ('X', [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001]),
+    ('Y', [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100]),
+    ('Z', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111]),
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    ('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+    (':', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000]),
+    ('/', [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000]),
+    (',', [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000]),
+    ('(', [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010]),
+    (')', [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000]),
+    ('\'', [0b01100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('&', [0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101]),
+    ('+', [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000]),
+    ('#', [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010]),
+    ('?', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100]),
+];
+
+/// Dots of a glyph of the label font
+fn glyph(c: char) -> [u8; 7] {
+    GLYPHS
+        .iter()
+        .find(|(glyph, _)| *glyph == c)
+        .map(|(_, rows)| *rows)
+        .unwrap_or([0; 7])
+}
+
+/// Text in capitals, characters missing from the label font become `?`
+fn label_text(text: &str) -> String {
+    text.trim()
+        .chars()
+        .flat_map(char::to_uppercase)
+        .map(|c| match GLYPHS.iter().any(|(glyph, _)| *glyph == c) {
+            true => c,
+            false => '?',
+        })
+        .collect()
+}
+
+/// Width in dots of text, characters are 6 dots apart at scale 1
+fn text_width(text: &str, scale: u32) -> u32 {
+    (text.chars().count() as u32 * 6).saturating_sub(1) * scale
+}
+
+/// What a parcel label shows
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParcelLabel {
+    /// The String ID of the parcel
+    pub parcel_id: String,
+
+    /// The String ID of the itinerary, if known
+    pub itinerary_id: Option<String>,
+
+    /// Name of the vertiport the parcel departs from
+    pub origin: String,
+
+    /// Name of the vertiport the parcel is delivered to
+    pub destination: String,
+
+    /// Weight of the parcel
+    pub weight_grams: u32,
+
+    /// Scheduled departure of the first flight
+    pub departure: DateTime<Utc>,
+}
+
+/// Something drawn on a label, positioned in dots from the top left corner
+#[derive(Debug, Clone, PartialEq)]
+enum Element {
+    /// Text in the label font, with glyph dots `scale` dots wide
+    Text {
+        x: u32,
+        y: u32,
+        scale: u32,
+        text: String,
+    },
+
+    /// Filled rectangle
+    Bar {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+
+    /// QR code of `data`, `size` modules wide, dark modules row by row
+    Qr {
+        x: u32,
+        y: u32,
+        data: String,
+        size: u32,
+        modules: Vec<bool>,
+    },
+}
+
+impl ParcelLabel {
+    /// The label in the given format
+    pub fn render(&self, format: LabelFormat) -> io::Result<Vec<u8>> {
+        let elements = self.layout()?;
+        match format {
+            LabelFormat::Png => render_png(&elements),
+            LabelFormat::Svg => Ok(render_svg(&elements).into_bytes()),
+            LabelFormat::Pdf => Ok(render_pdf(&elements)),
+            LabelFormat::Zpl => Ok(render_zpl(&elements).into_bytes()),
+        }
+    }
+
+    fn layout(&self) -> io::Result<Vec<Element>> {
+        let barcode = parcel_barcode(&self.parcel_id).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "parcel ID is not a UUID")
+        })?;
+        let qr = QrCode::with_error_correction_level(&barcode, EcLevel::M)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+
+        let text = |y: u32, scale: u32, text: &str| {
+            // Long names are cut to fit between the margins
+            let fits = ((WIDTH - 2 * MARGIN) / scale + 1) / 6;
+            Element::Text {
+                x: MARGIN,
+                y,
+                scale,
+                text: label_text(text).chars().take(fits as usize).collect(),
+            }
+        };
+        let centered = |y: u32, scale: u32, text: &str| {
+            let text = label_text(text);
+            Element::Text {
+                x: WIDTH.saturating_sub(text_width(&text, scale)) / 2,
+                y,
+                scale,
+                text,
+            }
+        };
+        let rule = |y: u32| Element::Bar {
+            x: MARGIN,
+            y,
+            width: WIDTH - 2 * MARGIN,
+            height: 4,
+        };
+
+        let size = qr.width() as u32;
+        let weight = format!("{:.2} kg", f64::from(self.weight_grams) / 1000.0);
+        let departure = self.departure.format("%Y-%m-%d %H:%M UTC").to_string();
+        Ok(vec![
+            text(40, 4, "Arrow Cargo"),
+            rule(90),
+            text(115, 3, "From"),
+            text(150, 5, &self.origin),
+            text(210, 3, "To"),
+            text(245, 6, &self.destination),
+            rule(315),
+            text(340, 3, "Departs"),
+            text(370, 4, &departure),
+            text(425, 3, "Weight"),
+            text(455, 4, &weight),
+            text(510, 3, "Itinerary"),
+            text(540, 3, self.itinerary_id.as_deref().unwrap_or("-")),
+            rule(585),
+            Element::Qr {
+                x: (WIDTH - size * QR_MODULE) / 2,
+                y: 630,
+                data: barcode.clone(),
+                size,
+                modules: qr
+                    .to_colors()
+                    .into_iter()
+                    .map(|c| c == Color::Dark)
+                    .collect(),
+            },
+            centered(925, 3, &barcode),
+            centered(975, 2, &format!("Parcel {}", self.parcel_id)),
+        ])
+    }
+}
+
+/// Rectangles of the dark modules of a QR code, joined along each row
+fn qr_bars(x: u32, y: u32, size: u32, modules: &[bool]) -> Vec<(u32, u32, u32, u32)> {
+    let mut bars = vec![];
+    for (row, dark) in modules.chunks(size as usize).enumerate() {
+        let mut column = 0;
+        while column < dark.len() {
+            if !dark[column] {
+                column += 1;
+                continue;
+            }
+
+            let start = column;
+            while column < dark.len() && dark[column] {
+                column += 1;
+            }
+            bars.push((
+                x + start as u32 * QR_MODULE,
+                y + row as u32 * QR_MODULE,
+                (column - start) as u32 * QR_MODULE,
+                QR_MODULE,
+            ));
+        }
+    }
+
+    bars
+}
+
+/// Rectangles of the dots of text in the label font
+fn text_bars(x: u32, y: u32, scale: u32, text: &str) -> Vec<(u32, u32, u32, u32)> {
+    let mut bars = vec![];
+    for (index, c) in text.chars().enumerate() {
+        let left = x + index as u32 * 6 * scale;
+        for (row, dots) in glyph(c).iter().enumerate() {
+            for column in 0..5 {
+                if dots & (0b10000 >> column) != 0 {
+                    bars.push((left + column * scale, y + row as u32 * scale, scale, scale));
+                }
+            }
+        }
+    }
+
+    bars
+}
+
+/// 1 bit grayscale image, one dot per pixel
+fn render_png(elements: &[Element]) -> io::Result<Vec<u8>> {
+    let mut dark = vec![false; (WIDTH * HEIGHT) as usize];
+    let mut fill = |(x, y, width, height): (u32, u32, u32, u32)| {
+        for row in y..(y + height).min(HEIGHT) {
+            for column in x..(x + width).min(WIDTH) {
+                dark[(row * WIDTH + column) as usize] = true;
+            }
+        }
+    };
+    for element in elements {
+        match element {
+            Element::Text { x, y, scale, text } => text_bars(*x, *y, *scale, text)
+                .into_iter()
+                .for_each(&mut fill),
+            Element::Bar {
+                x,
+                y,
+                width,
+                height,
+            } => fill((*x, *y, *width, *height)),
+            Element::Qr {
+                x,
+                y,
+                size,
+                modules,
+                ..
+            } => qr_bars(*x, *y, *size, modules)
+                .into_iter()
+                .for_each(&mut fill),
+        }
+    }
+
+    // White pixels are 1, 8 to a byte
+    let rows: Vec<u8> = dark
+        .chunks(WIDTH as usize)
+        .flat_map(|row| {
+            row.chunks(8).map(|pixels| {
+                pixels
+                    .iter()
+                    .enumerate()
+                    .fold(0xff, |byte, (i, dark)| match dark {
+                        true => byte & !(0x80 >> i),
+                        false => byte,
+                    })
+            })
+        })
+        .collect();
+
+    let mut png = vec![];
+    let mut encoder = png::Encoder::new(&mut png, WIDTH, HEIGHT);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::One);
+    encoder.set_pixel_dims(Some(png::PixelDimensions {
+        xppu: DPI * 10_000 / 254,
+        yppu: DPI * 10_000 / 254,
+        unit: png::Unit::Meter,
+    }));
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(&rows).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)?;
+
+    Ok(png)
+}
+
+/// Text escaped for XML
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&apos;")
+        .replace('"', "&quot;")
+}
+
+/// SVG image measured in dots, 4 by 6 inches when printed
+fn render_svg(elements: &[Element]) -> String {
+    let mut svg = String::new();
+    let _ = writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="4in" height="6in" viewBox="0 0 {WIDTH} {HEIGHT}">"#
+    );
+    let _ = writeln!(
+        svg,
+        r##"<rect width="{WIDTH}" height="{HEIGHT}" fill="#fff"/>"##
+    );
+    for element in elements {
+        match element {
+            // Courier characters are 0.6 em apart
+            Element::Text { x, y, scale, text } => {
+                let _ = writeln!(
+                    svg,
+                    r#"<text x="{x}" y="{}" font-family="Courier, monospace" font-size="{}">{}</text>"#,
+                    y + 7 * scale,
+                    10 * scale,
+                    xml_escape(text)
+                );
+            }
+            Element::Bar {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let _ = writeln!(
+                    svg,
+                    r#"<rect x="{x}" y="{y}" width="{width}" height="{height}"/>"#
+                );
+            }
+            Element::Qr {
+                x,
+                y,
+                size,
+                modules,
+                ..
+            } => {
+                let path: Vec<String> = qr_bars(*x, *y, *size, modules)
+                    .into_iter()
+                    .map(|(x, y, width, height)| format!("M{x} {y}h{width}v{height}h-{width}z"))
+                    .collect();
+                let _ = writeln!(svg, r#"<path d="{}"/>"#, path.join(""));
+            }
+        }
+    }
+    svg.push_str("</svg>\n");
+
+    svg
+}
+
+/// Single page PDF document of 4 by 6 inches, without creation date
+fn render_pdf(elements: &[Element]) -> Vec<u8> {
+    // Dots to points, with the origin at the bottom left
+    let scale = 72.0 / DPI as f32;
+    let page_height = HEIGHT as f32 * scale;
+    let rect = |content: &mut Content, (x, y, width, height): (u32, u32, u32, u32)| {
+        content
+            .rect(
+                x as f32 * scale,
+                page_height - (y + height) as f32 * scale,
+                width as f32 * scale,
+                height as f32 * scale,
+            )
+            .fill_nonzero();
+    };
+
+    let mut content = Content::new();
+    for element in elements {
+        match element {
+            // Courier characters are 0.6 em apart
+            Element::Text {
+                x,
+                y,
+                scale: size,
+                text,
+            } => {
+                content
+                    .begin_text()
+                    .set_font(Name(b"F1"), (10 * size) as f32 * scale)
+                    .next_line(
+                        *x as f32 * scale,
+                        page_height - (y + 7 * size) as f32 * scale,
+                    )
+                    .show(Str(text.as_bytes()))
+                    .end_text();
+            }
+            Element::Bar {
+                x,
+                y,
+                width,
+                height,
+            } => rect(&mut content, (*x, *y, *width, *height)),
+            Element::Qr {
+                x,
+                y,
+                size,
+                modules,
+                ..
+            } => qr_bars(*x, *y, *size, modules)
+                .into_iter()
+                .for_each(|bar| rect(&mut content, bar)),
+        }
+    }
+
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let page_id = Ref::new(3);
+    let font_id = Ref::new(4);
+    let content_id = Ref::new(5);
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id).kids([page_id]).count(1);
+    let mut page = pdf.page(page_id);
+    page.parent(page_tree_id)
+        .media_box(Rect::new(0.0, 0.0, WIDTH as f32 * scale, page_height))
+        .contents(content_id);
+    page.resources().fonts().pair(Name(b"F1"), font_id);
+    page.finish();
+    pdf.type1_font(font_id).base_font(Name(b"Courier"));
+    pdf.stream(content_id, &content.finish());
+
+    pdf.finish()
+}
+
+/// ZPL for 203 dpi printers, which draw the QR code themselves
+fn render_zpl(elements: &[Element]) -> String {
+    let mut zpl = String::new();
+    let _ = writeln!(zpl, "^XA");
+    let _ = writeln!(zpl, "^PW{WIDTH}");
+    let _ = writeln!(zpl, "^LL{HEIGHT}");
+    for element in elements {
+        match element {
+            Element::Text { x, y, scale, text } => {
+                let _ = writeln!(
+                    zpl,
+                    "^FO{x},{y}^A0N,{},{}^FD{text}^FS",
+                    10 * scale,
+                    6 * scale
+                );
+            }
+            Element::Bar {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let _ = writeln!(
+                    zpl,
+                    "^FO{x},{y}^GB{width},{height},{}^FS",
+                    width.min(height)
+                );
+            }
+            // Model 2, error correction M, automatic data mode
+            Element::Qr { x, y, data, .. } => {
+                let _ = writeln!(zpl, "^FO{x},{y}^BQN,2,{QR_MODULE}^FDMA,{data}^FS");
+            }
+        }
+    }
+    let _ = writeln!(zpl, "^XZ");
+
+    zpl
+}
+
+/// Content type and file extension of a label format
+fn media_type(format: LabelFormat) -> (&'static str, &'static str) {
+    match format {
+        LabelFormat::Png => ("image/png", "png"),
+        LabelFormat::Svg => ("image/svg+xml", "svg"),
+        LabelFormat::Pdf => ("application/pdf", "pdf"),
+        LabelFormat::Zpl => ("application/zpl", "zpl"),
+    }
+}
+
+/// Print a parcel label
+///
+/// The label has a QR code of the parcel barcode, the origin and destination
+///  vertiports, the itinerary, the weight and the scheduled departure. The
+///  itinerary is only known for parcels confirmed through this service.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/cargo/parcels/{id}/label",
+    tag = "svc-cargo",
+    params(
+        ("id" = String, Path, description = "The unique ID (UUID) of the parcel"),
+        LabelParams
+    ),
+    responses(
+        (status = 200, description = "Label in the requested format", content_type = "application/pdf"),
+        (status = 400, description = "Parcel ID is invalid", body = String),
+        (status = 404, description = "Parcel not found", body = String),
+        (status = 409, description = "Parcel is not on any flight", body = String),
+        (status = 500, description = "svc-storage returned error", body = String),
+        (status = 503, description = "Could not connect to other microservice dependencies"),
+        (status = 504, description = "Microservice dependencies did not respond in time")
+    )
+))]
+pub async fn get_parcel_label(
+    Extension(grpc_clients): Extension<GrpcClients>,
+    Extension(shipments): Extension<Shipments>,
+    Path(parcel_id): Path<String>,
+    Query(params): Query<LabelParams>,
+) -> Result<Response, (StatusCode, String)> {
+    rest_debug!("(get_parcel_label) entry.");
+
+    if !is_uuid(&parcel_id) {
+        let error_msg = "parcel ID not in UUID format.".to_string();
+        rest_error!("(get_parcel_label) {}", &error_msg);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
+    }
+
+    let storage_error = |status: StatusCode| {
+        let error_msg = match status {
+            StatusCode::NOT_FOUND => format!("parcel {parcel_id} not found."),
+            _ => "could not get the parcel from svc-storage.".to_string(),
+        };
+        rest_error!("(get_parcel_label) {}", &error_msg);
+        (status, error_msg)
+    };
+
+    let parcel = get_parcel_details(&parcel_id, &grpc_clients)
+        .await
+        .map_err(storage_error)?;
+    let legs = get_parcel_legs(&parcel_id, &grpc_clients)
+        .await
+        .map_err(storage_error)?;
+    let (Some(first), Some(last)) = (legs.first(), legs.last()) else {
+        let error_msg = "parcel is not on any flight.".to_string();
+        rest_error!("(get_parcel_label) {} {}", &error_msg, parcel_id);
+        return Err((StatusCode::CONFLICT, error_msg));
+    };
+
+    let (Some(origin_id), Some(destination_id), Some(departure)) = (
+        first.origin_vertiport_id.as_ref(),
+        last.target_vertiport_id.as_ref(),
+        first.origin_timeslot_start.clone(),
+    ) else {
+        let error_msg = "flight plans have no vertiports or timeslots.".to_string();
+        rest_error!("(get_parcel_label) {} parcel {}", &error_msg, parcel_id);
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, error_msg));
+    };
+
+    let origin = get_vertiport_details(origin_id, &grpc_clients)
+        .await
+        .map_err(storage_error)?;
+    let destination = get_vertiport_details(destination_id, &grpc_clients)
+        .await
+        .map_err(storage_error)?;
+
+    let itinerary_id = match shipments.get(&parcel_id) {
+        Ok(shipment) => shipment.map(|shipment| shipment.itinerary_id),
+        Err(e) => {
+            rest_warn!("(get_parcel_label) shipment store unavailable: {}", e);
+            None
+        }
+    };
+
+    let label = ParcelLabel {
+        parcel_id: parcel_id.clone(),
+        itinerary_id,
+        origin: origin.name,
+        destination: destination.name,
+        weight_grams: parcel.weight_grams,
+        departure: departure.into(),
+    };
+
+    let format = params.format.unwrap_or_default();
+    let body = label.render(format).map_err(|e| {
+        let error_msg = "could not render the label.".to_string();
+        rest_error!("(get_parcel_label) {} {}", &error_msg, e);
+        (StatusCode::INTERNAL_SERVER_ERROR, error_msg)
+    })?;
+
+    let (content_type, extension) = media_type(format);
+    let disposition = format!("inline; filename=\"parcel-{parcel_id}.{extension}\"");
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn mock_label() -> ParcelLabel {
+        ParcelLabel {
+            parcel_id: "cabcdd14-03ab-4ac0-b58c-dd4175bc587e".to_string(),
+            itinerary_id: Some("8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1".to_string()),
+            origin: "Amsterdam Centraal".to_string(),
+            destination: "Utrecht Science Park".to_string(),
+            weight_grams: 1250,
+            departure: Utc.with_ymd_and_hms(2026, 10, 19, 14, 30, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn ut_label_text() {
+        assert_eq!(label_text(" Zürich Hbf "), "Z?RICH HBF");
+        assert_eq!(label_text("<Dock #2>"), "?DOCK #2?");
+        assert_eq!(text_width("ABC", 2), 34);
+
+        // Every glyph of the font is printed as is
+        let font: String = GLYPHS.iter().map(|(c, _)| *c).collect();
+        assert_eq!(label_text(&font), font.trim());
+    }
+
+    #[test]
+    fn ut_label_layout() {
+        let elements = mock_label().layout().unwrap();
+        for element in &elements {
+            let (x, y, width, height) = match element {
+                Element::Text { x, y, scale, text } => {
+                    (*x, *y, text_width(text, *scale), 7 * scale)
+                }
+                Element::Bar {
+                    x,
+                    y,
+                    width,
+                    height,
+                } => (*x, *y, *width, *height),
+                Element::Qr { x, y, size, .. } => (*x, *y, size * QR_MODULE, size * QR_MODULE),
+            };
+            assert!(x >= MARGIN && x + width <= WIDTH - MARGIN, "{:?}", element);
+            assert!(
+                y >= MARGIN && y + height <= HEIGHT - MARGIN,
+                "{:?}",
+                element
+            );
+        }
+
+        // Long names are cut to fit
+        let label = ParcelLabel {
+            destination: "A".repeat(100),
+            ..mock_label()
+        };
+        assert!(label.layout().unwrap().iter().any(|element| matches!(
+            element,
+            Element::Text { scale: 6, text, .. } if text.len() == 20
+        )));
+
+        let label = ParcelLabel {
+            parcel_id: "not-a-uuid".to_string(),
+            ..mock_label()
+        };
+        assert!(label.render(LabelFormat::Pdf).is_err());
+    }
+
+    #[test]
+    fn ut_render_zpl() {
+        let zpl = String::from_utf8(mock_label().render(LabelFormat::Zpl).unwrap()).unwrap();
+        assert_eq!(
+            zpl,
+            "^XA\n\
+             ^PW812\n\
+             ^LL1218\n\
+             ^FO40,40^A0N,40,24^FDARROW CARGO^FS\n\
+             ^FO40,90^GB732,4,4^FS\n\
+             ^FO40,115^A0N,30,18^FDFROM^FS\n\
+             ^FO40,150^A0N,50,30^FDAMSTERDAM CENTRAAL^FS\n\
+             ^FO40,210^A0N,30,18^FDTO^FS\n\
+             ^FO40,245^A0N,60,36^FDUTRECHT SCIENCE PARK^FS\n\
+             ^FO40,315^GB732,4,4^FS\n\
+             ^FO40,340^A0N,30,18^FDDEPARTS^FS\n\
+             ^FO40,370^A0N,40,24^FD2026-10-19 14:30 UTC^FS\n\
+             ^FO40,425^A0N,30,18^FDWEIGHT^FS\n\
+             ^FO40,455^A0N,40,24^FD1.25 KG^FS\n\
+             ^FO40,510^A0N,30,18^FDITINERARY^FS\n\
+             ^FO40,540^A0N,30,18^FD8EC4D5EC-E9A7-4E93-A5D5-6D6DD4F5E2A1^FS\n\
+             ^FO40,585^GB732,4,4^FS\n\
+             ^FO281,630^BQN,2,10^FDMA,ARWCABCDD1403AB4AC0B58CDD4175BC587E^FS\n\
+             ^FO92,925^A0N,30,18^FDARWCABCDD1403AB4AC0B58CDD4175BC587E^FS\n\
+             ^FO149,975^A0N,20,12^FDPARCEL CABCDD14-03AB-4AC0-B58C-DD4175BC587E^FS\n\
+             ^XZ\n"
+        );
+    }
+
+    #[test]
+    fn ut_render_label() {
+        let label = mock_label();
+        for format in [
+            LabelFormat::Png,
+            LabelFormat::Svg,
+            LabelFormat::Pdf,
+            LabelFormat::Zpl,
+        ] {
+            // The same parcel always gets the same file
+            let first = label.render(format).unwrap();
+            assert_eq!(first, label.render(format).unwrap(), "{:?}", format);
+
+            let other = ParcelLabel {
+                weight_grams: 900,
+                ..label.clone()
+            };
+            assert_ne!(first, other.render(format).unwrap(), "{:?}", format);
+        }
+
+        let png = label.render(LabelFormat::Png).unwrap();
+        let decoder = png::Decoder::new(png.as_slice());
+        let reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        assert_eq!((info.width, info.height), (WIDTH, HEIGHT));
+        assert_eq!(info.bit_depth, png::BitDepth::One);
+
+        let svg = String::from_utf8(label.render(LabelFormat::Svg).unwrap()).unwrap();
+        assert!(svg.contains(r#"viewBox="0 0 812 1218""#), "{}", svg);
+        assert!(svg.contains(">UTRECHT SCIENCE PARK</text>"), "{}", svg);
+        assert!(svg.contains("<path d=\"M"), "{}", svg);
+
+        let pdf = label.render(LabelFormat::Pdf).unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+        let pdf = String::from_utf8_lossy(&pdf);
+        assert!(pdf.contains("/MediaBox [0 0 288 432]"), "{}", pdf);
+        assert!(pdf.contains("(AMSTERDAM CENTRAAL) Tj"), "{}", pdf);
+        assert!(!pdf.contains("CreationDate"));
+    }
+
+    #[tokio::test]
+    async fn ut_get_parcel_label() {
+        use super::super::pickup::PickupState;
+        use super::super::shipment::{MemoryShipmentStore, Shipment};
+        use crate::Config;
+        use axum::{body::Body, http::Request, routing, Router};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use svc_storage_client_grpc::resources::{
+            flight_plan, flight_plan_parcel, parcel, vertiport,
+        };
+        use tonic::Status;
+        use tower::ServiceExt;
+
+        let label = mock_label();
+        let grpc_clients = GrpcClients::default(Config::default());
+        let storage = &grpc_clients.backends.storage;
+        storage.stub("parcel.get_by_id", || {
+            tonic::Response::new(parcel::Object {
+                id: "cabcdd14-03ab-4ac0-b58c-dd4175bc587e".to_string(),
+                data: Some(parcel::Data {
+                    weight_grams: 1250,
+                    ..parcel::mock::get_data_obj()
+                }),
+            })
+        });
+        storage.stub("flight_plan_parcel.search", || {
+            tonic::Response::new(flight_plan_parcel::RowDataList {
+                list: vec![flight_plan_parcel::RowData {
+                    flight_plan_id: "59e51ad1-d57d-4d2c-bc2d-e2387367d17f".to_string(),
+                    parcel_id: "cabcdd14-03ab-4ac0-b58c-dd4175bc587e".to_string(),
+                    acquire: true,
+                    deliver: true,
+                }],
+            })
+        });
+        let departure = label.departure;
+        storage.stub("flight_plan.get_by_id", move || {
+            tonic::Response::new(flight_plan::Object {
+                id: "59e51ad1-d57d-4d2c-bc2d-e2387367d17f".to_string(),
+                data: Some(flight_plan::Data {
+                    origin_vertiport_id: Some("origin".to_string()),
+                    target_vertiport_id: Some("destination".to_string()),
+                    origin_timeslot_start: Some(departure.into()),
+                    ..flight_plan::mock::get_data_obj()
+                }),
+            })
+        });
+        // The origin is requested before the destination
+        let requests = Arc::new(AtomicUsize::new(0));
+        storage.stub("vertiport.get_by_id", move || {
+            let name = match requests.fetch_add(1, Ordering::SeqCst) % 2 {
+                0 => "Amsterdam Centraal",
+                _ => "Utrecht Science Park",
+            };
+            tonic::Response::new(vertiport::Object {
+                id: uuid::Uuid::new_v4().to_string(),
+                data: Some(vertiport::Data {
+                    name: name.to_string(),
+                    ..vertiport::mock::get_data_obj()
+                }),
+            })
+        });
+
+        let shipments = Shipments::with_store(Arc::new(MemoryShipmentStore::default()));
+        shipments
+            .record(Shipment {
+                parcel_id: label.parcel_id.clone(),
+                itinerary_id: label.itinerary_id.clone().unwrap(),
+                user_id: "b3c1d7a6-3b0b-4f7c-8c6c-4e0d0d2b9f4e".to_string(),
+                weight_grams: 1250,
+                confirmed_at: Utc::now(),
+                recipient: None,
+                tracking_token: None,
+                notified: vec![],
+                pickup: PickupState::default(),
+            })
+            .await
+            .unwrap();
+        let app = Router::new()
+            .route("/cargo/parcels/:id/label", routing::get(get_parcel_label))
+            .layer(Extension(shipments))
+            .layer(Extension(grpc_clients.clone()));
+        let request = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let uri = "/cargo/parcels/cabcdd14-03ab-4ac0-b58c-dd4175bc587e/label";
+
+        let response = app
+            .clone()
+            .oneshot(request("/cargo/parcels/not-a-uuid/label"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .clone()
+            .oneshot(request(&format!("{uri}?format=gif")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // The label is rendered from the parcel, its flight, vertiports and shipment
+        for format in [LabelFormat::Svg, LabelFormat::Zpl] {
+            let (content_type, extension) = media_type(format);
+            let response = app
+                .clone()
+                .oneshot(request(&format!("{uri}?format={extension}")))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()[header::CONTENT_TYPE], content_type);
+            assert_eq!(
+                response.headers()[header::CONTENT_DISPOSITION],
+                format!(
+                    "inline; filename=\"parcel-cabcdd14-03ab-4ac0-b58c-dd4175bc587e.{extension}\""
+                )
+            );
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            assert_eq!(body, label.render(format).unwrap(), "{:?}", format);
+        }
+
+        // Parcels that aren't on a flight have no label
+        storage.stub("flight_plan_parcel.search", || {
+            tonic::Response::new(flight_plan_parcel::RowDataList { list: vec![] })
+        });
+        let response = app.clone().oneshot(request(uri)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        storage.stub_failure("parcel.get_by_id", Status::not_found("no parcel"));
+        let response = app.oneshot(request(uri)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
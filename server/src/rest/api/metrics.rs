@@ -0,0 +1,29 @@
+use crate::metrics::metrics;
+use axum::http::header;
+use hyper::StatusCode;
+
+/// Content type of the Prometheus text exposition format
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Get the metrics of this service in the Prometheus text format
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "svc-cargo",
+    responses(
+        (status = 200, description = "Metrics retrieved successfully", body = String),
+        (status = 500, description = "Metrics could not be encoded")
+    )
+))]
+pub async fn get_metrics() -> Result<([(header::HeaderName, &'static str); 1], String), StatusCode>
+{
+    rest_debug!("(get_metrics) entry.");
+
+    match metrics().render() {
+        Ok(text) => Ok(([(header::CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)], text)),
+        Err(e) => {
+            rest_error!("(get_metrics) could not encode metrics: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
@@ -1,11 +1,27 @@
 /// Types Used in REST Messages
+// Some types are only referred to by the OpenAPI specification
+#[cfg_attr(not(feature = "openapi"), allow(dead_code))]
 pub mod rest_types {
     include!("../../../../openapi/types.rs");
 }
+pub mod anomaly;
+pub mod availability;
+pub mod barcode;
+pub mod blob;
 pub mod cancel;
 pub mod confirm;
+pub mod delivery;
+pub mod error;
+pub mod geofence;
 pub mod health;
+pub mod label;
+pub mod metrics;
+pub mod notification;
+pub mod pickup;
+pub mod public;
 pub mod query;
 pub mod request;
 pub mod scan;
+pub mod scanner;
+pub mod shipment;
 pub mod utils;
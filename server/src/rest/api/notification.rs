@@ -0,0 +1,540 @@
+//! Notifications to the recipients of parcels
+//!
+//! Recipients given when an itinerary is confirmed are told when their
+//!  parcel is confirmed, when it arrives at the destination vertiport and
+//!  when it is delivered, once each. Messages go out by email and by text
+//!  message, posted to the configured provider URLs. Without a provider they
+//!  are appended to a file, or logged, so nothing is sent while testing.
+
+use super::delivery::MAX_RECIPIENT_NAME_LENGTH;
+use super::rest_types::RecipientContact;
+use super::shipment::Shipments;
+use crate::Config;
+use chrono::{DateTime, Utc};
+use futures::future::{BoxFuture, FutureExt};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Time after which posting a notification to a provider is given up
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest email address allowed by SMTP
+const MAX_EMAIL_LENGTH: usize = 254;
+
+/// Digits of an international phone number, country code included
+const PHONE_DIGITS: std::ops::RangeInclusive<usize> = 8..=15;
+
+/// What happened to a parcel
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParcelEvent {
+    /// The itinerary carrying the parcel was confirmed
+    Confirmed,
+
+    /// The parcel was scanned at the destination vertiport after its last flight
+    Arrived,
+
+    /// The parcel was handed to its recipient
+    Delivered,
+}
+
+/// How a notification reaches the recipient
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationChannel {
+    /// Email
+    Email,
+
+    /// Text message
+    Sms,
+}
+
+/// A message to the recipient of a parcel, as posted to providers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Notification {
+    /// The String ID of the parcel
+    pub parcel_id: String,
+
+    /// What happened to the parcel
+    pub event: ParcelEvent,
+
+    /// How the notification is sent
+    pub channel: NotificationChannel,
+
+    /// Email address or phone number of the recipient
+    pub to: String,
+
+    /// Subject of emails, not used by text messages
+    pub subject: String,
+
+    /// The message
+    pub text: String,
+
+    /// Public tracking link of the parcel, if any
+    pub tracking_url: Option<String>,
+
+    /// When the notification was made
+    pub created_at: DateTime<Utc>,
+}
+
+/// Sends notifications through one channel
+pub trait NotificationSender: Send + Sync + fmt::Debug {
+    /// Sends a notification, returns once the provider accepted it
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, io::Result<()>>;
+}
+
+/// Posts notifications as JSON to a provider, or a relay in front of one
+#[derive(Debug)]
+pub struct HttpSender {
+    url: String,
+    http: reqwest::Client,
+}
+
+impl HttpSender {
+    /// Posts notifications to the given URL
+    pub fn new(url: impl Into<String>) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(PROVIDER_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        HttpSender {
+            url: url.into(),
+            http,
+        }
+    }
+}
+
+impl NotificationSender for HttpSender {
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, io::Result<()>> {
+        async move {
+            self.http
+                .post(&self.url)
+                .json(notification)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(io::Error::other)?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// Appends notifications to a file, one JSON object per line
+#[derive(Debug)]
+pub struct FileSink {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileSink {
+    /// Appends notifications to the given file, created with the first notification
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileSink {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn append(&self, notification: &Notification) -> io::Result<()> {
+        let mut line = serde_json::to_vec(notification)?;
+        line.push(b'\n');
+
+        let _lock = self
+            .lock
+            .lock()
+            .map_err(|_| io::Error::other("notification file lock poisoned"))?;
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&line)
+    }
+}
+
+impl NotificationSender for FileSink {
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, io::Result<()>> {
+        let result = self.append(notification);
+        async move { result }.boxed()
+    }
+}
+
+/// Logs notifications instead of sending them
+///
+/// Only the parcel, event and channel are logged, the recipient and the
+///  message are kept out of the logs.
+#[derive(Debug, Default)]
+pub struct LogSink;
+
+impl NotificationSender for LogSink {
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, io::Result<()>> {
+        rest_info!(
+            "(LogSink::send) {:?} notification of parcel {} by {:?}.",
+            notification.event,
+            notification.parcel_id,
+            notification.channel
+        );
+        async { Ok(()) }.boxed()
+    }
+}
+
+/// Checks the contact details of a recipient, returns them tidied up or why they're rejected
+///
+/// Spaces, dashes, dots and parentheses are removed from phone numbers.
+pub fn check_recipient(recipient: &RecipientContact) -> Result<RecipientContact, String> {
+    let trimmed = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+    let name = trimmed(&recipient.name);
+    let email = trimmed(&recipient.email);
+    let phone = trimmed(&recipient.phone).map(|phone| {
+        phone
+            .chars()
+            .filter(|c| !matches!(c, ' ' | '-' | '.' | '(' | ')'))
+            .collect::<String>()
+    });
+
+    if name
+        .as_ref()
+        .is_some_and(|name| name.chars().count() > MAX_RECIPIENT_NAME_LENGTH)
+    {
+        return Err(format!(
+            "recipient name must have at most {MAX_RECIPIENT_NAME_LENGTH} characters."
+        ));
+    }
+
+    if email.is_none() && phone.is_none() {
+        return Err("recipient needs an email address or a phone number.".to_string());
+    }
+
+    if let Some(email) = &email {
+        let valid = email.len() <= MAX_EMAIL_LENGTH
+            && !email.chars().any(char::is_whitespace)
+            && email.split_once('@').is_some_and(|(local, domain)| {
+                !local.is_empty()
+                    && !domain.contains('@')
+                    && domain.contains('.')
+                    && !domain.starts_with('.')
+                    && !domain.ends_with('.')
+            });
+        if !valid {
+            return Err(format!("recipient email address '{email}' is invalid."));
+        }
+    }
+
+    if let Some(phone) = &phone {
+        let valid = phone.strip_prefix('+').is_some_and(|digits| {
+            PHONE_DIGITS.contains(&digits.len()) && digits.chars().all(|c| c.is_ascii_digit())
+        });
+        if !valid {
+            return Err(format!(
+                "recipient phone number '{phone}' is not in international format, e.g. +31612345678."
+            ));
+        }
+    }
+
+    Ok(RecipientContact { name, email, phone })
+}
+
+/// Subject and text of the notification of an event
+fn message(event: ParcelEvent, name: Option<&str>, tracking_url: Option<&str>) -> (String, String) {
+    let greeting = match name {
+        Some(name) => format!("Hello {name},"),
+        None => "Hello,".to_string(),
+    };
+    let (subject, news) = match event {
+        ParcelEvent::Confirmed => ("A parcel is on its way", "a parcel is on its way to you."),
+        ParcelEvent::Arrived => (
+            "Your parcel is ready for pickup",
+            "your parcel arrived at its destination vertiport and is ready for pickup.",
+        ),
+        ParcelEvent::Delivered => ("Your parcel was delivered", "your parcel was delivered."),
+    };
+
+    let mut text = format!("{greeting} {news}");
+    if let Some(tracking_url) = tracking_url {
+        text.push_str(&format!(" Follow it at {tracking_url}"));
+    }
+
+    (subject.to_string(), text)
+}
+
+/// Notifies recipients of what happens to their parcels
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    shipments: Shipments,
+    email: Arc<dyn NotificationSender>,
+    sms: Arc<dyn NotificationSender>,
+    tracking_url: String,
+}
+
+impl Notifier {
+    /// Sends notifications to the configured providers, or the notification file
+    pub fn new(config: &Config, shipments: Shipments) -> Self {
+        let sink: Arc<dyn NotificationSender> = match config.rest_notification_file.as_str() {
+            "" => Arc::new(LogSink),
+            path => Arc::new(FileSink::new(path)),
+        };
+        let provider = |url: &str| -> Arc<dyn NotificationSender> {
+            match url {
+                "" => sink.clone(),
+                url => Arc::new(HttpSender::new(url)),
+            }
+        };
+
+        Self::with_senders(
+            shipments,
+            provider(&config.rest_notification_email_url),
+            provider(&config.rest_notification_sms_url),
+            &config.rest_public_tracking_url,
+        )
+    }
+
+    /// Sends notifications with the given senders
+    pub fn with_senders(
+        shipments: Shipments,
+        email: Arc<dyn NotificationSender>,
+        sms: Arc<dyn NotificationSender>,
+        tracking_url: &str,
+    ) -> Self {
+        Notifier {
+            shipments,
+            email,
+            sms,
+            tracking_url: tracking_url.to_string(),
+        }
+    }
+
+    /// Notifies the recipient of a parcel in the background
+    pub fn notify(&self, parcel_id: &str, event: ParcelEvent) {
+        let notifier = self.clone();
+        let parcel_id = parcel_id.to_string();
+        tokio::spawn(async move { notifier.dispatch(&parcel_id, event).await });
+    }
+
+    /// Notifies the recipient of a parcel, unless already notified of the event
+    ///
+    /// Notifications that fail aren't sent again, the recipient can still
+    ///  follow the parcel with its tracking link. Returns the number sent.
+    pub async fn dispatch(&self, parcel_id: &str, event: ParcelEvent) -> usize {
+        let shipment = match self.shipments.claim_notification(parcel_id, event).await {
+            Ok(Some(shipment)) => shipment,
+            Ok(None) => return 0,
+            Err(e) => {
+                rest_error!(
+                    "(Notifier::dispatch) shipment store unavailable, parcel {} not notified: {}",
+                    parcel_id,
+                    e
+                );
+                return 0;
+            }
+        };
+        let Some(recipient) = shipment.recipient else {
+            return 0;
+        };
+
+        let tracking_url = shipment
+            .tracking_token
+            .map(|token| format!("{}{token}", self.tracking_url));
+        let (subject, text) = message(event, recipient.name.as_deref(), tracking_url.as_deref());
+        let created_at = Utc::now();
+
+        let mut sent = 0;
+        for (channel, to) in [
+            (NotificationChannel::Email, recipient.email),
+            (NotificationChannel::Sms, recipient.phone),
+        ] {
+            let Some(to) = to else {
+                continue;
+            };
+
+            let notification = Notification {
+                parcel_id: parcel_id.to_string(),
+                event,
+                channel,
+                to,
+                subject: subject.clone(),
+                text: text.clone(),
+                tracking_url: tracking_url.clone(),
+                created_at,
+            };
+            let sender = match channel {
+                NotificationChannel::Email => &self.email,
+                NotificationChannel::Sms => &self.sms,
+            };
+            match sender.send(&notification).await {
+                Ok(()) => sent += 1,
+                Err(e) => rest_error!(
+                    "(Notifier::dispatch) could not send {:?} of parcel {} by {:?}: {}",
+                    event,
+                    parcel_id,
+                    channel,
+                    e
+                ),
+            }
+        }
+
+        rest_info!(
+            "(Notifier::dispatch) {} notifications of {:?} sent for parcel {}.",
+            sent,
+            event,
+            parcel_id
+        );
+        sent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::pickup::PickupState;
+    use super::super::shipment::{MemoryShipmentStore, Shipment};
+    use super::*;
+
+    fn contact(name: Option<&str>, email: Option<&str>, phone: Option<&str>) -> RecipientContact {
+        RecipientContact {
+            name: name.map(str::to_string),
+            email: email.map(str::to_string),
+            phone: phone.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn ut_check_recipient() {
+        let recipient = check_recipient(&contact(
+            Some(" Ada "),
+            Some(" ada@example.com"),
+            Some("+31 (6) 1234-5678"),
+        ))
+        .unwrap();
+        assert_eq!(
+            recipient,
+            contact(Some("Ada"), Some("ada@example.com"), Some("+31612345678"))
+        );
+
+        // Blank details are left out
+        let recipient = check_recipient(&contact(Some(""), Some("ada@example.com"), Some(" ")));
+        assert_eq!(
+            recipient.unwrap(),
+            contact(None, Some("ada@example.com"), None)
+        );
+
+        for recipient in [
+            contact(Some("Ada"), None, None),
+            contact(None, Some(""), None),
+            contact(None, Some("ada.example.com"), None),
+            contact(None, Some("ada@example"), None),
+            contact(None, Some("ada@@example.com"), None),
+            contact(None, Some("a da@example.com"), None),
+            contact(None, None, Some("0612345678")),
+            contact(None, None, Some("+3161234x678")),
+            contact(None, None, Some("+3161")),
+            contact(
+                Some(&"a".repeat(MAX_RECIPIENT_NAME_LENGTH + 1)),
+                Some("ada@example.com"),
+                None,
+            ),
+        ] {
+            assert!(check_recipient(&recipient).is_err(), "{recipient:?}");
+        }
+    }
+
+    #[test]
+    fn ut_message() {
+        let (subject, text) = message(
+            ParcelEvent::Arrived,
+            Some("Ada"),
+            Some("https://cargo.example.com/track/abc"),
+        );
+        assert_eq!(subject, "Your parcel is ready for pickup");
+        assert_eq!(
+            text,
+            "Hello Ada, your parcel arrived at its destination vertiport and is ready for pickup. \
+             Follow it at https://cargo.example.com/track/abc"
+        );
+
+        let (_, text) = message(ParcelEvent::Confirmed, None, None);
+        assert_eq!(text, "Hello, a parcel is on its way to you.");
+    }
+
+    #[tokio::test]
+    async fn ut_dispatch() {
+        let shipments = Shipments::with_store(Arc::new(MemoryShipmentStore::default()));
+        let path = std::env::temp_dir().join(format!(
+            "svc-cargo-notifications-{}.jsonl",
+            uuid::Uuid::new_v4()
+        ));
+        let sink = Arc::new(FileSink::new(&path));
+        let notifier = Notifier::with_senders(
+            shipments.clone(),
+            sink.clone(),
+            sink,
+            "https://cargo.example.com/track/",
+        );
+
+        let parcel_id = "cabcdd14-03ab-4ac0-b58c-dd4175bc587e";
+        let token = "4f".repeat(32);
+        shipments
+            .record(Shipment {
+                parcel_id: parcel_id.to_string(),
+                itinerary_id: "8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1".to_string(),
+                user_id: "b3c1d7a6-3b0b-4f7c-8c6c-4e0d0d2b9f4e".to_string(),
+                weight_grams: 1250,
+                confirmed_at: Utc::now(),
+                recipient: Some(contact(
+                    Some("Ada"),
+                    Some("ada@example.com"),
+                    Some("+31612345678"),
+                )),
+                tracking_token: Some(token.clone()),
+                notified: vec![],
+                pickup: PickupState::default(),
+            })
+            .await
+            .unwrap();
+
+        // Each event is sent once, by email and text message
+        assert_eq!(
+            notifier.dispatch(parcel_id, ParcelEvent::Confirmed).await,
+            2
+        );
+        assert_eq!(
+            notifier.dispatch(parcel_id, ParcelEvent::Confirmed).await,
+            0
+        );
+        assert_eq!(
+            notifier.dispatch(parcel_id, ParcelEvent::Delivered).await,
+            2
+        );
+
+        // Parcels without a shipment have no recipient
+        let unknown = "b3c1d7a6-3b0b-4f7c-8c6c-4e0d0d2b9f4e";
+        assert_eq!(notifier.dispatch(unknown, ParcelEvent::Arrived).await, 0);
+
+        let notifications: Vec<Notification> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(notifications.len(), 4);
+        assert_eq!(notifications[0].channel, NotificationChannel::Email);
+        assert_eq!(notifications[0].to, "ada@example.com");
+        assert_eq!(notifications[1].channel, NotificationChannel::Sms);
+        assert_eq!(notifications[1].to, "+31612345678");
+        assert_eq!(notifications[3].event, ParcelEvent::Delivered);
+        assert_eq!(
+            notifications[0].tracking_url,
+            Some(format!("https://cargo.example.com/track/{token}"))
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
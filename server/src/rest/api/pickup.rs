@@ -0,0 +1,893 @@
+//! Pickup of parcels with a one-time code
+//!
+//! Shippers get a pickup code when they confirm an itinerary, and pass it on
+//!  to the recipient. Vertiport staff check the code with their scanner at
+//!  the destination vertiport, which hands the parcel over: a pickup scan
+//!  and a delivery are recorded, and the parcel is complete. Codes are only
+//!  kept as a digest keyed with a server secret, and pickups of a parcel are
+//!  locked for a while after too many wrong codes.
+
+use super::delivery::Deliveries;
+use super::geofence::{distance_to_polygon, get_vertiport_polygon};
+use super::notification::{Notifier, ParcelEvent};
+use super::rest_types::{Delivery, DeliveryConfirmation, Pickup, PickupVerification};
+use super::scan::{authorize_scanner, insert_scan, is_valid_location, scan_data};
+use super::scanner::ScannerRegistry;
+use super::shipment::Shipments;
+use super::utils::{from_hex, get_parcel_legs, hex, is_uuid, update_parcel_status};
+use crate::grpc::client::GrpcClients;
+use crate::Config;
+use axum::{
+    extract::{Extension, Path},
+    http::HeaderMap,
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use geo::Coord;
+use hyper::StatusCode;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use svc_storage_client_grpc::resources::parcel::ParcelStatus;
+use tokio::sync::watch;
+
+/// Digits of a pickup code
+pub const PICKUP_CODE_LENGTH: usize = 6;
+
+/// Issues a random pickup code
+pub fn new_pickup_code() -> io::Result<String> {
+    // Values from this bound up would make some codes more likely
+    const BOUND: u32 = u32::MAX - u32::MAX % 1_000_000;
+
+    let rng = SystemRandom::new();
+    loop {
+        let mut bytes = [0u8; 4];
+        rng.fill(&mut bytes)
+            .map_err(|_| io::Error::other("no random bytes available"))?;
+        let value = u32::from_be_bytes(bytes);
+        if value < BOUND {
+            return Ok(format!("{:06}", value % 1_000_000));
+        }
+    }
+}
+
+/// Returns true if the code has the digits of a pickup code
+pub fn is_pickup_code(code: &str) -> bool {
+    code.len() == PICKUP_CODE_LENGTH && code.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Digests of pickup codes, keyed with the server secret
+///
+/// Codes have few digits, so without the secret a digest kept with the
+///  shipment would give its code away after a million tries.
+#[derive(Debug, Clone)]
+pub struct PickupCodes {
+    key: hmac::Key,
+}
+
+impl PickupCodes {
+    /// Keys digests with the configured secret, or a random one
+    ///
+    /// Fails if no random secret can be generated.
+    pub fn new(config: &Config) -> io::Result<Self> {
+        match config.rest_pickup_code_secret.as_str() {
+            "" => {
+                rest_warn!("(PickupCodes::new) no pickup code secret is configured, codes issued before a restart can't be verified after it.");
+                let key = hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new())
+                    .map_err(|_| io::Error::other("could not generate a pickup code secret"))?;
+                Ok(PickupCodes { key })
+            }
+            secret => Ok(Self::with_secret(secret.as_bytes())),
+        }
+    }
+
+    /// Keys digests with the given secret
+    pub fn with_secret(secret: &[u8]) -> Self {
+        PickupCodes {
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret),
+        }
+    }
+
+    /// Hexadecimal HMAC-SHA256 of a pickup code and the parcel it was issued for
+    pub fn digest(&self, parcel_id: &str, code: &str) -> String {
+        let message = format!("{parcel_id}:{code}");
+        hex(hmac::sign(&self.key, message.as_bytes()).as_ref())
+    }
+
+    /// Returns true if the code has the given digest, compared in constant time
+    pub fn verify(&self, parcel_id: &str, code: &str, digest: &str) -> bool {
+        let Some(digest) = from_hex(digest) else {
+            return false;
+        };
+
+        let message = format!("{parcel_id}:{code}");
+        hmac::verify(&self.key, message.as_bytes(), &digest).is_ok()
+    }
+}
+
+/// Pickup code of a parcel and the attempts to pick it up with it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PickupState {
+    /// Keyed digest of the pickup code and the parcel ID; None if no code was issued
+    pub code_digest: Option<String>,
+
+    /// Wrong codes given since the last lockout
+    pub failed_attempts: u32,
+
+    /// Pickups are refused until then
+    pub locked_until: Option<DateTime<Utc>>,
+
+    /// When the parcel was picked up, the code can't be used again
+    pub picked_up_at: Option<DateTime<Utc>>,
+}
+
+/// Why a parcel may not be picked up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PickupDenied {
+    /// No pickup code was issued for the parcel
+    NoCode,
+
+    /// The parcel was already picked up
+    PickedUp(DateTime<Utc>),
+
+    /// Too many wrong codes were given
+    Locked(DateTime<Utc>),
+
+    /// The code isn't the code of the parcel
+    WrongCode {
+        /// Attempts left before pickups are locked
+        remaining: u32,
+    },
+}
+
+impl PickupDenied {
+    /// Status of the response to the pickup
+    pub fn status(&self) -> StatusCode {
+        match self {
+            PickupDenied::NoCode | PickupDenied::PickedUp(_) => StatusCode::CONFLICT,
+            PickupDenied::Locked(_) => StatusCode::LOCKED,
+            PickupDenied::WrongCode { .. } => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+impl fmt::Display for PickupDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PickupDenied::NoCode => write!(f, "parcel has no pickup code."),
+            PickupDenied::PickedUp(at) => write!(f, "parcel was already picked up at {at}."),
+            PickupDenied::Locked(until) => {
+                write!(f, "too many wrong pickup codes, locked until {until}.")
+            }
+            PickupDenied::WrongCode { remaining } => {
+                write!(f, "wrong pickup code, {remaining} attempts left.")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PickupDenied {}
+
+impl PickupState {
+    /// The state of a parcel with a newly issued code
+    pub fn issue(codes: &PickupCodes, parcel_id: &str, code: &str) -> Self {
+        PickupState {
+            code_digest: Some(codes.digest(parcel_id, code)),
+            ..Default::default()
+        }
+    }
+
+    /// Checks a code given to pick up the parcel, and records the attempt
+    ///
+    /// The parcel is picked up if the code is right. After `max_attempts`
+    ///  wrong codes, pickups are locked for the lockout duration and the
+    ///  attempts are counted again after it.
+    pub fn attempt(
+        &mut self,
+        codes: &PickupCodes,
+        parcel_id: &str,
+        code: &str,
+        now: DateTime<Utc>,
+        max_attempts: u32,
+        lockout: Duration,
+    ) -> Result<(), PickupDenied> {
+        let Some(code_digest) = &self.code_digest else {
+            return Err(PickupDenied::NoCode);
+        };
+
+        if let Some(picked_up_at) = self.picked_up_at {
+            return Err(PickupDenied::PickedUp(picked_up_at));
+        }
+
+        if let Some(locked_until) = self.locked_until.filter(|until| *until > now) {
+            return Err(PickupDenied::Locked(locked_until));
+        }
+
+        if codes.verify(parcel_id, code, code_digest) {
+            self.failed_attempts = 0;
+            self.locked_until = None;
+            self.picked_up_at = Some(now);
+            return Ok(());
+        }
+
+        self.failed_attempts += 1;
+        if self.failed_attempts >= max_attempts {
+            let locked_until = now + lockout;
+            self.failed_attempts = 0;
+            self.locked_until = Some(locked_until);
+            return Err(PickupDenied::Locked(locked_until));
+        }
+
+        Err(PickupDenied::WrongCode {
+            remaining: max_attempts - self.failed_attempts,
+        })
+    }
+}
+
+/// Checks the pickup code given for a parcel, which is then picked up
+///
+/// Wrong codes count towards the lockout of the parcel, whether they were
+///  given for a pickup or a delivery.
+pub async fn check_pickup_code(
+    function: &str,
+    shipments: &Shipments,
+    codes: &PickupCodes,
+    parcel_id: &str,
+    code: &str,
+    now: DateTime<Utc>,
+    config: &Config,
+) -> Result<(), (StatusCode, String)> {
+    let lockout = Duration::seconds(config.rest_pickup_lockout_seconds.into());
+    let attempt = shipments
+        .update(parcel_id, |shipment| {
+            shipment.pickup.attempt(
+                codes,
+                parcel_id,
+                code,
+                now,
+                config.rest_pickup_max_attempts,
+                lockout,
+            )
+        })
+        .await
+        .map_err(|e| {
+            let error_msg = "shipment store unavailable.".to_string();
+            rest_error!("({}) {} {}", function, &error_msg, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, error_msg)
+        })?;
+
+    // Parcels not confirmed through svc-cargo have no code either
+    match attempt.unwrap_or(Err(PickupDenied::NoCode)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let error_msg = format!("parcel {parcel_id} not picked up: {e}");
+            rest_error!("({}) {}", function, &error_msg);
+            Err((e.status(), error_msg))
+        }
+    }
+}
+
+/// Makes the pickup code of a parcel usable again, when its pickup couldn't be recorded
+pub async fn release_pickup_code(function: &str, shipments: &Shipments, parcel_id: &str) {
+    let released = shipments
+        .update(parcel_id, |shipment| {
+            shipment.pickup.picked_up_at = None;
+        })
+        .await;
+    if let Err(e) = released {
+        rest_error!("({}) could not release the pickup code: {}", function, e);
+    }
+}
+
+/// Pick up a parcel with its pickup code
+///
+/// The scanner must be registered and active, and send its API key in the
+///  `X-Api-Key` header. Scanners assigned to a vertiport may only check codes
+///  of parcels to that vertiport. The pickup is the handover of the parcel:
+///  its delivery and a pickup scan are recorded, and the parcel is complete.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/cargo/parcels/{id}/pickup",
+    tag = "svc-cargo",
+    params(
+        ("id" = String, Path, description = "The String ID of the parcel")
+    ),
+    request_body = PickupVerification,
+    responses(
+        (status = 200, description = "Code verified, parcel picked up", body = Pickup),
+        (status = 400, description = "Request body is invalid format, or not at the destination vertiport", body = String),
+        (status = 401, description = "Scanner API key missing or invalid", body = String),
+        (status = 403, description = "Wrong pickup code, or scanner unknown, deactivated or assigned to another vertiport", body = String),
+        (status = 404, description = "Parcel not confirmed through svc-cargo", body = String),
+        (status = 409, description = "Parcel already picked up or delivered, has no pickup code, or is not on any flight", body = String),
+        (status = 423, description = "Too many wrong pickup codes, try again later", body = String),
+        (status = 500, description = "svc-storage, the shipment store or the delivery store returned error", body = String),
+        (status = 503, description = "Could not connect to other microservice dependencies"),
+        (status = 504, description = "Microservice dependencies did not respond in time")
+    )
+))]
+#[allow(clippy::too_many_arguments)] // Each argument is an extractor
+pub async fn verify_pickup(
+    Extension(grpc_clients): Extension<GrpcClients>,
+    Extension(shipments): Extension<Shipments>,
+    Extension(deliveries): Extension<Deliveries>,
+    Extension(pickup_codes): Extension<PickupCodes>,
+    Extension(scanner_registry): Extension<ScannerRegistry>,
+    Extension(notifier): Extension<Notifier>,
+    Extension(live_config): Extension<watch::Receiver<Config>>,
+    headers: HeaderMap,
+    Path(parcel_id): Path<String>,
+    Json(payload): Json<PickupVerification>,
+) -> Result<Json<Pickup>, (StatusCode, String)> {
+    rest_debug!("(verify_pickup) entry.");
+
+    //
+    // Validate Request
+    //
+    let bad_request = |error_msg: String| {
+        rest_error!("(verify_pickup) {}", &error_msg);
+        (StatusCode::BAD_REQUEST, error_msg)
+    };
+
+    if !is_uuid(&parcel_id) {
+        return Err(bad_request("parcel ID not in UUID format.".to_string()));
+    }
+
+    if !is_uuid(&payload.scanner_id) {
+        return Err(bad_request("scanner ID not in UUID format.".to_string()));
+    }
+
+    if !is_pickup_code(&payload.code) {
+        return Err(bad_request(format!(
+            "pickup code must have {PICKUP_CODE_LENGTH} digits."
+        )));
+    }
+
+    if !is_valid_location(payload.latitude, payload.longitude) {
+        return Err(bad_request("coordinates out of range.".to_string()));
+    }
+
+    let scanner = authorize_scanner(
+        "verify_pickup",
+        &scanner_registry,
+        &payload.scanner_id,
+        &headers,
+    )?;
+
+    let store_error = |e: io::Error| {
+        let error_msg = "shipment store unavailable.".to_string();
+        rest_error!("(verify_pickup) {} {}", &error_msg, e);
+        (StatusCode::INTERNAL_SERVER_ERROR, error_msg)
+    };
+
+    let Some(shipment) = shipments.get(&parcel_id).map_err(store_error)? else {
+        let error_msg = format!("parcel {parcel_id} not confirmed through svc-cargo.");
+        rest_error!("(verify_pickup) {}", &error_msg);
+        return Err((StatusCode::NOT_FOUND, error_msg));
+    };
+
+    let delivery_store_error = |e: io::Error| {
+        let error_msg = "delivery store unavailable.".to_string();
+        rest_error!("(verify_pickup) {} {}", &error_msg, e);
+        (StatusCode::INTERNAL_SERVER_ERROR, error_msg)
+    };
+    let already_delivered = || {
+        let error_msg = format!("parcel {parcel_id} already delivered.");
+        rest_error!("(verify_pickup) {}", &error_msg);
+        (StatusCode::CONFLICT, error_msg)
+    };
+
+    if deliveries
+        .get(&parcel_id)
+        .map_err(delivery_store_error)?
+        .is_some()
+    {
+        return Err(already_delivered());
+    }
+
+    //
+    // The pickup must be at the destination
+    //
+    let storage_error = |status: StatusCode| {
+        let error_msg = "could not get the itinerary of the parcel from svc-storage.".to_string();
+        rest_error!("(verify_pickup) {}", &error_msg);
+        (status, error_msg)
+    };
+
+    let legs = get_parcel_legs(&parcel_id, &grpc_clients)
+        .await
+        .map_err(storage_error)?;
+    let Some(last) = legs.last() else {
+        let error_msg = "parcel is not on any flight.".to_string();
+        rest_error!("(verify_pickup) {} {}", &error_msg, parcel_id);
+        return Err((StatusCode::CONFLICT, error_msg));
+    };
+
+    let Some(vertiport_id) = last.target_vertiport_id.clone() else {
+        let error_msg = "flight plan has no destination vertiport.".to_string();
+        rest_error!("(verify_pickup) {} parcel {}", &error_msg, parcel_id);
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, error_msg));
+    };
+
+    if scanner
+        .vertiport_id
+        .as_ref()
+        .is_some_and(|assigned| *assigned != vertiport_id)
+    {
+        let error_msg = format!(
+            "scanner {} is not assigned to destination vertiport {vertiport_id}.",
+            scanner.id
+        );
+        rest_error!("(verify_pickup) {}", &error_msg);
+        return Err((StatusCode::FORBIDDEN, error_msg));
+    }
+
+    let outline = get_vertiport_polygon(&vertiport_id, &grpc_clients)
+        .await
+        .map_err(storage_error)?;
+    let location = Coord {
+        x: payload.longitude,
+        y: payload.latitude,
+    };
+    let distance = distance_to_polygon(location, &outline);
+    let config = live_config.borrow().clone();
+    if distance > config.rest_scan_vertiport_margin_meters.into() {
+        return Err(bad_request(format!(
+            "pickup is {distance:.0}m away from destination vertiport {vertiport_id}."
+        )));
+    }
+
+    //
+    // Check the code, record the handover, then complete the parcel
+    //
+    let picked_up_at = Utc::now();
+    check_pickup_code(
+        "verify_pickup",
+        &shipments,
+        &pickup_codes,
+        &parcel_id,
+        &payload.code,
+        picked_up_at,
+        &config,
+    )
+    .await?;
+
+    let recipient_name = shipment
+        .recipient
+        .and_then(|recipient| recipient.name)
+        .unwrap_or_else(|| "-".to_string());
+    let delivery = Delivery {
+        parcel_id: parcel_id.clone(),
+        recipient_name,
+        confirmation: DeliveryConfirmation::Pin,
+        has_photo: false,
+        vertiport_id: vertiport_id.clone(),
+        latitude: payload.latitude,
+        longitude: payload.longitude,
+        delivered_at: picked_up_at,
+    };
+
+    // The code may be given again if the handover isn't recorded
+    match deliveries.record_handover(&delivery, &scanner.id) {
+        Ok(true) => (),
+        Ok(false) => {
+            release_pickup_code("verify_pickup", &shipments, &parcel_id).await;
+            return Err(already_delivered());
+        }
+        Err(e) => {
+            release_pickup_code("verify_pickup", &shipments, &parcel_id).await;
+            return Err(delivery_store_error(e));
+        }
+    }
+
+    let data = scan_data(
+        scanner.id.clone(),
+        parcel_id.clone(),
+        payload.latitude,
+        payload.longitude,
+        picked_up_at,
+    );
+    if let Err(status) = insert_scan(&grpc_clients, data).await {
+        deliveries.remove(&parcel_id);
+        release_pickup_code("verify_pickup", &shipments, &parcel_id).await;
+
+        let error_msg = "could not record the pickup scan in svc-storage.".to_string();
+        rest_error!("(verify_pickup) {}", &error_msg);
+        return Err((status, error_msg));
+    }
+
+    if let Err(status) =
+        update_parcel_status(&parcel_id, ParcelStatus::Complete, &grpc_clients).await
+    {
+        deliveries.remove(&parcel_id);
+        release_pickup_code("verify_pickup", &shipments, &parcel_id).await;
+
+        let error_msg = "could not complete the parcel in svc-storage.".to_string();
+        rest_error!("(verify_pickup) {}", &error_msg);
+        return Err((status, error_msg));
+    }
+
+    notifier.notify(&parcel_id, ParcelEvent::Delivered);
+
+    rest_info!("(verify_pickup) parcel {} picked up.", parcel_id);
+    Ok(Json(Pickup {
+        parcel_id,
+        scanner_id: scanner.id,
+        vertiport_id,
+        picked_up_at,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::blob::MemoryBlobStore;
+    use super::super::scanner::MemoryScannerStore;
+    use super::super::shipment::{MemoryShipmentStore, Shipment};
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn ut_new_pickup_code() {
+        let code = new_pickup_code().unwrap();
+        assert!(is_pickup_code(&code), "{code}");
+
+        assert!(!is_pickup_code("12345"));
+        assert!(!is_pickup_code("1234567"));
+        assert!(!is_pickup_code("12a456"));
+    }
+
+    #[test]
+    fn ut_pickup_codes() {
+        let parcel_id = "cabcdd14-03ab-4ac0-b58c-dd4175bc587e";
+        let codes = PickupCodes::with_secret(b"pickup-secret");
+        let digest = codes.digest(parcel_id, "123456");
+        assert_eq!(digest, codes.digest(parcel_id, "123456"));
+        assert_eq!(digest.len(), 64);
+        assert_ne!(digest, codes.digest(parcel_id, "123457"));
+        assert_ne!(digest, codes.digest("other", "123456"));
+
+        // Digests can't be computed without the secret
+        let other_secret = PickupCodes::with_secret(b"other-secret");
+        assert_ne!(digest, other_secret.digest(parcel_id, "123456"));
+        assert!(!other_secret.verify(parcel_id, "123456", &digest));
+
+        assert!(codes.verify(parcel_id, "123456", &digest));
+        assert!(!codes.verify(parcel_id, "123457", &digest));
+        assert!(!codes.verify("other", "123456", &digest));
+        assert!(!codes.verify(parcel_id, "123456", "not-a-digest"));
+        assert!(!codes.verify(parcel_id, "123456", &digest[..62]));
+    }
+
+    #[test]
+    fn ut_pickup_attempt() {
+        let parcel_id = "cabcdd14-03ab-4ac0-b58c-dd4175bc587e";
+        let codes = PickupCodes::with_secret(b"pickup-secret");
+        let now = Utc::now();
+        let lockout = Duration::minutes(15);
+
+        let mut state = PickupState::default();
+        assert_eq!(
+            state.attempt(&codes, parcel_id, "123456", now, 3, lockout),
+            Err(PickupDenied::NoCode)
+        );
+
+        // Codes are kept as a digest
+        let mut state = PickupState::issue(&codes, parcel_id, "123456");
+        assert_ne!(state.code_digest.as_deref(), Some("123456"));
+
+        assert_eq!(
+            state.attempt(&codes, parcel_id, "000000", now, 3, lockout),
+            Err(PickupDenied::WrongCode { remaining: 2 })
+        );
+        assert_eq!(
+            state.attempt(&codes, parcel_id, "000001", now, 3, lockout),
+            Err(PickupDenied::WrongCode { remaining: 1 })
+        );
+
+        // Locked after too many wrong codes, even for the right code
+        let locked_until = now + lockout;
+        assert_eq!(
+            state.attempt(&codes, parcel_id, "000002", now, 3, lockout),
+            Err(PickupDenied::Locked(locked_until))
+        );
+        assert_eq!(
+            state.attempt(&codes, parcel_id, "123456", now, 3, lockout),
+            Err(PickupDenied::Locked(locked_until))
+        );
+        assert_eq!(state.failed_attempts, 0);
+
+        // Codes of other parcels don't match
+        let later = locked_until + Duration::seconds(1);
+        let mut other =
+            PickupState::issue(&codes, "8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1", "123456");
+        assert_eq!(
+            other.attempt(&codes, parcel_id, "123456", later, 3, lockout),
+            Err(PickupDenied::WrongCode { remaining: 2 })
+        );
+
+        // Codes are used once
+        assert_eq!(
+            state.attempt(&codes, parcel_id, "123456", later, 3, lockout),
+            Ok(())
+        );
+        assert_eq!(state.picked_up_at, Some(later));
+        assert_eq!(state.locked_until, None);
+        assert_eq!(
+            state.attempt(&codes, parcel_id, "123456", later, 3, lockout),
+            Err(PickupDenied::PickedUp(later))
+        );
+    }
+
+    #[test]
+    fn ut_pickup_denied_status() {
+        assert_eq!(PickupDenied::NoCode.status(), StatusCode::CONFLICT);
+        assert_eq!(
+            PickupDenied::Locked(Utc::now()).status(),
+            StatusCode::LOCKED
+        );
+        assert_eq!(
+            PickupDenied::WrongCode { remaining: 1 }.status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn ut_verify_pickup() {
+        use super::super::rest_types::RecipientContact;
+        use crate::rest::limit::API_KEY_HEADER;
+        use axum::{body::Body, http::Request, routing, Router};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use svc_storage_client_grpc::prelude::{
+            GeoLineString, GeoPoint, GeoPolygon, ValidationResult,
+        };
+        use svc_storage_client_grpc::resources::{
+            flight_plan, flight_plan_parcel, parcel, parcel_scan, vertiport,
+        };
+        use tonic::Status;
+        use tower::ServiceExt;
+
+        let parcel_id = "cabcdd14-03ab-4ac0-b58c-dd4175bc587e";
+        let vertiport_id = "8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1";
+        let config = Config::default();
+        let (_, live_config) = watch::channel(config.clone());
+
+        // A parcel on one flight to a vertiport around 52.37, 4.90
+        let grpc_clients = GrpcClients::default(config.clone());
+        let storage = &grpc_clients.backends.storage;
+        storage.stub("flight_plan_parcel.search", move || {
+            tonic::Response::new(flight_plan_parcel::RowDataList {
+                list: vec![flight_plan_parcel::RowData {
+                    flight_plan_id: "6fd1e0a2-6a4d-4e0e-9f53-2b0c3f4b1a77".to_string(),
+                    parcel_id: parcel_id.to_string(),
+                    acquire: true,
+                    deliver: true,
+                }],
+            })
+        });
+        storage.stub("flight_plan.get_by_id", move || {
+            tonic::Response::new(flight_plan::Object {
+                id: "6fd1e0a2-6a4d-4e0e-9f53-2b0c3f4b1a77".to_string(),
+                data: Some(flight_plan::Data {
+                    target_vertiport_id: Some(vertiport_id.to_string()),
+                    ..flight_plan::mock::get_data_obj()
+                }),
+            })
+        });
+        storage.stub("vertiport.get_by_id", move || {
+            let d = 0.001;
+            let points = [(-d, -d), (d, -d), (d, d), (-d, d), (-d, -d)]
+                .into_iter()
+                .map(|(dy, dx)| GeoPoint {
+                    latitude: 52.37 + dy,
+                    longitude: 4.90 + dx,
+                })
+                .collect();
+            tonic::Response::new(vertiport::Object {
+                id: vertiport_id.to_string(),
+                data: Some(vertiport::Data {
+                    geo_location: Some(GeoPolygon {
+                        exterior: Some(GeoLineString { points }),
+                        interiors: vec![],
+                    }),
+                    ..vertiport::mock::get_data_obj()
+                }),
+            })
+        });
+        let scans = Arc::new(AtomicUsize::new(0));
+        storage.stub("parcel_scan.insert", {
+            let scans = scans.clone();
+            move || {
+                scans.fetch_add(1, Ordering::SeqCst);
+                tonic::Response::new(parcel_scan::Response {
+                    validation_result: Some(ValidationResult {
+                        success: true,
+                        errors: vec![],
+                    }),
+                    object: None,
+                })
+            }
+        });
+        let completed = Arc::new(AtomicUsize::new(0));
+        let complete = {
+            let completed = completed.clone();
+            move || {
+                completed.fetch_add(1, Ordering::SeqCst);
+                tonic::Response::new(parcel::Response {
+                    validation_result: Some(ValidationResult {
+                        success: true,
+                        errors: vec![],
+                    }),
+                    object: None,
+                })
+            }
+        };
+
+        let scanner_registry = ScannerRegistry::with_store(Arc::new(MemoryScannerStore::default()));
+        let credentials = scanner_registry
+            .register("Pickup desk".to_string(), None)
+            .await
+            .unwrap();
+        let shipments = Shipments::with_store(Arc::new(MemoryShipmentStore::default()));
+        let pickup_codes = PickupCodes::with_secret(b"pickup-secret");
+        shipments
+            .record(Shipment {
+                parcel_id: parcel_id.to_string(),
+                itinerary_id: "b1f0c2d3-4e5f-4a6b-8c7d-9e0f1a2b3c4d".to_string(),
+                user_id: "b3c1d7a6-3b0b-4f7c-8c6c-4e0d0d2b9f4e".to_string(),
+                weight_grams: 1250,
+                confirmed_at: Utc::now(),
+                recipient: Some(RecipientContact {
+                    name: Some("Ada Lovelace".to_string()),
+                    email: None,
+                    phone: Some("+31612345678".to_string()),
+                }),
+                tracking_token: None,
+                notified: vec![],
+                pickup: PickupState::issue(&pickup_codes, parcel_id, "123456"),
+            })
+            .await
+            .unwrap();
+        let deliveries = Deliveries::with_store(Arc::new(MemoryBlobStore::default()));
+
+        let app = Router::new()
+            .route("/cargo/parcels/:id/pickup", routing::post(verify_pickup))
+            .layer(Extension(Notifier::new(&config, shipments.clone())))
+            .layer(Extension(shipments.clone()))
+            .layer(Extension(deliveries.clone()))
+            .layer(Extension(pickup_codes))
+            .layer(Extension(scanner_registry))
+            .layer(Extension(live_config))
+            .layer(Extension(grpc_clients.clone()));
+        let request = |parcel_id: &str, verification: &PickupVerification, api_key: &str| {
+            Request::builder()
+                .method("POST")
+                .uri(format!("/cargo/parcels/{parcel_id}/pickup"))
+                .header("content-type", "application/json")
+                .header(API_KEY_HEADER, api_key)
+                .body(Body::from(serde_json::to_vec(verification).unwrap()))
+                .unwrap()
+        };
+        let with_code = |code: &str| PickupVerification {
+            scanner_id: credentials.scanner.id.clone(),
+            code: code.to_string(),
+            latitude: 52.37,
+            longitude: 4.90,
+        };
+        let api_key = credentials.api_key.as_str();
+        let picked_up = || {
+            shipments
+                .get(parcel_id)
+                .unwrap()
+                .unwrap()
+                .pickup
+                .picked_up_at
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request("not-a-uuid", &with_code("123456"), api_key))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .clone()
+            .oneshot(request(parcel_id, &with_code("1234"), api_key))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .clone()
+            .oneshot(request(parcel_id, &with_code("123456"), "wrong-key"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // Parcels not confirmed through svc-cargo have no code
+        let response = app
+            .clone()
+            .oneshot(request(
+                "8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1",
+                &with_code("123456"),
+                api_key,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let away = PickupVerification {
+            latitude: 52.09,
+            ..with_code("123456")
+        };
+        let response = app
+            .clone()
+            .oneshot(request(parcel_id, &away, api_key))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .clone()
+            .oneshot(request(parcel_id, &with_code("000000"), api_key))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(picked_up(), None);
+        assert_eq!(scans.load(Ordering::SeqCst), 0);
+
+        // Parcels that can't be completed aren't handed over, the code can be given again
+        storage.stub_failure(
+            "parcel.update",
+            Status::failed_precondition("parcel locked"),
+        );
+        let response = app
+            .clone()
+            .oneshot(request(parcel_id, &with_code("123456"), api_key))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(deliveries.get(parcel_id).unwrap(), None);
+        assert_eq!(picked_up(), None);
+
+        // The pickup is the handover: a scan and a delivery are recorded and the parcel is complete
+        storage.stub("parcel.update", complete);
+        let response = app
+            .clone()
+            .oneshot(request(parcel_id, &with_code("123456"), api_key))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let pickup: Pickup = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(pickup.parcel_id, parcel_id);
+        assert_eq!(pickup.scanner_id, credentials.scanner.id);
+        assert_eq!(pickup.vertiport_id, vertiport_id);
+        assert_eq!(picked_up(), Some(pickup.picked_up_at));
+        assert_eq!(completed.load(Ordering::SeqCst), 1);
+        assert_eq!(scans.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            deliveries.get(parcel_id).unwrap(),
+            Some(Delivery {
+                parcel_id: parcel_id.to_string(),
+                recipient_name: "Ada Lovelace".to_string(),
+                confirmation: DeliveryConfirmation::Pin,
+                has_photo: false,
+                vertiport_id: vertiport_id.to_string(),
+                latitude: 52.37,
+                longitude: 4.90,
+                delivered_at: pickup.picked_up_at,
+            })
+        );
+
+        // Parcels are handed over once
+        let response = app
+            .oneshot(request(parcel_id, &with_code("123456"), api_key))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(completed.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,345 @@
+//! Public tracking of parcels
+//!
+//! Recipients follow their parcel with a link holding its tracking token,
+//!  without an account. The token is the only credential, so an unknown
+//!  token and a malformed one are both reported as not found, and only the
+//!  progress of the parcel is shown: no IDs, weights, scans or proofs.
+
+use super::delivery::Deliveries;
+use super::notification::ParcelEvent;
+use super::rest_types::{PublicParcelStatus, PublicTracking};
+use super::shipment::Shipments;
+use super::utils::{get_parcel_legs, get_vertiport_details, is_token};
+use crate::grpc::client::GrpcClients;
+use axum::{
+    extract::{Extension, Path},
+    Json,
+};
+use hyper::StatusCode;
+use svc_storage_client_grpc::resources::flight_plan::Data as FlightPlanData;
+
+/// Progress of a parcel on its flights
+///
+/// Delivery is only known to svc-cargo, an arrival may be known from a scan
+///  at the destination before the last flight reports it.
+fn public_status(legs: &[FlightPlanData], arrived: bool, delivered: bool) -> PublicParcelStatus {
+    if delivered {
+        PublicParcelStatus::Delivered
+    } else if arrived
+        || legs
+            .last()
+            .is_some_and(|leg| leg.actual_arrival_time.is_some())
+    {
+        PublicParcelStatus::Arrived
+    } else if legs
+        .first()
+        .is_some_and(|leg| leg.actual_departure_time.is_some())
+    {
+        PublicParcelStatus::InTransit
+    } else {
+        PublicParcelStatus::Confirmed
+    }
+}
+
+/// Name of a vertiport, None if it can't be found
+async fn vertiport_name(
+    vertiport_id: Option<&String>,
+    grpc_clients: &GrpcClients,
+) -> Option<String> {
+    let vertiport_id = vertiport_id?;
+    match get_vertiport_details(vertiport_id, grpc_clients).await {
+        Ok(vertiport) => Some(vertiport.name),
+        Err(status) => {
+            rest_warn!(
+                "(vertiport_name) vertiport {} unavailable: {}",
+                vertiport_id,
+                status
+            );
+            None
+        }
+    }
+}
+
+/// Track a parcel with its public tracking token
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/cargo/public/track/{token}",
+    tag = "svc-cargo",
+    params(
+        ("token" = String, Path, description = "Tracking token of the parcel, from its tracking link")
+    ),
+    responses(
+        (status = 200, description = "Progress of the parcel", body = PublicTracking),
+        (status = 404, description = "No parcel has this tracking token", body = String),
+        (status = 500, description = "svc-storage returned error", body = String),
+        (status = 503, description = "Could not connect to other microservice dependencies"),
+        (status = 504, description = "Microservice dependencies did not respond in time")
+    )
+))]
+pub async fn get_public_tracking(
+    Extension(grpc_clients): Extension<GrpcClients>,
+    Extension(shipments): Extension<Shipments>,
+    Extension(deliveries): Extension<Deliveries>,
+    Path(token): Path<String>,
+) -> Result<Json<PublicTracking>, (StatusCode, String)> {
+    rest_debug!("(get_public_tracking) entry.");
+
+    let not_found = || {
+        let error_msg = "tracking link not found.".to_string();
+        rest_error!("(get_public_tracking) {}", &error_msg);
+        (StatusCode::NOT_FOUND, error_msg)
+    };
+
+    if !is_token(&token) {
+        return Err(not_found());
+    }
+
+    let shipment = match shipments.find_by_token(&token) {
+        Ok(Some(shipment)) => shipment,
+        Ok(None) => return Err(not_found()),
+        Err(e) => {
+            let error_msg = "could not read shipments.".to_string();
+            rest_error!("(get_public_tracking) {} {}", &error_msg, e);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, error_msg));
+        }
+    };
+
+    let delivery = deliveries.get(&shipment.parcel_id).map_err(|e| {
+        let error_msg = "could not read deliveries.".to_string();
+        rest_error!("(get_public_tracking) {} {}", &error_msg, e);
+        (StatusCode::INTERNAL_SERVER_ERROR, error_msg)
+    })?;
+
+    let legs = get_parcel_legs(&shipment.parcel_id, &grpc_clients)
+        .await
+        .map_err(|status| {
+            let error_msg = "could not get the flights of the parcel from svc-storage.".to_string();
+            rest_error!("(get_public_tracking) {}", &error_msg);
+            (status, error_msg)
+        })?;
+
+    // Parcels picked up with their code are delivered as well
+    let delivered_at = delivery
+        .map(|delivery| delivery.delivered_at)
+        .or(shipment.pickup.picked_up_at);
+    let arrived = shipment.notified.contains(&ParcelEvent::Arrived);
+    let status = public_status(&legs, arrived, delivered_at.is_some());
+    let (origin, destination, estimated_arrival) = match (legs.first(), legs.last()) {
+        (Some(first), Some(last)) => (
+            vertiport_name(first.origin_vertiport_id.as_ref(), &grpc_clients).await,
+            vertiport_name(last.target_vertiport_id.as_ref(), &grpc_clients).await,
+            last.actual_arrival_time
+                .clone()
+                .or_else(|| last.target_timeslot_start.clone())
+                .map(Into::into),
+        ),
+        _ => (None, None, None),
+    };
+
+    Ok(Json(PublicTracking {
+        status,
+        origin,
+        destination,
+        estimated_arrival,
+        delivered_at,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::blob::MemoryBlobStore;
+    use super::super::pickup::PickupState;
+    use super::super::shipment::{MemoryShipmentStore, Shipment};
+    use super::*;
+    use crate::Config;
+    use chrono::Utc;
+    use std::sync::Arc;
+    use svc_storage_client_grpc::resources::flight_plan;
+
+    #[test]
+    fn ut_public_status() {
+        let mut first = flight_plan::mock::get_data_obj();
+        first.actual_departure_time = None;
+        first.actual_arrival_time = None;
+        let mut last = first.clone();
+
+        assert_eq!(
+            public_status(&[], false, false),
+            PublicParcelStatus::Confirmed
+        );
+        assert_eq!(
+            public_status(&[first.clone(), last.clone()], false, false),
+            PublicParcelStatus::Confirmed
+        );
+
+        first.actual_departure_time = Some(Utc::now().into());
+        assert_eq!(
+            public_status(&[first.clone(), last.clone()], false, false),
+            PublicParcelStatus::InTransit
+        );
+
+        // Scanned at the destination before the last flight reports its arrival
+        assert_eq!(
+            public_status(&[first.clone(), last.clone()], true, false),
+            PublicParcelStatus::Arrived
+        );
+
+        last.actual_arrival_time = Some(Utc::now().into());
+        assert_eq!(
+            public_status(&[first.clone(), last.clone()], false, false),
+            PublicParcelStatus::Arrived
+        );
+
+        assert_eq!(
+            public_status(&[first, last], false, true),
+            PublicParcelStatus::Delivered
+        );
+    }
+
+    #[tokio::test]
+    async fn ut_get_public_tracking() {
+        use axum::{body::Body, http::Request, routing, Router};
+        use chrono::TimeZone;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use svc_storage_client_grpc::resources::{flight_plan_parcel, vertiport};
+        use tonic::Status;
+        use tower::ServiceExt;
+
+        let config = Config::default();
+        let grpc_clients = GrpcClients::default(config.clone());
+        let storage = &grpc_clients.backends.storage;
+        let parcel_id = "cabcdd14-03ab-4ac0-b58c-dd4175bc587e";
+        storage.stub("flight_plan_parcel.search", move || {
+            tonic::Response::new(flight_plan_parcel::RowDataList {
+                list: vec![flight_plan_parcel::RowData {
+                    flight_plan_id: "59e51ad1-d57d-4d2c-bc2d-e2387367d17f".to_string(),
+                    parcel_id: parcel_id.to_string(),
+                    acquire: true,
+                    deliver: true,
+                }],
+            })
+        });
+        let departure = Utc.with_ymd_and_hms(2026, 10, 19, 9, 0, 0).unwrap();
+        let arrival = Utc.with_ymd_and_hms(2026, 10, 19, 9, 40, 0).unwrap();
+        storage.stub("flight_plan.get_by_id", move || {
+            tonic::Response::new(flight_plan::Object {
+                id: "59e51ad1-d57d-4d2c-bc2d-e2387367d17f".to_string(),
+                data: Some(flight_plan::Data {
+                    origin_vertiport_id: Some("origin".to_string()),
+                    target_vertiport_id: Some("destination".to_string()),
+                    origin_timeslot_start: Some(departure.into()),
+                    target_timeslot_start: Some(arrival.into()),
+                    actual_departure_time: Some(departure.into()),
+                    actual_arrival_time: None,
+                    ..flight_plan::mock::get_data_obj()
+                }),
+            })
+        });
+        // The origin is requested before the destination
+        let requests = Arc::new(AtomicUsize::new(0));
+        storage.stub("vertiport.get_by_id", move || {
+            let name = match requests.fetch_add(1, Ordering::SeqCst) % 2 {
+                0 => "Amsterdam Centraal",
+                _ => "Utrecht Science Park",
+            };
+            tonic::Response::new(vertiport::Object {
+                id: uuid::Uuid::new_v4().to_string(),
+                data: Some(vertiport::Data {
+                    name: name.to_string(),
+                    ..vertiport::mock::get_data_obj()
+                }),
+            })
+        });
+
+        let shipments = Shipments::with_store(Arc::new(MemoryShipmentStore::default()));
+        let token = "4f".repeat(32);
+        shipments
+            .record(Shipment {
+                parcel_id: parcel_id.to_string(),
+                itinerary_id: "8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1".to_string(),
+                user_id: "b3c1d7a6-3b0b-4f7c-8c6c-4e0d0d2b9f4e".to_string(),
+                weight_grams: 1250,
+                confirmed_at: Utc::now(),
+                recipient: None,
+                tracking_token: Some(token.clone()),
+                notified: vec![],
+                pickup: PickupState::default(),
+            })
+            .await
+            .unwrap();
+
+        let app = Router::new()
+            .route(
+                "/cargo/public/track/:token",
+                routing::get(get_public_tracking),
+            )
+            .layer(Extension(shipments.clone()))
+            .layer(Extension(Deliveries::with_store(Arc::new(
+                MemoryBlobStore::default(),
+            ))))
+            .layer(Extension(grpc_clients.clone()));
+        let request = |token: &str| {
+            Request::builder()
+                .uri(format!("/cargo/public/track/{token}"))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let tracking = |response: axum::response::Response| async move {
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let body = String::from_utf8(body.to_vec()).unwrap();
+            // Nothing but the progress of the parcel is shown
+            assert!(!body.contains(parcel_id), "{body}");
+            serde_json::from_str::<PublicTracking>(&body).unwrap()
+        };
+
+        // Malformed and unknown tokens can't be told apart
+        for unknown in ["not-a-token".to_string(), "00".repeat(32)] {
+            let response = app.clone().oneshot(request(&unknown)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{unknown}");
+        }
+
+        // The parcel departed and is on its way
+        let response = app.clone().oneshot(request(&token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            tracking(response).await,
+            PublicTracking {
+                status: PublicParcelStatus::InTransit,
+                origin: Some("Amsterdam Centraal".to_string()),
+                destination: Some("Utrecht Science Park".to_string()),
+                estimated_arrival: Some(arrival),
+                delivered_at: None,
+            }
+        );
+
+        // Parcels picked up with their code are delivered
+        let picked_up_at = Utc.with_ymd_and_hms(2026, 10, 19, 10, 5, 0).unwrap();
+        shipments
+            .update(parcel_id, |shipment| {
+                shipment.pickup.picked_up_at = Some(picked_up_at)
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        let response = app.clone().oneshot(request(&token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            tracking(response).await,
+            PublicTracking {
+                status: PublicParcelStatus::Delivered,
+                origin: Some("Amsterdam Centraal".to_string()),
+                destination: Some("Utrecht Science Park".to_string()),
+                estimated_arrival: Some(arrival),
+                delivered_at: Some(picked_up_at),
+            }
+        );
+
+        // Without the flights of the parcel there is no progress to show
+        storage.stub_failure(
+            "flight_plan_parcel.search",
+            Status::failed_precondition("no flights"),
+        );
+        let response = app.oneshot(request(&token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
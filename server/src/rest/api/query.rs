@@ -1,14 +1,31 @@
-use super::rest_types::{Landing, LandingsQuery, LandingsResponse, MAX_LANDINGS_TO_RETURN};
+use super::delivery::Deliveries;
+use super::error::status_from_grpc;
+use super::rest_types::{
+    Landing, LandingKind, LandingStatus, LandingsParams, LandingsQuery, LandingsResponse,
+    TimeWindow, MAX_LANDINGS_TO_RETURN,
+};
 use super::rest_types::{ParcelScan, TrackingQuery, TrackingResponse};
 use super::rest_types::{Vertiport, VertiportsQuery};
-use super::utils::is_uuid;
-use crate::grpc::client::GrpcClients;
-use axum::{extract::Extension, Json};
+use super::utils::{get_flight_plans_parcels, get_vehicles, get_vertipads, is_uuid};
+use crate::grpc::client::{traced_request, GrpcClients};
+use axum::{
+    extract::{rejection::QueryRejection, Extension, Query, RawQuery},
+    http::{HeaderMap, HeaderName, HeaderValue},
+    Json,
+};
+use chrono::{DateTime, TimeZone, Utc};
 use hyper::StatusCode;
+use std::collections::BTreeSet;
 use svc_storage_client_grpc::prelude::*;
+use svc_storage_client_grpc::resources::flight_plan::{
+    Data as FlightPlanData, Object as FlightPlanObject,
+};
+
+/// Marks responses to requests made in a deprecated form
+const DEPRECATION_HEADER: HeaderName = HeaderName::from_static("deprecation");
 
 /// Get Regional Vertiports
-#[utoipa::path(
+#[cfg_attr(feature = "openapi", utoipa::path(
     post,
     path = "/cargo/vertiports",
     tag = "svc-cargo",
@@ -16,9 +33,10 @@ use svc_storage_client_grpc::prelude::*;
     responses(
         (status = 200, description = "List all cargo-accessible vertiports successfully", body = [Vertiport]),
         (status = 500, description = "Unable to get vertiports."),
-        (status = 503, description = "Could not connect to other microservice dependencies")
+        (status = 503, description = "Could not connect to other microservice dependencies"),
+        (status = 504, description = "Microservice dependencies did not respond in time")
     )
-)]
+))]
 pub async fn query_vertiports(
     Extension(grpc_clients): Extension<GrpcClients>,
     Json(payload): Json<VertiportsQuery>,
@@ -42,10 +60,26 @@ pub async fn query_vertiports(
     );
 
     // Make request, process response
-    let Ok(response) = grpc_clients.storage.vertiport.search(filter).await else {
-        let error_msg = "error response from svc-storage.".to_string();
-        rest_error!("(query_vertiports) {}.", &error_msg);
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    let response = match grpc_clients
+        .backends
+        .storage
+        .call_idempotent("vertiport.search", || async {
+            grpc_clients
+                .storage
+                .vertiport
+                .get_client()
+                .await?
+                .search(traced_request(filter.clone()))
+                .await
+        })
+        .await
+    {
+        Ok(response) => response,
+        Err(e) => {
+            let error_msg = "error response from svc-storage.".to_string();
+            rest_error!("(query_vertiports) {} {:?}", &error_msg, e);
+            return Err(status_from_grpc(&e));
+        }
     };
 
     let mut vertiports: Vec<Vertiport> = vec![];
@@ -82,25 +116,62 @@ pub async fn query_vertiports(
 }
 
 /// Request a list of landings for a vertiport.
-/// No more than [`MAX_LANDINGS_TO_RETURN`] landings will be returned.
-#[utoipa::path(
+/// Arrivals and departures are returned in chronological order.
+/// No more than [`MAX_LANDINGS_TO_RETURN`] landings will be returned per
+///  request, use the returned cursor to request more.
+///
+/// The JSON request body is deprecated in favor of query parameters.
+#[cfg_attr(feature = "openapi", utoipa::path(
     get,
     path = "/cargo/landings",
     tag = "svc-cargo",
+    params(LandingsParams),
     responses(
-        (status = 200, description = "Landings retrieved successfully"),
-        (status = 400, description = "Request body is invalid format"),
+        (status = 200, description = "Landings retrieved successfully", body = LandingsResponse),
+        (status = 400, description = "Request parameters are invalid"),
         (status = 500, description = "Dependencies returned error"),
-        (status = 503, description = "Could not connect to other microservice dependencies")
+        (status = 503, description = "Could not connect to other microservice dependencies"),
+        (status = 504, description = "Microservice dependencies did not respond in time")
     ),
-    request_body = LandingsQuery
-)]
+    request_body(
+        content = Option<LandingsQuery>,
+        description = "Deprecated, use query parameters instead"
+    )
+))]
 pub async fn query_landings(
     Extension(grpc_clients): Extension<GrpcClients>,
-    Json(payload): Json<LandingsQuery>,
-) -> Result<Json<LandingsResponse>, StatusCode> {
+    RawQuery(query): RawQuery,
+    params: Result<Query<LandingsParams>, QueryRejection>,
+    body: Option<Json<LandingsQuery>>,
+) -> Result<(HeaderMap, Json<LandingsResponse>), StatusCode> {
     rest_debug!("(query_landings) entry.");
 
+    let mut headers = HeaderMap::new();
+    let payload = match (query, params, body) {
+        (Some(_), Ok(Query(params)), _) => match LandingsQuery::try_from(params) {
+            Ok(payload) => payload,
+            Err(error_msg) => {
+                rest_error!("(query_landings) {}", &error_msg);
+                return Err(StatusCode::BAD_REQUEST);
+            }
+        },
+        (Some(_), Err(rejection), _) => {
+            let error_msg = format!("invalid query parameters: {}", rejection.body_text());
+            rest_error!("(query_landings) {}", &error_msg);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        (None, _, Some(Json(payload))) => {
+            rest_warn!("(query_landings) JSON request body is deprecated, use query parameters.");
+            headers.insert(DEPRECATION_HEADER, HeaderValue::from_static("true"));
+            payload
+        }
+        (None, _, None) => {
+            let error_msg = "missing query parameters.".to_string();
+            rest_error!("(query_landings) {}", &error_msg);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+
     if payload.limit > MAX_LANDINGS_TO_RETURN {
         let error_msg = format!(
             "requested number of landings exceeds maximum of {}.",
@@ -118,92 +189,442 @@ pub async fn query_landings(
         return Err(StatusCode::BAD_REQUEST);
     }
 
-    let Some(arrival_window) = payload.arrival_window else {
-        let error_msg = "arrival window not specified.".to_string();
+    if payload.arrival_window.is_none() && payload.departure_window.is_none() {
+        let error_msg = "neither arrival nor departure window specified.".to_string();
         rest_error!("(query_landings) {}", &error_msg);
         return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let cursor = match payload.cursor.as_deref().map(LandingsCursor::decode) {
+        None => None,
+        Some(Some(cursor)) => Some(cursor),
+        Some(None) => {
+            let error_msg = "invalid cursor.".to_string();
+            rest_error!("(query_landings) {}", &error_msg);
+            return Err(StatusCode::BAD_REQUEST);
+        }
     };
 
     //
     // Request flight plans
     //
-    let mut filter = AdvancedSearchFilter::search_equals(
-        "destination_vertiport_id".to_string(),
-        payload.vertiport_id.clone(),
-    )
-    .and_between(
-        "scheduled_arrival".to_string(),
-        arrival_window.timestamp_min.to_string(),
-        arrival_window.timestamp_max.to_string(),
+    let mut landings: Vec<Landing> = vec![];
+    if let Some(arrival_window) = payload.arrival_window {
+        landings.extend(
+            search_landings(
+                LandingKind::Arrival,
+                &payload.vertiport_id,
+                &arrival_window,
+                cursor.as_ref(),
+                payload.limit,
+                &grpc_clients,
+            )
+            .await?,
+        );
+    }
+
+    if let Some(departure_window) = payload.departure_window {
+        landings.extend(
+            search_landings(
+                LandingKind::Departure,
+                &payload.vertiport_id,
+                &departure_window,
+                cursor.as_ref(),
+                payload.limit,
+                &grpc_clients,
+            )
+            .await?,
+        );
+    }
+
+    landings
+        .sort_by(|a, b| (a.timestamp, &a.flight_plan_id).cmp(&(b.timestamp, &b.flight_plan_id)));
+    let has_more = landings.len() > payload.limit as usize;
+    landings.truncate(payload.limit as usize);
+
+    // Only offer a cursor if landings were left out of the page
+    let next_cursor = match landings.last() {
+        Some(last) if has_more => Some(
+            LandingsCursor {
+                timestamp: last.timestamp,
+                flight_plan_id: last.flight_plan_id.clone(),
+            }
+            .encode(),
+        ),
+        _ => None,
+    };
+
+    rest_info!("(query_landings) found {} landings.", landings.len());
+    Ok((
+        headers,
+        Json(LandingsResponse {
+            landings,
+            next_cursor,
+        }),
+    ))
+}
+
+impl TryFrom<LandingsParams> for LandingsQuery {
+    type Error = String;
+
+    fn try_from(params: LandingsParams) -> Result<Self, Self::Error> {
+        let window =
+            |min: Option<DateTime<Utc>>, max: Option<DateTime<Utc>>, name: &str| match (min, max) {
+                (Some(timestamp_min), Some(timestamp_max)) => Ok(Some(TimeWindow {
+                    timestamp_min,
+                    timestamp_max,
+                })),
+                (None, None) => Ok(None),
+                _ => Err(format!(
+                    "{name} window requires both a minimum and a maximum."
+                )),
+            };
+
+        Ok(LandingsQuery {
+            vertiport_id: params.vertiport_id,
+            arrival_window: window(params.arrival_min, params.arrival_max, "arrival")?,
+            departure_window: window(params.departure_min, params.departure_max, "departure")?,
+            limit: params.limit.unwrap_or(MAX_LANDINGS_TO_RETURN),
+            cursor: params.cursor,
+        })
+    }
+}
+
+/// Position of the last landing returned in a page
+#[derive(Debug, Clone, PartialEq)]
+struct LandingsCursor {
+    timestamp: DateTime<Utc>,
+    flight_plan_id: String,
+}
+
+impl LandingsCursor {
+    /// Opaque string handed to clients, safe to use in a query string
+    fn encode(&self) -> String {
+        format!(
+            "{}_{}",
+            self.timestamp.timestamp_micros(),
+            self.flight_plan_id
+        )
+    }
+
+    /// Parses a string previously produced by [`LandingsCursor::encode`]
+    fn decode(cursor: &str) -> Option<Self> {
+        let (timestamp, flight_plan_id) = cursor.split_once('_')?;
+        let timestamp = Utc.timestamp_micros(timestamp.parse().ok()?).single()?;
+        if !is_uuid(flight_plan_id) {
+            return None;
+        }
+
+        Some(LandingsCursor {
+            timestamp,
+            flight_plan_id: flight_plan_id.to_string(),
+        })
+    }
+
+    /// True if the landing comes after the cursor position
+    fn precedes(&self, landing: &Landing) -> bool {
+        (&self.timestamp, &self.flight_plan_id) < (&landing.timestamp, &landing.flight_plan_id)
+    }
+}
+
+/// Builds a svc-storage filter for flight plans at a vertiport within a window
+/// Flight plans before the cursor are excluded where possible
+fn landings_filter(
+    vertiport_field: &str,
+    timestamp_field: &str,
+    vertiport_id: &str,
+    window: &TimeWindow,
+    cursor: Option<&LandingsCursor>,
+    limit: u32,
+) -> AdvancedSearchFilter {
+    let timestamp_min = match cursor {
+        Some(cursor) => std::cmp::max(window.timestamp_min, cursor.timestamp),
+        None => window.timestamp_min,
+    };
+
+    let mut filter =
+        AdvancedSearchFilter::search_equals(vertiport_field.to_string(), vertiport_id.to_string())
+            .and_between(
+                timestamp_field.to_string(),
+                timestamp_min.to_string(),
+                window.timestamp_max.to_string(),
+            );
+    filter.page_number = 1;
+    filter.results_per_page = limit as i32;
+    filter.order_by = vec![
+        SortOption {
+            sort_field: timestamp_field.to_string(),
+            sort_order: SortOrder::Asc as i32,
+        },
+        SortOption {
+            sort_field: "flight_plan_id".to_string(),
+            sort_order: SortOrder::Asc as i32,
+        },
+    ];
+
+    filter
+}
+
+/// Landings of one kind after the cursor, requesting pages of flight plans
+///  from svc-storage until more than `limit` are found or none are left
+///
+/// Flight plans sharing the timestamp of the cursor are requested again and
+///  dropped here, so a page may need several requests to fill.
+async fn search_landings(
+    kind: LandingKind,
+    vertiport_id: &str,
+    window: &TimeWindow,
+    cursor: Option<&LandingsCursor>,
+    limit: u32,
+    grpc_clients: &GrpcClients,
+) -> Result<Vec<Landing>, StatusCode> {
+    let (vertiport_field, timestamp_field) = match kind {
+        LandingKind::Arrival => ("target_vertiport_id", "target_timeslot_start"),
+        LandingKind::Departure => ("origin_vertiport_id", "origin_timeslot_start"),
+    };
+
+    let mut filter = landings_filter(
+        vertiport_field,
+        timestamp_field,
+        vertiport_id,
+        window,
+        cursor,
+        limit.saturating_add(1),
     );
-    filter.results_per_page = payload.limit as i32;
-    filter.order_by = vec![SortOption {
-        sort_field: "scheduled_arrival".to_string(),
-        sort_order: SortOrder::Asc as i32,
-    }];
 
-    let response = match grpc_clients.storage.flight_plan.search(filter).await {
-        Ok(response) => response.into_inner(),
+    let mut landings: Vec<Landing> = vec![];
+    loop {
+        let flight_plans = search_flight_plans(filter.clone(), grpc_clients).await?;
+        let last_page = flight_plans.len() < filter.results_per_page as usize;
+        for landing in get_landings(flight_plans, kind, grpc_clients).await? {
+            let after_cursor = match cursor {
+                Some(cursor) => cursor.precedes(&landing),
+                None => true,
+            };
+
+            if after_cursor {
+                landings.push(landing);
+            }
+        }
+
+        if last_page || landings.len() > limit as usize {
+            return Ok(landings);
+        }
+
+        filter.page_number += 1;
+    }
+}
+
+/// Request flight plans from svc-storage
+async fn search_flight_plans(
+    filter: AdvancedSearchFilter,
+    grpc_clients: &GrpcClients,
+) -> Result<Vec<FlightPlanObject>, StatusCode> {
+    match grpc_clients
+        .backends
+        .storage
+        .call_idempotent("flight_plan.search", || async {
+            grpc_clients
+                .storage
+                .flight_plan
+                .get_client()
+                .await?
+                .search(traced_request(filter.clone()))
+                .await
+        })
+        .await
+    {
+        Ok(response) => Ok(response.into_inner().list),
         Err(e) => {
             let error_msg = "svc-storage error.".to_string();
-            rest_error!("(query_landings) {} {:?}", &error_msg, e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            rest_error!("(search_flight_plans) {} {:?}", &error_msg, e);
+            Err(status_from_grpc(&e))
         }
-    };
+    }
+}
 
-    let mut landings: Vec<Landing> = vec![];
-    for fp in response.list {
+/// Derives the progress of a flight from its actual departure and arrival
+fn landing_status(data: &FlightPlanData) -> LandingStatus {
+    if data.actual_arrival_time.is_some() {
+        LandingStatus::Landed
+    } else if data.actual_departure_time.is_some() {
+        LandingStatus::InFlight
+    } else {
+        LandingStatus::Scheduled
+    }
+}
+
+/// Seconds between the start and end of a vertipad timeslot
+fn dwell_seconds(start: DateTime<Utc>, end: Option<DateTime<Utc>>) -> u32 {
+    end.map(|end| (end - start).num_seconds())
+        .unwrap_or(0)
+        .clamp(0, u32::MAX as i64) as u32
+}
+
+/// A flight plan with the timeslot of its landing
+struct ScheduledLanding {
+    flight_plan_id: String,
+    data: FlightPlanData,
+    vertipad_id: String,
+    timestamp: DateTime<Utc>,
+    estimated_dwell_seconds: u32,
+}
+
+/// Joins flight plans with their vertipads, vehicles and parcels
+///
+/// The vertipads, vehicles and parcels of all flight plans are requested
+///  together, not once per flight plan.
+async fn get_landings(
+    flight_plans: Vec<FlightPlanObject>,
+    kind: LandingKind,
+    grpc_clients: &GrpcClients,
+) -> Result<Vec<Landing>, StatusCode> {
+    let mut scheduled = vec![];
+    for fp in flight_plans {
         let Some(data) = fp.data else {
             let error_msg = "flight plan data is None.".to_string();
-            rest_error!("(query_landings) {}", &error_msg);
+            rest_error!("(get_landings) {} {}", &error_msg, fp.id);
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         };
 
-        let Some(scheduled_arrival) = data.target_timeslot_start else {
-            let error_msg = "flight plan has no scheduled arrival.".to_string();
-            rest_error!("(query_landings) {}", &error_msg);
+        let (vertipad_id, timeslot_start, timeslot_end) = match kind {
+            LandingKind::Arrival => (
+                data.target_vertipad_id.clone(),
+                data.target_timeslot_start.clone(),
+                data.target_timeslot_end.clone(),
+            ),
+            LandingKind::Departure => (
+                data.origin_vertipad_id.clone(),
+                data.origin_timeslot_start.clone(),
+                data.origin_timeslot_end.clone(),
+            ),
+        };
+
+        let Some(timeslot_start) = timeslot_start else {
+            let error_msg = "flight plan has no scheduled timeslot.".to_string();
+            rest_error!("(get_landings) {} {}", &error_msg, fp.id);
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         };
 
-        let vertipad_name =
-            super::utils::get_vertipad_details(&data.target_vertipad_id, &grpc_clients)
-                .await?
-                .name;
+        let timestamp: DateTime<Utc> = timeslot_start.into();
+        scheduled.push(ScheduledLanding {
+            flight_plan_id: fp.id,
+            estimated_dwell_seconds: dwell_seconds(timestamp, timeslot_end.map(Into::into)),
+            timestamp,
+            vertipad_id,
+            data,
+        });
+    }
 
-        let aircraft_callsign = super::utils::get_vehicle_details(&data.vehicle_id, &grpc_clients)
-            .await?
-            .registration_number;
+    if scheduled.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let unique = |ids: BTreeSet<&String>| ids.into_iter().cloned().collect::<Vec<String>>();
+    let vertipad_ids = unique(scheduled.iter().map(|s| &s.vertipad_id).collect());
+    let vehicle_ids = unique(scheduled.iter().map(|s| &s.data.vehicle_id).collect());
+    let flight_plan_ids = scheduled.iter().map(|s| s.flight_plan_id.clone()).collect();
+
+    let vertipads = get_vertipads(vertipad_ids, grpc_clients).await?;
+    let vehicles = get_vehicles(vehicle_ids, grpc_clients).await?;
+    let parcels = get_flight_plans_parcels(flight_plan_ids, grpc_clients).await?;
+
+    let mut landings = vec![];
+    for landing in scheduled {
+        let Some(vertipad) = vertipads.get(&landing.vertipad_id) else {
+            let error_msg = format!("vertipad {} not found.", landing.vertipad_id);
+            rest_error!("(get_landings) {}", &error_msg);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        };
+
+        let Some(vehicle) = vehicles.get(&landing.data.vehicle_id) else {
+            let error_msg = format!("vehicle {} not found.", landing.data.vehicle_id);
+            rest_error!("(get_landings) {}", &error_msg);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        };
+
+        let carried = || {
+            parcels
+                .iter()
+                .filter(|parcel| parcel.flight_plan_id == landing.flight_plan_id)
+        };
+        let parcels_deliver = carried()
+            .filter(|parcel| parcel.deliver)
+            .map(|parcel| parcel.parcel_id.clone())
+            .collect();
+        let parcels_acquire = carried()
+            .filter(|parcel| parcel.acquire)
+            .map(|parcel| parcel.parcel_id.clone())
+            .collect();
 
         landings.push(Landing {
-            flight_plan_id: fp.id,
-            vertipad_name,
-            aircraft_callsign,
-            timestamp: scheduled_arrival.into(),
-        })
+            flight_plan_id: landing.flight_plan_id.clone(),
+            kind,
+            status: landing_status(&landing.data),
+            vertipad_name: vertipad.name.clone(),
+            vertiport_depart_id: landing.data.origin_vertiport_id.clone(),
+            vertiport_arrive_id: landing.data.target_vertiport_id.clone(),
+            aircraft_callsign: vehicle.registration_number.clone(),
+            aircraft_nickname: vehicle.description.clone(),
+            timestamp: landing.timestamp,
+            estimated_dwell_seconds: landing.estimated_dwell_seconds,
+            parcels_deliver,
+            parcels_acquire,
+        });
     }
 
-    Ok(Json(LandingsResponse { landings }))
+    Ok(landings)
 }
 
-/// Request a list of landings for a vertiport.
-/// No more than [`MAX_LANDINGS_TO_RETURN`] landings will be returned.
-#[utoipa::path(
+/// Request the list of scans of a parcel, and its delivery once delivered.
+///
+/// The JSON request body is deprecated in favor of query parameters.
+#[cfg_attr(feature = "openapi", utoipa::path(
     get,
     path = "/cargo/track",
     tag = "svc-cargo",
+    params(TrackingQuery),
     responses(
-        (status = 200, description = "Parcel scans retrieved successfully"),
-        (status = 400, description = "Request body is invalid format"),
+        (status = 200, description = "Parcel scans retrieved successfully", body = TrackingResponse),
+        (status = 400, description = "Request parameters are invalid"),
         (status = 500, description = "Dependencies returned error"),
-        (status = 503, description = "Could not connect to other microservice dependencies")
+        (status = 503, description = "Could not connect to other microservice dependencies"),
+        (status = 504, description = "Microservice dependencies did not respond in time")
     ),
-    request_body = TrackingQuery
-)]
+    request_body(
+        content = Option<TrackingQuery>,
+        description = "Deprecated, use query parameters instead"
+    )
+))]
 pub async fn query_scans(
     Extension(grpc_clients): Extension<GrpcClients>,
-    Json(payload): Json<TrackingQuery>,
-) -> Result<Json<TrackingResponse>, StatusCode> {
+    Extension(deliveries): Extension<Deliveries>,
+    RawQuery(query): RawQuery,
+    params: Result<Query<TrackingQuery>, QueryRejection>,
+    body: Option<Json<TrackingQuery>>,
+) -> Result<(HeaderMap, Json<TrackingResponse>), StatusCode> {
     rest_debug!("(query_scans) entry.");
+
+    let mut headers = HeaderMap::new();
+    let payload = match (query, params, body) {
+        (Some(_), Ok(Query(payload)), _) => payload,
+        (Some(_), Err(rejection), _) => {
+            let error_msg = format!("invalid query parameters: {}", rejection.body_text());
+            rest_error!("(query_scans) {}", &error_msg);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        (None, _, Some(Json(payload))) => {
+            rest_warn!("(query_scans) JSON request body is deprecated, use query parameters.");
+            headers.insert(DEPRECATION_HEADER, HeaderValue::from_static("true"));
+            payload
+        }
+        (None, _, None) => {
+            let error_msg = "missing query parameters.".to_string();
+            rest_error!("(query_scans) {}", &error_msg);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+
     if !is_uuid(&payload.parcel_id) {
         rest_error!(
             "(query_scans) parcel ID not in UUID format: {}",
@@ -223,12 +644,25 @@ pub async fn query_scans(
         sort_order: SortOrder::Asc as i32,
     }];
 
-    let response = match grpc_clients.storage.parcel_scan.search(filter).await {
+    let response = match grpc_clients
+        .backends
+        .storage
+        .call_idempotent("parcel_scan.search", || async {
+            grpc_clients
+                .storage
+                .parcel_scan
+                .get_client()
+                .await?
+                .search(traced_request(filter.clone()))
+                .await
+        })
+        .await
+    {
         Ok(response) => response.into_inner().list,
         Err(e) => {
             let error_msg = "svc-storage error.".to_string();
             rest_error!("(query_scans) {} {:?}", &error_msg, e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(status_from_grpc(&e));
         }
     };
 
@@ -249,7 +683,7 @@ pub async fn query_scans(
             };
 
             Some(ParcelScan {
-                parcel_id: scan.id,
+                parcel_id: data.parcel_id,
                 scanner_id: data.scanner_id,
                 latitude: geo_location.latitude,
                 longitude: geo_location.longitude,
@@ -257,5 +691,304 @@ pub async fn query_scans(
         })
         .collect::<Vec<ParcelScan>>();
 
-    Ok(Json(TrackingResponse { scans }))
+    let delivery = match deliveries.get(&payload.parcel_id) {
+        Ok(delivery) => delivery,
+        Err(e) => {
+            rest_warn!("(query_scans) delivery store unavailable: {}", e);
+            None
+        }
+    };
+
+    Ok((headers, Json(TrackingResponse { scans, delivery })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::sync::Arc;
+    use svc_storage_client_grpc::resources::flight_plan;
+
+    #[test]
+    fn ut_landing_status() {
+        let mut data = flight_plan::mock::get_data_obj();
+        data.actual_departure_time = None;
+        data.actual_arrival_time = None;
+        assert_eq!(landing_status(&data), LandingStatus::Scheduled);
+
+        data.actual_departure_time = Some(Utc::now().into());
+        assert_eq!(landing_status(&data), LandingStatus::InFlight);
+
+        data.actual_arrival_time = Some(Utc::now().into());
+        assert_eq!(landing_status(&data), LandingStatus::Landed);
+    }
+
+    fn mock_params() -> LandingsParams {
+        LandingsParams {
+            vertiport_id: uuid::Uuid::new_v4().to_string(),
+            arrival_min: None,
+            arrival_max: None,
+            departure_min: None,
+            departure_max: None,
+            limit: None,
+            cursor: None,
+        }
+    }
+
+    #[test]
+    fn ut_landings_query_from_params() {
+        let now = Utc::now();
+        let params = LandingsParams {
+            arrival_min: Some(now),
+            arrival_max: Some(now + Duration::hours(1)),
+            ..mock_params()
+        };
+
+        let query = LandingsQuery::try_from(params).unwrap();
+        let arrival_window = query.arrival_window.unwrap();
+        assert_eq!(arrival_window.timestamp_min, now);
+        assert_eq!(arrival_window.timestamp_max, now + Duration::hours(1));
+        assert!(query.departure_window.is_none());
+        assert_eq!(query.limit, MAX_LANDINGS_TO_RETURN);
+
+        // Half a window
+        let params = LandingsParams {
+            departure_min: Some(now),
+            ..mock_params()
+        };
+        assert!(LandingsQuery::try_from(params).is_err());
+    }
+
+    #[test]
+    fn ut_landings_cursor() {
+        let cursor = LandingsCursor {
+            timestamp: Utc.timestamp_micros(1_700_000_000_123_456).unwrap(),
+            flight_plan_id: uuid::Uuid::new_v4().to_string(),
+        };
+
+        let encoded = cursor.encode();
+        assert_eq!(LandingsCursor::decode(&encoded), Some(cursor.clone()));
+
+        assert!(LandingsCursor::decode("").is_none());
+        assert!(LandingsCursor::decode("abc_def").is_none());
+        assert!(LandingsCursor::decode("1700000000123456_not-a-uuid").is_none());
+    }
+
+    #[test]
+    fn ut_dwell_seconds() {
+        let start = Utc::now();
+        assert_eq!(
+            dwell_seconds(start, Some(start + Duration::minutes(5))),
+            300
+        );
+        assert_eq!(dwell_seconds(start, Some(start - Duration::minutes(5))), 0);
+        assert_eq!(dwell_seconds(start, None), 0);
+    }
+
+    #[tokio::test]
+    async fn ut_query_landings_pages() {
+        use axum::{body::Body, http::Request, routing, Router};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use svc_storage_client_grpc::resources::{flight_plan_parcel, vehicle, vertipad};
+        use tower::ServiceExt;
+
+        let config = crate::Config::default();
+        let grpc_clients = GrpcClients::default(config);
+        let timestamp = Utc.timestamp_opt(1_790_000_000, 0).unwrap();
+        let mut ids: Vec<String> = (0..5).map(|_| uuid::Uuid::new_v4().to_string()).collect();
+        ids.sort();
+
+        // Flights sharing the timestamp of the cursor, two pages of three
+        let vertipad_id = uuid::Uuid::new_v4().to_string();
+        let vehicle_id = uuid::Uuid::new_v4().to_string();
+        let flight_plan = {
+            let (vertipad_id, vehicle_id) = (vertipad_id.clone(), vehicle_id.clone());
+            move |id: &String| flight_plan::Object {
+                id: id.clone(),
+                data: Some(flight_plan::Data {
+                    target_vertipad_id: vertipad_id.clone(),
+                    vehicle_id: vehicle_id.clone(),
+                    target_timeslot_start: Some(timestamp.into()),
+                    ..flight_plan::mock::get_data_obj()
+                }),
+            }
+        };
+        let pages = [ids[..3].to_vec(), ids[3..].to_vec()];
+        let requests = Arc::new(AtomicUsize::new(0));
+        let storage = &grpc_clients.backends.storage;
+        storage.stub("flight_plan.search", {
+            let requests = requests.clone();
+            move || {
+                let page = pages
+                    .get(requests.fetch_add(1, Ordering::SeqCst))
+                    .cloned()
+                    .unwrap_or_default();
+                tonic::Response::new(flight_plan::List {
+                    list: page.iter().map(&flight_plan).collect(),
+                })
+            }
+        });
+        // Vertipads, vehicles and parcels are requested once per page
+        let lookups = Arc::new(AtomicUsize::new(0));
+        storage.stub("vertipad.search", {
+            let lookups = lookups.clone();
+            move || {
+                lookups.fetch_add(1, Ordering::SeqCst);
+                tonic::Response::new(vertipad::List {
+                    list: vec![vertipad::Object {
+                        id: vertipad_id.clone(),
+                        data: Some(vertipad::Data {
+                            name: "Pad 1".to_string(),
+                            ..vertipad::mock::get_data_obj()
+                        }),
+                    }],
+                })
+            }
+        });
+        storage.stub("vehicle.search", {
+            let lookups = lookups.clone();
+            move || {
+                lookups.fetch_add(1, Ordering::SeqCst);
+                tonic::Response::new(vehicle::List {
+                    list: vec![vehicle::Object {
+                        id: vehicle_id.clone(),
+                        data: Some(vehicle::mock::get_data_obj()),
+                    }],
+                })
+            }
+        });
+        let carried = ids[2].clone();
+        storage.stub("flight_plan_parcel.search", {
+            let lookups = lookups.clone();
+            move || {
+                lookups.fetch_add(1, Ordering::SeqCst);
+                tonic::Response::new(flight_plan_parcel::RowDataList {
+                    list: vec![flight_plan_parcel::RowData {
+                        flight_plan_id: carried.clone(),
+                        parcel_id: "cabcdd14-03ab-4ac0-b58c-dd4175bc587e".to_string(),
+                        acquire: false,
+                        deliver: true,
+                    }],
+                })
+            }
+        });
+
+        let app = Router::new()
+            .route("/cargo/landings", routing::get(query_landings))
+            .layer(Extension(grpc_clients));
+        let cursor = LandingsCursor {
+            timestamp,
+            flight_plan_id: ids[1].clone(),
+        };
+        let window = format!(
+            "arrival_min={}&arrival_max={}",
+            (timestamp - Duration::hours(1)).format("%Y-%m-%dT%H:%M:%SZ"),
+            (timestamp + Duration::hours(1)).format("%Y-%m-%dT%H:%M:%SZ"),
+        );
+        let uri = format!(
+            "/cargo/landings?vertiport_id={}&{window}&limit=2&cursor={}",
+            uuid::Uuid::new_v4(),
+            cursor.encode()
+        );
+
+        let request = Request::builder().uri(&uri).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let page: LandingsResponse = serde_json::from_slice(&body).unwrap();
+
+        // The flights up to the cursor are skipped, the page still fills up
+        let landings: Vec<&str> = page
+            .landings
+            .iter()
+            .map(|landing| landing.flight_plan_id.as_str())
+            .collect();
+        assert_eq!(landings, [ids[2].as_str(), ids[3].as_str()]);
+        assert_eq!(page.landings[0].vertipad_name, "Pad 1");
+        assert_eq!(
+            page.landings[0].parcels_deliver,
+            ["cabcdd14-03ab-4ac0-b58c-dd4175bc587e"]
+        );
+        assert!(page.landings[1].parcels_deliver.is_empty());
+        let next = LandingsCursor {
+            timestamp,
+            flight_plan_id: ids[3].clone(),
+        };
+        assert_eq!(page.next_cursor, Some(next.encode()));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(lookups.load(Ordering::SeqCst), 6);
+
+        // A malformed query string isn't replaced by the deprecated body
+        let request = Request::builder()
+            .uri(format!(
+                "/cargo/landings?vertiport_id=x&{window}&limit=many"
+            ))
+            .header("content-type", "application/json")
+            .body(Body::from(
+                serde_json::json!({
+                    "vertiport_id": uuid::Uuid::new_v4().to_string(),
+                    "arrival_window": {
+                        "timestamp_min": timestamp - Duration::hours(1),
+                        "timestamp_max": timestamp + Duration::hours(1),
+                    },
+                    "limit": 2,
+                })
+                .to_string(),
+            ))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn ut_query_scans() {
+        use super::super::blob::MemoryBlobStore;
+        use axum::{body::Body, http::Request, routing, Router};
+        use svc_storage_client_grpc::resources::parcel_scan;
+        use tower::ServiceExt;
+
+        let parcel_id = "cabcdd14-03ab-4ac0-b58c-dd4175bc587e";
+        let scanner_id = "59e51ad1-d57d-4d2c-bc2d-e2387367d17f";
+        let grpc_clients = GrpcClients::default(crate::Config::default());
+        grpc_clients
+            .backends
+            .storage
+            .stub("parcel_scan.search", move || {
+                tonic::Response::new(parcel_scan::List {
+                    list: vec![parcel_scan::Object {
+                        id: "6fd1e0a2-6a4d-4e0e-9f53-2b0c3f4b1a77".to_string(),
+                        data: Some(parcel_scan::Data {
+                            scanner_id: scanner_id.to_string(),
+                            parcel_id: parcel_id.to_string(),
+                            geo_location: Some(GeoPoint {
+                                latitude: 52.37,
+                                longitude: 4.90,
+                            }),
+                            created_at: Some(Utc::now().into()),
+                        }),
+                    }],
+                })
+            });
+
+        let app = Router::new()
+            .route("/cargo/track", routing::get(query_scans))
+            .layer(Extension(Deliveries::with_store(Arc::new(
+                MemoryBlobStore::default(),
+            ))))
+            .layer(Extension(grpc_clients));
+        let request = Request::builder()
+            .uri(format!("/cargo/track?parcel_id={parcel_id}"))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let tracking: TrackingResponse = serde_json::from_slice(&body).unwrap();
+
+        // Scans carry the ID of their parcel, not their own
+        assert_eq!(tracking.scans.len(), 1);
+        assert_eq!(tracking.scans[0].parcel_id, parcel_id);
+        assert_eq!(tracking.scans[0].scanner_id, scanner_id);
+        assert!(tracking.delivery.is_none());
+    }
 }
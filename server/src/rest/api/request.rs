@@ -1,11 +1,15 @@
-use super::rest_types::{FlightLeg, FlightRequest, Itinerary};
+use super::error::status_from_grpc;
+use super::rest_types::{
+    FlightLeg, FlightRequest, Itinerary, ItineraryFilter, ItinerarySortBy, TimeWindow,
+};
 use super::utils::is_uuid;
-use crate::grpc::client::GrpcClients;
+use crate::grpc::client::{traced_request, GrpcClients};
 use axum::{extract::Extension, Json};
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use geo::HaversineDistance;
 use hyper::StatusCode;
 use lib_common::grpc::Client;
+use std::cmp::Ordering;
 
 //
 // Other Service Dependencies
@@ -17,7 +21,133 @@ use svc_scheduler_client_grpc::prelude::scheduler_storage::{
 use svc_scheduler_client_grpc::prelude::*;
 
 /// Don't allow excessively heavy loads
-const MAX_CARGO_WEIGHT_G: u32 = 1_000_000; // 1000 kg
+pub const MAX_CARGO_WEIGHT_G: u32 = 1_000_000; // 1000 kg
+
+/// Don't allow excessively long departure or arrival windows
+const MAX_TIME_WINDOW_HOURS: i64 = 24;
+
+/// How far beyond a window svc-scheduler is searched when the other window
+///  isn't provided, e.g. the latest arrival for a departure window
+pub const MAX_ITINERARY_DURATION_HOURS: i64 = 12;
+
+/// Reasons the time windows of a flight request are rejected
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TimeWindowError {
+    /// Neither a departure nor an arrival window was provided
+    Missing,
+    /// The departure window does not start before it ends
+    DepartureInverted,
+    /// The arrival window does not start before it ends
+    ArrivalInverted,
+    /// The departure window ends in the past
+    DeparturePast,
+    /// The arrival window ends in the past
+    ArrivalPast,
+    /// The departure window exceeds [`MAX_TIME_WINDOW_HOURS`]
+    DepartureTooLong,
+    /// The arrival window exceeds [`MAX_TIME_WINDOW_HOURS`]
+    ArrivalTooLong,
+    /// The arrival window ends before the departure window starts
+    ArrivalBeforeDeparture,
+}
+
+impl std::fmt::Display for TimeWindowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msg = match self {
+            TimeWindowError::Missing => "no departure or arrival window provided.".to_string(),
+            TimeWindowError::DepartureInverted => {
+                "departure window min must be earlier than its max.".to_string()
+            }
+            TimeWindowError::ArrivalInverted => {
+                "arrival window min must be earlier than its max.".to_string()
+            }
+            TimeWindowError::DeparturePast => "max depart time is in the past.".to_string(),
+            TimeWindowError::ArrivalPast => "max arrival time is in the past.".to_string(),
+            TimeWindowError::DepartureTooLong => {
+                format!("departure window exceeds {MAX_TIME_WINDOW_HOURS} hours.")
+            }
+            TimeWindowError::ArrivalTooLong => {
+                format!("arrival window exceeds {MAX_TIME_WINDOW_HOURS} hours.")
+            }
+            TimeWindowError::ArrivalBeforeDeparture => {
+                "arrival window ends before the departure window starts.".to_string()
+            }
+        };
+
+        write!(f, "{msg}")
+    }
+}
+
+/// Validates the departure and arrival windows of a flight request
+/// Returns the earliest departure and latest arrival to query svc-scheduler with
+fn validate_time_windows(
+    depart_window: Option<TimeWindow>,
+    arrive_window: Option<TimeWindow>,
+    current_time: DateTime<Utc>,
+) -> Result<(DateTime<Utc>, DateTime<Utc>), TimeWindowError> {
+    let max_window = Duration::hours(MAX_TIME_WINDOW_HOURS);
+    let max_itinerary = Duration::hours(MAX_ITINERARY_DURATION_HOURS);
+
+    if let Some(window) = depart_window {
+        if window.timestamp_min >= window.timestamp_max {
+            return Err(TimeWindowError::DepartureInverted);
+        }
+
+        if window.timestamp_max <= current_time {
+            return Err(TimeWindowError::DeparturePast);
+        }
+
+        if window.timestamp_max - window.timestamp_min > max_window {
+            return Err(TimeWindowError::DepartureTooLong);
+        }
+    }
+
+    if let Some(window) = arrive_window {
+        if window.timestamp_min >= window.timestamp_max {
+            return Err(TimeWindowError::ArrivalInverted);
+        }
+
+        if window.timestamp_max <= current_time {
+            return Err(TimeWindowError::ArrivalPast);
+        }
+
+        if window.timestamp_max - window.timestamp_min > max_window {
+            return Err(TimeWindowError::ArrivalTooLong);
+        }
+    }
+
+    let (earliest_departure, latest_arrival) = match (depart_window, arrive_window) {
+        (None, None) => return Err(TimeWindowError::Missing),
+        (Some(depart), Some(arrive)) => {
+            if arrive.timestamp_max <= depart.timestamp_min {
+                return Err(TimeWindowError::ArrivalBeforeDeparture);
+            }
+
+            (depart.timestamp_min, arrive.timestamp_max)
+        }
+        (Some(depart), None) => (depart.timestamp_min, depart.timestamp_max + max_itinerary),
+        (None, Some(arrive)) => (arrive.timestamp_min - max_itinerary, arrive.timestamp_max),
+    };
+
+    // Flights can't depart in the past
+    Ok((earliest_departure.max(current_time), latest_arrival))
+}
+
+/// Returns true if the itinerary departs within the departure window
+///  and arrives within the arrival window
+fn itinerary_in_windows(
+    itinerary: &Itinerary,
+    depart_window: Option<TimeWindow>,
+    arrive_window: Option<TimeWindow>,
+) -> bool {
+    let in_window = |window: Option<TimeWindow>, timestamp: DateTime<Utc>| match window {
+        Some(window) => window.timestamp_min <= timestamp && timestamp <= window.timestamp_max,
+        None => true,
+    };
+
+    in_window(depart_window, itinerary.timestamp_depart)
+        && in_window(arrive_window, itinerary.timestamp_arrive)
+}
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum FlightPlanError {
@@ -111,7 +241,13 @@ impl TryFrom<FlightPlanObject> for FlightLeg {
             vertiport_arrive_id,
             timestamp_depart: timestamp_depart.into(),
             timestamp_arrive: timestamp_arrive.into(),
-            path,
+            path: path
+                .into_iter()
+                .map(|point| super::rest_types::GeoPoint {
+                    latitude: point.latitude,
+                    longitude: point.longitude,
+                })
+                .collect(),
             distance_meters,
             base_pricing: None,
             currency_type: None,
@@ -119,25 +255,214 @@ impl TryFrom<FlightPlanObject> for FlightLeg {
     }
 }
 
+/// Creates an [`Itinerary`] from its legs, computing the summary fields
+/// Returns `None` if there are no legs
+fn build_itinerary(id: String, legs: Vec<FlightLeg>) -> Option<Itinerary> {
+    let timestamp_depart = legs.first()?.timestamp_depart;
+    let timestamp_arrive = legs.last()?.timestamp_arrive;
+
+    Some(Itinerary {
+        id,
+        total_duration_seconds: (timestamp_arrive - timestamp_depart).num_seconds(),
+        total_distance_meters: legs.iter().map(|leg| leg.distance_meters).sum(),
+        num_legs: legs.len() as u32,
+        timestamp_depart,
+        timestamp_arrive,
+        legs,
+        base_pricing: None,
+        // TODO(R4): Vary currency by region
+        currency_type: Some("usd".to_string()),
+    })
+}
+
+/// Checks the constraints of an itinerary filter, returns why it's rejected
+fn validate_filter(filter: &ItineraryFilter) -> Result<(), String> {
+    if filter.max_legs == Some(0) {
+        return Err("max legs must be greater than zero.".to_string());
+    }
+
+    if let Some(max_price) = filter.max_price {
+        if !max_price.is_finite() {
+            return Err("max price must be a finite number.".to_string());
+        }
+
+        if max_price < 0.0 {
+            return Err("max price is negative.".to_string());
+        }
+    }
+
+    if let Some(max_distance_meters) = filter.max_distance_meters {
+        if !max_distance_meters.is_finite() || max_distance_meters <= 0.0 {
+            return Err("max distance must be a finite number greater than zero.".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns true if the itinerary satisfies every constraint of the filter
+fn itinerary_matches(itinerary: &Itinerary, filter: &ItineraryFilter) -> bool {
+    if let Some(max_legs) = filter.max_legs {
+        if itinerary.num_legs > max_legs {
+            return false;
+        }
+    }
+
+    if let Some(max_price) = filter.max_price {
+        // An itinerary without pricing can't satisfy a price limit
+        match itinerary.base_pricing {
+            Some(price) if price <= max_price => (),
+            _ => return false,
+        }
+    }
+
+    if let Some(latest_arrival) = filter.latest_arrival {
+        if itinerary.timestamp_arrive > latest_arrival {
+            return false;
+        }
+    }
+
+    if let Some(max_distance_meters) = filter.max_distance_meters {
+        if itinerary.total_distance_meters > max_distance_meters {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Sorts itineraries by the requested criterion
+/// The sort is stable, ties keep the order provided by svc-scheduler
+fn sort_itineraries(itineraries: &mut [Itinerary], sort_by: ItinerarySortBy) {
+    match sort_by {
+        ItinerarySortBy::Price => itineraries.sort_by(|a, b| {
+            // Itineraries without pricing go last
+            match (a.base_pricing, b.base_pricing) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }
+        }),
+        ItinerarySortBy::EarliestDeparture => {
+            itineraries.sort_by_key(|itinerary| itinerary.timestamp_depart)
+        }
+        ItinerarySortBy::EarliestArrival => {
+            itineraries.sort_by_key(|itinerary| itinerary.timestamp_arrive)
+        }
+        ItinerarySortBy::Duration => {
+            itineraries.sort_by_key(|itinerary| itinerary.total_duration_seconds)
+        }
+        ItinerarySortBy::Distance => {
+            itineraries.sort_by(|a, b| a.total_distance_meters.total_cmp(&b.total_distance_meters))
+        }
+        ItinerarySortBy::FewestLegs => itineraries.sort_by_key(|itinerary| itinerary.num_legs),
+    }
+}
+
+/// Converts svc-scheduler itineraries into [`Itinerary`] offerings
+/// Itineraries with invalid flight plans are discarded
+pub fn unpack_itineraries(itineraries: Vec<scheduler::Itinerary>) -> Vec<Itinerary> {
+    let mut offerings: Vec<Itinerary> = vec![];
+    for itinerary in itineraries.into_iter() {
+        let id = itinerary.id.clone();
+        let legs = itinerary
+            .flight_plans
+            .into_iter()
+            .map(FlightLeg::try_from)
+            .collect::<Result<Vec<FlightLeg>, FlightPlanError>>();
+
+        let Ok(legs) = legs else {
+            rest_error!("(unpack_itineraries) Itinerary contained invalid flight plan(s).");
+            continue;
+        };
+
+        let Some(itinerary) = build_itinerary(id, legs) else {
+            rest_error!("(unpack_itineraries) Itinerary contained no flight plans.");
+            continue;
+        };
+
+        offerings.push(itinerary);
+    }
+
+    offerings
+}
+
+/// Requests pricing for each leg of the itinerary from svc-pricing
+/// Sets the price of each leg and the total price of the itinerary
+pub async fn price_itinerary(
+    grpc_clients: &mut GrpcClients,
+    itinerary: &mut Itinerary,
+    cargo_weight_kg: f32,
+) -> Result<(), StatusCode> {
+    let mut pricing_requests = pricing::PricingRequests { requests: vec![] };
+
+    for leg in &itinerary.legs {
+        let pricing_query = pricing::PricingRequest {
+            service_type: pricing::pricing_request::ServiceType::Cargo as i32,
+            distance_km: leg.distance_meters / 1000.0,
+            weight_kg: cargo_weight_kg,
+        };
+
+        pricing_requests.requests.push(pricing_query);
+    }
+
+    // Make request, process response
+    let response = grpc_clients
+        .backends
+        .pricing
+        .call("get_pricing", || async {
+            grpc_clients
+                .pricing
+                .get_client()
+                .await?
+                .get_pricing(traced_request(pricing_requests))
+                .await
+        })
+        .await;
+
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            rest_error!("(price_itinerary) svc-pricing error. {:?}", e);
+            rest_error!("(price_itinerary) invalidating svc-pricing client.");
+            grpc_clients.pricing.invalidate().await;
+            return Err(status_from_grpc(&e));
+        }
+    };
+
+    let response = response.into_inner();
+
+    for (price, leg) in response.prices.iter().zip(itinerary.legs.iter_mut()) {
+        leg.base_pricing = Some(*price);
+        leg.currency_type = Some("usd".to_string());
+    }
+
+    itinerary.base_pricing = Some(response.prices.iter().sum());
+    Ok(())
+}
+
 // Get Available Flights
 ///
 /// Search for available trips and return a list of [`Itinerary`].
-#[utoipa::path(
+/// The client's filter, sort and limit preferences are applied after pricing.
+#[cfg_attr(feature = "openapi", utoipa::path(
     post,
     path = "/cargo/request",
     tag = "svc-cargo",
     request_body = FlightRequest,
     responses(
         (status = 200, description = "List available flight plans", body = [Itinerary]),
-        (status = 400, description = "Request body is invalid format"),
+        (status = 400, description = "Request body is invalid format or time windows are invalid", body = String),
         (status = 500, description = "svc-scheduler or svc-pricing returned error"),
-        (status = 503, description = "Could not connect to other microservice dependencies")
+        (status = 503, description = "Could not connect to other microservice dependencies"),
+        (status = 504, description = "Microservice dependencies did not respond in time")
     )
-)]
+))]
 pub async fn request_flight(
     Extension(mut grpc_clients): Extension<GrpcClients>,
     Json(payload): Json<FlightRequest>,
-) -> Result<Json<Vec<Itinerary>>, StatusCode> {
+) -> Result<Json<Vec<Itinerary>>, (StatusCode, String)> {
     rest_debug!("(request_flight) entry.");
 
     //
@@ -149,81 +474,84 @@ pub async fn request_flight(
     if weight_g >= MAX_CARGO_WEIGHT_G {
         let error_msg = format!("request cargo weight exceeds {MAX_CARGO_WEIGHT_G}.");
         rest_error!("(request_flight) {}", &error_msg);
-        return Err(StatusCode::BAD_REQUEST);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
     }
 
     // Check UUID validity
     if !is_uuid(&payload.vertiport_arrive_id) {
         let error_msg = "arrival port ID not UUID format.".to_string();
         rest_error!("(request_flight) {}", &error_msg);
-        return Err(StatusCode::BAD_REQUEST);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
     }
 
     if !is_uuid(&payload.vertiport_depart_id) {
         let error_msg = "departure port ID not UUID format.".to_string();
         rest_error!("(request_flight) {}", &error_msg);
-        return Err(StatusCode::BAD_REQUEST);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
     }
 
-    let mut flight_query = scheduler::QueryFlightRequest {
-        is_cargo: true,
-        persons: None,
-        weight_grams: Some(weight_g),
-        vertiport_depart_id: payload.vertiport_depart_id,
-        vertiport_arrive_id: payload.vertiport_arrive_id,
-        earliest_departure_time: None,
-        latest_arrival_time: None,
-    };
-
-    let current_time = Utc::now();
+    if payload.limit == Some(0) {
+        let error_msg = "limit must be greater than zero.".to_string();
+        rest_error!("(request_flight) {}", &error_msg);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
+    }
 
-    // Time windows are properly specified
-    if let Some(window) = payload.time_arrive_window {
-        if window.timestamp_max <= current_time {
-            let error_msg = "max arrival time is in the past.".to_string();
-            rest_error!("(request_flight) {} {:?}", &error_msg, window.timestamp_max);
-            return Err(StatusCode::BAD_REQUEST);
+    if let Some(filter) = &payload.filter {
+        if let Err(error_msg) = validate_filter(filter) {
+            rest_error!("(request_flight) {}", &error_msg);
+            return Err((StatusCode::BAD_REQUEST, error_msg));
         }
-
-        // TODO(R4) - Rework this interface to be more intuitive
-        flight_query.earliest_departure_time =
-            Some((window.timestamp_min - Duration::hours(2)).into());
-        flight_query.latest_arrival_time = Some(window.timestamp_max.into());
     }
 
-    if let Some(window) = payload.time_depart_window {
-        if window.timestamp_max <= current_time {
-            let error_msg = "max depart time is in the past.".to_string();
-            rest_error!("(request_flight) {} {:?}", &error_msg, window.timestamp_max);
-            return Err(StatusCode::BAD_REQUEST);
+    // Time windows are properly specified
+    let current_time = Utc::now();
+    let (earliest_departure, latest_arrival) = match validate_time_windows(
+        payload.time_depart_window,
+        payload.time_arrive_window,
+        current_time,
+    ) {
+        Ok(bounds) => bounds,
+        Err(e) => {
+            let error_msg = e.to_string();
+            rest_error!("(request_flight) {}", &error_msg);
+            return Err((StatusCode::BAD_REQUEST, error_msg));
         }
+    };
 
-        // TODO(R4) - Rework this interface to be more intuitive
-        flight_query.earliest_departure_time = Some(window.timestamp_min.into());
-        flight_query.latest_arrival_time = Some((window.timestamp_max + Duration::hours(2)).into());
-    }
-
-    if flight_query.earliest_departure_time.is_none() || flight_query.latest_arrival_time.is_none()
-    {
-        let error_msg = "invalid time window.".to_string();
-        rest_error!("(request_flight) {}", &error_msg);
-        return Err(StatusCode::BAD_REQUEST);
-    }
+    let flight_query = scheduler::QueryFlightRequest {
+        is_cargo: true,
+        persons: None,
+        weight_grams: Some(weight_g),
+        vertiport_depart_id: payload.vertiport_depart_id,
+        vertiport_arrive_id: payload.vertiport_arrive_id,
+        earliest_departure_time: Some(earliest_departure.into()),
+        latest_arrival_time: Some(latest_arrival.into()),
+    };
 
     //
     // GRPC Request
     //
-    let response = grpc_clients.scheduler.query_flight(flight_query).await;
-    let Ok(response) = response else {
-        let error_msg = "svc-scheduler error.".to_string();
-        rest_error!(
-            "(request_flight) {} {:?}",
-            &error_msg,
-            response.unwrap_err()
-        );
-        rest_error!("(request_flight) invalidating svc-scheduler client.");
-        grpc_clients.scheduler.invalidate().await;
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    let response = grpc_clients
+        .backends
+        .scheduler
+        .call("query_flight", || async {
+            grpc_clients
+                .scheduler
+                .get_client()
+                .await?
+                .query_flight(traced_request(flight_query))
+                .await
+        })
+        .await;
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            let error_msg = "svc-scheduler error.".to_string();
+            rest_error!("(request_flight) {} {:?}", &error_msg, e);
+            rest_error!("(request_flight) invalidating svc-scheduler client.");
+            grpc_clients.scheduler.invalidate().await;
+            return Err((status_from_grpc(&e), error_msg));
+        }
     };
 
     let itineraries: Vec<scheduler::Itinerary> = response.into_inner().itineraries;
@@ -231,30 +559,16 @@ pub async fn request_flight(
     //
     // Unpack flight itineraries
     //
-
-    // List of lists of flights
-    let mut offerings: Vec<Itinerary> = vec![];
-    for itinerary in itineraries.into_iter() {
-        let id = itinerary.id.clone();
-        let legs = itinerary
-            .flight_plans
-            .into_iter()
-            .map(FlightLeg::try_from)
-            .collect::<Result<Vec<FlightLeg>, FlightPlanError>>();
-
-        let Ok(legs) = legs else {
-            rest_error!("(request_flight) Itinerary contained invalid flight plan(s).");
-            continue;
-        };
-
-        offerings.push(Itinerary {
-            id,
-            legs,
-            base_pricing: None,
-            // TODO(R4): Vary currency by region
-            currency_type: Some("usd".to_string()),
-        })
-    }
+    let mut offerings = unpack_itineraries(itineraries);
+
+    // svc-scheduler is queried with a wider range than the requested windows
+    offerings.retain(|itinerary| {
+        itinerary_in_windows(
+            itinerary,
+            payload.time_depart_window,
+            payload.time_arrive_window,
+        )
+    });
     rest_info!("(request_flight) found {} flight options.", offerings.len());
 
     //
@@ -264,41 +578,27 @@ pub async fn request_flight(
     // StatusUpdate message to customer?
     // e.g. Got your flights! Calculating prices...
     for itinerary in &mut offerings {
-        let mut pricing_requests = pricing::PricingRequests { requests: vec![] };
-
-        for leg in &itinerary.legs {
-            let pricing_query = pricing::PricingRequest {
-                service_type: pricing::pricing_request::ServiceType::Cargo as i32,
-                distance_km: leg.distance_meters / 1000.0,
-                weight_kg: payload.cargo_weight_kg,
-            };
-
-            pricing_requests.requests.push(pricing_query);
-        }
-
-        // Make request, process response
-        let response = grpc_clients.pricing.get_pricing(pricing_requests).await;
-
-        let Ok(response) = response else {
+        if let Err(e) = price_itinerary(&mut grpc_clients, itinerary, payload.cargo_weight_kg).await
+        {
             let error_msg = "svc-pricing error.".to_string();
-            rest_error!(
-                "(request_flight) {} {:?}",
-                &error_msg,
-                response.unwrap_err()
-            );
-            rest_error!("(request_flight) invalidating svc-pricing client.");
-            grpc_clients.pricing.invalidate().await;
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        };
+            rest_error!("(request_flight) {}", &error_msg);
+            return Err((e, error_msg));
+        }
+    }
 
-        let response = response.into_inner();
+    //
+    // Apply client preferences
+    //
+    if let Some(filter) = payload.filter {
+        offerings.retain(|itinerary| itinerary_matches(itinerary, &filter));
+    }
 
-        for (price, leg) in response.prices.iter().zip(itinerary.legs.iter_mut()) {
-            leg.base_pricing = Some(*price);
-            leg.currency_type = Some("usd".to_string());
-        }
+    if let Some(sort_by) = payload.sort_by {
+        sort_itineraries(&mut offerings, sort_by);
+    }
 
-        itinerary.base_pricing = Some(response.prices.iter().sum());
+    if let Some(limit) = payload.limit {
+        offerings.truncate(limit as usize);
     }
 
     rest_debug!(
@@ -312,7 +612,7 @@ pub async fn request_flight(
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
-    use chrono::{Duration, Utc};
+    use chrono::{DateTime, Duration, Utc};
     use svc_scheduler_client_grpc::prelude::scheduler_storage::flight_plan;
     use svc_scheduler_client_grpc::prelude::scheduler_storage::GeoLineString;
     use uuid::Uuid;
@@ -355,7 +655,15 @@ mod tests {
             result_data.target_vertiport_id.unwrap(),
             leg.vertiport_arrive_id
         );
-        assert_eq!(result_data.path.unwrap().points, leg.path);
+        let path: Vec<GeoPoint> = leg
+            .path
+            .iter()
+            .map(|point| GeoPoint {
+                latitude: point.latitude,
+                longitude: point.longitude,
+            })
+            .collect();
+        assert_eq!(result_data.path.unwrap().points, path);
 
         // Bad time arguments
         {
@@ -407,4 +715,289 @@ mod tests {
             assert_eq!(e, FlightPlanError::Data);
         }
     }
+
+    fn mock_itinerary(
+        depart: DateTime<Utc>,
+        leg_minutes: &[i64],
+        distance_meters: f32,
+        price: Option<f32>,
+    ) -> Itinerary {
+        let mut timestamp_depart = depart;
+        let legs = leg_minutes
+            .iter()
+            .map(|minutes| {
+                let timestamp_arrive = timestamp_depart + Duration::minutes(*minutes);
+                let leg = FlightLeg {
+                    flight_plan_id: Uuid::new_v4().to_string(),
+                    vertiport_depart_id: Uuid::new_v4().to_string(),
+                    vertiport_arrive_id: Uuid::new_v4().to_string(),
+                    timestamp_depart,
+                    timestamp_arrive,
+                    path: vec![],
+                    distance_meters: distance_meters / leg_minutes.len() as f32,
+                    currency_type: None,
+                    base_pricing: None,
+                };
+
+                timestamp_depart = timestamp_arrive + Duration::minutes(10);
+                leg
+            })
+            .collect();
+
+        let mut itinerary = build_itinerary(Uuid::new_v4().to_string(), legs).unwrap();
+        itinerary.base_pricing = price;
+        itinerary
+    }
+
+    #[test]
+    fn ut_build_itinerary_summary() {
+        let depart = Utc::now();
+        let itinerary = mock_itinerary(depart, &[30, 20], 3000.0, None);
+
+        assert_eq!(itinerary.num_legs, 2);
+        assert_eq!(itinerary.timestamp_depart, depart);
+        assert_eq!(
+            itinerary.timestamp_arrive,
+            depart + Duration::minutes(30 + 10 + 20)
+        );
+        assert_eq!(itinerary.total_duration_seconds, 60 * 60);
+        assert_eq!(itinerary.total_distance_meters, 3000.0);
+
+        assert!(build_itinerary(Uuid::new_v4().to_string(), vec![]).is_none());
+    }
+
+    #[test]
+    fn ut_itinerary_filter() {
+        let depart = Utc::now();
+        let itinerary = mock_itinerary(depart, &[30, 20], 3000.0, Some(50.0));
+
+        assert!(itinerary_matches(&itinerary, &ItineraryFilter::default()));
+
+        let filter = ItineraryFilter {
+            max_legs: Some(1),
+            ..Default::default()
+        };
+        assert!(!itinerary_matches(&itinerary, &filter));
+
+        let filter = ItineraryFilter {
+            max_legs: Some(2),
+            max_price: Some(50.0),
+            latest_arrival: Some(itinerary.timestamp_arrive),
+            max_distance_meters: Some(3000.0),
+        };
+        assert!(itinerary_matches(&itinerary, &filter));
+
+        let filter = ItineraryFilter {
+            max_price: Some(49.99),
+            ..Default::default()
+        };
+        assert!(!itinerary_matches(&itinerary, &filter));
+
+        let filter = ItineraryFilter {
+            latest_arrival: Some(itinerary.timestamp_arrive - Duration::seconds(1)),
+            ..Default::default()
+        };
+        assert!(!itinerary_matches(&itinerary, &filter));
+
+        let filter = ItineraryFilter {
+            max_distance_meters: Some(2999.0),
+            ..Default::default()
+        };
+        assert!(!itinerary_matches(&itinerary, &filter));
+
+        // Unpriced itineraries never satisfy a price limit
+        let unpriced = mock_itinerary(depart, &[30], 1000.0, None);
+        let filter = ItineraryFilter {
+            max_price: Some(1000.0),
+            ..Default::default()
+        };
+        assert!(!itinerary_matches(&unpriced, &filter));
+    }
+
+    #[test]
+    fn ut_validate_filter() {
+        assert!(validate_filter(&ItineraryFilter::default()).is_ok());
+        assert!(validate_filter(&ItineraryFilter {
+            max_legs: Some(2),
+            max_price: Some(0.0),
+            max_distance_meters: Some(25_000.0),
+            ..Default::default()
+        })
+        .is_ok());
+
+        for filter in [
+            ItineraryFilter {
+                max_legs: Some(0),
+                ..Default::default()
+            },
+            ItineraryFilter {
+                max_price: Some(-1.0),
+                ..Default::default()
+            },
+            ItineraryFilter {
+                max_price: Some(f32::NAN),
+                ..Default::default()
+            },
+            ItineraryFilter {
+                max_price: Some(f32::INFINITY),
+                ..Default::default()
+            },
+            ItineraryFilter {
+                max_distance_meters: Some(0.0),
+                ..Default::default()
+            },
+            ItineraryFilter {
+                max_distance_meters: Some(f32::NAN),
+                ..Default::default()
+            },
+        ] {
+            assert!(validate_filter(&filter).is_err(), "{:?}", filter);
+        }
+    }
+
+    #[test]
+    fn ut_sort_itineraries() {
+        let now = Utc::now();
+        let cheap_late = mock_itinerary(now + Duration::hours(2), &[60], 9000.0, Some(10.0));
+        let pricey_early = mock_itinerary(now, &[20, 20], 4000.0, Some(90.0));
+        let unpriced = mock_itinerary(now + Duration::hours(1), &[15], 2000.0, None);
+
+        let ids = |itineraries: &[Itinerary]| {
+            itineraries
+                .iter()
+                .map(|itinerary| itinerary.id.clone())
+                .collect::<Vec<String>>()
+        };
+
+        let mut itineraries = vec![unpriced.clone(), pricey_early.clone(), cheap_late.clone()];
+
+        sort_itineraries(&mut itineraries, ItinerarySortBy::Price);
+        assert_eq!(
+            ids(&itineraries),
+            ids(&[cheap_late.clone(), pricey_early.clone(), unpriced.clone()])
+        );
+
+        sort_itineraries(&mut itineraries, ItinerarySortBy::EarliestDeparture);
+        assert_eq!(
+            ids(&itineraries),
+            ids(&[pricey_early.clone(), unpriced.clone(), cheap_late.clone()])
+        );
+
+        sort_itineraries(&mut itineraries, ItinerarySortBy::EarliestArrival);
+        assert_eq!(
+            ids(&itineraries),
+            ids(&[pricey_early.clone(), unpriced.clone(), cheap_late.clone()])
+        );
+
+        sort_itineraries(&mut itineraries, ItinerarySortBy::Duration);
+        assert_eq!(
+            ids(&itineraries),
+            ids(&[unpriced.clone(), pricey_early.clone(), cheap_late.clone()])
+        );
+
+        sort_itineraries(&mut itineraries, ItinerarySortBy::Distance);
+        assert_eq!(
+            ids(&itineraries),
+            ids(&[unpriced.clone(), pricey_early.clone(), cheap_late.clone()])
+        );
+
+        sort_itineraries(&mut itineraries, ItinerarySortBy::FewestLegs);
+        assert_eq!(
+            ids(&itineraries),
+            ids(&[unpriced, cheap_late, pricey_early])
+        );
+    }
+
+    #[test]
+    fn ut_validate_time_windows() {
+        let now = Utc::now();
+        let window = |min: i64, max: i64| {
+            Some(TimeWindow {
+                timestamp_min: now + Duration::minutes(min),
+                timestamp_max: now + Duration::minutes(max),
+            })
+        };
+
+        // Both windows are honored exactly
+        let (earliest, latest) =
+            validate_time_windows(window(10, 70), window(60, 120), now).unwrap();
+        assert_eq!(earliest, now + Duration::minutes(10));
+        assert_eq!(latest, now + Duration::minutes(120));
+
+        // Departure window only
+        let (earliest, latest) = validate_time_windows(window(10, 70), None, now).unwrap();
+        assert_eq!(earliest, now + Duration::minutes(10));
+        assert_eq!(
+            latest,
+            now + Duration::minutes(70) + Duration::hours(MAX_ITINERARY_DURATION_HOURS)
+        );
+
+        // Arrival window only, can't depart in the past
+        let (earliest, latest) = validate_time_windows(None, window(60, 120), now).unwrap();
+        assert_eq!(earliest, now);
+        assert_eq!(latest, now + Duration::minutes(120));
+
+        assert_eq!(
+            validate_time_windows(None, None, now).unwrap_err(),
+            TimeWindowError::Missing
+        );
+        assert_eq!(
+            validate_time_windows(window(70, 10), None, now).unwrap_err(),
+            TimeWindowError::DepartureInverted
+        );
+        assert_eq!(
+            validate_time_windows(None, window(60, 60), now).unwrap_err(),
+            TimeWindowError::ArrivalInverted
+        );
+        assert_eq!(
+            validate_time_windows(window(-70, -10), None, now).unwrap_err(),
+            TimeWindowError::DeparturePast
+        );
+        assert_eq!(
+            validate_time_windows(None, window(-70, -10), now).unwrap_err(),
+            TimeWindowError::ArrivalPast
+        );
+
+        let too_long = MAX_TIME_WINDOW_HOURS * 60 + 1;
+        assert_eq!(
+            validate_time_windows(window(0, too_long), None, now).unwrap_err(),
+            TimeWindowError::DepartureTooLong
+        );
+        assert_eq!(
+            validate_time_windows(None, window(0, too_long), now).unwrap_err(),
+            TimeWindowError::ArrivalTooLong
+        );
+        assert_eq!(
+            validate_time_windows(window(60, 120), window(10, 50), now).unwrap_err(),
+            TimeWindowError::ArrivalBeforeDeparture
+        );
+    }
+
+    #[test]
+    fn ut_itinerary_in_windows() {
+        let now = Utc::now();
+        let window = |min: i64, max: i64| {
+            Some(TimeWindow {
+                timestamp_min: now + Duration::minutes(min),
+                timestamp_max: now + Duration::minutes(max),
+            })
+        };
+
+        // Departs at +30, arrives at +90
+        let itinerary = mock_itinerary(now + Duration::minutes(30), &[20, 30], 1000.0, None);
+
+        assert!(itinerary_in_windows(&itinerary, None, None));
+        assert!(itinerary_in_windows(&itinerary, window(30, 40), None));
+        assert!(itinerary_in_windows(&itinerary, None, window(80, 90)));
+        assert!(itinerary_in_windows(
+            &itinerary,
+            window(0, 60),
+            window(60, 120)
+        ));
+
+        assert!(!itinerary_in_windows(&itinerary, window(31, 60), None));
+        assert!(!itinerary_in_windows(&itinerary, window(0, 29), None));
+        assert!(!itinerary_in_windows(&itinerary, None, window(91, 120)));
+        assert!(!itinerary_in_windows(&itinerary, None, window(0, 89)));
+    }
 }
@@ -1,28 +1,172 @@
-use super::rest_types::ParcelScan;
-use super::utils::is_uuid;
-use crate::grpc::client::GrpcClients;
-use axum::{extract::Extension, Json};
-use chrono::Utc;
+use super::anomaly::{RecordedScan, ScanMonitor};
+use super::barcode::parse_parcel_barcode;
+use super::error::status_from_grpc;
+use super::rest_types::{
+    BatchScan, BatchScanResult, BatchScanStatus, ParcelScan, ParcelScanBatch,
+    ParcelScanBatchResponse, Scanner,
+};
+use super::scanner::{AssignedVertiport, ScannerRegistry};
+use super::utils::{from_hex, is_uuid};
+use crate::grpc::client::{traced_request, GrpcClients};
+use crate::rest::limit::API_KEY_HEADER;
+use crate::Config;
+use axum::{
+    body::Bytes,
+    extract::Extension,
+    http::{HeaderMap, HeaderName},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use futures::stream::{self, StreamExt};
 use hyper::StatusCode;
+use ring::hmac;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use svc_storage_client_grpc::prelude::*;
 use svc_storage_client_grpc::resources::parcel_scan::Data as ParcelScanData;
+use tokio::sync::watch;
+
+/// Header carrying the HMAC-SHA256 of a scan batch, in hexadecimal
+pub const SCANNER_SIGNATURE_HEADER: HeaderName = HeaderName::from_static("x-scanner-signature");
+
+/// Don't allow overly large batches of scans
+pub const MAX_SCAN_BATCH_SIZE: usize = 500;
+
+/// Scans of a batch inserted in svc-storage at the same time
+const MAX_CONCURRENT_SCAN_INSERTS: usize = 8;
+
+/// Returns true if the coordinates are in range
+pub fn is_valid_location(latitude: f64, longitude: f64) -> bool {
+    (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude)
+}
+
+/// Parcel scan record for svc-storage
+pub fn scan_data(
+    scanner_id: String,
+    parcel_id: String,
+    latitude: f64,
+    longitude: f64,
+    created_at: DateTime<Utc>,
+) -> ParcelScanData {
+    ParcelScanData {
+        scanner_id,
+        parcel_id,
+        geo_location: Some(GeoPoint {
+            latitude,
+            longitude,
+        }),
+        created_at: Some(created_at.into()),
+    }
+}
+
+/// The registered scanner sending a scan, with its API key in the headers
+pub fn authorize_scanner(
+    function: &str,
+    scanner_registry: &ScannerRegistry,
+    scanner_id: &str,
+    headers: &HeaderMap,
+) -> Result<Scanner, (StatusCode, String)> {
+    let api_key = headers
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    scanner_registry
+        .authorize(scanner_id, api_key)
+        .map_err(|e| {
+            let error_msg = format!("scanner {scanner_id} rejected: {e}");
+            rest_error!("({}) {}", function, &error_msg);
+            (e.status(), error_msg)
+        })
+}
+
+/// Requests the vertiport a scanner is assigned to
+async fn assigned_vertiport(
+    function: &str,
+    scanner: &Scanner,
+    grpc_clients: &GrpcClients,
+) -> Result<Option<AssignedVertiport>, (StatusCode, String)> {
+    AssignedVertiport::of(scanner, grpc_clients)
+        .await
+        .map_err(|status| {
+            let error_msg = "could not get the vertiport of the scanner.".to_string();
+            rest_error!("({}) {}", function, &error_msg);
+            (status, error_msg)
+        })
+}
+
+/// Inserts a parcel scan in svc-storage
+pub async fn insert_scan(
+    grpc_clients: &GrpcClients,
+    data: ParcelScanData,
+) -> Result<(), StatusCode> {
+    let response = match grpc_clients
+        .backends
+        .storage
+        .call("parcel_scan.insert", || async {
+            grpc_clients
+                .storage
+                .parcel_scan
+                .get_client()
+                .await?
+                .insert(traced_request(data.clone()))
+                .await
+        })
+        .await
+    {
+        Ok(response) => response.into_inner(),
+        Err(e) => {
+            let error_msg = "svc-storage error.".to_string();
+            rest_error!("(insert_scan) {} {:?}", &error_msg, e);
+            return Err(status_from_grpc(&e));
+        }
+    };
+
+    let Some(response) = response.validation_result else {
+        let error_msg = "svc-storage response invalid.".to_string();
+        rest_error!("(insert_scan) {}", &error_msg);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+
+    if response.success {
+        rest_info!("(insert_scan) svc-storage success.");
+        Ok(())
+    } else {
+        let error_msg = "svc-storage failure.".to_string();
+        rest_error!("(insert_scan) {}", &error_msg);
+        Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
 
 /// Scan a parcel
 /// The provided parcel ID and scanner ID must already exist in the database
-#[utoipa::path(
+///
+/// The scanner must be registered and active, and send its API key in the
+///  `X-Api-Key` header. Scanners assigned to a vertiport may only scan there.
+///
+/// Recorded scans are compared with the itinerary of the parcel afterwards,
+///  see `GET /cargo/scan/anomalies`.
+#[cfg_attr(feature = "openapi", utoipa::path(
     put,
     path = "/cargo/scan",
     tag = "svc-cargo",
     request_body = ParcelScan,
     responses(
         (status = 200, description = "Scan succeeded", body = String),
-        (status = 400, description = "Request body is invalid format"),
+        (status = 400, description = "Request body is invalid format, or scan not at the vertiport of the scanner"),
+        (status = 401, description = "Scanner API key missing or invalid"),
+        (status = 403, description = "Scanner unknown or deactivated"),
+        (status = 409, description = "Scan already recorded"),
         (status = 500, description = "svc-storage returned error"),
-        (status = 503, description = "Could not connect to other microservice dependencies")
+        (status = 503, description = "Could not connect to other microservice dependencies"),
+        (status = 504, description = "Microservice dependencies did not respond in time")
     )
-)]
+))]
 pub async fn scan_parcel(
     Extension(grpc_clients): Extension<GrpcClients>,
+    Extension(scan_monitor): Extension<ScanMonitor>,
+    Extension(scanner_registry): Extension<ScannerRegistry>,
+    Extension(live_config): Extension<watch::Receiver<Config>>,
+    headers: HeaderMap,
     Json(payload): Json<ParcelScan>,
 ) -> Result<(), StatusCode> {
     rest_debug!("(scan_parcel) entry.");
@@ -39,11 +183,7 @@ pub async fn scan_parcel(
         return Err(StatusCode::BAD_REQUEST);
     }
 
-    if payload.latitude < -90.0
-        || payload.latitude > 90.0
-        || payload.longitude < -180.0
-        || payload.longitude > 180.0
-    {
+    if !is_valid_location(payload.latitude, payload.longitude) {
         let error_msg = "coordinates out of range.".to_string();
         rest_error!(
             "(scan_parcel) {}: (lat: {}, lon: {})",
@@ -54,38 +194,680 @@ pub async fn scan_parcel(
         return Err(StatusCode::BAD_REQUEST);
     }
 
+    let scanner = authorize_scanner(
+        "scan_parcel",
+        &scanner_registry,
+        &payload.scanner_id,
+        &headers,
+    )
+    .map_err(|(status, _)| status)?;
+
+    let config = live_config.borrow().clone();
+    let margin = config.rest_scan_vertiport_margin_meters.into();
+    if let Some(vertiport) = assigned_vertiport("scan_parcel", &scanner, &grpc_clients)
+        .await
+        .map_err(|(status, _)| status)?
+    {
+        if let Err(e) = vertiport.check(payload.latitude, payload.longitude, margin) {
+            rest_error!("(scan_parcel) {}", &e);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
     // Make request, process response
-    let data = ParcelScanData {
+    let scan = RecordedScan {
         scanner_id: payload.scanner_id,
         parcel_id: payload.parcel_id,
-        geo_location: Some(GeoPoint {
-            latitude: payload.longitude,
-            longitude: payload.latitude,
-        }),
-        created_at: Some(Utc::now().into()),
+        latitude: payload.latitude,
+        longitude: payload.longitude,
+        scanned_at: Utc::now(),
     };
+    let data = scan_data(
+        scan.scanner_id.clone(),
+        scan.parcel_id.clone(),
+        scan.latitude,
+        scan.longitude,
+        scan.scanned_at,
+    );
 
-    let response = match grpc_clients.storage.parcel_scan.insert(data).await {
-        Ok(response) => response.into_inner(),
-        Err(e) => {
-            let error_msg = "svc-storage error.".to_string();
-            rest_error!("(scan_parcel) {} {:?}", &error_msg, e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    insert_scan(&grpc_clients, data).await?;
+
+    scan_monitor.inspect(grpc_clients, config, vec![scan]);
+    Ok(())
+}
+
+/// Identifies a scan for deduplication
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ScanKey {
+    scanner_id: String,
+    parcel_id: String,
+    /// Device timestamp in whole seconds, uploads may round differently
+    timestamp: i64,
+}
+
+/// Scans recently recorded through batches
+///
+/// Devices upload a batch again when they miss the response, the scans of
+///  the first upload are reported as duplicates rather than recorded twice.
+///  Scans are only remembered for as long as uploads of them are accepted.
+#[derive(Debug, Clone, Default)]
+pub struct RecentScans {
+    scans: Arc<Mutex<HashMap<ScanKey, DateTime<Utc>>>>,
+}
+
+impl RecentScans {
+    /// Remembers a scan, returns false if it was already known
+    ///
+    /// Scans captured before `oldest` are forgotten.
+    fn claim(&self, key: ScanKey, captured_at: DateTime<Utc>, oldest: DateTime<Utc>) -> bool {
+        let Ok(mut scans) = self.scans.lock() else {
+            rest_warn!("(RecentScans::claim) lock poisoned.");
+            return true;
+        };
+
+        scans.retain(|_, captured_at| *captured_at >= oldest);
+        if scans.contains_key(&key) {
+            return false;
         }
+
+        scans.insert(key, captured_at);
+        true
+    }
+
+    /// Forgets a scan that couldn't be recorded, so it can be sent again
+    fn release(&self, key: &ScanKey) {
+        let Ok(mut scans) = self.scans.lock() else {
+            rest_warn!("(RecentScans::release) lock poisoned.");
+            return;
+        };
+
+        scans.remove(key);
+    }
+}
+
+/// Returns true if the hexadecimal signature is the HMAC-SHA256 of the body
+fn is_valid_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Some(signature) = from_hex(signature.trim()) else {
+        return false;
     };
 
-    let Some(response) = response.validation_result else {
-        let error_msg = "svc-storage response invalid.".to_string();
-        rest_error!("(scan_parcel) {}", &error_msg);
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    hmac::verify(&key, body, &signature).is_ok()
+}
+
+/// Checks a scan of a batch, returns its parcel ID or why it's rejected
+fn check_batch_scan(
+    scan: &BatchScan,
+    current_time: DateTime<Utc>,
+    config: &Config,
+    vertiport: Option<&AssignedVertiport>,
+) -> Result<String, String> {
+    let parcel_id = match (&scan.parcel_id, &scan.barcode) {
+        (Some(parcel_id), _) if is_uuid(parcel_id) => parcel_id.clone(),
+        (Some(_), _) => return Err("parcel ID not in UUID format.".to_string()),
+        (None, Some(barcode)) => parse_parcel_barcode(barcode)
+            .ok_or_else(|| "barcode is not a parcel barcode.".to_string())?,
+        (None, None) => return Err("parcel ID or barcode required.".to_string()),
     };
 
-    if response.success {
-        rest_info!("(scan_parcel) svc-storage success.");
-        Ok(())
-    } else {
-        let error_msg = "svc-storage failure.".to_string();
-        rest_error!("(scan_parcel) {}", &error_msg);
-        Err(StatusCode::INTERNAL_SERVER_ERROR)
+    if !is_valid_location(scan.latitude, scan.longitude) {
+        return Err("coordinates out of range.".to_string());
+    }
+
+    if let Some(vertiport) = vertiport {
+        let margin = config.rest_scan_vertiport_margin_meters.into();
+        vertiport.check(scan.latitude, scan.longitude, margin)?;
+    }
+
+    let max_skew = Duration::seconds(config.rest_scan_max_clock_skew_seconds.into());
+    if scan.timestamp > current_time + max_skew {
+        return Err("timestamp is in the future.".to_string());
+    }
+
+    let max_age = Duration::hours(config.rest_scan_max_age_hours.into());
+    if scan.timestamp < current_time - max_age {
+        return Err(format!(
+            "scan is older than {} hours.",
+            config.rest_scan_max_age_hours
+        ));
+    }
+
+    Ok(parcel_id)
+}
+
+/// Result of a scan of a batch
+fn batch_result(
+    index: usize,
+    parcel_id: Option<String>,
+    status: BatchScanStatus,
+    message: Option<String>,
+) -> BatchScanResult {
+    BatchScanResult {
+        index: index as u32,
+        parcel_id,
+        status,
+        message,
+    }
+}
+
+/// Upload scans captured by a scanner device, e.g. while offline
+///
+/// Each scan keeps the time it was captured at. Scans are checked and
+///  recorded one by one, the response has the outcome of each scan. Scans
+///  that failed may be sent again, duplicates of recorded scans aren't
+///  recorded twice.
+///
+/// With a scanner signing secret configured, the body must be signed with
+///  its HMAC-SHA256 in the `X-Scanner-Signature` header. The scanner must be
+///  registered and active, and send its API key in the `X-Api-Key` header.
+///  Scans away from the vertiport the scanner is assigned to are rejected.
+///
+/// Recorded scans are compared with the itineraries of their parcels
+///  afterwards, see `GET /cargo/scan/anomalies`.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    put,
+    path = "/cargo/scan/batch",
+    tag = "svc-cargo",
+    request_body = ParcelScanBatch,
+    responses(
+        (status = 200, description = "Outcome of each scan", body = ParcelScanBatchResponse),
+        (status = 400, description = "Request body is invalid format", body = String),
+        (status = 401, description = "Scanner signature or API key missing or invalid", body = String),
+        (status = 403, description = "Scanner unknown or deactivated", body = String)
+    )
+))]
+pub async fn scan_parcel_batch(
+    Extension(grpc_clients): Extension<GrpcClients>,
+    Extension(recent_scans): Extension<RecentScans>,
+    Extension(scan_monitor): Extension<ScanMonitor>,
+    Extension(scanner_registry): Extension<ScannerRegistry>,
+    Extension(live_config): Extension<watch::Receiver<Config>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ParcelScanBatchResponse>, (StatusCode, String)> {
+    rest_debug!("(scan_parcel_batch) entry.");
+    let config = live_config.borrow().clone();
+
+    //
+    // Validate Request
+    //
+    if !config.rest_scanner_signing_secret.is_empty() {
+        let signature = headers
+            .get(SCANNER_SIGNATURE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if !is_valid_signature(&config.rest_scanner_signing_secret, &body, signature) {
+            let error_msg = "scanner signature missing or invalid.".to_string();
+            rest_error!("(scan_parcel_batch) {}", &error_msg);
+            return Err((StatusCode::UNAUTHORIZED, error_msg));
+        }
+    }
+
+    let batch: ParcelScanBatch = serde_json::from_slice(&body).map_err(|e| {
+        let error_msg = format!("invalid scan batch: {e}");
+        rest_error!("(scan_parcel_batch) {}", &error_msg);
+        (StatusCode::BAD_REQUEST, error_msg)
+    })?;
+
+    if !is_uuid(&batch.scanner_id) {
+        let error_msg = "scanner ID not in UUID format.".to_string();
+        rest_error!("(scan_parcel_batch) {}", &error_msg);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
+    }
+
+    if batch.scans.is_empty() || batch.scans.len() > MAX_SCAN_BATCH_SIZE {
+        let error_msg = format!("a batch must have 1 to {MAX_SCAN_BATCH_SIZE} scans.");
+        rest_error!("(scan_parcel_batch) {}", &error_msg);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
+    }
+
+    let scanner = authorize_scanner(
+        "scan_parcel_batch",
+        &scanner_registry,
+        &batch.scanner_id,
+        &headers,
+    )?;
+    let vertiport = assigned_vertiport("scan_parcel_batch", &scanner, &grpc_clients).await?;
+
+    //
+    // Check each scan, skipping scans already recorded
+    //
+    let current_time = Utc::now();
+    let oldest = current_time - Duration::hours(config.rest_scan_max_age_hours.into());
+    let mut results = vec![];
+    let mut pending = vec![];
+    let mut batch_keys: HashMap<ScanKey, usize> = HashMap::new();
+    for (index, scan) in batch.scans.iter().enumerate() {
+        let parcel_id = match check_batch_scan(scan, current_time, &config, vertiport.as_ref()) {
+            Ok(parcel_id) => parcel_id,
+            Err(e) => {
+                rest_info!("(scan_parcel_batch) scan {} rejected: {}", index, e);
+                let parcel_id = scan.parcel_id.clone().filter(|id| is_uuid(id));
+                results.push(batch_result(
+                    index,
+                    parcel_id,
+                    BatchScanStatus::Rejected,
+                    Some(e),
+                ));
+                continue;
+            }
+        };
+
+        let key = ScanKey {
+            scanner_id: batch.scanner_id.clone(),
+            parcel_id: parcel_id.clone(),
+            timestamp: scan.timestamp.timestamp(),
+        };
+        if let Some(first) = batch_keys.get(&key) {
+            results.push(batch_result(
+                index,
+                Some(parcel_id),
+                BatchScanStatus::Duplicate,
+                Some(format!("same scan as scan {first}.")),
+            ));
+            continue;
+        }
+        batch_keys.insert(key.clone(), index);
+
+        if !recent_scans.claim(key.clone(), scan.timestamp, oldest) {
+            results.push(batch_result(
+                index,
+                Some(parcel_id),
+                BatchScanStatus::Duplicate,
+                None,
+            ));
+            continue;
+        }
+
+        let data = scan_data(
+            batch.scanner_id.clone(),
+            parcel_id,
+            scan.latitude,
+            scan.longitude,
+            scan.timestamp,
+        );
+        pending.push((index, key, data));
+    }
+
+    //
+    // Record the remaining scans, a few at a time
+    //
+    let recorded: Vec<BatchScanResult> = stream::iter(pending)
+        .map(|(index, key, data)| {
+            let grpc_clients = grpc_clients.clone();
+            let recent_scans = recent_scans.clone();
+            async move {
+                let parcel_id = Some(key.parcel_id.clone());
+                let (status, message) = match insert_scan(&grpc_clients, data).await {
+                    Ok(()) => (BatchScanStatus::Recorded, None),
+                    Err(StatusCode::CONFLICT) => (BatchScanStatus::Duplicate, None),
+                    Err(status) => {
+                        recent_scans.release(&key);
+                        let message = Some(format!("svc-storage answered {status}."));
+                        match status {
+                            StatusCode::BAD_REQUEST | StatusCode::NOT_FOUND => {
+                                (BatchScanStatus::Rejected, message)
+                            }
+                            _ => (BatchScanStatus::Failed, message),
+                        }
+                    }
+                };
+
+                batch_result(index, parcel_id, status, message)
+            }
+        })
+        .buffer_unordered(MAX_CONCURRENT_SCAN_INSERTS)
+        .collect()
+        .await;
+
+    results.extend(recorded);
+    results.sort_by_key(|result| result.index);
+
+    let recorded_scans = results
+        .iter()
+        .filter(|result| result.status == BatchScanStatus::Recorded)
+        .filter_map(|result| {
+            let scan = &batch.scans[result.index as usize];
+            Some(RecordedScan {
+                scanner_id: batch.scanner_id.clone(),
+                parcel_id: result.parcel_id.clone()?,
+                latitude: scan.latitude,
+                longitude: scan.longitude,
+                scanned_at: scan.timestamp,
+            })
+        })
+        .collect();
+    scan_monitor.inspect(grpc_clients, config, recorded_scans);
+
+    let count = |status| results.iter().filter(|r| r.status == status).count() as u32;
+    let response = ParcelScanBatchResponse {
+        recorded: count(BatchScanStatus::Recorded),
+        duplicates: count(BatchScanStatus::Duplicate),
+        rejected: count(BatchScanStatus::Rejected),
+        failed: count(BatchScanStatus::Failed),
+        results,
+    };
+
+    rest_info!(
+        "(scan_parcel_batch) {} recorded, {} duplicates, {} rejected, {} failed.",
+        response.recorded,
+        response.duplicates,
+        response.rejected,
+        response.failed
+    );
+    Ok(Json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::scanner::MemoryScannerStore;
+    use super::super::utils::hex;
+    use super::*;
+
+    fn mock_scan(timestamp: DateTime<Utc>) -> BatchScan {
+        BatchScan {
+            parcel_id: Some("cabcdd14-03ab-4ac0-b58c-dd4175bc587e".to_string()),
+            barcode: None,
+            latitude: 52.3747,
+            longitude: 4.9167,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn ut_scan_data() {
+        let data = scan_data(
+            "scanner".to_string(),
+            "parcel".to_string(),
+            52.3747,
+            4.9167,
+            Utc::now(),
+        );
+        let location = data.geo_location.unwrap();
+        assert_eq!(location.latitude, 52.3747);
+        assert_eq!(location.longitude, 4.9167);
+    }
+
+    #[test]
+    fn ut_check_batch_scan() {
+        let config = Config::default();
+        let now = Utc::now();
+        let parcel_id = "cabcdd14-03ab-4ac0-b58c-dd4175bc587e".to_string();
+
+        assert_eq!(
+            check_batch_scan(&mock_scan(now), now, &config, None),
+            Ok(parcel_id.clone())
+        );
+
+        // The barcode stands in for the parcel ID
+        let scan = BatchScan {
+            parcel_id: None,
+            barcode: Some("ARWCABCDD1403AB4AC0B58CDD4175BC587E".to_string()),
+            ..mock_scan(now)
+        };
+        assert_eq!(check_batch_scan(&scan, now, &config, None), Ok(parcel_id));
+
+        let scan = BatchScan {
+            parcel_id: None,
+            barcode: Some("0123456789".to_string()),
+            ..mock_scan(now)
+        };
+        assert!(check_batch_scan(&scan, now, &config, None).is_err());
+        let scan = BatchScan {
+            parcel_id: None,
+            ..mock_scan(now)
+        };
+        assert!(check_batch_scan(&scan, now, &config, None).is_err());
+        let scan = BatchScan {
+            latitude: 91.0,
+            ..mock_scan(now)
+        };
+        assert!(check_batch_scan(&scan, now, &config, None).is_err());
+
+        // Device clocks may be a little ahead
+        let ahead = now + Duration::seconds(config.rest_scan_max_clock_skew_seconds.into());
+        assert!(check_batch_scan(&mock_scan(ahead), now, &config, None).is_ok());
+        let ahead = ahead + Duration::seconds(1);
+        assert!(check_batch_scan(&mock_scan(ahead), now, &config, None).is_err());
+
+        let old = now - Duration::hours(config.rest_scan_max_age_hours.into());
+        assert!(check_batch_scan(&mock_scan(old), now, &config, None).is_ok());
+        let old = old - Duration::seconds(1);
+        assert!(check_batch_scan(&mock_scan(old), now, &config, None).is_err());
+
+        // Scanners assigned to a vertiport scan there
+        let d = 0.001;
+        let vertiport = AssignedVertiport {
+            id: "vertiport".to_string(),
+            outline: geo::Polygon::new(
+                geo::LineString::from(vec![
+                    (4.9167 - d, 52.3747 - d),
+                    (4.9167 + d, 52.3747 - d),
+                    (4.9167 + d, 52.3747 + d),
+                    (4.9167 - d, 52.3747 + d),
+                ]),
+                vec![],
+            ),
+        };
+        let scan = mock_scan(now);
+        assert!(check_batch_scan(&scan, now, &config, Some(&vertiport)).is_ok());
+        let scan = BatchScan {
+            latitude: 52.09,
+            ..mock_scan(now)
+        };
+        assert!(check_batch_scan(&scan, now, &config, Some(&vertiport)).is_err());
+    }
+
+    #[test]
+    fn ut_recent_scans() {
+        let recent_scans = RecentScans::default();
+        let now = Utc::now();
+        let key = ScanKey {
+            scanner_id: "scanner".to_string(),
+            parcel_id: "parcel".to_string(),
+            timestamp: now.timestamp(),
+        };
+
+        assert!(recent_scans.claim(key.clone(), now, now - Duration::hours(1)));
+        assert!(!recent_scans.claim(key.clone(), now, now - Duration::hours(1)));
+
+        // Released scans may be sent again
+        recent_scans.release(&key);
+        assert!(recent_scans.claim(key.clone(), now, now - Duration::hours(1)));
+
+        // Scans too old to be uploaded are forgotten
+        let other = ScanKey {
+            parcel_id: "other".to_string(),
+            ..key.clone()
+        };
+        assert!(recent_scans.claim(other, now, now + Duration::seconds(1)));
+        assert!(recent_scans.claim(key, now, now + Duration::seconds(1)));
+    }
+
+    #[tokio::test]
+    async fn ut_scan_parcel_batch() {
+        use axum::{body::Body, http::Request, routing, Router};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use svc_storage_client_grpc::resources::parcel_scan;
+        use tonic::Status;
+        use tower::ServiceExt;
+
+        let config = Config {
+            rest_scanner_signing_secret: "scanner-secret".to_string(),
+            ..Config::default()
+        };
+        let (_, live_config) = watch::channel(config.clone());
+        let scanner_registry = ScannerRegistry::with_store(Arc::new(MemoryScannerStore::default()));
+        let credentials = scanner_registry
+            .register("Handheld".to_string(), None)
+            .await
+            .unwrap();
+        let grpc_clients = GrpcClients::default(config.clone());
+        let inserts = Arc::new(AtomicUsize::new(0));
+        let insert = {
+            let inserts = inserts.clone();
+            move || {
+                inserts.fetch_add(1, Ordering::SeqCst);
+                tonic::Response::new(parcel_scan::Response {
+                    validation_result: Some(ValidationResult {
+                        success: true,
+                        errors: vec![],
+                    }),
+                    object: None,
+                })
+            }
+        };
+        grpc_clients
+            .backends
+            .storage
+            .stub("parcel_scan.insert", insert.clone());
+        let app = Router::new()
+            .route("/cargo/scan/batch", routing::put(scan_parcel_batch))
+            .layer(Extension(live_config))
+            .layer(Extension(RecentScans::default()))
+            .layer(Extension(ScanMonitor::new(&config)))
+            .layer(Extension(scanner_registry))
+            .layer(Extension(grpc_clients.clone()));
+        let request_with_key = |body: &str, signature: &str, api_key: &str| {
+            Request::builder()
+                .method("PUT")
+                .uri("/cargo/scan/batch")
+                .header("content-type", "application/json")
+                .header(SCANNER_SIGNATURE_HEADER, signature)
+                .header(API_KEY_HEADER, api_key)
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+        let request =
+            |body: &str, signature: &str| request_with_key(body, signature, &credentials.api_key);
+        let sign = |body: &str| {
+            let key = hmac::Key::new(hmac::HMAC_SHA256, b"scanner-secret");
+            hex(hmac::sign(&key, body.as_bytes()).as_ref())
+        };
+
+        let now = Utc::now();
+        let batch = ParcelScanBatch {
+            scanner_id: credentials.scanner.id.clone(),
+            scans: vec![
+                mock_scan(now),
+                BatchScan {
+                    longitude: 181.0,
+                    ..mock_scan(now)
+                },
+                mock_scan(now),
+            ],
+        };
+        let body = serde_json::to_string(&batch).unwrap();
+
+        // Batches must be signed
+        let response = app
+            .clone()
+            .oneshot(request(&body, &"0".repeat(64)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app
+            .clone()
+            .oneshot(request("{}", &sign("{}")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // Scanners must be registered and send their key
+        let response = app
+            .clone()
+            .oneshot(request_with_key(&body, &sign(&body), "other"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let unknown = ParcelScanBatch {
+            scanner_id: "8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1".to_string(),
+            ..batch.clone()
+        };
+        let unknown_body = serde_json::to_string(&unknown).unwrap();
+        let response = app
+            .clone()
+            .oneshot(request(&unknown_body, &sign(&unknown_body)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let upload = |body: String| {
+            let app = app.clone();
+            async move {
+                let signature = sign(&body);
+                let response = app.oneshot(request(&body, &signature)).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+                serde_json::from_slice::<ParcelScanBatchResponse>(&bytes).unwrap()
+            }
+        };
+        let statuses = |response: &ParcelScanBatchResponse| -> Vec<BatchScanStatus> {
+            response.results.iter().map(|r| r.status).collect()
+        };
+
+        // Each scan gets a result, in the order of the batch
+        let response = upload(body).await;
+        assert_eq!(
+            statuses(&response),
+            [
+                BatchScanStatus::Recorded,
+                BatchScanStatus::Rejected,
+                BatchScanStatus::Duplicate
+            ]
+        );
+        assert_eq!(response.recorded, 1);
+        assert_eq!(response.rejected, 1);
+        assert_eq!(response.duplicates, 1);
+        assert_eq!(response.failed, 0);
+        assert_eq!(inserts.load(Ordering::SeqCst), 1);
+
+        // Scans svc-storage fails to record can be uploaded again
+        let retried = ParcelScanBatch {
+            scanner_id: credentials.scanner.id.clone(),
+            scans: vec![mock_scan(now - Duration::minutes(5))],
+        };
+        let retried_body = serde_json::to_string(&retried).unwrap();
+        grpc_clients
+            .backends
+            .storage
+            .stub_failure("parcel_scan.insert", Status::internal("storage failure"));
+        let response = upload(retried_body.clone()).await;
+        assert_eq!(statuses(&response), [BatchScanStatus::Failed]);
+        assert_eq!(response.failed, 1);
+        assert_eq!(response.recorded, 0);
+
+        grpc_clients
+            .backends
+            .storage
+            .stub("parcel_scan.insert", insert);
+        let response = upload(retried_body).await;
+        assert_eq!(statuses(&response), [BatchScanStatus::Recorded]);
+        assert_eq!(inserts.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn ut_is_valid_signature() {
+        // RFC 4231 test case 2
+        let signature = "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843";
+        assert!(is_valid_signature(
+            "Jefe",
+            b"what do ya want for nothing?",
+            signature
+        ));
+        assert!(is_valid_signature(
+            "Jefe",
+            b"what do ya want for nothing?",
+            &signature.to_uppercase()
+        ));
+        assert!(!is_valid_signature(
+            "Jefe",
+            b"what do ya want for nothing!",
+            signature
+        ));
+        assert!(!is_valid_signature("Jefe", b"", ""));
+        assert!(!is_valid_signature("Jefe", b"", &"zz".repeat(32)));
     }
 }
@@ -0,0 +1,1065 @@
+//! Registered scanner devices
+//!
+//! Scanners are registered with a label and optionally the vertiport they are
+//!  used at. Each scanner gets an API key it sends in the `X-Api-Key` header
+//!  with its scans; only a SHA-256 digest of the key is kept. Deactivated
+//!  scanners stay registered, so their past scans remain attributable.
+
+use super::geofence::{distance_to_polygon, get_vertiport_polygon};
+use super::rest_types::{
+    Scanner, ScannerAssignment, ScannerCredentials, ScannerRegistration, ScannersParams,
+    ScannersResponse,
+};
+use super::utils::{get_vertiport_details, hex, is_uuid, random_token};
+use crate::grpc::client::GrpcClients;
+use crate::rest::limit::API_KEY_HEADER;
+use crate::Config;
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use geo::{Coord, Polygon};
+use hyper::StatusCode;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+/// Don't allow overly long scanner labels
+const MAX_LABEL_LENGTH: usize = 100;
+
+/// A registered scanner as kept by a [`ScannerStore`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScannerRecord {
+    /// The scanner device
+    #[serde(flatten)]
+    pub scanner: Scanner,
+
+    /// Hexadecimal SHA-256 digest of the API key
+    pub key_digest: String,
+}
+
+/// Keeps registered scanners
+pub trait ScannerStore: Send + Sync + fmt::Debug {
+    /// The scanner with the given ID, if registered
+    fn get(&self, scanner_id: &str) -> io::Result<Option<ScannerRecord>>;
+
+    /// The scanner with the given API key digest, if any
+    fn find_by_key_digest(&self, key_digest: &str) -> io::Result<Option<ScannerRecord>>;
+
+    /// All scanners, in the order they were registered
+    fn list(&self) -> io::Result<Vec<ScannerRecord>>;
+
+    /// Adds a scanner, or replaces the scanner with the same ID
+    fn put(&self, record: ScannerRecord) -> io::Result<()>;
+}
+
+/// Scanners in the order they were registered, indexed by ID and key digest
+#[derive(Debug, Default, Clone)]
+struct ScannerIndex {
+    records: Vec<ScannerRecord>,
+    by_id: HashMap<String, usize>,
+    by_digest: HashMap<String, usize>,
+}
+
+impl ScannerIndex {
+    fn new(records: Vec<ScannerRecord>) -> Self {
+        let mut index = ScannerIndex::default();
+        for record in records {
+            index.put(record);
+        }
+
+        index
+    }
+
+    fn get(&self, scanner_id: &str) -> Option<&ScannerRecord> {
+        self.by_id
+            .get(scanner_id)
+            .map(|position| &self.records[*position])
+    }
+
+    fn find_by_key_digest(&self, key_digest: &str) -> Option<&ScannerRecord> {
+        self.by_digest
+            .get(key_digest)
+            .map(|position| &self.records[*position])
+    }
+
+    /// Replaces the record with the same ID, or adds it last
+    fn put(&mut self, record: ScannerRecord) {
+        let position = match self.by_id.get(&record.scanner.id) {
+            Some(position) => *position,
+            None => self.records.len(),
+        };
+
+        self.by_digest.insert(record.key_digest.clone(), position);
+        if position == self.records.len() {
+            self.by_id.insert(record.scanner.id.clone(), position);
+            self.records.push(record);
+            return;
+        }
+
+        let replaced = std::mem::replace(&mut self.records[position], record);
+        if replaced.key_digest != self.records[position].key_digest {
+            self.by_digest.remove(&replaced.key_digest);
+        }
+    }
+}
+
+/// Keeps scanners until the service stops
+#[derive(Debug, Default)]
+pub struct MemoryScannerStore {
+    index: Mutex<ScannerIndex>,
+}
+
+impl ScannerStore for MemoryScannerStore {
+    fn get(&self, scanner_id: &str) -> io::Result<Option<ScannerRecord>> {
+        let index = self.index.lock().map_err(|_| poisoned())?;
+        Ok(index.get(scanner_id).cloned())
+    }
+
+    fn find_by_key_digest(&self, key_digest: &str) -> io::Result<Option<ScannerRecord>> {
+        let index = self.index.lock().map_err(|_| poisoned())?;
+        Ok(index.find_by_key_digest(key_digest).cloned())
+    }
+
+    fn list(&self) -> io::Result<Vec<ScannerRecord>> {
+        Ok(self.index.lock().map_err(|_| poisoned())?.records.clone())
+    }
+
+    fn put(&self, record: ScannerRecord) -> io::Result<()> {
+        self.index.lock().map_err(|_| poisoned())?.put(record);
+        Ok(())
+    }
+}
+
+/// Keeps scanners in a JSON file, replaced as a whole on each change
+///
+/// The file is read when the store is opened and then kept in memory with
+///  its index, so looking up scanners doesn't touch the file. It must not be
+///  changed by anything else while the service runs.
+#[derive(Debug)]
+pub struct FileScannerStore {
+    path: PathBuf,
+    index: Mutex<ScannerIndex>,
+
+    /// Changes are written one at a time, in the order they are made
+    writes: Mutex<()>,
+}
+
+impl FileScannerStore {
+    /// Stores scanners in the given file, created when the first scanner is registered
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let records = match std::fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+
+        Ok(FileScannerStore {
+            path,
+            index: Mutex::new(ScannerIndex::new(records)),
+            writes: Mutex::new(()),
+        })
+    }
+
+    /// Writes a temporary file first, so the file is never left half written
+    fn write(&self, records: &[ScannerRecord]) -> io::Result<()> {
+        let temporary = self.path.with_extension("tmp");
+        let mut file = std::fs::File::create(&temporary)?;
+        file.write_all(&serde_json::to_vec_pretty(records)?)?;
+        file.sync_all()?;
+        std::fs::rename(&temporary, &self.path)
+    }
+}
+
+impl ScannerStore for FileScannerStore {
+    fn get(&self, scanner_id: &str) -> io::Result<Option<ScannerRecord>> {
+        let index = self.index.lock().map_err(|_| poisoned())?;
+        Ok(index.get(scanner_id).cloned())
+    }
+
+    fn find_by_key_digest(&self, key_digest: &str) -> io::Result<Option<ScannerRecord>> {
+        let index = self.index.lock().map_err(|_| poisoned())?;
+        Ok(index.find_by_key_digest(key_digest).cloned())
+    }
+
+    fn list(&self) -> io::Result<Vec<ScannerRecord>> {
+        Ok(self.index.lock().map_err(|_| poisoned())?.records.clone())
+    }
+
+    /// Writes the change to the file before lookups see it, lookups don't
+    ///  wait for the file to be written
+    fn put(&self, record: ScannerRecord) -> io::Result<()> {
+        let _write = self.writes.lock().map_err(|_| poisoned())?;
+        let mut changed = self.index.lock().map_err(|_| poisoned())?.clone();
+        changed.put(record);
+        self.write(&changed.records)?;
+
+        *self.index.lock().map_err(|_| poisoned())? = changed;
+        Ok(())
+    }
+}
+
+fn poisoned() -> io::Error {
+    io::Error::other("scanner store lock poisoned")
+}
+
+/// Why a scanner may not record scans
+#[derive(Debug)]
+pub enum ScannerDenied {
+    /// The scanner isn't registered
+    Unknown,
+
+    /// The scanner was deactivated
+    Inactive,
+
+    /// The API key is missing or isn't the key of the scanner
+    InvalidKey,
+
+    /// The registry couldn't be read
+    Unavailable(io::Error),
+}
+
+impl ScannerDenied {
+    /// Status of the response to the scan
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ScannerDenied::Unknown | ScannerDenied::Inactive => StatusCode::FORBIDDEN,
+            ScannerDenied::InvalidKey => StatusCode::UNAUTHORIZED,
+            ScannerDenied::Unavailable(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl fmt::Display for ScannerDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScannerDenied::Unknown => write!(f, "scanner is not registered."),
+            ScannerDenied::Inactive => write!(f, "scanner is deactivated."),
+            ScannerDenied::InvalidKey => write!(f, "scanner API key missing or invalid."),
+            ScannerDenied::Unavailable(e) => write!(f, "scanner registry unavailable: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ScannerDenied {}
+
+/// Registers scanners and checks the scanners of scans
+///
+/// Scanners are looked up in memory, changes are written to the store off
+///  the runtime.
+#[derive(Debug, Clone)]
+pub struct ScannerRegistry {
+    store: Arc<dyn ScannerStore>,
+
+    /// Changes read a scanner and put it back, one at a time
+    updates: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl ScannerRegistry {
+    /// Keeps scanners in the configured file, or in memory
+    ///
+    /// Fails if the file can't be read.
+    pub async fn new(config: &Config) -> io::Result<Self> {
+        let store: Arc<dyn ScannerStore> = match config.rest_scanner_registry_file.as_str() {
+            "" => {
+                rest_warn!("(ScannerRegistry::new) scanners are kept in memory, registrations are lost on restart.");
+                Arc::new(MemoryScannerStore::default())
+            }
+            path => {
+                let path = PathBuf::from(path);
+                let store = tokio::task::spawn_blocking(move || FileScannerStore::open(path))
+                    .await
+                    .map_err(io::Error::other)?
+                    .map_err(|e| {
+                        rest_error!(
+                            "(ScannerRegistry::new) could not read the scanner registry file: {}",
+                            e
+                        );
+                        e
+                    })?;
+                Arc::new(store)
+            }
+        };
+
+        Ok(Self::with_store(store))
+    }
+
+    /// Keeps scanners in the given store
+    pub fn with_store(store: Arc<dyn ScannerStore>) -> Self {
+        ScannerRegistry {
+            store,
+            updates: Arc::default(),
+        }
+    }
+
+    /// Registers a new active scanner and issues its API key
+    pub async fn register(
+        &self,
+        label: String,
+        vertiport_id: Option<String>,
+    ) -> io::Result<ScannerCredentials> {
+        let now = Utc::now();
+        let api_key = random_token()?;
+        let scanner = Scanner {
+            id: uuid::Uuid::new_v4().to_string(),
+            label,
+            vertiport_id,
+            active: true,
+            registered_at: now,
+            key_issued_at: now,
+        };
+
+        self.put(ScannerRecord {
+            scanner: scanner.clone(),
+            key_digest: key_digest(&api_key),
+        })
+        .await?;
+
+        Ok(ScannerCredentials { scanner, api_key })
+    }
+
+    /// The scanner with the given ID, if registered
+    pub fn get(&self, scanner_id: &str) -> io::Result<Option<Scanner>> {
+        Ok(self.store.get(scanner_id)?.map(|record| record.scanner))
+    }
+
+    /// The scanners matching the parameters, in the order they were registered
+    pub fn list(&self, params: &ScannersParams) -> io::Result<Vec<Scanner>> {
+        Ok(self
+            .store
+            .list()?
+            .into_iter()
+            .map(|record| record.scanner)
+            .filter(|scanner| {
+                let vertiport_matches = match &params.vertiport_id {
+                    Some(vertiport_id) => scanner.vertiport_id.as_ref() == Some(vertiport_id),
+                    None => true,
+                };
+                let active_matches = match params.active {
+                    Some(active) => scanner.active == active,
+                    None => true,
+                };
+
+                vertiport_matches && active_matches
+            })
+            .collect())
+    }
+
+    /// Issues a new API key, the previous key is no longer accepted
+    pub async fn rotate_key(&self, scanner_id: &str) -> io::Result<Option<ScannerCredentials>> {
+        let api_key = random_token()?;
+        let scanner = self
+            .update(scanner_id, |record| {
+                record.key_digest = key_digest(&api_key);
+                record.scanner.key_issued_at = Utc::now();
+            })
+            .await?;
+
+        Ok(scanner.map(|scanner| ScannerCredentials { scanner, api_key }))
+    }
+
+    /// Assigns the scanner to a vertiport, or unassigns it
+    pub async fn assign(
+        &self,
+        scanner_id: &str,
+        vertiport_id: Option<String>,
+    ) -> io::Result<Option<Scanner>> {
+        self.update(scanner_id, |record| {
+            record.scanner.vertiport_id = vertiport_id
+        })
+        .await
+    }
+
+    /// Deactivates the scanner, its scans are no longer accepted
+    pub async fn deactivate(&self, scanner_id: &str) -> io::Result<Option<Scanner>> {
+        self.update(scanner_id, |record| record.scanner.active = false)
+            .await
+    }
+
+    /// The active scanner the API key was issued to, if any
+    pub fn identify(&self, api_key: &str) -> io::Result<Option<Scanner>> {
+        Ok(self
+            .store
+            .find_by_key_digest(&key_digest(api_key))?
+            .filter(|record| record.scanner.active)
+            .map(|record| record.scanner))
+    }
+
+    /// The scanner of a scan, if it is active and the API key is its key
+    pub fn authorize(
+        &self,
+        scanner_id: &str,
+        api_key: Option<&str>,
+    ) -> Result<Scanner, ScannerDenied> {
+        let record = self
+            .store
+            .get(scanner_id)
+            .map_err(ScannerDenied::Unavailable)?
+            .ok_or(ScannerDenied::Unknown)?;
+
+        if !record.scanner.active {
+            return Err(ScannerDenied::Inactive);
+        }
+
+        let key_matches =
+            api_key.is_some_and(|api_key| digests_equal(&key_digest(api_key), &record.key_digest));
+        if !key_matches {
+            return Err(ScannerDenied::InvalidKey);
+        }
+
+        Ok(record.scanner)
+    }
+
+    /// Changes a scanner, None if it isn't registered
+    async fn update(
+        &self,
+        scanner_id: &str,
+        change: impl FnOnce(&mut ScannerRecord),
+    ) -> io::Result<Option<Scanner>> {
+        let _lock = self.updates.lock().await;
+        let Some(mut record) = self.store.get(scanner_id)? else {
+            return Ok(None);
+        };
+
+        change(&mut record);
+        let scanner = record.scanner.clone();
+        self.put(record).await?;
+        Ok(Some(scanner))
+    }
+
+    /// Puts a scanner in the store, off the runtime as it may write a file
+    async fn put(&self, record: ScannerRecord) -> io::Result<()> {
+        let store = Arc::clone(&self.store);
+        tokio::task::spawn_blocking(move || store.put(record))
+            .await
+            .map_err(io::Error::other)?
+    }
+}
+
+/// Hexadecimal SHA-256 digest of an API key
+fn key_digest(api_key: &str) -> String {
+    hex(digest::digest(&digest::SHA256, api_key.as_bytes()).as_ref())
+}
+
+/// Compares key digests in constant time, so timing doesn't reveal how much matched
+fn digests_equal(a: &str, b: &str) -> bool {
+    // Deprecated by ring without a replacement, it still compares in constant time
+    #[allow(deprecated)]
+    let equal = ring::constant_time::verify_slices_are_equal(a.as_bytes(), b.as_bytes()).is_ok();
+    equal
+}
+
+/// The vertiport a scanner is assigned to, scans must be made there
+#[derive(Debug, Clone)]
+pub struct AssignedVertiport {
+    /// The String ID of the vertiport
+    pub id: String,
+
+    /// Outline of the vertiport
+    pub outline: Polygon,
+}
+
+impl AssignedVertiport {
+    /// Requests the outline of the vertiport of the scanner, None if it isn't assigned
+    pub async fn of(
+        scanner: &Scanner,
+        grpc_clients: &GrpcClients,
+    ) -> Result<Option<Self>, StatusCode> {
+        let Some(vertiport_id) = &scanner.vertiport_id else {
+            return Ok(None);
+        };
+
+        Ok(Some(AssignedVertiport {
+            id: vertiport_id.clone(),
+            outline: get_vertiport_polygon(vertiport_id, grpc_clients).await?,
+        }))
+    }
+
+    /// Checks a scan is within the margin of the vertiport
+    pub fn check(&self, latitude: f64, longitude: f64, margin_meters: f64) -> Result<(), String> {
+        let location = Coord {
+            x: longitude,
+            y: latitude,
+        };
+        let distance = distance_to_polygon(location, &self.outline);
+        if distance > margin_meters {
+            return Err(format!(
+                "scan is {distance:.0}m away from vertiport {} of the scanner.",
+                self.id
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Rejects requests without the admin API key, all of them if none is configured
+pub async fn require_admin_key<B>(
+    State(live_config): State<watch::Receiver<Config>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let admin_key = live_config.borrow().rest_scanner_admin_key.clone();
+    if admin_key.is_empty() {
+        rest_warn!("(require_admin_key) request rejected, no admin API key is configured.");
+        return (
+            StatusCode::FORBIDDEN,
+            "Admin endpoints are disabled, no admin API key is configured.".to_string(),
+        )
+            .into_response();
+    }
+
+    let key_matches = request
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|api_key| digests_equal(&key_digest(api_key), &key_digest(&admin_key)));
+    if !key_matches {
+        rest_warn!("(require_admin_key) request without the admin API key rejected.");
+        return (
+            StatusCode::UNAUTHORIZED,
+            "The admin API key is required.".to_string(),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+fn registry_error(function: &str, e: io::Error) -> (StatusCode, String) {
+    let error_msg = "scanner registry unavailable.".to_string();
+    rest_error!("({}) {} {}", function, &error_msg, e);
+    (StatusCode::INTERNAL_SERVER_ERROR, error_msg)
+}
+
+fn scanner_not_found(function: &str, scanner_id: &str) -> (StatusCode, String) {
+    let error_msg = format!("scanner {scanner_id} is not registered.");
+    rest_info!("({}) {}", function, &error_msg);
+    (StatusCode::NOT_FOUND, error_msg)
+}
+
+/// Checks a vertiport a scanner is assigned to exists
+async fn check_vertiport(
+    function: &str,
+    vertiport_id: &Option<String>,
+    grpc_clients: &GrpcClients,
+) -> Result<(), (StatusCode, String)> {
+    let Some(vertiport_id) = vertiport_id else {
+        return Ok(());
+    };
+
+    if !is_uuid(vertiport_id) {
+        let error_msg = "vertiport ID not in UUID format.".to_string();
+        rest_error!("({}) {}", function, &error_msg);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
+    }
+
+    match get_vertiport_details(vertiport_id, grpc_clients).await {
+        Ok(_) => Ok(()),
+        Err(StatusCode::NOT_FOUND) => {
+            let error_msg = format!("vertiport {vertiport_id} does not exist.");
+            rest_error!("({}) {}", function, &error_msg);
+            Err((StatusCode::BAD_REQUEST, error_msg))
+        }
+        Err(status) => {
+            let error_msg = "could not get the vertiport from svc-storage.".to_string();
+            rest_error!("({}) {}", function, &error_msg);
+            Err((status, error_msg))
+        }
+    }
+}
+
+/// Register a scanner device
+///
+/// The API key of the scanner is only returned now and when it is rotated.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/cargo/scanners",
+    tag = "svc-cargo",
+    request_body = ScannerRegistration,
+    responses(
+        (status = 201, description = "Scanner registered", body = ScannerCredentials),
+        (status = 400, description = "Request body is invalid", body = String),
+        (status = 401, description = "Admin API key missing or invalid", body = String),
+        (status = 403, description = "No admin API key is configured", body = String),
+        (status = 500, description = "Scanner registry unavailable", body = String)
+    )
+))]
+pub async fn register_scanner(
+    Extension(grpc_clients): Extension<GrpcClients>,
+    Extension(scanner_registry): Extension<ScannerRegistry>,
+    Json(payload): Json<ScannerRegistration>,
+) -> Result<(StatusCode, Json<ScannerCredentials>), (StatusCode, String)> {
+    rest_debug!("(register_scanner) entry.");
+
+    let label = payload.label.trim();
+    if label.is_empty() || label.chars().count() > MAX_LABEL_LENGTH {
+        let error_msg = format!("label must have 1 to {MAX_LABEL_LENGTH} characters.");
+        rest_error!("(register_scanner) {}", &error_msg);
+        return Err((StatusCode::BAD_REQUEST, error_msg));
+    }
+
+    check_vertiport("register_scanner", &payload.vertiport_id, &grpc_clients).await?;
+
+    let credentials = scanner_registry
+        .register(label.to_string(), payload.vertiport_id)
+        .await
+        .map_err(|e| registry_error("register_scanner", e))?;
+
+    rest_info!(
+        "(register_scanner) scanner {} registered.",
+        credentials.scanner.id
+    );
+    Ok((StatusCode::CREATED, Json(credentials)))
+}
+
+/// List the registered scanners
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/cargo/scanners",
+    tag = "svc-cargo",
+    params(ScannersParams),
+    responses(
+        (status = 200, description = "Scanners retrieved successfully", body = ScannersResponse),
+        (status = 401, description = "Admin API key missing or invalid", body = String),
+        (status = 403, description = "No admin API key is configured", body = String),
+        (status = 500, description = "Scanner registry unavailable", body = String)
+    )
+))]
+pub async fn list_scanners(
+    Extension(scanner_registry): Extension<ScannerRegistry>,
+    Query(params): Query<ScannersParams>,
+) -> Result<Json<ScannersResponse>, (StatusCode, String)> {
+    rest_debug!("(list_scanners) entry.");
+
+    let scanners = scanner_registry
+        .list(&params)
+        .map_err(|e| registry_error("list_scanners", e))?;
+
+    Ok(Json(ScannersResponse { scanners }))
+}
+
+/// Get a registered scanner
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/cargo/scanners/{id}",
+    tag = "svc-cargo",
+    params(("id" = String, Path, description = "The unique ID (UUID) of the scanner")),
+    responses(
+        (status = 200, description = "Scanner retrieved successfully", body = Scanner),
+        (status = 401, description = "Admin API key missing or invalid", body = String),
+        (status = 403, description = "No admin API key is configured", body = String),
+        (status = 404, description = "Scanner not registered", body = String),
+        (status = 500, description = "Scanner registry unavailable", body = String)
+    )
+))]
+pub async fn get_scanner(
+    Extension(scanner_registry): Extension<ScannerRegistry>,
+    Path(scanner_id): Path<String>,
+) -> Result<Json<Scanner>, (StatusCode, String)> {
+    rest_debug!("(get_scanner) entry.");
+
+    scanner_registry
+        .get(&scanner_id)
+        .map_err(|e| registry_error("get_scanner", e))?
+        .map(Json)
+        .ok_or_else(|| scanner_not_found("get_scanner", &scanner_id))
+}
+
+/// Issue a new API key for a scanner
+///
+/// The previous key is no longer accepted.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/cargo/scanners/{id}/key",
+    tag = "svc-cargo",
+    params(("id" = String, Path, description = "The unique ID (UUID) of the scanner")),
+    responses(
+        (status = 200, description = "API key rotated", body = ScannerCredentials),
+        (status = 401, description = "Admin API key missing or invalid", body = String),
+        (status = 403, description = "No admin API key is configured", body = String),
+        (status = 404, description = "Scanner not registered", body = String),
+        (status = 500, description = "Scanner registry unavailable", body = String)
+    )
+))]
+pub async fn rotate_scanner_key(
+    Extension(scanner_registry): Extension<ScannerRegistry>,
+    Path(scanner_id): Path<String>,
+) -> Result<Json<ScannerCredentials>, (StatusCode, String)> {
+    rest_debug!("(rotate_scanner_key) entry.");
+
+    let credentials = scanner_registry
+        .rotate_key(&scanner_id)
+        .await
+        .map_err(|e| registry_error("rotate_scanner_key", e))?
+        .ok_or_else(|| scanner_not_found("rotate_scanner_key", &scanner_id))?;
+
+    rest_info!(
+        "(rotate_scanner_key) key of scanner {} rotated.",
+        scanner_id
+    );
+    Ok(Json(credentials))
+}
+
+/// Assign a scanner to a vertiport
+///
+/// Scans of assigned scanners must be made at their vertiport.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    put,
+    path = "/cargo/scanners/{id}/vertiport",
+    tag = "svc-cargo",
+    params(("id" = String, Path, description = "The unique ID (UUID) of the scanner")),
+    request_body = ScannerAssignment,
+    responses(
+        (status = 200, description = "Scanner assigned", body = Scanner),
+        (status = 400, description = "Vertiport invalid", body = String),
+        (status = 401, description = "Admin API key missing or invalid", body = String),
+        (status = 403, description = "No admin API key is configured", body = String),
+        (status = 404, description = "Scanner not registered", body = String),
+        (status = 500, description = "Scanner registry unavailable", body = String)
+    )
+))]
+pub async fn assign_scanner(
+    Extension(grpc_clients): Extension<GrpcClients>,
+    Extension(scanner_registry): Extension<ScannerRegistry>,
+    Path(scanner_id): Path<String>,
+    Json(payload): Json<ScannerAssignment>,
+) -> Result<Json<Scanner>, (StatusCode, String)> {
+    rest_debug!("(assign_scanner) entry.");
+
+    check_vertiport("assign_scanner", &payload.vertiport_id, &grpc_clients).await?;
+
+    let scanner = scanner_registry
+        .assign(&scanner_id, payload.vertiport_id)
+        .await
+        .map_err(|e| registry_error("assign_scanner", e))?
+        .ok_or_else(|| scanner_not_found("assign_scanner", &scanner_id))?;
+
+    rest_info!(
+        "(assign_scanner) scanner {} assigned to {:?}.",
+        scanner_id,
+        scanner.vertiport_id
+    );
+    Ok(Json(scanner))
+}
+
+/// Deactivate a scanner
+///
+/// The scanner stays registered, its scans are no longer accepted.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    delete,
+    path = "/cargo/scanners/{id}",
+    tag = "svc-cargo",
+    params(("id" = String, Path, description = "The unique ID (UUID) of the scanner")),
+    responses(
+        (status = 200, description = "Scanner deactivated", body = Scanner),
+        (status = 401, description = "Admin API key missing or invalid", body = String),
+        (status = 403, description = "No admin API key is configured", body = String),
+        (status = 404, description = "Scanner not registered", body = String),
+        (status = 500, description = "Scanner registry unavailable", body = String)
+    )
+))]
+pub async fn deactivate_scanner(
+    Extension(scanner_registry): Extension<ScannerRegistry>,
+    Path(scanner_id): Path<String>,
+) -> Result<Json<Scanner>, (StatusCode, String)> {
+    rest_debug!("(deactivate_scanner) entry.");
+
+    let scanner = scanner_registry
+        .deactivate(&scanner_id)
+        .await
+        .map_err(|e| registry_error("deactivate_scanner", e))?
+        .ok_or_else(|| scanner_not_found("deactivate_scanner", &scanner_id))?;
+
+    rest_info!("(deactivate_scanner) scanner {} deactivated.", scanner_id);
+    Ok(Json(scanner))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn check_registry(registry: &ScannerRegistry) {
+        let vertiport_id = "cabcdd14-03ab-4ac0-b58c-dd4175bc587e".to_string();
+        let first = registry
+            .register("Dock 1".to_string(), Some(vertiport_id.clone()))
+            .await
+            .unwrap();
+        let second = registry
+            .register("Handheld".to_string(), None)
+            .await
+            .unwrap();
+        assert!(first.scanner.active);
+        assert_eq!(first.api_key.len(), 64);
+        assert_ne!(first.api_key, second.api_key);
+
+        // Scans need the key of an active scanner
+        let id = &first.scanner.id;
+        assert_eq!(
+            registry.authorize(id, Some(&first.api_key)).unwrap(),
+            first.scanner
+        );
+        assert!(matches!(
+            registry.authorize(id, Some(&second.api_key)),
+            Err(ScannerDenied::InvalidKey)
+        ));
+        assert!(matches!(
+            registry.authorize(id, None),
+            Err(ScannerDenied::InvalidKey)
+        ));
+        assert!(matches!(
+            registry.authorize(&uuid::Uuid::new_v4().to_string(), Some(&first.api_key)),
+            Err(ScannerDenied::Unknown)
+        ));
+
+        // Only the latest key is accepted
+        let rotated = registry.rotate_key(id).await.unwrap().unwrap();
+        assert!(registry.authorize(id, Some(&first.api_key)).is_err());
+        assert!(registry.authorize(id, Some(&rotated.api_key)).is_ok());
+        assert_eq!(registry.identify(&first.api_key).unwrap(), None);
+        assert_eq!(
+            registry.identify(&rotated.api_key).unwrap(),
+            Some(rotated.scanner.clone())
+        );
+
+        let params = ScannersParams {
+            vertiport_id: Some(vertiport_id),
+            ..Default::default()
+        };
+        assert_eq!(registry.list(&params).unwrap(), vec![rotated.scanner]);
+        let scanner = registry.assign(id, None).await.unwrap().unwrap();
+        assert_eq!(scanner.vertiport_id, None);
+        assert!(registry.list(&params).unwrap().is_empty());
+
+        let scanner = registry.deactivate(id).await.unwrap().unwrap();
+        assert!(!scanner.active);
+        assert!(matches!(
+            registry.authorize(id, Some(&rotated.api_key)),
+            Err(ScannerDenied::Inactive)
+        ));
+        assert_eq!(registry.identify(&rotated.api_key).unwrap(), None);
+        let params = ScannersParams {
+            active: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(registry.list(&params).unwrap(), vec![second.scanner]);
+        assert_eq!(registry.list(&ScannersParams::default()).unwrap().len(), 2);
+
+        let unknown = uuid::Uuid::new_v4().to_string();
+        assert_eq!(registry.deactivate(&unknown).await.unwrap(), None);
+        assert!(registry.rotate_key(&unknown).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn ut_memory_scanner_registry() {
+        check_registry(&ScannerRegistry::with_store(Arc::new(
+            MemoryScannerStore::default(),
+        )))
+        .await;
+    }
+
+    #[tokio::test]
+    async fn ut_file_scanner_registry() {
+        let path =
+            std::env::temp_dir().join(format!("svc-cargo-scanners-{}.json", uuid::Uuid::new_v4()));
+        let config = Config {
+            rest_scanner_registry_file: path.to_string_lossy().to_string(),
+            ..Config::default()
+        };
+        let registry = ScannerRegistry::new(&config).await.unwrap();
+        check_registry(&registry).await;
+
+        // Scanners are read back from the file, without their keys
+        let store = FileScannerStore::open(&path).unwrap();
+        let records = store.list().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].key_digest.len(), 64);
+        assert_eq!(
+            store.find_by_key_digest(&records[1].key_digest).unwrap(),
+            Some(records[1].clone())
+        );
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("api_key"));
+
+        // A registry that can't be read isn't used
+        std::fs::write(&path, "not json").unwrap();
+        assert!(ScannerRegistry::new(&config).await.is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        // Changes that couldn't be written aren't looked up
+        let path = std::env::temp_dir()
+            .join(uuid::Uuid::new_v4().to_string())
+            .join("scanners.json");
+        let registry =
+            ScannerRegistry::with_store(Arc::new(FileScannerStore::open(&path).unwrap()));
+        assert!(registry.register("Dock 1".to_string(), None).await.is_err());
+        assert!(registry
+            .list(&ScannersParams::default())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn ut_assigned_vertiport() {
+        let d = 0.001;
+        let vertiport = AssignedVertiport {
+            id: "vertiport".to_string(),
+            outline: Polygon::new(
+                geo::LineString::from(vec![
+                    (4.90 - d, 52.37 - d),
+                    (4.90 + d, 52.37 - d),
+                    (4.90 + d, 52.37 + d),
+                    (4.90 - d, 52.37 + d),
+                ]),
+                vec![],
+            ),
+        };
+
+        assert!(vertiport.check(52.37, 4.90, 100.0).is_ok());
+        assert!(vertiport.check(52.3715, 4.90, 100.0).is_ok());
+        let error = vertiport.check(52.09, 5.12, 100.0).unwrap_err();
+        assert!(error.contains("vertiport vertiport"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn ut_scanner_endpoints() {
+        use axum::{body::Body, middleware, routing, Router};
+        use tower::ServiceExt;
+
+        let config = Config {
+            rest_scanner_admin_key: "admin-key".to_string(),
+            ..Config::default()
+        };
+        let (_, live_config) = watch::channel(config.clone());
+        let app = Router::new()
+            .route(
+                "/cargo/scanners",
+                routing::get(list_scanners).post(register_scanner),
+            )
+            .route(
+                "/cargo/scanners/:id",
+                routing::get(get_scanner).delete(deactivate_scanner),
+            )
+            .route("/cargo/scanners/:id/key", routing::post(rotate_scanner_key))
+            .route_layer(middleware::from_fn_with_state(
+                live_config,
+                require_admin_key,
+            ))
+            .layer(Extension(ScannerRegistry::with_store(Arc::new(
+                MemoryScannerStore::default(),
+            ))))
+            .layer(Extension(GrpcClients::default(config)));
+        let request = |method: &str, uri: &str, api_key: &str, body: &str| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header(API_KEY_HEADER, api_key)
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+        async fn body<T: serde::de::DeserializeOwned>(response: Response) -> T {
+            let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            serde_json::from_slice(&bytes).unwrap()
+        }
+
+        // The admin API key is required
+        let registration = r#"{"label": "Dock 1", "vertiport_id": null}"#;
+        let response = app
+            .clone()
+            .oneshot(request("POST", "/cargo/scanners", "other", registration))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app
+            .clone()
+            .oneshot(request(
+                "POST",
+                "/cargo/scanners",
+                "admin-key",
+                registration,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let credentials: ScannerCredentials = body(response).await;
+
+        let response = app
+            .clone()
+            .oneshot(request(
+                "POST",
+                "/cargo/scanners",
+                "admin-key",
+                r#"{"label": " ", "vertiport_id": null}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let uri = format!("/cargo/scanners/{}", credentials.scanner.id);
+        let response = app
+            .clone()
+            .oneshot(request("DELETE", &uri, "admin-key", ""))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let scanner: Scanner = body(response).await;
+        assert!(!scanner.active);
+
+        let response = app
+            .clone()
+            .oneshot(request("GET", "/cargo/scanners", "admin-key", ""))
+            .await
+            .unwrap();
+        let scanners: ScannersResponse = body(response).await;
+        assert_eq!(scanners.scanners, vec![scanner]);
+
+        let uri = format!("/cargo/scanners/{}/key", uuid::Uuid::new_v4());
+        let response = app
+            .oneshot(request("POST", &uri, "admin-key", ""))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn ut_require_admin_key_unconfigured() {
+        use axum::{body::Body, middleware, routing, Router};
+        use tower::ServiceExt;
+
+        // Without an admin API key the endpoints are disabled, even for an empty key
+        let (_, live_config) = watch::channel(Config {
+            rest_scanner_admin_key: String::new(),
+            ..Config::default()
+        });
+        let app = Router::new()
+            .route("/cargo/scanners", routing::get(|| async { "scanners" }))
+            .route_layer(middleware::from_fn_with_state(
+                live_config,
+                require_admin_key,
+            ));
+        for api_key in [None, Some("")] {
+            let mut request = Request::builder().uri("/cargo/scanners");
+            if let Some(api_key) = api_key {
+                request = request.header(API_KEY_HEADER, api_key);
+            }
+            let response = app
+                .clone()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{:?}", api_key);
+        }
+    }
+}
@@ -0,0 +1,642 @@
+//! Shipments confirmed through svc-cargo
+//!
+//! svc-storage keeps parcels without the itinerary they were confirmed
+//!  with, so svc-cargo keeps a record of each confirmation for what is only
+//!  known at that time, such as the itinerary printed on parcel labels, the
+//!  recipient to notify and the pickup code of the parcel.
+
+use super::notification::ParcelEvent;
+use super::pickup::PickupState;
+use super::rest_types::RecipientContact;
+use crate::Config;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Changes appended to the shipment file between removals of expired shipments
+const CHANGES_BETWEEN_COMPACTIONS: usize = 1_000;
+
+/// A parcel confirmed with an itinerary
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shipment {
+    /// The String ID of the parcel
+    pub parcel_id: String,
+
+    /// The String ID of the itinerary carrying the parcel
+    pub itinerary_id: String,
+
+    /// The String ID of the shipper
+    pub user_id: String,
+
+    /// Weight of the parcel
+    pub weight_grams: u32,
+
+    /// When the itinerary was confirmed
+    pub confirmed_at: DateTime<Utc>,
+
+    /// Who the parcel is for, if given
+    pub recipient: Option<RecipientContact>,
+
+    /// Token of the public tracking link
+    pub tracking_token: Option<String>,
+
+    /// Events the recipient was notified of
+    #[serde(default)]
+    pub notified: Vec<ParcelEvent>,
+
+    /// Pickup code and attempts to pick up the parcel with it
+    #[serde(default)]
+    pub pickup: PickupState,
+}
+
+/// Keeps confirmed shipments
+pub trait ShipmentStore: Send + Sync + fmt::Debug {
+    /// The shipment of the given parcel, if confirmed through svc-cargo
+    fn get(&self, parcel_id: &str) -> io::Result<Option<Shipment>>;
+
+    /// The shipment with the given public tracking token
+    fn find_by_token(&self, token: &str) -> io::Result<Option<Shipment>>;
+
+    /// Adds a shipment, or replaces the shipment of the same parcel
+    fn put(&self, shipment: Shipment) -> io::Result<()>;
+}
+
+/// Shipments in the order they were confirmed, indexed by parcel and tracking token
+#[derive(Debug, Default, Clone)]
+struct ShipmentIndex {
+    shipments: Vec<Shipment>,
+    by_parcel: HashMap<String, usize>,
+    by_token: HashMap<String, usize>,
+}
+
+impl ShipmentIndex {
+    fn new(shipments: Vec<Shipment>) -> Self {
+        let mut index = ShipmentIndex::default();
+        for shipment in shipments {
+            index.put(shipment);
+        }
+
+        index
+    }
+
+    fn get(&self, parcel_id: &str) -> Option<&Shipment> {
+        self.by_parcel
+            .get(parcel_id)
+            .map(|position| &self.shipments[*position])
+    }
+
+    fn find_by_token(&self, token: &str) -> Option<&Shipment> {
+        self.by_token
+            .get(token)
+            .map(|position| &self.shipments[*position])
+    }
+
+    /// Replaces the shipment of the same parcel, or adds it last
+    fn put(&mut self, shipment: Shipment) {
+        let position = match self.by_parcel.get(&shipment.parcel_id) {
+            Some(position) => *position,
+            None => self.shipments.len(),
+        };
+
+        if let Some(token) = &shipment.tracking_token {
+            self.by_token.insert(token.clone(), position);
+        }
+
+        if position == self.shipments.len() {
+            self.by_parcel.insert(shipment.parcel_id.clone(), position);
+            self.shipments.push(shipment);
+            return;
+        }
+
+        let replaced = std::mem::replace(&mut self.shipments[position], shipment);
+        let new_token = self.shipments[position].tracking_token.as_ref();
+        if let Some(token) = replaced.tracking_token {
+            if new_token != Some(&token) {
+                self.by_token.remove(&token);
+            }
+        }
+    }
+}
+
+fn poisoned() -> io::Error {
+    io::Error::other("shipment store lock poisoned")
+}
+
+/// Keeps shipments until the service stops
+#[derive(Debug, Default)]
+pub struct MemoryShipmentStore {
+    index: Mutex<ShipmentIndex>,
+}
+
+impl ShipmentStore for MemoryShipmentStore {
+    fn get(&self, parcel_id: &str) -> io::Result<Option<Shipment>> {
+        let index = self.index.lock().map_err(|_| poisoned())?;
+        Ok(index.get(parcel_id).cloned())
+    }
+
+    fn find_by_token(&self, token: &str) -> io::Result<Option<Shipment>> {
+        let index = self.index.lock().map_err(|_| poisoned())?;
+        Ok(index.find_by_token(token).cloned())
+    }
+
+    fn put(&self, shipment: Shipment) -> io::Result<()> {
+        let mut index = self.index.lock().map_err(|_| poisoned())?;
+        index.put(shipment);
+        Ok(())
+    }
+}
+
+/// Keeps shipments in a file, one JSON object per line
+///
+/// Each change appends the changed shipment, the last line of a parcel holds
+///  its shipment. The file is read when the store is opened and then kept in
+///  memory with its index, it must not be changed by anything else while the
+///  service runs. Shipments confirmed before the retention period are no
+///  longer found; they and the lines of changed shipments are removed from
+///  the file when the store is opened and every
+///  [`CHANGES_BETWEEN_COMPACTIONS`] changes after.
+#[derive(Debug)]
+pub struct FileShipmentStore {
+    path: PathBuf,
+    retention: chrono::Duration,
+    index: Mutex<ShipmentIndex>,
+
+    /// Changes appended until the file is compacted again, compacted before
+    ///  the next change if 0
+    writes: Mutex<usize>,
+}
+
+impl FileShipmentStore {
+    /// Stores shipments in the given file, created when the first shipment is confirmed
+    pub fn open(path: impl Into<PathBuf>, retention: chrono::Duration) -> io::Result<Self> {
+        let mut store = FileShipmentStore {
+            path: path.into(),
+            retention,
+            index: Mutex::default(),
+            writes: Mutex::new(CHANGES_BETWEEN_COMPACTIONS),
+        };
+
+        let (index, rewrite) = store.read()?;
+        let expiry = store.expiry();
+        let index = match rewrite || index.shipments.iter().any(|s| s.confirmed_at < expiry) {
+            true => store.compact(&index)?,
+            false => index,
+        };
+        store.index = Mutex::new(index);
+        Ok(store)
+    }
+
+    /// When shipments confirmed before have expired
+    fn expiry(&self) -> DateTime<Utc> {
+        Utc::now() - self.retention
+    }
+
+    /// Shipments in the file, and whether the file holds more than their lines
+    fn read(&self) -> io::Result<(ShipmentIndex, bool)> {
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok((ShipmentIndex::default(), false))
+            }
+            Err(e) => return Err(e),
+        };
+
+        // Files written before shipments were appended hold a single array
+        if contents.trim_start().starts_with('[') {
+            let shipments: Vec<Shipment> = serde_json::from_str(&contents)?;
+            return Ok((ShipmentIndex::new(shipments), true));
+        }
+
+        let mut lines = 0;
+        let mut index = ShipmentIndex::default();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            lines += 1;
+            match serde_json::from_str(line) {
+                Ok(shipment) => index.put(shipment),
+                Err(e) => rest_warn!("(FileShipmentStore::read) skipping invalid line: {}", e),
+            }
+        }
+
+        // An unfinished last line would spoil the next one appended
+        let unfinished = !contents.is_empty() && !contents.ends_with('\n');
+        let rewrite = unfinished || lines != index.shipments.len();
+        Ok((index, rewrite))
+    }
+
+    /// Rewrites the file with the shipments not yet expired, one line each
+    fn compact(&self, index: &ShipmentIndex) -> io::Result<ShipmentIndex> {
+        let expiry = self.expiry();
+        let kept = ShipmentIndex::new(
+            index
+                .shipments
+                .iter()
+                .filter(|shipment| shipment.confirmed_at >= expiry)
+                .cloned()
+                .collect(),
+        );
+
+        let mut contents = vec![];
+        for shipment in &kept.shipments {
+            serde_json::to_writer(&mut contents, shipment)?;
+            contents.push(b'\n');
+        }
+
+        // Replaced in one step so a failed write leaves the old file
+        let temporary = self.path.with_extension("tmp");
+        let mut file = std::fs::File::create(&temporary)?;
+        file.write_all(&contents)?;
+        file.sync_all()?;
+        std::fs::rename(&temporary, &self.path)?;
+
+        let expired = index.shipments.len() - kept.shipments.len();
+        if expired > 0 {
+            rest_info!(
+                "(FileShipmentStore::compact) removed {} expired shipments.",
+                expired
+            );
+        }
+        Ok(kept)
+    }
+
+    /// The shipment, unless it has expired
+    fn unexpired(&self, shipment: Option<&Shipment>) -> Option<Shipment> {
+        shipment
+            .filter(|shipment| shipment.confirmed_at >= self.expiry())
+            .cloned()
+    }
+}
+
+impl ShipmentStore for FileShipmentStore {
+    fn get(&self, parcel_id: &str) -> io::Result<Option<Shipment>> {
+        let index = self.index.lock().map_err(|_| poisoned())?;
+        Ok(self.unexpired(index.get(parcel_id)))
+    }
+
+    fn find_by_token(&self, token: &str) -> io::Result<Option<Shipment>> {
+        let index = self.index.lock().map_err(|_| poisoned())?;
+        Ok(self.unexpired(index.find_by_token(token)))
+    }
+
+    /// Appends the change to the file before lookups see it, lookups don't
+    ///  wait for the file to be written
+    fn put(&self, shipment: Shipment) -> io::Result<()> {
+        let mut line = serde_json::to_vec(&shipment)?;
+        line.push(b'\n');
+
+        let mut until_compaction = self.writes.lock().map_err(|_| poisoned())?;
+        if *until_compaction == 0 {
+            let index = self.index.lock().map_err(|_| poisoned())?.clone();
+            let kept = self.compact(&index)?;
+            *self.index.lock().map_err(|_| poisoned())? = kept;
+            *until_compaction = CHANGES_BETWEEN_COMPACTIONS;
+        }
+
+        let appended = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| {
+                file.write_all(&line)?;
+                file.sync_data()
+            });
+        if let Err(e) = appended {
+            // Part of the line may have been written, the file is rewritten first
+            *until_compaction = 0;
+            return Err(e);
+        }
+
+        self.index.lock().map_err(|_| poisoned())?.put(shipment);
+        *until_compaction -= 1;
+        Ok(())
+    }
+}
+
+/// Confirmed shipments, shared by the handlers
+///
+/// Shipments are looked up in memory, changes are written to the store off
+///  the runtime.
+#[derive(Debug, Clone)]
+pub struct Shipments {
+    store: Arc<dyn ShipmentStore>,
+
+    /// Makes changes to shipments atomic
+    updates: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Shipments {
+    /// Keeps shipments in the configured file, or in memory
+    ///
+    /// Fails if the file can't be read.
+    pub async fn new(config: &Config) -> io::Result<Self> {
+        let store: Arc<dyn ShipmentStore> = match config.rest_shipment_file.as_str() {
+            "" => {
+                rest_warn!("(Shipments::new) shipments are kept in memory, labels and pickup codes are lost on restart.");
+                Arc::new(MemoryShipmentStore::default())
+            }
+            path => {
+                let path = PathBuf::from(path);
+                let retention = chrono::Duration::days(config.rest_shipment_retention_days.into());
+                let store =
+                    tokio::task::spawn_blocking(move || FileShipmentStore::open(path, retention))
+                        .await
+                        .map_err(io::Error::other)?
+                        .map_err(|e| {
+                            rest_error!("(Shipments::new) could not read the shipment file: {}", e);
+                            e
+                        })?;
+                Arc::new(store)
+            }
+        };
+
+        Ok(Self::with_store(store))
+    }
+
+    /// Keeps shipments in the given store
+    pub fn with_store(store: Arc<dyn ShipmentStore>) -> Self {
+        Shipments {
+            store,
+            updates: Arc::default(),
+        }
+    }
+
+    /// Records a confirmed shipment
+    pub async fn record(&self, shipment: Shipment) -> io::Result<()> {
+        let _lock = self.updates.lock().await;
+        self.put(shipment).await
+    }
+
+    /// The shipment of the given parcel, if confirmed through svc-cargo
+    pub fn get(&self, parcel_id: &str) -> io::Result<Option<Shipment>> {
+        self.store.get(parcel_id)
+    }
+
+    /// The shipment with the given public tracking token
+    pub fn find_by_token(&self, token: &str) -> io::Result<Option<Shipment>> {
+        self.store.find_by_token(token)
+    }
+
+    /// Changes the shipment of a parcel, None if there is none
+    ///
+    /// Returns what the change returns, no other change is made to the
+    ///  shipment in the meantime.
+    pub async fn update<T>(
+        &self,
+        parcel_id: &str,
+        change: impl FnOnce(&mut Shipment) -> T,
+    ) -> io::Result<Option<T>> {
+        let _lock = self.updates.lock().await;
+        let Some(mut shipment) = self.store.get(parcel_id)? else {
+            return Ok(None);
+        };
+
+        let result = change(&mut shipment);
+        self.put(shipment).await?;
+        Ok(Some(result))
+    }
+
+    /// Marks the recipient of a parcel as notified of an event
+    ///
+    /// Returns the shipment if its recipient is to be notified now, None if
+    ///  there is no recipient or they were already notified of the event.
+    pub async fn claim_notification(
+        &self,
+        parcel_id: &str,
+        event: ParcelEvent,
+    ) -> io::Result<Option<Shipment>> {
+        let _lock = self.updates.lock().await;
+        let Some(mut shipment) = self.store.get(parcel_id)? else {
+            return Ok(None);
+        };
+
+        if shipment.recipient.is_none() || shipment.notified.contains(&event) {
+            return Ok(None);
+        }
+
+        shipment.notified.push(event);
+        self.put(shipment.clone()).await?;
+        Ok(Some(shipment))
+    }
+
+    /// Puts a shipment in the store, off the runtime as it may write a file
+    async fn put(&self, shipment: Shipment) -> io::Result<()> {
+        let store = Arc::clone(&self.store);
+        tokio::task::spawn_blocking(move || store.put(shipment))
+            .await
+            .map_err(io::Error::other)?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock_shipment(parcel_id: &str, confirmed_at: DateTime<Utc>) -> Shipment {
+        Shipment {
+            parcel_id: parcel_id.to_string(),
+            itinerary_id: "8ec4d5ec-e9a7-4e93-a5d5-6d6dd4f5e2a1".to_string(),
+            user_id: "b3c1d7a6-3b0b-4f7c-8c6c-4e0d0d2b9f4e".to_string(),
+            weight_grams: 1250,
+            confirmed_at,
+            recipient: Some(RecipientContact {
+                name: Some("Ada".to_string()),
+                email: Some("ada@example.com".to_string()),
+                phone: None,
+            }),
+            tracking_token: Some("4f".repeat(32)),
+            notified: vec![],
+            pickup: PickupState::default(),
+        }
+    }
+
+    async fn check_shipments(shipments: &Shipments) {
+        let shipment = mock_shipment("cabcdd14-03ab-4ac0-b58c-dd4175bc587e", Utc::now());
+        assert_eq!(shipments.get(&shipment.parcel_id).unwrap(), None);
+        assert_eq!(
+            shipments
+                .claim_notification(&shipment.parcel_id, ParcelEvent::Confirmed)
+                .await
+                .unwrap(),
+            None
+        );
+
+        shipments.record(shipment.clone()).await.unwrap();
+        assert_eq!(
+            shipments.get(&shipment.parcel_id).unwrap(),
+            Some(shipment.clone())
+        );
+
+        // Confirming the parcel again replaces its shipment
+        let shipment = Shipment {
+            weight_grams: 900,
+            ..shipment
+        };
+        shipments.record(shipment.clone()).await.unwrap();
+        assert_eq!(
+            shipments.get(&shipment.parcel_id).unwrap(),
+            Some(shipment.clone())
+        );
+
+        let token = shipment.tracking_token.as_deref().unwrap();
+        assert_eq!(
+            shipments.find_by_token(token).unwrap(),
+            Some(shipment.clone())
+        );
+        assert_eq!(shipments.find_by_token(&"00".repeat(32)).unwrap(), None);
+
+        // Recipients are notified of each event once
+        let claimed = shipments
+            .claim_notification(&shipment.parcel_id, ParcelEvent::Confirmed)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(claimed.notified, vec![ParcelEvent::Confirmed]);
+        assert_eq!(
+            shipments
+                .claim_notification(&shipment.parcel_id, ParcelEvent::Confirmed)
+                .await
+                .unwrap(),
+            None
+        );
+        assert!(shipments
+            .claim_notification(&shipment.parcel_id, ParcelEvent::Arrived)
+            .await
+            .unwrap()
+            .is_some());
+
+        // Changes are kept
+        let attempts = shipments
+            .update(&shipment.parcel_id, |shipment| {
+                shipment.pickup.failed_attempts += 1;
+                shipment.pickup.failed_attempts
+            })
+            .await
+            .unwrap();
+        assert_eq!(attempts, Some(1));
+        let shipment = shipments.get(&shipment.parcel_id).unwrap().unwrap();
+        assert_eq!(shipment.pickup.failed_attempts, 1);
+        assert_eq!(shipments.update("unknown", |_| ()).await.unwrap(), None);
+
+        // Without a recipient there is no one to notify
+        let shipment = Shipment {
+            recipient: None,
+            notified: vec![],
+            ..shipment
+        };
+        shipments.record(shipment.clone()).await.unwrap();
+        assert_eq!(
+            shipments
+                .claim_notification(&shipment.parcel_id, ParcelEvent::Delivered)
+                .await
+                .unwrap(),
+            None
+        );
+
+        // A new tracking token replaces the old one
+        let shipment = Shipment {
+            tracking_token: Some("5a".repeat(32)),
+            ..shipment
+        };
+        shipments.record(shipment.clone()).await.unwrap();
+        assert_eq!(shipments.find_by_token(token).unwrap(), None);
+        assert_eq!(
+            shipments.find_by_token(&"5a".repeat(32)).unwrap(),
+            Some(shipment)
+        );
+    }
+
+    #[tokio::test]
+    async fn ut_memory_shipments() {
+        check_shipments(&Shipments::with_store(Arc::new(
+            MemoryShipmentStore::default(),
+        )))
+        .await;
+    }
+
+    fn lines(path: &std::path::Path) -> Vec<Shipment> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn ut_file_shipments() {
+        let path =
+            std::env::temp_dir().join(format!("svc-cargo-shipments-{}.json", uuid::Uuid::new_v4()));
+        let config = Config {
+            rest_shipment_file: path.to_string_lossy().to_string(),
+            ..Config::default()
+        };
+        check_shipments(&Shipments::new(&config).await.unwrap()).await;
+
+        // Each change is appended
+        assert_eq!(lines(&path).len(), 7);
+
+        // Shipments are read back from the file, which then keeps the last of each
+        let retention = chrono::Duration::days(90);
+        let store = FileShipmentStore::open(&path, retention).unwrap();
+        let shipment = store.find_by_token(&"5a".repeat(32)).unwrap().unwrap();
+        assert_eq!(lines(&path), vec![shipment.clone()]);
+        assert_eq!(store.get(&shipment.parcel_id).unwrap(), Some(shipment));
+
+        // A file that can't be read isn't used
+        std::fs::write(&path, "[").unwrap();
+        assert!(Shipments::new(&config).await.is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn ut_file_shipments_retention() {
+        let path =
+            std::env::temp_dir().join(format!("svc-cargo-shipments-{}.json", uuid::Uuid::new_v4()));
+        let retention = chrono::Duration::days(90);
+        let expired = mock_shipment(
+            "59e51ad1-d57d-4d2c-bc2d-e2387367d17f",
+            Utc::now() - chrono::Duration::days(91),
+        );
+        let kept = Shipment {
+            tracking_token: Some("5a".repeat(32)),
+            ..mock_shipment("cabcdd14-03ab-4ac0-b58c-dd4175bc587e", Utc::now())
+        };
+
+        // Files holding a single array are still read, and rewritten without expired shipments
+        let array = serde_json::to_vec(&vec![expired.clone(), kept.clone()]).unwrap();
+        std::fs::write(&path, array).unwrap();
+        let store = FileShipmentStore::open(&path, retention).unwrap();
+        assert_eq!(store.get(&expired.parcel_id).unwrap(), None);
+        assert_eq!(store.get(&kept.parcel_id).unwrap(), Some(kept.clone()));
+        assert_eq!(lines(&path), vec![kept.clone()]);
+
+        // Expired shipments are no longer found before they are removed from the file
+        store.put(expired.clone()).unwrap();
+        assert_eq!(store.get(&expired.parcel_id).unwrap(), None);
+        assert_eq!(store.find_by_token(&"4f".repeat(32)).unwrap(), None);
+        assert_eq!(lines(&path).len(), 2);
+
+        // An unfinished last line is dropped, so the next change is kept
+        let mut contents = std::fs::read_to_string(&path).unwrap();
+        contents.push_str("{\"parcel_id\":");
+        std::fs::write(&path, contents).unwrap();
+        let store = FileShipmentStore::open(&path, retention).unwrap();
+        assert_eq!(lines(&path), vec![kept.clone()]);
+        let changed = Shipment {
+            weight_grams: 900,
+            ..kept
+        };
+        store.put(changed.clone()).unwrap();
+        let store = FileShipmentStore::open(&path, retention).unwrap();
+        assert_eq!(
+            store.get(&changed.parcel_id).unwrap(),
+            Some(changed.clone())
+        );
+        assert_eq!(lines(&path), vec![changed]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,8 +1,19 @@
-use crate::grpc::client::GrpcClients;
+use super::error::status_from_grpc;
+use crate::grpc::client::{traced_request, GrpcClients};
+use chrono::{DateTime, Utc};
 use hyper::StatusCode;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::HashMap;
+use std::io;
 use svc_storage_client_grpc::prelude::*;
+use svc_storage_client_grpc::resources::flight_plan::Data as FlightPlanData;
+use svc_storage_client_grpc::resources::flight_plan_parcel::RowData as FlightPlanParcel;
+use svc_storage_client_grpc::resources::parcel::{
+    Data as ParcelData, ParcelStatus, UpdateObject as ParcelUpdate,
+};
 use svc_storage_client_grpc::resources::vehicle::Data as VehicleData;
 use svc_storage_client_grpc::resources::vertipad::Data as VertipadData;
+use svc_storage_client_grpc::resources::vertiport::Data as VertiportData;
 use uuid::Uuid;
 
 /// Don't allow large UUID strings
@@ -19,55 +30,355 @@ pub fn is_uuid(s: &str) -> bool {
     Uuid::parse_str(s).is_ok()
 }
 
-/// Request a vertipad record by id
-pub async fn get_vertipad_details(
-    vertipad_id: &str,
+/// Lowercase hexadecimal representation of bytes
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Bytes of a hexadecimal string, None if it isn't one
+pub fn from_hex(text: &str) -> Option<Vec<u8>> {
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            if pair.len() != 2 || !pair.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()
+        })
+        .collect()
+}
+
+/// 256 random bits in hexadecimal, for API keys and tokens
+pub fn random_token() -> io::Result<String> {
+    let mut token = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut token)
+        .map_err(|_| io::Error::other("could not generate a random token"))?;
+
+    Ok(hex(&token))
+}
+
+/// Returns true if the string could be a token of [`random_token`]
+pub fn is_token(s: &str) -> bool {
+    s.len() == 64 && s.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+}
+
+/// Request the vertipad records with the given ids, keyed by id
+pub async fn get_vertipads(
+    vertipad_ids: Vec<String>,
+    grpc_clients: &GrpcClients,
+) -> Result<HashMap<String, VertipadData>, StatusCode> {
+    let filter = AdvancedSearchFilter::search_in("vertipad_id".to_string(), vertipad_ids);
+
+    let response = match grpc_clients
+        .backends
+        .storage
+        .call_idempotent("vertipad.search", || async {
+            grpc_clients
+                .storage
+                .vertipad
+                .get_client()
+                .await?
+                .search(traced_request(filter.clone()))
+                .await
+        })
+        .await
+    {
+        Ok(response) => response.into_inner(),
+        Err(e) => {
+            let error_msg = "svc-storage error.".to_string();
+            rest_error!("(get_vertipads) {} {:?}", &error_msg, e);
+            return Err(status_from_grpc(&e));
+        }
+    };
+
+    Ok(response
+        .list
+        .into_iter()
+        .filter_map(|vertipad| Some((vertipad.id, vertipad.data?)))
+        .collect())
+}
+
+/// Request the vehicle records with the given ids, keyed by id
+pub async fn get_vehicles(
+    vehicle_ids: Vec<String>,
+    grpc_clients: &GrpcClients,
+) -> Result<HashMap<String, VehicleData>, StatusCode> {
+    let filter = AdvancedSearchFilter::search_in("vehicle_id".to_string(), vehicle_ids);
+
+    let response = match grpc_clients
+        .backends
+        .storage
+        .call_idempotent("vehicle.search", || async {
+            grpc_clients
+                .storage
+                .vehicle
+                .get_client()
+                .await?
+                .search(traced_request(filter.clone()))
+                .await
+        })
+        .await
+    {
+        Ok(response) => response.into_inner(),
+        Err(e) => {
+            let error_msg = "svc-storage error.".to_string();
+            rest_error!("(get_vehicles) {} {:?}", &error_msg, e);
+            return Err(status_from_grpc(&e));
+        }
+    };
+
+    Ok(response
+        .list
+        .into_iter()
+        .filter_map(|vehicle| Some((vehicle.id, vehicle.data?)))
+        .collect())
+}
+
+/// Request the parcels carried by the given flight plans
+pub async fn get_flight_plans_parcels(
+    flight_plan_ids: Vec<String>,
+    grpc_clients: &GrpcClients,
+) -> Result<Vec<FlightPlanParcel>, StatusCode> {
+    let filter = AdvancedSearchFilter::search_in("flight_plan_id".to_string(), flight_plan_ids);
+
+    match grpc_clients
+        .backends
+        .storage
+        .call_idempotent("flight_plan_parcel.search", || async {
+            grpc_clients
+                .storage
+                .flight_plan_parcel
+                .get_client()
+                .await?
+                .search(traced_request(filter.clone()))
+                .await
+        })
+        .await
+    {
+        Ok(response) => Ok(response.into_inner().list),
+        Err(e) => {
+            let error_msg = "svc-storage error.".to_string();
+            rest_error!("(get_flight_plans_parcels) {} {:?}", &error_msg, e);
+            Err(status_from_grpc(&e))
+        }
+    }
+}
+
+/// Request the flight plans carrying a parcel
+pub async fn get_parcel_flight_plans(
+    parcel_id: &str,
+    grpc_clients: &GrpcClients,
+) -> Result<Vec<FlightPlanParcel>, StatusCode> {
+    let filter =
+        AdvancedSearchFilter::search_equals("parcel_id".to_string(), parcel_id.to_string());
+
+    match grpc_clients
+        .backends
+        .storage
+        .call_idempotent("flight_plan_parcel.search", || async {
+            grpc_clients
+                .storage
+                .flight_plan_parcel
+                .get_client()
+                .await?
+                .search(traced_request(filter.clone()))
+                .await
+        })
+        .await
+    {
+        Ok(response) => Ok(response.into_inner().list),
+        Err(e) => {
+            let error_msg = "svc-storage error.".to_string();
+            rest_error!("(get_parcel_flight_plans) {} {:?}", &error_msg, e);
+            Err(status_from_grpc(&e))
+        }
+    }
+}
+
+/// Request a flight plan record by id
+pub async fn get_flight_plan_details(
+    flight_plan_id: &str,
     grpc_clients: &GrpcClients,
-) -> Result<VertipadData, StatusCode> {
+) -> Result<FlightPlanData, StatusCode> {
     let request = Id {
-        id: vertipad_id.to_string(),
+        id: flight_plan_id.to_string(),
     };
 
-    let response = match grpc_clients.storage.vertipad.get_by_id(request).await {
+    let response = match grpc_clients
+        .backends
+        .storage
+        .call_idempotent("flight_plan.get_by_id", || async {
+            grpc_clients
+                .storage
+                .flight_plan
+                .get_client()
+                .await?
+                .get_by_id(traced_request(request.clone()))
+                .await
+        })
+        .await
+    {
         Ok(response) => response.into_inner(),
         Err(e) => {
             let error_msg = "svc-storage error.".to_string();
-            rest_error!("(get_vertipad_details) {} {:?}", &error_msg, e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            rest_error!("(get_flight_plan_details) {} {:?}", &error_msg, e);
+            return Err(status_from_grpc(&e));
         }
     };
 
     let Some(data) = response.data else {
         let error_msg = "svc-storage error; no data.".to_string();
-        rest_error!("(get_vertipad_details) {}", &error_msg);
+        rest_error!("(get_flight_plan_details) {}", &error_msg);
         return Err(StatusCode::INTERNAL_SERVER_ERROR);
     };
 
     Ok(data)
 }
 
-pub async fn get_vehicle_details(
-    vehicle_id: &str,
+/// Request a vertiport record by id
+pub async fn get_vertiport_details(
+    vertiport_id: &str,
     grpc_clients: &GrpcClients,
-) -> Result<VehicleData, StatusCode> {
+) -> Result<VertiportData, StatusCode> {
     let request = Id {
-        id: vehicle_id.to_string(),
+        id: vertiport_id.to_string(),
     };
 
-    let response = match grpc_clients.storage.vehicle.get_by_id(request).await {
+    let response = match grpc_clients
+        .backends
+        .storage
+        .call_idempotent("vertiport.get_by_id", || async {
+            grpc_clients
+                .storage
+                .vertiport
+                .get_client()
+                .await?
+                .get_by_id(traced_request(request.clone()))
+                .await
+        })
+        .await
+    {
         Ok(response) => response.into_inner(),
         Err(e) => {
-            let error_msg = "svc-storage error, could not get by id.".to_string();
-            rest_error!("(get_vehicle_details) {} {:?}", &error_msg, e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            let error_msg = "svc-storage error.".to_string();
+            rest_error!("(get_vertiport_details) {} {:?}", &error_msg, e);
+            return Err(status_from_grpc(&e));
         }
     };
 
     let Some(data) = response.data else {
         let error_msg = "svc-storage error; no data.".to_string();
-        rest_error!("(get_vehicle_details) {}", &error_msg);
+        rest_error!("(get_vertiport_details) {}", &error_msg);
         return Err(StatusCode::INTERNAL_SERVER_ERROR);
     };
 
     Ok(data)
 }
+
+/// Request the flight plans carrying a parcel, ordered by scheduled departure
+pub async fn get_parcel_legs(
+    parcel_id: &str,
+    grpc_clients: &GrpcClients,
+) -> Result<Vec<FlightPlanData>, StatusCode> {
+    let mut legs = vec![];
+    for flight_plan in get_parcel_flight_plans(parcel_id, grpc_clients).await? {
+        legs.push(get_flight_plan_details(&flight_plan.flight_plan_id, grpc_clients).await?);
+    }
+
+    legs.sort_by_key(|leg| {
+        leg.origin_timeslot_start
+            .clone()
+            .map(Into::<DateTime<Utc>>::into)
+    });
+    Ok(legs)
+}
+
+/// Request a parcel record by id
+pub async fn get_parcel_details(
+    parcel_id: &str,
+    grpc_clients: &GrpcClients,
+) -> Result<ParcelData, StatusCode> {
+    let request = Id {
+        id: parcel_id.to_string(),
+    };
+
+    let response = match grpc_clients
+        .backends
+        .storage
+        .call_idempotent("parcel.get_by_id", || async {
+            grpc_clients
+                .storage
+                .parcel
+                .get_client()
+                .await?
+                .get_by_id(traced_request(request.clone()))
+                .await
+        })
+        .await
+    {
+        Ok(response) => response.into_inner(),
+        Err(e) => {
+            let error_msg = "svc-storage error.".to_string();
+            rest_error!("(get_parcel_details) {} {:?}", &error_msg, e);
+            return Err(status_from_grpc(&e));
+        }
+    };
+
+    let Some(data) = response.data else {
+        let error_msg = "svc-storage error; no data.".to_string();
+        rest_error!("(get_parcel_details) {}", &error_msg);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+
+    Ok(data)
+}
+
+/// Sets the status of a parcel, leaving its other fields unchanged
+pub async fn update_parcel_status(
+    parcel_id: &str,
+    status: ParcelStatus,
+    grpc_clients: &GrpcClients,
+) -> Result<(), StatusCode> {
+    let request = ParcelUpdate {
+        id: parcel_id.to_string(),
+        data: Some(ParcelData {
+            status: status as i32,
+            ..Default::default()
+        }),
+        mask: Some(FieldMask {
+            paths: vec!["status".to_string()],
+        }),
+    };
+
+    let response = match grpc_clients
+        .backends
+        .storage
+        .call_idempotent("parcel.update", || async {
+            grpc_clients
+                .storage
+                .parcel
+                .get_client()
+                .await?
+                .update(traced_request(request.clone()))
+                .await
+        })
+        .await
+    {
+        Ok(response) => response.into_inner(),
+        Err(e) => {
+            let error_msg = "svc-storage error.".to_string();
+            rest_error!("(update_parcel_status) {} {:?}", &error_msg, e);
+            return Err(status_from_grpc(&e));
+        }
+    };
+
+    match response.validation_result {
+        Some(result) if result.success => Ok(()),
+        _ => {
+            let error_msg = "svc-storage failure.".to_string();
+            rest_error!("(update_parcel_status) {} parcel {}", &error_msg, parcel_id);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
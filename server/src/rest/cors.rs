@@ -0,0 +1,186 @@
+//! Cross-origin resource sharing policy of the REST API
+//!
+//! Allowed origins are read from the current configuration for every
+//! request, so that a reloaded configuration applies without restarting the
+//! server. Each route only allows its own methods.
+
+use super::limit::API_KEY_HEADER;
+use crate::request_id::REQUEST_ID_HEADER;
+use crate::Config;
+use axum::http::{header, HeaderName, HeaderValue, Method};
+use std::time::Duration;
+use tokio::sync::watch;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// Response headers browsers may read
+const EXPOSED_HEADERS: [&str; 5] = [
+    "x-request-id",
+    "retry-after",
+    "ratelimit-limit",
+    "ratelimit-remaining",
+    "ratelimit-reset",
+];
+
+/// Returns if an origin matches an allowed origin pattern
+///
+/// Patterns are origins like `https://app.example.com`, or match any
+///  subdomain with a wildcard like `https://*.example.com`.
+pub fn origin_matches(pattern: &str, origin: &str) -> bool {
+    let Some((prefix, suffix)) = pattern.split_once("*.") else {
+        return pattern == origin;
+    };
+
+    let Some(subdomain) = origin
+        .strip_prefix(prefix)
+        .and_then(|rest| rest.strip_suffix(suffix))
+        .and_then(|rest| rest.strip_suffix('.'))
+    else {
+        return false;
+    };
+
+    !subdomain.is_empty()
+        && subdomain.split('.').all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Returns if an origin matches one of the comma separated allowed origin patterns
+pub fn origin_allowed(allowed_origins: &str, origin: &str) -> bool {
+    allowed_origins
+        .split(',')
+        .map(str::trim)
+        .any(|pattern| origin_matches(pattern, origin))
+}
+
+/// Parses comma separated request header names
+pub fn parse_header_names(headers: &str) -> Result<Vec<HeaderName>, String> {
+    headers
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            name.parse::<HeaderName>()
+                .map_err(|_| format!("'{name}' is not a valid header name."))
+        })
+        .collect()
+}
+
+/// Builds the CORS layers of the routes, following configuration reloads for origins
+#[derive(Debug, Clone)]
+pub struct CorsPolicy {
+    live_config: watch::Receiver<Config>,
+    allowed_headers: Vec<HeaderName>,
+    allow_credentials: bool,
+    max_age: Duration,
+}
+
+impl CorsPolicy {
+    /// Creates the policy from the configuration
+    ///
+    /// Allowed headers, credentials and max age are fixed when the server starts.
+    pub fn new(config: &Config, live_config: watch::Receiver<Config>) -> Self {
+        let mut allowed_headers = parse_header_names(&config.rest_cors_allowed_headers)
+            .unwrap_or_else(|e| {
+                rest_warn!("(CorsPolicy::new) {} using the default headers.", e);
+                vec![]
+            });
+        for required in [header::CONTENT_TYPE, REQUEST_ID_HEADER, API_KEY_HEADER] {
+            if !allowed_headers.contains(&required) {
+                allowed_headers.push(required);
+            }
+        }
+
+        CorsPolicy {
+            live_config,
+            allowed_headers,
+            allow_credentials: config.rest_cors_allow_credentials,
+            max_age: Duration::from_secs(config.rest_cors_max_age_seconds.into()),
+        }
+    }
+
+    /// CORS layer of a route accepting the given methods
+    pub fn layer(&self, methods: &[Method]) -> CorsLayer {
+        let live_config = self.live_config.clone();
+        let allow_origin = AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+            origin.to_str().is_ok_and(|origin| {
+                origin_allowed(&live_config.borrow().rest_cors_allowed_origin, origin)
+            })
+        });
+
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods(methods.to_vec())
+            .allow_headers(self.allowed_headers.clone())
+            .expose_headers(EXPOSED_HEADERS.map(HeaderName::from_static))
+            .allow_credentials(self.allow_credentials)
+            .max_age(self.max_age)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ut_origin_matches() {
+        assert!(origin_matches(
+            "https://app.example.com",
+            "https://app.example.com"
+        ));
+        assert!(!origin_matches(
+            "https://app.example.com",
+            "http://app.example.com"
+        ));
+        assert!(!origin_matches(
+            "https://app.example.com",
+            "https://app.example.com:8443"
+        ));
+
+        assert!(origin_matches(
+            "https://*.example.com",
+            "https://staging.example.com"
+        ));
+        assert!(origin_matches(
+            "https://*.example.com",
+            "https://a.b.example.com"
+        ));
+        assert!(!origin_matches(
+            "https://*.example.com",
+            "https://example.com"
+        ));
+        assert!(!origin_matches(
+            "https://*.example.com",
+            "https://evilexample.com"
+        ));
+        assert!(!origin_matches(
+            "https://*.example.com",
+            "https://example.com.evil.io"
+        ));
+        assert!(!origin_matches(
+            "https://*.example.com",
+            "https://a/b.example.com"
+        ));
+        assert!(origin_matches(
+            "http://*.example.com:3000",
+            "http://dev.example.com:3000"
+        ));
+        assert!(!origin_matches(
+            "http://*.example.com:3000",
+            "http://dev.example.com"
+        ));
+
+        let allowed = "https://app.example.com, https://*.partners.example.com";
+        assert!(origin_allowed(allowed, "https://app.example.com"));
+        assert!(origin_allowed(allowed, "https://acme.partners.example.com"));
+        assert!(!origin_allowed(allowed, "https://other.example.com"));
+    }
+
+    #[test]
+    fn ut_parse_header_names() {
+        assert_eq!(
+            parse_header_names("content-type, Authorization,"),
+            Ok(vec![header::CONTENT_TYPE, header::AUTHORIZATION])
+        );
+        assert!(parse_header_names("bad header").is_err());
+    }
+}
@@ -0,0 +1,952 @@
+//! Request rate limits, daily quotas and concurrency limit
+//!
+//! Each client gets its own token bucket per route, so that one busy client
+//! doesn't throttle the others. Clients are identified by the registered
+//! scanner of their API key, the user set by the gateway, or their IP address,
+//! in that order. Unknown API keys are ignored, and the user and the IP address
+//! from `X-Forwarded-For` are only taken when the connection comes from a
+//! trusted proxy, so that clients can't pick their own bucket.
+//!
+//! The limits are parsed again whenever the configuration is reloaded, so
+//! that a reloaded configuration applies without restarting the server.
+
+use super::api::scanner::ScannerRegistry;
+use crate::metrics::metrics;
+use crate::request_id::USER_HEADER;
+use crate::tls::TlsConnectInfo;
+use crate::Config;
+use axum::{
+    extract::{ConnectInfo, MatchedPath, State},
+    http::{header, Extensions, HeaderMap, HeaderName, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, Notify};
+
+/// Header carrying the API key of a client
+pub const API_KEY_HEADER: HeaderName = HeaderName::from_static("x-api-key");
+
+/// Header listing the client and the proxies a request went through
+const FORWARDED_FOR_HEADER: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// Rate limit headers, see the IETF `RateLimit` header fields draft
+const RATE_LIMIT_LIMIT_HEADER: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATE_LIMIT_REMAINING_HEADER: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATE_LIMIT_RESET_HEADER: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// Interval at which the token buckets of idle clients are dropped
+const BUCKET_SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Token bucket refilled at `rate` tokens per second, holding at most `rate` tokens
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+    /// Rate of the route when a token was last taken
+    rate: u32,
+}
+
+impl TokenBucket {
+    fn new(rate: u32, now: Instant) -> Self {
+        TokenBucket {
+            tokens: rate.into(),
+            refilled_at: now,
+            rate,
+        }
+    }
+
+    fn refill(&mut self, rate: f64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * rate).min(rate);
+        self.refilled_at = now;
+    }
+
+    /// Takes a token, or returns how long until the next one is available
+    fn try_take(&mut self, rate: u32, now: Instant) -> Result<(), Duration> {
+        self.rate = rate;
+        let rate = f64::from(rate.max(1));
+        self.refill(rate, now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+
+        Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+    }
+
+    /// Whole tokens left
+    fn remaining(&self) -> u32 {
+        self.tokens.floor() as u32
+    }
+
+    /// Time until the bucket is full again
+    fn reset_after(&self, rate: u32) -> Duration {
+        let rate = f64::from(rate.max(1));
+        Duration::from_secs_f64((rate - self.tokens).max(0.0) / rate)
+    }
+
+    /// Full buckets can be dropped, a new one would be the same
+    fn is_full(&mut self, now: Instant) -> bool {
+        let rate = f64::from(self.rate.max(1));
+        self.refill(rate, now);
+        self.tokens >= rate
+    }
+}
+
+/// Parses route limits like `/cargo/request=2,/cargo/track=20`
+pub fn parse_route_limits(limits: &str) -> Result<Vec<(String, u32)>, String> {
+    limits
+        .split(',')
+        .map(str::trim)
+        .filter(|limit| !limit.is_empty())
+        .map(|limit| {
+            let (route, rate) = limit
+                .split_once('=')
+                .ok_or_else(|| format!("'{limit}' is not like /route=requests."))?;
+            match rate.trim().parse::<u32>() {
+                Ok(rate) if rate > 0 && route.trim().starts_with('/') => {
+                    Ok((route.trim().to_string(), rate))
+                }
+                _ => Err(format!("'{limit}' is not like /route=requests.")),
+            }
+        })
+        .collect()
+}
+
+/// Address or network of a trusted proxy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrustedProxy {
+    network: IpAddr,
+    prefix_length: u8,
+}
+
+impl TrustedProxy {
+    /// Returns if the address belongs to the proxy's network
+    pub fn contains(&self, address: IpAddr) -> bool {
+        // IPv4 clients of dual stack listeners show up as mapped IPv6 addresses
+        let address = match address {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(address, IpAddr::V4),
+            address => address,
+        };
+
+        match (self.network, address) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix_length))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix_length))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Parses proxy addresses and networks like `10.0.0.0/8,::1`
+pub fn parse_trusted_proxies(proxies: &str) -> Result<Vec<TrustedProxy>, String> {
+    proxies
+        .split(',')
+        .map(str::trim)
+        .filter(|proxy| !proxy.is_empty())
+        .map(|proxy| {
+            let invalid = || format!("'{proxy}' is not an IP address or network.");
+            let (address, prefix_length) = match proxy.split_once('/') {
+                Some((address, prefix_length)) => (
+                    address,
+                    Some(prefix_length.parse::<u8>().map_err(|_| invalid())?),
+                ),
+                None => (proxy, None),
+            };
+
+            let network: IpAddr = address.parse().map_err(|_| invalid())?;
+            let max_length = if network.is_ipv4() { 32 } else { 128 };
+            let prefix_length = prefix_length.unwrap_or(max_length);
+            if prefix_length > max_length {
+                return Err(invalid());
+            }
+
+            Ok(TrustedProxy {
+                network,
+                prefix_length,
+            })
+        })
+        .collect()
+}
+
+/// Limits of the current configuration, parsed once per configuration change
+#[derive(Debug, Clone, PartialEq)]
+struct Limits {
+    /// Requests per second of the routes with a limit of their own
+    route_rates: HashMap<String, u32>,
+    /// Requests per second of the other routes
+    default_rate: u32,
+    daily_quota: u32,
+    concurrency: usize,
+    trusted_proxies: Vec<TrustedProxy>,
+}
+
+impl Limits {
+    fn new(config: &Config) -> Self {
+        // Reloaded configurations are validated, this only guards against bugs
+        let route_limits =
+            parse_route_limits(&config.rest_route_limits_per_second).unwrap_or_else(|e| {
+                rest_warn!("(Limits::new) route limits ignored: {}", e);
+                vec![]
+            });
+        let trusted_proxies =
+            parse_trusted_proxies(&config.rest_trusted_proxies).unwrap_or_else(|e| {
+                rest_warn!("(Limits::new) trusted proxies ignored: {}", e);
+                vec![]
+            });
+
+        let mut route_rates = HashMap::new();
+        for (route, rate) in route_limits {
+            route_rates.entry(route).or_insert(rate);
+        }
+
+        Limits {
+            route_rates,
+            default_rate: u32::from(config.rest_request_limit_per_second).max(1),
+            daily_quota: config.rest_daily_quota_per_api_key,
+            concurrency: usize::from(config.rest_concurrency_limit_per_service).max(1),
+            trusted_proxies,
+        }
+    }
+
+    /// Requests per second allowed on a route, for each client
+    fn route_rate(&self, route: &str) -> u32 {
+        self.route_rates
+            .get(route)
+            .copied()
+            .unwrap_or(self.default_rate)
+            .max(1)
+    }
+}
+
+/// Parses the limits of every new configuration, until the limits are no longer used
+fn follow_limits(mut config: watch::Receiver<Config>) -> watch::Receiver<Limits> {
+    let (sender, receiver) = watch::channel(Limits::new(&config.borrow_and_update()));
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                changed = config.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    let limits = Limits::new(&config.borrow_and_update());
+                    sender.send_replace(limits);
+                }
+                _ = sender.closed() => break,
+            }
+        }
+    });
+
+    receiver
+}
+
+/// Who a request is counted against
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Client {
+    /// The ID of the registered scanner of the API key
+    Scanner(String),
+    User(String),
+    Ip(IpAddr),
+    Unknown,
+}
+
+/// Address of the client, following `X-Forwarded-For` through trusted proxies
+fn client_ip(
+    headers: &HeaderMap,
+    peer: Option<IpAddr>,
+    trusted: &[TrustedProxy],
+) -> Option<IpAddr> {
+    let is_trusted = |address: IpAddr| trusted.iter().any(|proxy| proxy.contains(address));
+
+    let mut client = peer?;
+    if !is_trusted(client) {
+        return Some(client);
+    }
+
+    // The closest hops are appended last
+    let forwarded = headers
+        .get_all(&FORWARDED_FOR_HEADER)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect::<Vec<_>>();
+    for hop in forwarded.into_iter().rev() {
+        match hop.trim().parse::<IpAddr>() {
+            Ok(address) => {
+                client = address;
+                if !is_trusted(address) {
+                    break;
+                }
+            }
+            // Anything before an invalid entry can't be trusted either
+            Err(_) => break,
+        }
+    }
+
+    Some(client)
+}
+
+fn identify_client(
+    headers: &HeaderMap,
+    peer: Option<IpAddr>,
+    trusted: &[TrustedProxy],
+    scanners: &ScannerRegistry,
+) -> Client {
+    let header = |name: &HeaderName| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| !value.is_empty())
+    };
+
+    if let Some(api_key) = header(&API_KEY_HEADER) {
+        match scanners.identify(api_key) {
+            Ok(Some(scanner)) => return Client::Scanner(scanner.id),
+            Ok(None) => (),
+            Err(e) => rest_warn!("(identify_client) scanner registry unavailable: {}", e),
+        }
+    }
+
+    // Only the gateway sets the user
+    let from_proxy = peer.is_some_and(|peer| trusted.iter().any(|proxy| proxy.contains(peer)));
+    if let Some(user) = header(&USER_HEADER).filter(|_| from_proxy) {
+        return Client::User(user.to_string());
+    }
+
+    match client_ip(headers, peer, trusted) {
+        Some(address) => Client::Ip(address),
+        None => Client::Unknown,
+    }
+}
+
+/// Address of the connection, over plain HTTP or TLS
+fn peer_ip(extensions: &Extensions) -> Option<IpAddr> {
+    if let Some(ConnectInfo(address)) = extensions.get::<ConnectInfo<SocketAddr>>() {
+        return Some(address.ip());
+    }
+
+    extensions
+        .get::<ConnectInfo<TlsConnectInfo>>()
+        .and_then(|ConnectInfo(info)| info.remote_addr)
+        .map(|address| address.ip())
+}
+
+/// Requests made with the API key of each scanner on the current day
+#[derive(Debug, Default)]
+struct DailyQuotas {
+    day: Option<NaiveDate>,
+    used: HashMap<String, u32>,
+}
+
+impl DailyQuotas {
+    fn used(&mut self, scanner_id: &str, now: DateTime<Utc>) -> u32 {
+        let today = now.date_naive();
+        if self.day != Some(today) {
+            self.day = Some(today);
+            self.used.clear();
+        }
+
+        self.used.get(scanner_id).copied().unwrap_or(0)
+    }
+
+    fn consume(&mut self, scanner_id: &str) {
+        *self.used.entry(scanner_id.to_string()).or_insert(0) += 1;
+    }
+}
+
+/// Time until quotas are reset, at midnight UTC
+fn until_tomorrow(now: DateTime<Utc>) -> Duration {
+    let tomorrow = now
+        .date_naive()
+        .succ_opt()
+        .and_then(|day| day.and_hms_opt(0, 0, 0))
+        .map(|midnight| Utc.from_utc_datetime(&midnight));
+    tomorrow
+        .and_then(|tomorrow| (tomorrow - now).to_std().ok())
+        .unwrap_or(Duration::from_secs(1))
+}
+
+/// Rate limit state of a request that was let through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Allowed {
+    limit: u32,
+    remaining: u32,
+    reset_after: Duration,
+}
+
+/// Why a request was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rejected {
+    RateLimited { limit: u32, retry_after: Duration },
+    QuotaExceeded { retry_after: Duration },
+}
+
+/// Request in progress, frees its slot when dropped
+#[derive(Debug)]
+struct InFlight<'a> {
+    limiter: &'a RequestLimiter,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.limiter.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.limiter.released.notify_waiters();
+    }
+}
+
+/// Token bucket of each client on each route
+type Buckets = Mutex<HashMap<(Client, String), TokenBucket>>;
+
+/// Drops the full buckets, until the limiter is dropped
+fn sweep_buckets(buckets: Weak<Buckets>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(BUCKET_SWEEP_INTERVAL);
+        // The first tick completes immediately
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let Some(buckets) = buckets.upgrade() else {
+                break;
+            };
+            drop_idle_buckets(&buckets, Instant::now());
+        }
+    });
+}
+
+/// Drops full buckets, a new one would be the same
+fn drop_idle_buckets(buckets: &Buckets, now: Instant) {
+    lock(buckets).retain(|_, bucket| !bucket.is_full(now));
+}
+
+/// Shared state of the [`limit_requests`] middleware
+#[derive(Debug, Clone)]
+pub struct RequestLimiter {
+    limits: watch::Receiver<Limits>,
+    scanners: ScannerRegistry,
+    buckets: Arc<Buckets>,
+    quotas: Arc<Mutex<DailyQuotas>>,
+    in_flight: Arc<AtomicUsize>,
+    released: Arc<Notify>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // The state stays consistent even if a holder panicked
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl RequestLimiter {
+    /// Creates a limiter following the limits of the given configuration,
+    ///  counting requests with the API key of a registered scanner against the scanner
+    ///
+    /// Must be called within a Tokio runtime, which follows the configuration
+    ///  and drops the buckets of idle clients.
+    pub fn new(config: watch::Receiver<Config>, scanners: ScannerRegistry) -> Self {
+        let buckets = Arc::new(Mutex::new(HashMap::new()));
+        sweep_buckets(Arc::downgrade(&buckets));
+
+        RequestLimiter {
+            limits: follow_limits(config),
+            scanners,
+            buckets,
+            quotas: Arc::new(Mutex::new(DailyQuotas::default())),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            released: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Checks the quota and rate limit of a client, counting the request if allowed
+    fn check(
+        &self,
+        client: &Client,
+        route: &str,
+        now: Instant,
+        today: DateTime<Utc>,
+    ) -> Result<Allowed, Rejected> {
+        let (rate, daily_quota) = {
+            let limits = self.limits.borrow();
+            (limits.route_rate(route), limits.daily_quota)
+        };
+
+        let mut quotas = lock(&self.quotas);
+        if let Client::Scanner(scanner_id) = client {
+            if daily_quota > 0 && quotas.used(scanner_id, today) >= daily_quota {
+                return Err(Rejected::QuotaExceeded {
+                    retry_after: until_tomorrow(today),
+                });
+            }
+        }
+
+        let mut buckets = lock(&self.buckets);
+        let bucket = buckets
+            .entry((client.clone(), route.to_string()))
+            .or_insert_with(|| TokenBucket::new(rate, now));
+        if let Err(retry_after) = bucket.try_take(rate, now) {
+            return Err(Rejected::RateLimited {
+                limit: rate,
+                retry_after,
+            });
+        }
+
+        if let Client::Scanner(scanner_id) = client {
+            quotas.consume(scanner_id);
+        }
+
+        Ok(Allowed {
+            limit: rate,
+            remaining: bucket.remaining(),
+            reset_after: bucket.reset_after(rate),
+        })
+    }
+
+    /// Waits until fewer requests than the concurrency limit are in progress
+    async fn acquire(&self) -> InFlight<'_> {
+        let mut limits = self.limits.clone();
+        loop {
+            // Created before checking so that no release or raised limit is missed
+            let released = self.released.notified();
+            let limit = limits.borrow_and_update().concurrency;
+            let in_flight = self.in_flight.load(Ordering::SeqCst);
+            if in_flight < limit {
+                if self
+                    .in_flight
+                    .compare_exchange(in_flight, in_flight + 1, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+                {
+                    return InFlight { limiter: self };
+                }
+                continue;
+            }
+
+            tokio::select! {
+                _ = released => (),
+                _ = limits.changed() => (),
+            }
+        }
+    }
+}
+
+/// Whole seconds, rounded up
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs_f64().ceil() as u64
+}
+
+/// Rejects requests over the rate limit or daily quota of their client and
+///  queues requests over the concurrency limit
+pub async fn limit_requests<B>(
+    State(limiter): State<RequestLimiter>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let client = identify_client(
+        request.headers(),
+        peer_ip(request.extensions()),
+        &limiter.limits.borrow().trusted_proxies,
+        &limiter.scanners,
+    );
+
+    let allowed = match limiter.check(&client, &route, Instant::now(), Utc::now()) {
+        Ok(allowed) => allowed,
+        Err(rejected) => {
+            rest_warn!(
+                "(limit_requests) {:?} for {:?} on {}.",
+                rejected,
+                client,
+                route
+            );
+            metrics().rest_rate_limited.inc();
+            let retry_after = rejected_retry_after(rejected);
+            let message = match rejected {
+                Rejected::RateLimited { .. } => "(server) too many requests.",
+                Rejected::QuotaExceeded { .. } => "(server) daily quota exceeded.",
+            };
+
+            let mut response = (StatusCode::TOO_MANY_REQUESTS, message).into_response();
+            let headers = response.headers_mut();
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+            if let Rejected::RateLimited { limit, .. } = rejected {
+                headers.insert(RATE_LIMIT_LIMIT_HEADER, HeaderValue::from(limit));
+                headers.insert(RATE_LIMIT_REMAINING_HEADER, HeaderValue::from(0));
+                headers.insert(RATE_LIMIT_RESET_HEADER, HeaderValue::from(retry_after));
+            }
+            return response;
+        }
+    };
+
+    let _in_flight = limiter.acquire().await;
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert(RATE_LIMIT_LIMIT_HEADER, HeaderValue::from(allowed.limit));
+    headers.insert(
+        RATE_LIMIT_REMAINING_HEADER,
+        HeaderValue::from(allowed.remaining),
+    );
+    headers.insert(
+        RATE_LIMIT_RESET_HEADER,
+        HeaderValue::from(ceil_secs(allowed.reset_after)),
+    );
+    response
+}
+
+/// Seconds a rejected client should wait, at least one
+fn rejected_retry_after(rejected: Rejected) -> u64 {
+    let retry_after = match rejected {
+        Rejected::RateLimited { retry_after, .. } => retry_after,
+        Rejected::QuotaExceeded { retry_after } => retry_after,
+    };
+    ceil_secs(retry_after).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::api::scanner::MemoryScannerStore;
+    use super::*;
+    use axum::{body::Body, middleware, routing, Router};
+    use tower::ServiceExt;
+
+    #[test]
+    fn ut_token_bucket() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(2, now);
+        assert!(bucket.try_take(2, now).is_ok());
+        assert!(bucket.try_take(2, now).is_ok());
+        assert_eq!(bucket.try_take(2, now), Err(Duration::from_millis(500)));
+
+        // Refilled over time, up to the rate
+        let later = now + Duration::from_secs(10);
+        assert!(bucket.try_take(2, later).is_ok());
+        assert!(bucket.try_take(2, later).is_ok());
+        assert!(bucket.try_take(2, later).is_err());
+
+        // A raised rate applies immediately
+        let much_later = later + Duration::from_secs(10);
+        for _ in 0..5 {
+            assert!(bucket.try_take(5, much_later).is_ok());
+        }
+
+        // Buckets are only full at the rate of their own route
+        let mut bucket = TokenBucket::new(100, now);
+        assert!(bucket.try_take(100, now).is_ok());
+        assert!(!bucket.is_full(now));
+        assert!(bucket.is_full(now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn ut_parse_limits() {
+        assert_eq!(
+            parse_route_limits(" /cargo/request=2, /cargo/track = 20 ,"),
+            Ok(vec![
+                ("/cargo/request".to_string(), 2),
+                ("/cargo/track".to_string(), 20)
+            ])
+        );
+        assert_eq!(parse_route_limits(""), Ok(vec![]));
+        assert!(parse_route_limits("/cargo/request").is_err());
+        assert!(parse_route_limits("/cargo/request=0").is_err());
+        assert!(parse_route_limits("cargo=2").is_err());
+
+        let proxies = parse_trusted_proxies("10.0.0.0/8, 192.168.1.1, fd00::/8").unwrap();
+        assert!(proxies[0].contains("10.20.30.40".parse().unwrap()));
+        assert!(!proxies[0].contains("11.0.0.1".parse().unwrap()));
+        assert!(proxies[1].contains("192.168.1.1".parse().unwrap()));
+        assert!(!proxies[1].contains("192.168.1.2".parse().unwrap()));
+        assert!(proxies[2].contains("fd12::1".parse().unwrap()));
+        assert!(proxies[0].contains("::ffff:10.0.0.1".parse().unwrap()));
+        assert!(parse_trusted_proxies("10.0.0.0/33").is_err());
+        assert!(parse_trusted_proxies("proxy.local").is_err());
+    }
+
+    fn registry() -> ScannerRegistry {
+        ScannerRegistry::with_store(Arc::new(MemoryScannerStore::default()))
+    }
+
+    #[tokio::test]
+    async fn ut_identify_client() {
+        let trusted = parse_trusted_proxies("10.0.0.0/8").unwrap();
+        let proxy: IpAddr = "10.0.0.2".parse().unwrap();
+        let remote: IpAddr = "203.0.113.7".parse().unwrap();
+        let scanners = registry();
+        let scanner = scanners.register("Dock 1".to_string(), None).await.unwrap();
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            identify_client(&headers, None, &trusted, &scanners),
+            Client::Unknown
+        );
+        assert_eq!(
+            identify_client(&headers, Some(remote), &trusted, &scanners),
+            Client::Ip(remote)
+        );
+
+        // Forwarded addresses are only used behind a trusted proxy
+        headers.insert(
+            FORWARDED_FOR_HEADER,
+            HeaderValue::from_static("198.51.100.1, 203.0.113.7, 10.0.0.1"),
+        );
+        assert_eq!(
+            identify_client(&headers, Some(proxy), &trusted, &scanners),
+            Client::Ip(remote)
+        );
+        assert_eq!(
+            identify_client(&headers, Some(remote), &trusted, &scanners),
+            Client::Ip(remote)
+        );
+
+        // So is the user
+        headers.insert(USER_HEADER, HeaderValue::from_static("alice"));
+        assert_eq!(
+            identify_client(&headers, Some(proxy), &trusted, &scanners),
+            Client::User("alice".to_string())
+        );
+        assert_eq!(
+            identify_client(&headers, Some(remote), &trusted, &scanners),
+            Client::Ip(remote)
+        );
+
+        // Unknown API keys are ignored
+        headers.insert(API_KEY_HEADER, HeaderValue::from_static("key-1"));
+        assert_eq!(
+            identify_client(&headers, Some(proxy), &trusted, &scanners),
+            Client::User("alice".to_string())
+        );
+
+        headers.insert(
+            API_KEY_HEADER,
+            HeaderValue::from_str(&scanner.api_key).unwrap(),
+        );
+        assert_eq!(
+            identify_client(&headers, Some(remote), &trusted, &scanners),
+            Client::Scanner(scanner.scanner.id.clone())
+        );
+
+        // Not once the scanner is deactivated
+        scanners.deactivate(&scanner.scanner.id).await.unwrap();
+        assert_eq!(
+            identify_client(&headers, Some(remote), &trusted, &scanners),
+            Client::Ip(remote)
+        );
+    }
+
+    #[test]
+    fn ut_limits() {
+        let limits = Limits::new(&Config {
+            rest_request_limit_per_second: 2,
+            rest_route_limits_per_second: "/cargo/track=20,/cargo/track=30".to_string(),
+            rest_trusted_proxies: "10.0.0.0/8".to_string(),
+            ..Config::default()
+        });
+        assert_eq!(limits.route_rate("/cargo/track"), 20);
+        assert_eq!(limits.route_rate("/cargo/request"), 2);
+        assert_eq!(
+            limits.trusted_proxies,
+            parse_trusted_proxies("10.0.0.0/8").unwrap()
+        );
+
+        // Invalid limits are ignored rather than applied in part
+        let limits = Limits::new(&Config {
+            rest_route_limits_per_second: "/cargo/track=20,/cargo/request".to_string(),
+            rest_trusted_proxies: "10.0.0.0/8,proxy.local".to_string(),
+            ..Config::default()
+        });
+        assert!(limits.route_rates.is_empty());
+        assert!(limits.trusted_proxies.is_empty());
+    }
+
+    #[tokio::test]
+    async fn ut_follow_limits() {
+        let (sender, receiver) = watch::channel(Config {
+            rest_request_limit_per_second: 1,
+            ..Config::default()
+        });
+        let mut limits = follow_limits(receiver);
+        assert_eq!(limits.borrow_and_update().default_rate, 1);
+
+        // Limits are parsed again when the configuration changes
+        sender.send_modify(|config| {
+            config.rest_route_limits_per_second = "/cargo/track=5".to_string()
+        });
+        limits.changed().await.unwrap();
+        assert_eq!(limits.borrow_and_update().route_rate("/cargo/track"), 5);
+
+        // Until the limits are no longer used
+        drop(limits);
+        tokio::time::timeout(Duration::from_secs(1), sender.closed())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn ut_per_client_limits() {
+        let (_sender, receiver) = watch::channel(Config {
+            rest_request_limit_per_second: 1,
+            rest_route_limits_per_second: "/cargo/track=3".to_string(),
+            ..Config::default()
+        });
+        let limiter = RequestLimiter::new(receiver, registry());
+        let now = Instant::now();
+        let today = Utc::now();
+        let first = Client::Scanner("scanner-1".to_string());
+        let second = Client::Scanner("scanner-2".to_string());
+
+        let allowed = limiter.check(&first, "/cargo/request", now, today).unwrap();
+        assert_eq!(allowed.limit, 1);
+        assert_eq!(allowed.remaining, 0);
+        assert!(matches!(
+            limiter.check(&first, "/cargo/request", now, today),
+            Err(Rejected::RateLimited { limit: 1, .. })
+        ));
+
+        // Other clients and routes have their own buckets
+        assert!(limiter.check(&second, "/cargo/request", now, today).is_ok());
+        for remaining in [2, 1, 0] {
+            let allowed = limiter.check(&first, "/cargo/track", now, today).unwrap();
+            assert_eq!(allowed.limit, 3);
+            assert_eq!(allowed.remaining, remaining);
+        }
+        assert!(limiter.check(&first, "/cargo/track", now, today).is_err());
+
+        // Buckets of idle clients are dropped once they are full again
+        drop_idle_buckets(&limiter.buckets, now + Duration::from_millis(500));
+        assert_eq!(lock(&limiter.buckets).len(), 3);
+        drop_idle_buckets(&limiter.buckets, now + Duration::from_secs(1));
+        assert!(lock(&limiter.buckets).is_empty());
+    }
+
+    #[tokio::test]
+    async fn ut_daily_quota() {
+        let (_sender, receiver) = watch::channel(Config {
+            rest_request_limit_per_second: 100,
+            rest_daily_quota_per_api_key: 2,
+            ..Config::default()
+        });
+        let limiter = RequestLimiter::new(receiver, registry());
+        let now = Instant::now();
+        let today = "2024-03-01T23:59:00Z".parse::<DateTime<Utc>>().unwrap();
+        let client = Client::Scanner("scanner-1".to_string());
+
+        assert!(limiter.check(&client, "/cargo/track", now, today).is_ok());
+        assert!(limiter.check(&client, "/cargo/request", now, today).is_ok());
+        assert_eq!(
+            limiter.check(&client, "/cargo/track", now, today),
+            Err(Rejected::QuotaExceeded {
+                retry_after: Duration::from_secs(60)
+            })
+        );
+
+        // Clients without a scanner API key have no quota
+        let user = Client::User("alice".to_string());
+        for _ in 0..3 {
+            assert!(limiter.check(&user, "/cargo/track", now, today).is_ok());
+        }
+
+        // Quotas are reset every day
+        let tomorrow = today + chrono::Duration::minutes(1);
+        assert!(limiter
+            .check(&client, "/cargo/track", now, tomorrow)
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn ut_concurrency_limit() {
+        let (sender, receiver) = watch::channel(Config {
+            rest_concurrency_limit_per_service: 1,
+            ..Config::default()
+        });
+        let limiter = RequestLimiter::new(receiver, registry());
+
+        let first = limiter.acquire().await;
+        let waiting = tokio::time::timeout(Duration::from_millis(20), limiter.acquire()).await;
+        assert!(waiting.is_err());
+
+        drop(first);
+        let _first = limiter.acquire().await;
+
+        // A raised limit applies to the next request
+        sender.send_modify(|config| config.rest_concurrency_limit_per_service = 2);
+        let _second = limiter.acquire().await;
+        assert_eq!(limiter.in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn ut_limit_requests() {
+        let (sender, receiver) = watch::channel(Config {
+            rest_request_limit_per_second: 1,
+            ..Config::default()
+        });
+        let scanners = registry();
+        let scanner = scanners.register("Dock 1".to_string(), None).await.unwrap();
+        let limiter = RequestLimiter::new(receiver, scanners);
+        let app = Router::new()
+            .route("/", routing::get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(limiter, limit_requests));
+
+        let response = app
+            .clone()
+            .oneshot(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(RATE_LIMIT_LIMIT_HEADER).unwrap(),
+            "1"
+        );
+        assert_eq!(
+            response.headers().get(RATE_LIMIT_REMAINING_HEADER).unwrap(),
+            "0"
+        );
+        assert_eq!(
+            response.headers().get(RATE_LIMIT_RESET_HEADER).unwrap(),
+            "1"
+        );
+
+        let response = app
+            .clone()
+            .oneshot(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "1");
+
+        // Made up API keys don't get a fresh bucket
+        let request = Request::builder()
+            .header(API_KEY_HEADER, "key-1")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // A scanner isn't affected
+        let request = Request::builder()
+            .header(API_KEY_HEADER, &scanner.api_key)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Raising the limit on reload lets more requests through
+        sender.send_modify(|config| config.rest_request_limit_per_second = 100);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let response = app.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -1,25 +1,44 @@
 #[macro_use]
 pub mod macros;
+pub mod cors;
+pub mod limit;
 pub mod server;
 
 mod api;
+#[cfg(feature = "openapi")]
 use api::*;
 
+#[cfg(feature = "openapi")]
 use utoipa::OpenApi;
 
-use svc_scheduler_client_grpc::prelude::scheduler_storage::GeoPoint;
-
+#[cfg(feature = "openapi")]
 #[derive(OpenApi)]
 #[openapi(
     paths(
         request::request_flight,
+        availability::query_availability,
         query::query_vertiports,
         confirm::confirm_itinerary,
         cancel::cancel_itinerary,
         scan::scan_parcel,
+        scan::scan_parcel_batch,
+        anomaly::query_anomalies,
+        scanner::register_scanner,
+        scanner::list_scanners,
+        scanner::get_scanner,
+        scanner::rotate_scanner_key,
+        scanner::assign_scanner,
+        scanner::deactivate_scanner,
+        label::get_parcel_label,
+        delivery::deliver_parcel,
+        pickup::verify_pickup,
+        public::get_public_tracking,
         query::query_landings,
         query::query_scans,
-        health::health_check
+        health::health_check,
+        health::health_live,
+        health::health_ready,
+        metrics::get_metrics
     ),
     components(
         schemas(
@@ -30,16 +49,52 @@ use svc_scheduler_client_grpc::prelude::scheduler_storage::GeoPoint;
             rest_types::VertiportsQuery,
             rest_types::ItineraryCancel,
             rest_types::FlightRequest,
+            rest_types::ItinerarySortBy,
+            rest_types::ItineraryFilter,
+            rest_types::AvailabilityQuery,
+            rest_types::AvailabilityResponse,
+            rest_types::AvailabilityBucket,
             rest_types::ItineraryConfirm,
             rest_types::ItineraryConfirmation,
+            rest_types::RecipientContact,
             rest_types::ParcelScan,
+            rest_types::ParcelScanBatch,
+            rest_types::BatchScan,
+            rest_types::BatchScanStatus,
+            rest_types::BatchScanResult,
+            rest_types::ParcelScanBatchResponse,
+            rest_types::ScannerRegistration,
+            rest_types::Scanner,
+            rest_types::ScannerCredentials,
+            rest_types::ScannerAssignment,
+            rest_types::ScannersParams,
+            rest_types::ScannersResponse,
+            rest_types::ScanAnomalyKind,
+            rest_types::ScanAnomaly,
+            rest_types::ScanAnomaliesParams,
+            rest_types::ScanAnomaliesResponse,
+            rest_types::LabelFormat,
+            rest_types::LabelParams,
+            rest_types::DeliveryProof,
+            rest_types::DeliveryConfirmation,
+            rest_types::Delivery,
+            rest_types::PickupVerification,
+            rest_types::Pickup,
+            rest_types::PublicParcelStatus,
+            rest_types::PublicTracking,
             rest_types::TimeWindow,
             rest_types::Landing,
+            rest_types::LandingKind,
+            rest_types::LandingStatus,
             rest_types::LandingsQuery,
+            rest_types::LandingsParams,
             rest_types::LandingsResponse,
             rest_types::TrackingQuery,
             rest_types::TrackingResponse,
-            GeoPoint
+            rest_types::ErrorResponse,
+            rest_types::DependencyHealth,
+            rest_types::HealthResponse,
+            rest_types::GeoPoint
         )
     ),
     tags(
@@ -49,6 +104,7 @@ use svc_scheduler_client_grpc::prelude::scheduler_storage::GeoPoint;
 struct ApiDoc;
 
 /// Create OpenAPI3 Specification File
+#[cfg(feature = "openapi")]
 pub fn generate_openapi_spec(target: &str) -> Result<(), Box<dyn std::error::Error>> {
     let output = ApiDoc::openapi()
         .to_pretty_json()
@@ -1,24 +1,230 @@
 //! Rest server implementation
 
 use super::api;
+use super::cors::CorsPolicy;
+use super::limit::{limit_requests, RequestLimiter};
 use crate::grpc::client::GrpcClients;
+use crate::metrics::track_rest_metrics;
+use crate::request_id::RequestIdLayer;
 use crate::shutdown_signal;
+use crate::telemetry::TraceContextLayer;
+use crate::tls::{self, ReloadableAcceptor, TlsConnectInfo, TlsSettings};
 use crate::Config;
-use axum::{
-    error_handling::HandleErrorLayer,
-    extract::Extension,
-    http::{HeaderValue, StatusCode},
-    routing, BoxError, Router,
-};
+use axum::http::Method;
+use axum::{extract::Extension, middleware, routing, Router};
 use std::net::SocketAddr;
-use tower::{
-    buffer::BufferLayer,
-    limit::{ConcurrencyLimitLayer, RateLimitLayer},
-    ServiceBuilder,
-};
-use tower_http::cors::{Any, CorsLayer};
+use std::sync::Arc;
+use tokio::sync::watch;
+use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
 
+/// Routes of the REST API, each with the CORS policy of its methods
+///
+/// Rate limiting and the extensions used by the handlers are added by [`rest_server`].
+pub fn build_router(
+    config: &Config,
+    live_config: watch::Receiver<Config>,
+    scanner_certificate_required: bool,
+) -> Router {
+    let cors = CorsPolicy::new(config, live_config.clone());
+    let admin = middleware::from_fn_with_state(live_config, api::scanner::require_admin_key);
+
+    Router::new()
+        .route(
+            "/health",
+            routing::get(api::health::health_check).layer(cors.layer(&[Method::GET])),
+        )
+        .route(
+            "/health/live",
+            routing::get(api::health::health_live).layer(cors.layer(&[Method::GET])),
+        )
+        .route(
+            "/health/ready",
+            routing::get(api::health::health_ready).layer(cors.layer(&[Method::GET])),
+        )
+        .route(
+            "/metrics",
+            routing::get(api::metrics::get_metrics).layer(cors.layer(&[Method::GET])),
+        )
+        .route(
+            "/cargo/cancel",
+            routing::delete(api::cancel::cancel_itinerary).layer(cors.layer(&[Method::DELETE])),
+        )
+        .route(
+            "/cargo/request",
+            routing::post(api::request::request_flight).layer(cors.layer(&[Method::POST])),
+        )
+        .route(
+            "/cargo/availability",
+            routing::get(api::availability::query_availability).layer(cors.layer(&[Method::GET])),
+        )
+        .route(
+            "/cargo/confirm",
+            routing::put(api::confirm::confirm_itinerary).layer(cors.layer(&[Method::PUT])),
+        )
+        .route(
+            "/cargo/vertiports",
+            routing::post(api::query::query_vertiports).layer(cors.layer(&[Method::POST])),
+        )
+        .route(
+            "/cargo/scan",
+            // Preflight requests are answered without a client certificate
+            routing::put(api::scan::scan_parcel)
+                .route_layer(middleware::from_fn_with_state(
+                    scanner_certificate_required,
+                    tls::require_client_certificate,
+                ))
+                .layer(cors.layer(&[Method::PUT])),
+        )
+        .route(
+            "/cargo/scan/batch",
+            routing::put(api::scan::scan_parcel_batch)
+                .route_layer(middleware::from_fn_with_state(
+                    scanner_certificate_required,
+                    tls::require_client_certificate,
+                ))
+                .layer(cors.layer(&[Method::PUT])),
+        )
+        .route(
+            "/cargo/scan/anomalies",
+            routing::get(api::anomaly::query_anomalies)
+                .route_layer(admin.clone())
+                .layer(cors.layer(&[Method::GET])),
+        )
+        .route(
+            "/cargo/scanners",
+            routing::get(api::scanner::list_scanners)
+                .post(api::scanner::register_scanner)
+                .route_layer(admin.clone())
+                .layer(cors.layer(&[Method::GET, Method::POST])),
+        )
+        .route(
+            "/cargo/scanners/:id",
+            routing::get(api::scanner::get_scanner)
+                .delete(api::scanner::deactivate_scanner)
+                .route_layer(admin.clone())
+                .layer(cors.layer(&[Method::GET, Method::DELETE])),
+        )
+        .route(
+            "/cargo/scanners/:id/key",
+            routing::post(api::scanner::rotate_scanner_key)
+                .route_layer(admin.clone())
+                .layer(cors.layer(&[Method::POST])),
+        )
+        .route(
+            "/cargo/scanners/:id/vertiport",
+            routing::put(api::scanner::assign_scanner)
+                .route_layer(admin)
+                .layer(cors.layer(&[Method::PUT])),
+        )
+        .route(
+            "/cargo/parcels/:id/label",
+            routing::get(api::label::get_parcel_label).layer(cors.layer(&[Method::GET])),
+        )
+        .route(
+            "/cargo/parcels/:id/delivery",
+            routing::post(api::delivery::deliver_parcel)
+                .route_layer(middleware::from_fn_with_state(
+                    scanner_certificate_required,
+                    tls::require_client_certificate,
+                ))
+                .layer(cors.layer(&[Method::POST])),
+        )
+        .route(
+            "/cargo/parcels/:id/pickup",
+            routing::post(api::pickup::verify_pickup)
+                .route_layer(middleware::from_fn_with_state(
+                    scanner_certificate_required,
+                    tls::require_client_certificate,
+                ))
+                .layer(cors.layer(&[Method::POST])),
+        )
+        .route(
+            "/cargo/public/track/:token",
+            routing::get(api::public::get_public_tracking).layer(cors.layer(&[Method::GET])),
+        )
+        .route(
+            "/cargo/track",
+            routing::get(api::query::query_scans).layer(cors.layer(&[Method::GET])),
+        )
+        .route(
+            "/cargo/landings",
+            routing::get(api::query::query_landings).layer(cors.layer(&[Method::GET])),
+        )
+}
+
+/// The REST API with its middleware and the state used by the handlers
+///
+/// Used by [`rest_server`], and by tests serving the API in process.
+/// Fails if the state of the handlers can't be set up.
+pub async fn build_app(
+    config: &Config,
+    live_config: watch::Receiver<Config>,
+    scanner_certificate_required: bool,
+) -> std::io::Result<Router> {
+    // Scanner devices allowed to record scans
+    let scanner_registry = api::scanner::ScannerRegistry::new(config).await?;
+
+    // Rate limiting
+    let limit_middleware = ServiceBuilder::new()
+        .layer(RequestIdLayer::new("rest"))
+        .layer(TraceContextLayer)
+        .layer(TraceLayer::new_for_http())
+        .layer(middleware::from_fn(track_rest_metrics))
+        .layer(middleware::from_fn(api::error::json_error_body))
+        .layer(middleware::from_fn(api::error::circuit_breaker_retry_after))
+        .layer(middleware::from_fn_with_state(
+            RequestLimiter::new(live_config.clone(), scanner_registry.clone()),
+            limit_requests,
+        ));
+
+    //
+    // Extensions
+    //
+    // GRPC Clients
+    let grpc_clients = GrpcClients::default(config.clone());
+
+    // Latest dependency health, probed in the background
+    let health = crate::health::get_health().await.clone();
+
+    // Recently computed availability buckets
+    let availability_cache = api::availability::AvailabilityCache::default();
+
+    // Recently uploaded scans, to skip repeated uploads
+    let recent_scans = api::scan::RecentScans::default();
+
+    // Shipments confirmed through this service
+    let shipments = api::shipment::Shipments::new(config).await?;
+
+    // Notifications to the recipients of shipments
+    let notifier = api::notification::Notifier::new(config, shipments.clone());
+
+    // Scans away from the itineraries of their parcels, and arrivals
+    let scan_monitor = api::anomaly::ScanMonitor::new(config).notify_arrivals(notifier.clone());
+
+    // Proofs of delivery of parcels
+    let deliveries = api::delivery::Deliveries::new(config);
+
+    // Key of the digests of pickup codes
+    let pickup_codes = api::pickup::PickupCodes::new(config)?;
+
+    let app = build_router(config, live_config.clone(), scanner_certificate_required)
+        .layer(limit_middleware)
+        .layer(Extension(live_config))
+        .layer(Extension(health))
+        .layer(Extension(availability_cache))
+        .layer(Extension(recent_scans))
+        .layer(Extension(scan_monitor))
+        .layer(Extension(scanner_registry))
+        .layer(Extension(shipments))
+        .layer(Extension(deliveries))
+        .layer(Extension(pickup_codes))
+        .layer(Extension(notifier))
+        .layer(Extension(grpc_clients)); // Extension layer must be last
+
+    Ok(app)
+}
+
 /// Starts the REST API server for this microservice
 ///
 /// # Example:
@@ -48,80 +254,61 @@ pub async fn rest_server(
         }
     };
 
-    let cors_allowed_origin = match config.rest_cors_allowed_origin.parse::<HeaderValue>() {
-        Ok(url) => url,
+    // Limits and CORS origins follow configuration reloads
+    let live_config = crate::reload::subscribe(&config);
+
+    // TLS, with certificates reloaded along with the configuration
+    let tls_acceptor = match TlsSettings::rest(&config).map(ReloadableAcceptor::new) {
+        None => None,
+        Some(Ok(acceptor)) => Some(Arc::new(acceptor)),
+        Some(Err(e)) => {
+            rest_error!("(rest_server) could not set up TLS: {}, exiting.", e);
+            return Err(());
+        }
+    };
+    let scanner_certificate_required =
+        tls_acceptor.is_some() && !config.rest_tls_client_ca_file.is_empty();
+
+    let app = match build_app(&config, live_config.clone(), scanner_certificate_required).await {
+        Ok(app) => app,
         Err(e) => {
             rest_error!(
-                "(rest_server) invalid cors_allowed_origin address: {:?}, exiting.",
+                "(rest_server) could not set up the handlers: {}, exiting.",
                 e
             );
             return Err(());
         }
     };
 
-    // Rate limiting
-    let rate_limit = config.rest_request_limit_per_second as u64;
-    let concurrency_limit = config.rest_concurrency_limit_per_service as usize;
-    let limit_middleware = ServiceBuilder::new()
-        .layer(TraceLayer::new_for_http())
-        .layer(HandleErrorLayer::new(|e: BoxError| async move {
-            rest_warn!("(server) too many requests: {}", e);
-            (
-                StatusCode::TOO_MANY_REQUESTS,
-                "(server) too many requests.".to_string(),
-            )
-        }))
-        .layer(BufferLayer::new(100))
-        .layer(ConcurrencyLimitLayer::new(concurrency_limit))
-        .layer(RateLimitLayer::new(
-            rate_limit,
-            std::time::Duration::from_secs(1),
-        ));
-
     //
-    // Extensions
+    // Bind to address
     //
-    // GRPC Clients
-    let grpc_clients = GrpcClients::default(config.clone());
+    let result = match tls_acceptor {
+        Some(acceptor) => {
+            acceptor.reload_on_change(live_config);
+            let listener = match tokio::net::TcpListener::bind(full_rest_addr).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    rest_error!("(rest_server) could not bind {}: {}", full_rest_addr, e);
+                    return Err(());
+                }
+            };
 
-    let app = Router::new()
-        .route("/health", routing::get(api::health::health_check))
-        .route(
-            "/cargo/cancel",
-            routing::delete(api::cancel::cancel_itinerary),
-        )
-        .route(
-            "/cargo/request",
-            routing::post(api::request::request_flight),
-        )
-        .route(
-            "/cargo/confirm",
-            routing::put(api::confirm::confirm_itinerary),
-        )
-        .route(
-            "/cargo/vertiports",
-            routing::post(api::query::query_vertiports),
-        )
-        .route("/cargo/scan", routing::put(api::scan::scan_parcel))
-        .route("/cargo/track", routing::get(api::query::query_scans))
-        .route("/cargo/landings", routing::get(api::query::query_landings))
-        .layer(
-            CorsLayer::new()
-                .allow_origin(cors_allowed_origin)
-                .allow_headers(Any)
-                .allow_methods(Any),
-        )
-        .layer(limit_middleware)
-        .layer(Extension(grpc_clients)); // Extension layer must be last
+            let incoming = tls::incoming(listener, acceptor);
+            axum::Server::builder(hyper::server::accept::from_stream(incoming))
+                .serve(app.into_make_service_with_connect_info::<TlsConnectInfo>())
+                .with_graceful_shutdown(shutdown_signal("rest", shutdown_rx))
+                .await
+        }
+        None => {
+            axum::Server::bind(&full_rest_addr)
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .with_graceful_shutdown(shutdown_signal("rest", shutdown_rx))
+                .await
+        }
+    };
 
-    //
-    // Bind to address
-    //
-    match axum::Server::bind(&full_rest_addr)
-        .serve(app.into_make_service())
-        .with_graceful_shutdown(shutdown_signal("rest", shutdown_rx))
-        .await
-    {
+    match result {
         Ok(_) => {
             rest_info!("(rest_server) hosted at: {}.", full_rest_addr);
             Ok(())
@@ -132,3 +319,201 @@ pub async fn rest_server(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{header, Request, StatusCode};
+    use tower::ServiceExt;
+
+    fn router(config: Config) -> Router {
+        let (_, live_config) = watch::channel(config.clone());
+        build_router(&config, live_config, false)
+    }
+
+    fn preflight(uri: &str, origin: &str, method: &str) -> Request<Body> {
+        Request::builder()
+            .method(Method::OPTIONS)
+            .uri(uri)
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, method)
+            .header(
+                header::ACCESS_CONTROL_REQUEST_HEADERS,
+                "content-type,x-api-key",
+            )
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn ut_cors_preflight() {
+        let config = Config {
+            rest_cors_allowed_origin: "https://app.example.com,https://*.partners.example.com"
+                .to_string(),
+            rest_cors_allow_credentials: true,
+            rest_cors_max_age_seconds: 1200,
+            ..Config::default()
+        };
+        let app = router(config);
+
+        // Each route allows the methods it is routed with
+        for (uri, method) in [
+            ("/cargo/request", "POST"),
+            ("/cargo/confirm", "PUT"),
+            ("/cargo/cancel", "DELETE"),
+            ("/cargo/track", "GET"),
+            ("/cargo/scan", "PUT"),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(preflight(uri, "https://app.example.com", method))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+
+            let headers = response.headers();
+            assert_eq!(
+                headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+                "https://app.example.com"
+            );
+            assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], method);
+            assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+            assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "1200");
+
+            let allowed_headers = headers[header::ACCESS_CONTROL_ALLOW_HEADERS]
+                .to_str()
+                .unwrap();
+            assert!(
+                allowed_headers.contains("content-type"),
+                "{}",
+                allowed_headers
+            );
+            assert!(allowed_headers.contains("x-api-key"), "{}", allowed_headers);
+        }
+
+        // Any subdomain matching a wildcard origin is allowed
+        let response = app
+            .clone()
+            .oneshot(preflight(
+                "/cargo/request",
+                "https://acme.partners.example.com",
+                "POST",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://acme.partners.example.com"
+        );
+
+        // Other origins get no CORS headers, so browsers reject the response
+        for origin in ["https://evil.example.com", "https://partners.example.com"] {
+            let response = app
+                .clone()
+                .oneshot(preflight("/cargo/request", origin, "POST"))
+                .await
+                .unwrap();
+            assert!(
+                !response
+                    .headers()
+                    .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN),
+                "{}",
+                origin
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn ut_scanner_routes_require_certificate() {
+        let config = Config::default();
+        let (_, live_config) = watch::channel(config.clone());
+        let app = build_router(&config, live_config, true);
+
+        // Requests of scanner devices without a client certificate are rejected
+        for (uri, method) in [
+            ("/cargo/scan", Method::PUT),
+            ("/cargo/scan/batch", Method::PUT),
+            (
+                "/cargo/parcels/cabcdd14-03ab-4ac0-b58c-dd4175bc587e/delivery",
+                Method::POST,
+            ),
+            (
+                "/cargo/parcels/cabcdd14-03ab-4ac0-b58c-dd4175bc587e/pickup",
+                Method::POST,
+            ),
+        ] {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn ut_cors_simple_request() {
+        let app = router(Config::default());
+
+        let request = Request::builder()
+            .uri("/health/live")
+            .header(header::ORIGIN, "http://localhost:3000")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let headers = response.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost:3000"
+        );
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_ALLOW_CREDENTIALS));
+        assert!(headers[header::ACCESS_CONTROL_EXPOSE_HEADERS]
+            .to_str()
+            .unwrap()
+            .contains("ratelimit-remaining"));
+
+        // Methods a route isn't routed with are still rejected
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/health/live")
+            .header(header::ORIGIN, "http://localhost:3000")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[tokio::test]
+    async fn ut_cors_follows_reload() {
+        let config = Config::default();
+        let (sender, live_config) = watch::channel(config.clone());
+        let app = build_router(&config, live_config, false);
+
+        let origin = "https://app.example.com";
+        let response = app
+            .clone()
+            .oneshot(preflight("/cargo/track", origin, "GET"))
+            .await
+            .unwrap();
+        assert!(!response
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        sender.send_replace(Config {
+            rest_cors_allowed_origin: origin.to_string(),
+            ..config
+        });
+        let response = app
+            .oneshot(preflight("/cargo/track", origin, "GET"))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            origin
+        );
+    }
+}
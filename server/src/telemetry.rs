@@ -0,0 +1,314 @@
+//! OpenTelemetry distributed tracing
+//!
+//! Incoming REST and gRPC requests continue the trace of the caller when a
+//! W3C `traceparent` header is present. Outgoing gRPC requests carry the
+//! current trace context in their metadata.
+
+use crate::Config;
+use futures::future::BoxFuture;
+use hyper::http::{HeaderMap, Request};
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::{TraceError, TracerProvider as _};
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Tracer, TracerProvider};
+use opentelemetry_sdk::Resource;
+use std::io::Write;
+use std::task::{Context, Poll};
+use std::time::UNIX_EPOCH;
+use tonic::metadata::{MetadataKey, MetadataMap, MetadataValue};
+use tower::{Layer, Service};
+use tracing::instrument::{Instrument, Instrumented};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+
+/// Name reported for this service in exported spans
+const SERVICE_NAME: &str = "svc-cargo";
+
+/// Destination of finished spans
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TracingExporter {
+    /// Spans are propagated but not exported
+    None,
+
+    /// Spans are sent to an OTLP collector over gRPC at the given endpoint
+    Otlp(String),
+
+    /// Spans are written to stdout, one JSON object per line
+    Stdout,
+
+    /// Spans are appended to the given file, one JSON object per line
+    File(String),
+}
+
+impl TracingExporter {
+    /// Reads the exporter from the `tracing_exporter` setting
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        match config.tracing_exporter.as_str() {
+            "none" => Ok(TracingExporter::None),
+            "otlp" => Ok(TracingExporter::Otlp(config.tracing_otlp_endpoint.clone())),
+            "stdout" => Ok(TracingExporter::Stdout),
+            "file" => Ok(TracingExporter::File(config.tracing_file.clone())),
+            other => Err(format!(
+                "unknown tracing exporter '{other}', expected one of none, otlp, stdout or file."
+            )),
+        }
+    }
+}
+
+/// Sets up the global tracer and the W3C trace context propagator
+///
+/// Should be called once at startup, before any server is started.
+pub fn init_tracing(config: &Config) -> Result<(), String> {
+    let exporter = TracingExporter::from_config(config)?;
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let tracer = build_tracer(exporter).map_err(|e| e.to_string())?;
+    let subscriber =
+        tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+
+    tracing::subscriber::set_global_default(subscriber).map_err(|e| e.to_string())
+}
+
+/// Flushes and stops the exporter
+pub fn shutdown_tracing() {
+    global::shutdown_tracer_provider();
+}
+
+fn build_tracer(exporter: TracingExporter) -> Result<Tracer, TraceError> {
+    let trace_config =
+        opentelemetry_sdk::trace::config().with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            SERVICE_NAME,
+        )]));
+
+    let provider = match exporter {
+        TracingExporter::Otlp(endpoint) => {
+            return opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(trace_config)
+                .install_batch(opentelemetry_sdk::runtime::Tokio);
+        }
+        TracingExporter::Stdout => TracerProvider::builder()
+            .with_config(trace_config)
+            .with_simple_exporter(JsonSpanExporter::new(Box::new(std::io::stdout())))
+            .build(),
+        TracingExporter::File(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| TraceError::Other(Box::new(e)))?;
+
+            TracerProvider::builder()
+                .with_config(trace_config)
+                .with_simple_exporter(JsonSpanExporter::new(Box::new(file)))
+                .build()
+        }
+        TracingExporter::None => TracerProvider::builder().with_config(trace_config).build(),
+    };
+
+    let tracer = provider.tracer(SERVICE_NAME);
+    global::set_tracer_provider(provider);
+    Ok(tracer)
+}
+
+/// Writes finished spans as JSON lines, for local and offline testing
+struct JsonSpanExporter {
+    writer: Box<dyn Write + Send + Sync>,
+}
+
+impl JsonSpanExporter {
+    fn new(writer: Box<dyn Write + Send + Sync>) -> Self {
+        JsonSpanExporter { writer }
+    }
+}
+
+impl std::fmt::Debug for JsonSpanExporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsonSpanExporter").finish_non_exhaustive()
+    }
+}
+
+/// Converts a span to a single line of JSON
+fn span_to_json(span: &SpanData) -> serde_json::Value {
+    let micros = |time: std::time::SystemTime| {
+        time.duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_micros() as u64)
+            .unwrap_or(0)
+    };
+
+    let attributes: serde_json::Map<String, serde_json::Value> = span
+        .attributes
+        .iter()
+        .map(|kv| (kv.key.to_string(), kv.value.to_string().into()))
+        .collect();
+
+    serde_json::json!({
+        "trace_id": span.span_context.trace_id().to_string(),
+        "span_id": span.span_context.span_id().to_string(),
+        "parent_span_id": span.parent_span_id.to_string(),
+        "name": span.name,
+        "start_time_us": micros(span.start_time),
+        "end_time_us": micros(span.end_time),
+        "attributes": attributes,
+    })
+}
+
+impl SpanExporter for JsonSpanExporter {
+    fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        let result = batch
+            .iter()
+            .try_for_each(|span| writeln!(self.writer, "{}", span_to_json(span)))
+            .and_then(|_| self.writer.flush())
+            .map_err(|e| TraceError::Other(Box::new(e)));
+
+        Box::pin(std::future::ready(result))
+    }
+}
+
+/// Reads trace context from HTTP headers
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// Writes trace context into gRPC metadata
+struct MetadataInjector<'a>(&'a mut MetadataMap);
+
+impl Injector for MetadataInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        let Ok(key) = MetadataKey::from_bytes(key.as_bytes()) else {
+            return;
+        };
+
+        let Ok(value) = MetadataValue::try_from(value.as_str()) else {
+            return;
+        };
+
+        self.0.insert(key, value);
+    }
+}
+
+/// Adds the trace context of the current span to outgoing gRPC metadata
+pub fn inject_trace_context(metadata: &mut MetadataMap) {
+    let context = tracing::Span::current().context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut MetadataInjector(metadata))
+    });
+}
+
+/// Tower layer running each request in a span that continues the caller's trace
+///
+/// Used by both the REST and the gRPC server.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct TraceContextLayer;
+
+impl<S> Layer<S> for TraceContextLayer {
+    type Service = TraceContextService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TraceContextService { inner }
+    }
+}
+
+/// Service created by [`TraceContextLayer`]
+#[derive(Debug, Clone)]
+pub struct TraceContextService<S> {
+    inner: S,
+}
+
+impl<S, B> Service<Request<B>> for TraceContextService<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Instrumented<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let parent = global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(request.headers()))
+        });
+
+        let span = tracing::info_span!(
+            "request",
+            method = %request.method(),
+            path = %request.uri().path()
+        );
+        span.set_parent(parent);
+
+        let future = span.in_scope(|| self.inner.call(request));
+        future.instrument(span)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ut_tracing_exporter_from_config() {
+        let mut config = Config::default();
+        assert_eq!(
+            TracingExporter::from_config(&config),
+            Ok(TracingExporter::None)
+        );
+
+        config.tracing_exporter = "otlp".to_string();
+        assert_eq!(
+            TracingExporter::from_config(&config),
+            Ok(TracingExporter::Otlp(config.tracing_otlp_endpoint.clone()))
+        );
+
+        config.tracing_exporter = "file".to_string();
+        assert_eq!(
+            TracingExporter::from_config(&config),
+            Ok(TracingExporter::File(config.tracing_file.clone()))
+        );
+
+        config.tracing_exporter = "jaeger".to_string();
+        assert!(TracingExporter::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn ut_propagate_trace_context() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let mut headers = HeaderMap::new();
+        headers.insert("traceparent", traceparent.parse().unwrap());
+
+        let context = global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(&headers))
+        });
+
+        let mut metadata = MetadataMap::new();
+        global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&context, &mut MetadataInjector(&mut metadata))
+        });
+
+        assert_eq!(
+            metadata.get("traceparent").and_then(|v| v.to_str().ok()),
+            Some(traceparent)
+        );
+    }
+}
@@ -0,0 +1,760 @@
+//! TLS for the REST and gRPC servers
+//!
+//! Both servers share the certificate and key configured in `tls_cert_file`
+//! and `tls_key_file`. The gRPC server can require client certificates
+//! signed by `grpc_tls_client_ca_file`. The REST server accepts client
+//! certificates signed by `rest_tls_client_ca_file`, which scanner devices
+//! must present to use the scan endpoints.
+//!
+//! Certificates are read again each time the configuration is reloaded, new
+//! connections use the new certificates while open connections are kept.
+//!
+//! Connections to the backend services use TLS when `backend_tls_ca_file`
+//! is set, presenting `backend_tls_cert_file` if set. These are read once,
+//! when the clients are created.
+
+use crate::Config;
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures::stream::{self, Stream};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch, Semaphore};
+use tokio_rustls::rustls::server::{
+    AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient,
+};
+use tokio_rustls::rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+use tonic::transport::{Certificate as TonicCertificate, ClientTlsConfig, Identity};
+
+/// Connections waiting for their handshake to be accepted by the server
+const ACCEPT_BACKLOG: usize = 128;
+
+/// Handshakes running at the same time, more connections wait in the listen queue
+const MAX_CONCURRENT_HANDSHAKES: usize = 256;
+
+/// Clients not done with their handshake by then are disconnected
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pause after failing to accept a connection, e.g. when out of file descriptors
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_secs(1);
+
+/// Whether clients are asked for a certificate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientAuth {
+    /// Clients aren't asked for a certificate
+    None,
+    /// Clients may present a certificate signed by the given CA file
+    Optional(String),
+    /// Clients must present a certificate signed by the given CA file
+    Required(String),
+}
+
+/// Server using TLS, its connections are logged with its log macros
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsServer {
+    /// The REST server
+    Rest,
+    /// The gRPC server
+    Grpc,
+}
+
+/// Logs with the `rest_*` or `grpc_*` macro of the given server
+macro_rules! server_log {
+    ($server:expr, $rest:ident, $grpc:ident, $($arg:tt)+) => {
+        match $server {
+            TlsServer::Rest => $rest!($($arg)+),
+            TlsServer::Grpc => $grpc!($($arg)+),
+        }
+    };
+}
+
+/// Certificate files and client authentication of a server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsSettings {
+    /// Server these settings are for
+    pub server: TlsServer,
+    /// PEM certificate chain
+    pub cert_file: String,
+    /// PEM private key
+    pub key_file: String,
+    /// Client certificate verification
+    pub client_auth: ClientAuth,
+    /// Protocols offered with ALPN, e.g. `h2` for gRPC
+    pub alpn_protocols: Vec<Vec<u8>>,
+}
+
+impl TlsSettings {
+    /// Settings of the REST server, `None` if TLS is disabled
+    pub fn rest(config: &Config) -> Option<Self> {
+        Self::from_config(config, TlsServer::Rest)
+            .map(|settings| settings.with_alpn(&[b"h2", b"http/1.1"]))
+    }
+
+    /// Settings of the gRPC server, `None` if TLS is disabled
+    pub fn grpc(config: &Config) -> Option<Self> {
+        Self::from_config(config, TlsServer::Grpc).map(|settings| settings.with_alpn(&[b"h2"]))
+    }
+
+    fn from_config(config: &Config, server: TlsServer) -> Option<Self> {
+        if config.tls_cert_file.is_empty() {
+            return None;
+        }
+
+        let (client_ca_file, required) = match server {
+            TlsServer::Rest => (&config.rest_tls_client_ca_file, false),
+            TlsServer::Grpc => (&config.grpc_tls_client_ca_file, true),
+        };
+
+        let client_auth = match (client_ca_file.is_empty(), required) {
+            (true, _) => ClientAuth::None,
+            (false, true) => ClientAuth::Required(client_ca_file.to_string()),
+            (false, false) => ClientAuth::Optional(client_ca_file.to_string()),
+        };
+
+        Some(TlsSettings {
+            server,
+            cert_file: config.tls_cert_file.clone(),
+            key_file: config.tls_key_file.clone(),
+            client_auth,
+            alpn_protocols: vec![],
+        })
+    }
+
+    fn with_alpn(mut self, protocols: &[&[u8]]) -> Self {
+        self.alpn_protocols = protocols.iter().map(|p| p.to_vec()).collect();
+        self
+    }
+
+    /// Reads the certificate files into a rustls server configuration
+    pub fn load(&self) -> Result<ServerConfig, String> {
+        let certs = read_certs(&self.cert_file)?;
+        let key = read_key(&self.key_file)?;
+
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = match &self.client_auth {
+            ClientAuth::None => builder.with_no_client_auth(),
+            ClientAuth::Optional(ca_file) => builder.with_client_cert_verifier(
+                AllowAnyAnonymousOrAuthenticatedClient::new(read_roots(ca_file)?).boxed(),
+            ),
+            ClientAuth::Required(ca_file) => builder.with_client_cert_verifier(
+                AllowAnyAuthenticatedClient::new(read_roots(ca_file)?).boxed(),
+            ),
+        };
+
+        let mut server_config = builder
+            .with_single_cert(certs, key)
+            .map_err(|e| format!("invalid certificate or key {}: {}", self.cert_file, e))?;
+        server_config.alpn_protocols = self.alpn_protocols.clone();
+        Ok(server_config)
+    }
+}
+
+/// TLS of the connections to the backend services, `None` if they are plaintext
+pub fn backend_tls(config: &Config) -> Result<Option<ClientTlsConfig>, String> {
+    let ca_file = &config.backend_tls_ca_file;
+    let cert_file = &config.backend_tls_cert_file;
+    let key_file = &config.backend_tls_key_file;
+    if cert_file.is_empty() != key_file.is_empty() {
+        return Err(
+            "backend_tls_cert_file and backend_tls_key_file must be set together.".to_string(),
+        );
+    }
+
+    if ca_file.is_empty() {
+        return match cert_file.is_empty() {
+            true => Ok(None),
+            false => Err("backend_tls_cert_file needs TLS, set backend_tls_ca_file.".to_string()),
+        };
+    }
+
+    // Checked here, tonic only reads them when connecting
+    read_roots(ca_file)?;
+    let mut tls = ClientTlsConfig::new().ca_certificate(TonicCertificate::from_pem(read(ca_file)?));
+    if !cert_file.is_empty() {
+        read_certs(cert_file)?;
+        read_key(key_file)?;
+        tls = tls.identity(Identity::from_pem(read(cert_file)?, read(key_file)?));
+    }
+
+    Ok(Some(tls))
+}
+
+fn read(file: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(file).map_err(|e| format!("could not read {}: {}", file, e))
+}
+
+fn open(file: &str) -> Result<BufReader<File>, String> {
+    File::open(file)
+        .map(BufReader::new)
+        .map_err(|e| format!("could not open {}: {}", file, e))
+}
+
+fn read_certs(file: &str) -> Result<Vec<Certificate>, String> {
+    let certs = rustls_pemfile::certs(&mut open(file)?)
+        .map_err(|e| format!("could not read certificates from {}: {}", file, e))?;
+    if certs.is_empty() {
+        return Err(format!("no certificate found in {}.", file));
+    }
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn read_key(file: &str) -> Result<PrivateKey, String> {
+    let mut reader = open(file)?;
+    loop {
+        match rustls_pemfile::read_one(&mut reader) {
+            Ok(Some(rustls_pemfile::Item::PKCS8Key(key)))
+            | Ok(Some(rustls_pemfile::Item::RSAKey(key)))
+            | Ok(Some(rustls_pemfile::Item::ECKey(key))) => return Ok(PrivateKey(key)),
+            Ok(Some(_)) => continue,
+            Ok(None) => return Err(format!("no private key found in {}.", file)),
+            Err(e) => return Err(format!("could not read private key from {}: {}", file, e)),
+        }
+    }
+}
+
+fn read_roots(file: &str) -> Result<RootCertStore, String> {
+    let mut roots = RootCertStore::empty();
+    for cert in read_certs(file)? {
+        roots
+            .add(&cert)
+            .map_err(|e| format!("invalid CA certificate in {}: {}", file, e))?;
+    }
+
+    Ok(roots)
+}
+
+/// TLS acceptor whose certificates can be replaced while the server runs
+#[derive(Debug)]
+pub struct ReloadableAcceptor {
+    settings: TlsSettings,
+    server_config: RwLock<Arc<ServerConfig>>,
+}
+
+impl ReloadableAcceptor {
+    /// Creates an acceptor, failing if the certificate files can't be read
+    pub fn new(settings: TlsSettings) -> Result<Self, String> {
+        let server_config = settings.load()?;
+        Ok(ReloadableAcceptor {
+            settings,
+            server_config: RwLock::new(Arc::new(server_config)),
+        })
+    }
+
+    /// Reads the certificate files again, keeping the current ones on failure
+    pub fn reload(&self) -> Result<(), String> {
+        let server_config = Arc::new(self.settings.load()?);
+        *self
+            .server_config
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = server_config;
+        Ok(())
+    }
+
+    /// Acceptor for a new connection, using the latest certificates
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(
+            self.server_config
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+        )
+    }
+
+    /// Reloads the certificates each time the configuration is reloaded
+    pub fn reload_on_change(self: &Arc<Self>, mut config: watch::Receiver<Config>) {
+        let acceptor = self.clone();
+        tokio::spawn(async move {
+            while config.changed().await.is_ok() {
+                let server = acceptor.settings.server;
+                match acceptor.reload() {
+                    Ok(()) => server_log!(
+                        server,
+                        rest_info,
+                        grpc_info,
+                        "(reload_on_change) certificate {} reloaded.",
+                        acceptor.settings.cert_file
+                    ),
+                    Err(e) => server_log!(
+                        server,
+                        rest_error,
+                        grpc_error,
+                        "(reload_on_change) keeping the current certificate, {}",
+                        e
+                    ),
+                }
+            }
+        });
+    }
+}
+
+/// Peer of a TLS connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConnectInfo {
+    /// Address of the client
+    pub remote_addr: Option<SocketAddr>,
+    /// DER certificate of the client, only present once verified
+    pub client_certificate: Option<Vec<u8>>,
+}
+
+/// Accepted TLS connection
+#[derive(Debug)]
+pub struct TlsConnection {
+    stream: tokio_rustls::server::TlsStream<TcpStream>,
+}
+
+impl TlsConnection {
+    /// Peer of this connection
+    pub fn connect_info(&self) -> TlsConnectInfo {
+        let (tcp, session) = self.stream.get_ref();
+        TlsConnectInfo {
+            remote_addr: tcp.peer_addr().ok(),
+            client_certificate: session
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .map(|cert| cert.0.clone()),
+        }
+    }
+}
+
+impl AsyncRead for TlsConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+impl tonic::transport::server::Connected for TlsConnection {
+    type ConnectInfo = TlsConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        TlsConnection::connect_info(self)
+    }
+}
+
+impl axum::extract::connect_info::Connected<&TlsConnection> for TlsConnectInfo {
+    fn connect_info(connection: &TlsConnection) -> Self {
+        connection.connect_info()
+    }
+}
+
+/// Errors of a single connection, the listener itself is fine
+fn is_connection_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    )
+}
+
+/// Accepts TCP connections and yields them once their TLS handshake succeeded
+///
+/// Handshakes run in their own tasks so that slow clients don't hold up
+///  others. Failed handshakes are logged and dropped, they don't stop the server.
+pub fn incoming(
+    listener: TcpListener,
+    acceptor: Arc<ReloadableAcceptor>,
+) -> impl Stream<Item = io::Result<TlsConnection>> {
+    incoming_with_limits(
+        listener,
+        acceptor,
+        MAX_CONCURRENT_HANDSHAKES,
+        HANDSHAKE_TIMEOUT,
+    )
+}
+
+fn incoming_with_limits(
+    listener: TcpListener,
+    acceptor: Arc<ReloadableAcceptor>,
+    max_handshakes: usize,
+    handshake_timeout: Duration,
+) -> impl Stream<Item = io::Result<TlsConnection>> {
+    let (sender, receiver) = mpsc::channel(ACCEPT_BACKLOG);
+    let handshakes = Arc::new(Semaphore::new(max_handshakes));
+    tokio::spawn(async move {
+        let server = acceptor.settings.server;
+        loop {
+            // Leave connections in the listen queue while enough handshakes run
+            let Ok(permit) = handshakes.clone().acquire_owned().await else {
+                break;
+            };
+
+            let (tcp, remote_addr) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) if is_connection_error(&e) => continue,
+                Err(e) => {
+                    server_log!(
+                        server,
+                        rest_warn,
+                        grpc_warn,
+                        "(incoming) could not accept connection: {}",
+                        e
+                    );
+                    tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                    continue;
+                }
+            };
+
+            if sender.is_closed() {
+                // The server stopped
+                break;
+            }
+
+            let tls_acceptor = acceptor.acceptor();
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                let handshake =
+                    tokio::time::timeout(handshake_timeout, tls_acceptor.accept(tcp)).await;
+                drop(permit);
+                match handshake {
+                    Ok(Ok(stream)) => {
+                        let _ = sender.send(Ok(TlsConnection { stream })).await;
+                    }
+                    Ok(Err(e)) => {
+                        server_log!(
+                            server,
+                            rest_debug,
+                            grpc_debug,
+                            "(incoming) TLS handshake with {} failed: {}",
+                            remote_addr,
+                            e
+                        );
+                    }
+                    Err(_) => {
+                        server_log!(
+                            server,
+                            rest_debug,
+                            grpc_debug,
+                            "(incoming) TLS handshake with {} timed out.",
+                            remote_addr
+                        );
+                    }
+                }
+            });
+        }
+    });
+
+    stream::unfold(receiver, |mut receiver| async move {
+        receiver
+            .recv()
+            .await
+            .map(|connection| (connection, receiver))
+    })
+}
+
+/// Rejects requests without a verified client certificate when `required`
+///
+/// Used for the endpoints of scanner devices when `rest_tls_client_ca_file` is set.
+pub async fn require_client_certificate<B>(
+    State(required): State<bool>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let has_certificate = request
+        .extensions()
+        .get::<ConnectInfo<TlsConnectInfo>>()
+        .is_some_and(|info| info.client_certificate.is_some());
+    if required && !has_certificate {
+        rest_warn!("(require_client_certificate) request without client certificate rejected.");
+        return (
+            StatusCode::FORBIDDEN,
+            "A client certificate is required.".to_string(),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use rcgen::{BasicConstraints, CertificateParams, IsCa};
+    use tokio_rustls::rustls::{ClientConfig, ServerName};
+    use tokio_rustls::TlsConnector;
+
+    /// Self signed CA, generated for each test
+    pub(crate) struct TestCa {
+        ca: rcgen::Certificate,
+        dir: std::path::PathBuf,
+    }
+
+    impl TestCa {
+        pub(crate) fn new() -> Self {
+            let mut params = CertificateParams::new(vec![]);
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let dir = std::env::temp_dir().join(format!("svc-cargo-tls-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            TestCa {
+                ca: rcgen::Certificate::from_params(params).unwrap(),
+                dir,
+            }
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.dir.join(name).to_string_lossy().into_owned()
+        }
+
+        /// Writes the CA certificate, returns its path
+        pub(crate) fn write_ca(&self) -> String {
+            let path = self.path("ca.pem");
+            std::fs::write(&path, self.ca.serialize_pem().unwrap()).unwrap();
+            path
+        }
+
+        /// Writes a certificate signed by the CA, returns the cert and key paths
+        pub(crate) fn write_cert(&self, name: &str) -> (String, String) {
+            let cert = rcgen::Certificate::from_params(CertificateParams::new(vec![
+                "localhost".to_string()
+            ]))
+            .unwrap();
+            let cert_path = self.path(&format!("{name}.pem"));
+            let key_path = self.path(&format!("{name}.key"));
+            std::fs::write(
+                &cert_path,
+                cert.serialize_pem_with_signer(&self.ca).unwrap(),
+            )
+            .unwrap();
+            std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+            (cert_path, key_path)
+        }
+
+        fn client_config(&self, client_cert: Option<(String, String)>) -> ClientConfig {
+            let builder = ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(read_roots(&self.write_ca()).unwrap());
+            match client_cert {
+                Some((cert, key)) => builder
+                    .with_client_auth_cert(read_certs(&cert).unwrap(), read_key(&key).unwrap())
+                    .unwrap(),
+                None => builder.with_no_client_auth(),
+            }
+        }
+    }
+
+    impl Drop for TestCa {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// Runs a handshake over an in-memory connection, returning the server's
+    ///  certificate as seen by the client and the client's as seen by the server
+    async fn handshake(
+        acceptor: &ReloadableAcceptor,
+        client_config: ClientConfig,
+    ) -> Result<(Vec<u8>, Option<Vec<u8>>), String> {
+        let (client_io, server_io) = tokio::io::duplex(16 * 1024);
+        let connector = TlsConnector::from(Arc::new(client_config));
+        let name = ServerName::try_from("localhost").unwrap();
+
+        let (client, server) = tokio::join!(
+            connector.connect(name, client_io),
+            acceptor.acceptor().accept(server_io)
+        );
+        let client = client.map_err(|e| e.to_string())?;
+        let server = server.map_err(|e| e.to_string())?;
+
+        let server_cert = client.get_ref().1.peer_certificates().unwrap()[0].0.clone();
+        let client_cert = server
+            .get_ref()
+            .1
+            .peer_certificates()
+            .map(|certs| certs[0].0.clone());
+        Ok((server_cert, client_cert))
+    }
+
+    fn settings(cert: (String, String), client_auth: ClientAuth) -> TlsSettings {
+        TlsSettings {
+            server: TlsServer::Rest,
+            cert_file: cert.0,
+            key_file: cert.1,
+            client_auth,
+            alpn_protocols: vec![],
+        }
+    }
+
+    #[test]
+    fn ut_tls_settings_from_config() {
+        assert_eq!(TlsSettings::rest(&Config::default()), None);
+
+        let config = Config {
+            tls_cert_file: "server.pem".to_string(),
+            tls_key_file: "server.key".to_string(),
+            grpc_tls_client_ca_file: "ca.pem".to_string(),
+            ..Config::default()
+        };
+        let rest = TlsSettings::rest(&config).unwrap();
+        assert_eq!(rest.client_auth, ClientAuth::None);
+
+        let grpc = TlsSettings::grpc(&config).unwrap();
+        assert_eq!(grpc.client_auth, ClientAuth::Required("ca.pem".to_string()));
+        assert_eq!(grpc.alpn_protocols, vec![b"h2".to_vec()]);
+
+        let config = Config {
+            rest_tls_client_ca_file: "ca.pem".to_string(),
+            ..config
+        };
+        let rest = TlsSettings::rest(&config).unwrap();
+        assert_eq!(rest.client_auth, ClientAuth::Optional("ca.pem".to_string()));
+    }
+
+    #[tokio::test]
+    async fn ut_tls_client_auth() {
+        let ca = TestCa::new();
+        let server = ca.write_cert("server");
+        let client = ca.write_cert("client");
+
+        // Required client certificates
+        let acceptor = ReloadableAcceptor::new(settings(
+            server.clone(),
+            ClientAuth::Required(ca.write_ca()),
+        ))
+        .unwrap();
+        assert!(handshake(&acceptor, ca.client_config(None)).await.is_err());
+        let (_, client_cert) = handshake(&acceptor, ca.client_config(Some(client.clone())))
+            .await
+            .unwrap();
+        assert!(client_cert.is_some());
+
+        // Optional client certificates
+        let acceptor =
+            ReloadableAcceptor::new(settings(server, ClientAuth::Optional(ca.write_ca()))).unwrap();
+        let (_, client_cert) = handshake(&acceptor, ca.client_config(None)).await.unwrap();
+        assert!(client_cert.is_none());
+        let (_, client_cert) = handshake(&acceptor, ca.client_config(Some(client)))
+            .await
+            .unwrap();
+        assert!(client_cert.is_some());
+
+        // Certificates of another CA are rejected
+        let other_ca = TestCa::new();
+        let other_client = other_ca.write_cert("client");
+        assert!(handshake(&acceptor, ca.client_config(Some(other_client)))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn ut_tls_reload() {
+        let ca = TestCa::new();
+        let server = ca.write_cert("server");
+        let acceptor = ReloadableAcceptor::new(settings(server.clone(), ClientAuth::None)).unwrap();
+        let (first_cert, _) = handshake(&acceptor, ca.client_config(None)).await.unwrap();
+
+        // A renewed certificate is used once reloaded
+        let renewed = ca.write_cert("server");
+        assert_eq!(renewed, server);
+        let (cert, _) = handshake(&acceptor, ca.client_config(None)).await.unwrap();
+        assert_eq!(cert, first_cert);
+        acceptor.reload().unwrap();
+        let (cert, _) = handshake(&acceptor, ca.client_config(None)).await.unwrap();
+        assert_ne!(cert, first_cert);
+
+        // A broken certificate is not applied
+        std::fs::write(&server.0, "not a certificate").unwrap();
+        assert!(acceptor.reload().is_err());
+        assert!(handshake(&acceptor, ca.client_config(None)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn ut_incoming_handshake_limits() {
+        use futures::StreamExt;
+
+        let ca = TestCa::new();
+        let acceptor = Arc::new(
+            ReloadableAcceptor::new(settings(ca.write_cert("server"), ClientAuth::None)).unwrap(),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming = incoming_with_limits(listener, acceptor, 1, Duration::from_millis(200));
+        tokio::pin!(incoming);
+
+        // A client that never starts its handshake holds the only slot...
+        let _idle = TcpStream::connect(addr).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // ...until it times out, then the next client gets through
+        let start = std::time::Instant::now();
+        let connector = TlsConnector::from(Arc::new(ca.client_config(None)));
+        let client = tokio::spawn(async move {
+            let tcp = TcpStream::connect(addr).await.unwrap();
+            let name = ServerName::try_from("localhost").unwrap();
+            connector.connect(name, tcp).await
+        });
+
+        let connection = tokio::time::timeout(Duration::from_secs(5), incoming.next())
+            .await
+            .unwrap();
+        assert!(connection.unwrap().is_ok());
+        assert!(client.await.unwrap().is_ok());
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn ut_require_client_certificate() {
+        use axum::{body::Body, middleware, routing, Router};
+        use tower::ServiceExt;
+
+        let app = |required: bool| {
+            Router::new().route(
+                "/cargo/scan",
+                routing::put(|| async { "ok" }).route_layer(middleware::from_fn_with_state(
+                    required,
+                    require_client_certificate,
+                )),
+            )
+        };
+        let request = |client_certificate: Option<Vec<u8>>| {
+            let mut request = Request::put("/cargo/scan").body(Body::empty()).unwrap();
+            request.extensions_mut().insert(ConnectInfo(TlsConnectInfo {
+                remote_addr: None,
+                client_certificate,
+            }));
+            request
+        };
+
+        let response = app(true).oneshot(request(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = app(true).oneshot(request(Some(vec![1]))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app(false).oneshot(request(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}